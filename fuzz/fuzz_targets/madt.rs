@@ -0,0 +1,23 @@
+//! Parses the input as a MADT, then goes through everything the accessors derive from its
+//! interrupt controller structures
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use pril::efi::acpi::MADT;
+
+fuzz_target!(|data: &[u8]| {
+    let Ok(madt) = MADT::from_bytes(data) else {
+        return;
+    };
+
+    for processor in madt.processors() {
+        let _ = madt.nmi_lint(processor.uid);
+    }
+    for irq in 0..16 {
+        let _ = madt.isa_irq_to_gsi(irq);
+    }
+    let _ = madt.io_apics().count();
+    let _ = madt.nmi_sources().count();
+    let _ = madt.mp_wakeup();
+    let _ = madt.local_apic_addr();
+});
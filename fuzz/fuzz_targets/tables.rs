@@ -0,0 +1,37 @@
+//! Builds the table registry out of the input, which stands in for physical memory with the RSDP
+//! at its start, then parses every table it found. Addresses in the input are physical addresses,
+//! such that the RSDP and the root tables point into the input itself.
+//!
+//! The physical memory stand-in only exists when the crate is built with `--cfg fuzzing`, which
+//! `cargo fuzz` does.
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use pril::efi::acpi::{
+    with_phys_memory, AcpiTables, BGRT, DBG2, DMAR, FADT, HPET, MADT, MCFG, SLIT, SPCR, SRAT,
+};
+
+// Physical address the input is found at
+const RSDP_ADDR: usize = 0x1000;
+
+fuzz_target!(|data: &[u8]| {
+    with_phys_memory(&[(RSDP_ADDR, data)], || {
+        let Some(tables) = AcpiTables::new(RSDP_ADDR) else {
+            return;
+        };
+
+        for table in tables.iter() {
+            let _ = table.header();
+        }
+        let _ = tables.find::<FADT>();
+        let _ = tables.find::<MADT>();
+        let _ = tables.find::<HPET>();
+        let _ = tables.find::<MCFG>();
+        let _ = tables.find::<SRAT>();
+        let _ = tables.find::<SLIT>();
+        let _ = tables.find::<SPCR>();
+        let _ = tables.find::<DBG2>();
+        let _ = tables.find::<DMAR>();
+        let _ = tables.find::<BGRT>();
+    });
+});
@@ -1,3 +1,11 @@
+# With --svam-reset, pril shuts the machine down through the runtime services right after
+# SetVirtualAddressMap, instead of going on
+features=""
+if [ "$1" = "--svam-reset" ]; then
+    features="--features svam-reset"
+fi
+cargo build $features || exit 1
+
 qemu-system-x86_64 \
     -smp 8 \
     -enable-kvm \
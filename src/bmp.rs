@@ -0,0 +1,675 @@
+//! Module that parses BMP images, like the boot logo the BGRT points to. Everything in here works
+//! on byte slices only, such that it does not depend on the firmware or on physical memory.
+use crate::efi::acpi::{self as parse, FromBytes};
+use core::mem::size_of;
+
+/// The largest width or height we accept, such that the size of the pixel array always fits in a
+/// `usize`
+pub const MAX_BMP_DIMENSION: u32 = 1 << 15;
+
+/// Reasons for which a BMP image could not be parsed
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BmpError {
+    /// The image needs `needed` bytes, but only `available` bytes are there
+    Truncated { needed: usize, available: usize },
+    /// The file does not start with the "BM" signature
+    InvalidSignature,
+    /// The info header is not one of the versions we know, which are at least 40 bytes long
+    UnsupportedHeader(u32),
+    /// The image is compressed in a way we do not decode
+    UnsupportedCompression(u32),
+    /// We only decode 8, 24 and 32 bits per pixel images
+    UnsupportedBitCount(u16),
+    /// The width or the height is zero, or above `MAX_BMP_DIMENSION`
+    InvalidDimensions { width: i32, height: i32 },
+    /// A pixel refers to a color past the end of the color table
+    InvalidColorIndex(u8),
+}
+
+/// A color, with 8 bits per component
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Rgb {
+    pub red: u8,
+    pub green: u8,
+    pub blue: u8,
+}
+
+// BITMAPFILEHEADER, at the start of every BMP file
+#[derive(Clone, Copy)]
+#[repr(C, packed)]
+struct FileHeader {
+    signature: [u8; 2],
+    // Size of the whole file, in bytes
+    file_size: u32,
+    reserved: u32,
+    // Offset of the pixel array from the start of the file
+    pixel_offset: u32,
+}
+
+// BITMAPINFOHEADER, which later header versions extend
+#[derive(Clone, Copy)]
+#[repr(C, packed)]
+struct InfoHeader {
+    header_size: u32,
+    width: i32,
+    // Positive for bottom-up images, which start with their last row, negative for top-down ones
+    height: i32,
+    planes: u16,
+    bit_count: u16,
+    compression: u32,
+    image_size: u32,
+    x_pixels_per_meter: i32,
+    y_pixels_per_meter: i32,
+    // Number of entries in the color table, or 0 for as many as the bit count allows
+    colors_used: u32,
+    colors_important: u32,
+}
+
+mod compression {
+    /// Uncompressed pixels
+    pub const RGB: u32 = 0;
+    /// Uncompressed pixels whose components are given by masks
+    pub const BITFIELDS: u32 = 3;
+}
+
+// The size of the BITMAPINFOHEADER, the smallest info header we accept
+const INFO_HEADER_SIZE: u32 = 40;
+
+unsafe impl FromBytes for FileHeader {}
+unsafe impl FromBytes for InfoHeader {}
+
+// Reads a `T` from `bytes`, `offset` bytes from its start
+fn read<T: FromBytes>(bytes: &[u8], offset: usize) -> Result<T, BmpError> {
+    parse::read(bytes, offset).map_err(|_| BmpError::Truncated {
+        needed: offset + size_of::<T>(),
+        available: bytes.len(),
+    })
+}
+
+/// A validated, uncompressed BMP image, borrowing the bytes of the file
+#[derive(Debug, Clone, Copy)]
+pub struct Bmp<'a> {
+    // The bytes of the whole file
+    bytes: &'a [u8],
+    width: u32,
+    height: u32,
+    // Whether the first row of the pixel array is the top one
+    top_down: bool,
+    bit_count: u16,
+    // The color table of 8 bits per pixel images, as 4 bytes blue, green, red, reserved entries
+    palette: &'a [u8],
+    // The pixel array
+    pixels: &'a [u8],
+    // The number of bytes in a row of the pixel array, padded to a multiple of 4
+    row_stride: usize,
+    // The masks of the red, green and blue components of 32 bits per pixel images
+    masks: [u32; 3],
+}
+
+impl<'a> Bmp<'a> {
+    /// Parses and validates the BMP file held by `bytes`. The file size from its header is only
+    /// used to bound the image, such that trailing bytes are ignored.
+    pub fn from_bytes(bytes: &'a [u8]) -> Result<Self, BmpError> {
+        let file_header: FileHeader = read(bytes, 0)?;
+        if &file_header.signature != b"BM" {
+            return Err(BmpError::InvalidSignature);
+        }
+        let file_size = file_header.file_size as usize;
+        let bytes = bytes.get(..file_size).ok_or(BmpError::Truncated {
+            needed: file_size,
+            available: bytes.len(),
+        })?;
+
+        let info: InfoHeader = read(bytes, size_of::<FileHeader>())?;
+        if info.header_size < INFO_HEADER_SIZE {
+            return Err(BmpError::UnsupportedHeader(info.header_size));
+        }
+
+        let (width, height) = (info.width, info.height);
+        let abs_height = height.unsigned_abs();
+        if width <= 0
+            || width as u32 > MAX_BMP_DIMENSION
+            || abs_height == 0
+            || abs_height > MAX_BMP_DIMENSION
+        {
+            return Err(BmpError::InvalidDimensions { width, height });
+        }
+
+        let bit_count = info.bit_count;
+        let compression = info.compression;
+        let masks = match (compression, bit_count) {
+            (compression::RGB, 8 | 24) => [0; 3],
+            (compression::RGB, 32) => [0x00FF_0000, 0x0000_FF00, 0x0000_00FF],
+            // The masks follow the BITMAPINFOHEADER, whether they are part of a later header
+            // version or not
+            (compression::BITFIELDS, 32) => {
+                let masks_offset = size_of::<FileHeader>() + INFO_HEADER_SIZE as usize;
+                [
+                    read(bytes, masks_offset)?,
+                    read(bytes, masks_offset + 4)?,
+                    read(bytes, masks_offset + 8)?,
+                ]
+            }
+            (compression::RGB | compression::BITFIELDS, _) => {
+                return Err(BmpError::UnsupportedBitCount(bit_count))
+            }
+            _ => return Err(BmpError::UnsupportedCompression(compression)),
+        };
+
+        // The color table of 8 bits per pixel images follows the info header
+        let palette = if bit_count == 8 {
+            let colors = match info.colors_used {
+                0 => 256,
+                colors => colors.min(256) as usize,
+            };
+            let palette_offset = size_of::<FileHeader>() + info.header_size as usize;
+            bytes
+                .get(palette_offset..)
+                .and_then(|palette| palette.get(..colors * 4))
+                .ok_or(BmpError::Truncated {
+                    needed: palette_offset + colors * 4,
+                    available: bytes.len(),
+                })?
+        } else {
+            &[]
+        };
+
+        let row_stride = (width as usize * bit_count as usize).div_ceil(32) * 4;
+        let pixels_offset = file_header.pixel_offset as usize;
+        let pixels_size = row_stride * abs_height as usize;
+        let pixels = bytes
+            .get(pixels_offset..)
+            .and_then(|pixels| pixels.get(..pixels_size))
+            .ok_or(BmpError::Truncated {
+                needed: pixels_offset.saturating_add(pixels_size),
+                available: bytes.len(),
+            })?;
+
+        let bmp = Self {
+            bytes,
+            width: width as u32,
+            height: abs_height,
+            top_down: height < 0,
+            bit_count,
+            palette,
+            pixels,
+            row_stride,
+            masks,
+        };
+
+        // Make sure every pixel can be decoded, such that drawing the image cannot fail halfway
+        if bit_count == 8 {
+            for y in 0..bmp.height {
+                let row = &bmp.row(y)[..bmp.width as usize];
+                if let Some(&index) = row
+                    .iter()
+                    .find(|&&index| index as usize * 4 >= palette.len())
+                {
+                    return Err(BmpError::InvalidColorIndex(index));
+                }
+            }
+        }
+
+        Ok(bmp)
+    }
+
+    /// Returns the size of the BMP file, in bytes
+    pub fn file_size(&self) -> usize {
+        self.bytes.len()
+    }
+    /// Returns the bytes of the whole file
+    pub fn bytes(&self) -> &'a [u8] {
+        self.bytes
+    }
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+    pub fn bit_count(&self) -> u16 {
+        self.bit_count
+    }
+
+    // Returns the bytes of the row `y`, counting from the top of the image
+    fn row(&self, y: u32) -> &'a [u8] {
+        let row = if self.top_down {
+            y
+        } else {
+            self.height - 1 - y
+        };
+        let start = row as usize * self.row_stride;
+        &self.pixels[start..start + self.row_stride]
+    }
+
+    /// Returns the color of the pixel at column `x` and row `y`, counting from the top left
+    /// corner of the image
+    pub fn pixel(&self, x: u32, y: u32) -> Option<Rgb> {
+        if x >= self.width || y >= self.height {
+            return None;
+        }
+
+        let row = self.row(y);
+        let x = x as usize;
+        match self.bit_count {
+            8 => {
+                let entry = self.palette.get(row[x] as usize * 4..)?;
+                Some(Rgb {
+                    red: entry[2],
+                    green: entry[1],
+                    blue: entry[0],
+                })
+            }
+            24 => Some(Rgb {
+                red: row[x * 3 + 2],
+                green: row[x * 3 + 1],
+                blue: row[x * 3],
+            }),
+            _ => {
+                let value = u32::from_le_bytes(row[x * 4..x * 4 + 4].try_into().ok()?);
+                let [red, green, blue] = self.masks.map(|mask| scale_component(value, mask));
+                Some(Rgb { red, green, blue })
+            }
+        }
+    }
+
+    /// Returns an iterator over the rows of the image, from the top one, each being an iterator
+    /// over the colors of its pixels, from the left one
+    pub fn rows(&self) -> impl Iterator<Item = impl Iterator<Item = Rgb> + '_> + '_ {
+        (0..self.height)
+            .map(move |y| (0..self.width).map(move |x| self.pixel(x, y).unwrap_or_default()))
+    }
+}
+
+// Extracts the component selected by `mask` from `value`, scaled to 8 bits
+fn scale_component(value: u32, mask: u32) -> u8 {
+    if mask == 0 {
+        return 0;
+    }
+
+    let component = (value & mask) >> mask.trailing_zeros();
+    let bits = (mask >> mask.trailing_zeros()).trailing_ones();
+    if bits >= 8 {
+        (component >> (bits - 8)) as u8
+    } else {
+        // Replicate the high bits into the low ones, such that the full range is covered
+        let mut scaled = component << (8 - bits);
+        let mut shift = bits;
+        while shift < 8 {
+            scaled |= scaled >> shift;
+            shift *= 2;
+        }
+        scaled as u8
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RED: Rgb = Rgb {
+        red: 0xFF,
+        green: 0,
+        blue: 0,
+    };
+    const GREEN: Rgb = Rgb {
+        red: 0,
+        green: 0xFF,
+        blue: 0,
+    };
+    const BLUE: Rgb = Rgb {
+        red: 0,
+        green: 0,
+        blue: 0xFF,
+    };
+    const WHITE: Rgb = Rgb {
+        red: 0xFF,
+        green: 0xFF,
+        blue: 0xFF,
+    };
+
+    // The parts of a BMP file that the tests vary
+    struct Image<'a> {
+        width: i32,
+        height: i32,
+        bit_count: u16,
+        compression: u32,
+        // The masks that follow the info header of BITFIELDS images
+        masks: Option<[u32; 3]>,
+        // The color table, as blue, green, red, reserved entries
+        palette: &'a [[u8; 4]],
+        // The rows of the pixel array, in file order and already padded
+        rows: &'a [&'a [u8]],
+    }
+
+    impl Image<'_> {
+        // Builds the BMP file
+        fn encode(&self) -> Vec<u8> {
+            let masks = self.masks.map_or(vec![], |masks| {
+                masks.iter().flat_map(|mask| mask.to_le_bytes()).collect()
+            });
+            let palette: Vec<u8> = self.palette.concat();
+            let pixels: Vec<u8> = self.rows.concat();
+            let pixel_offset = 14 + INFO_HEADER_SIZE as usize + masks.len() + palette.len();
+            let file_size = pixel_offset + pixels.len();
+
+            let mut file = Vec::new();
+            file.extend_from_slice(b"BM");
+            file.extend_from_slice(&(file_size as u32).to_le_bytes());
+            file.extend_from_slice(&0u32.to_le_bytes());
+            file.extend_from_slice(&(pixel_offset as u32).to_le_bytes());
+            file.extend_from_slice(&INFO_HEADER_SIZE.to_le_bytes());
+            file.extend_from_slice(&self.width.to_le_bytes());
+            file.extend_from_slice(&self.height.to_le_bytes());
+            file.extend_from_slice(&1u16.to_le_bytes());
+            file.extend_from_slice(&self.bit_count.to_le_bytes());
+            file.extend_from_slice(&self.compression.to_le_bytes());
+            file.extend_from_slice(&(pixels.len() as u32).to_le_bytes());
+            file.extend_from_slice(&[0; 8]);
+            file.extend_from_slice(&(self.palette.len() as u32).to_le_bytes());
+            file.extend_from_slice(&0u32.to_le_bytes());
+            file.extend_from_slice(&masks);
+            file.extend_from_slice(&palette);
+            file.extend_from_slice(&pixels);
+            file
+        }
+    }
+
+    // A 2x2 image with a red and a green pixel on its top row, and a blue and a white one on its
+    // bottom row, in `bit_count` bits per pixel and stored top-down or bottom-up
+    fn two_by_two(bit_count: u16, top_down: bool) -> Vec<u8> {
+        let palette = [[0, 0, 0xFF, 0], [0, 0xFF, 0, 0], [0xFF, 0, 0, 0], [0xFF; 4]];
+        // Rows are padded to 4 bytes
+        let (top, bottom): (&[u8], &[u8]) = match bit_count {
+            8 => (&[0, 1, 0, 0], &[2, 3, 0, 0]),
+            24 => (
+                &[0, 0, 0xFF, 0, 0xFF, 0, 0, 0],
+                &[0xFF, 0, 0, 0xFF, 0xFF, 0xFF, 0, 0],
+            ),
+            _ => (
+                &[0, 0, 0xFF, 0, 0, 0xFF, 0, 0],
+                &[0xFF, 0, 0, 0, 0xFF, 0xFF, 0xFF, 0],
+            ),
+        };
+        let rows: &[&[u8]] = match top_down {
+            true => &[top, bottom],
+            false => &[bottom, top],
+        };
+
+        Image {
+            width: 2,
+            height: if top_down { -2 } else { 2 },
+            bit_count,
+            compression: compression::RGB,
+            masks: None,
+            palette: if bit_count == 8 { &palette } else { &[] },
+            rows,
+        }
+        .encode()
+    }
+
+    #[test]
+    fn orientations_and_bit_counts() {
+        for bit_count in [8, 24, 32] {
+            for top_down in [false, true] {
+                let file = two_by_two(bit_count, top_down);
+                let bmp = Bmp::from_bytes(&file).unwrap();
+
+                assert_eq!((bmp.width(), bmp.height()), (2, 2));
+                assert_eq!(bmp.bit_count(), bit_count);
+                assert_eq!(bmp.file_size(), file.len());
+                let pixels: Vec<Vec<Rgb>> = bmp.rows().map(|row| row.collect()).collect();
+                assert_eq!(pixels, [[RED, GREEN], [BLUE, WHITE]], "{bit_count} bits");
+                assert_eq!(bmp.pixel(2, 0), None);
+                assert_eq!(bmp.pixel(0, 2), None);
+            }
+        }
+    }
+
+    #[test]
+    fn row_padding() {
+        // 3 pixels of 24 bits take 9 bytes, padded to 12, whose padding must not be read as
+        // pixels
+        let rows: &[&[u8]] = &[
+            &[0xFF, 0, 0, 0, 0xFF, 0, 0, 0, 0xFF, 0xAA, 0xAA, 0xAA],
+            &[0, 0, 0xFF, 0, 0xFF, 0, 0xFF, 0, 0, 0xAA, 0xAA, 0xAA],
+        ];
+        let file = Image {
+            width: 3,
+            height: 2,
+            bit_count: 24,
+            compression: compression::RGB,
+            masks: None,
+            palette: &[],
+            rows,
+        }
+        .encode();
+        let bmp = Bmp::from_bytes(&file).unwrap();
+
+        let top: Vec<Rgb> = bmp.rows().next().unwrap().collect();
+        assert_eq!(top, [RED, GREEN, BLUE]);
+        assert_eq!(bmp.pixel(2, 1), Some(RED));
+
+        // Without the padding of its last row, the pixel array is truncated
+        let mut unpadded = file[..file.len() - 3].to_vec();
+        unpadded[2..6].copy_from_slice(&(file.len() as u32 - 3).to_le_bytes());
+        assert_eq!(
+            Bmp::from_bytes(&unpadded).err(),
+            Some(BmpError::Truncated {
+                needed: file.len(),
+                available: file.len() - 3
+            })
+        );
+    }
+
+    #[test]
+    fn bitfields() {
+        // 10 bits per component, with blue in the high bits
+        let masks = [0x0000_03FF, 0x000F_FC00, 0x3FF0_0000];
+        let pixel = 0x3FFu32 | 0x200 << 10;
+        let file = Image {
+            width: 1,
+            height: 1,
+            bit_count: 32,
+            compression: compression::BITFIELDS,
+            masks: Some(masks),
+            palette: &[],
+            rows: &[&pixel.to_le_bytes()],
+        }
+        .encode();
+        let bmp = Bmp::from_bytes(&file).unwrap();
+        assert_eq!(
+            bmp.pixel(0, 0),
+            Some(Rgb {
+                red: 0xFF,
+                green: 0x80,
+                blue: 0
+            })
+        );
+
+        // 5 bits per component, scaled up to cover the whole range
+        let masks = [0x7C00, 0x03E0, 0x001F];
+        let pixel = 0x7C00u32 | 0x10 << 5 | 0x01;
+        let file = Image {
+            width: 1,
+            height: 1,
+            bit_count: 32,
+            compression: compression::BITFIELDS,
+            masks: Some(masks),
+            palette: &[],
+            rows: &[&pixel.to_le_bytes()],
+        }
+        .encode();
+        let bmp = Bmp::from_bytes(&file).unwrap();
+        assert_eq!(
+            bmp.pixel(0, 0),
+            Some(Rgb {
+                red: 0xFF,
+                green: 0x84,
+                blue: 0x08
+            })
+        );
+
+        // The masks must be there
+        let len = 14 + INFO_HEADER_SIZE as usize + 4;
+        let mut truncated = file[..len].to_vec();
+        truncated[2..6].copy_from_slice(&(len as u32).to_le_bytes());
+        assert_eq!(
+            Bmp::from_bytes(&truncated).err(),
+            Some(BmpError::Truncated {
+                needed: 14 + INFO_HEADER_SIZE as usize + 8,
+                available: truncated.len()
+            })
+        );
+    }
+
+    #[test]
+    fn truncated_pixel_array() {
+        // The file header says the file is shorter than the pixel array
+        let mut file = two_by_two(24, false);
+        let len = file.len();
+        file[2..6].copy_from_slice(&(len as u32 - 1).to_le_bytes());
+        assert_eq!(
+            Bmp::from_bytes(&file).err(),
+            Some(BmpError::Truncated {
+                needed: len,
+                available: len - 1
+            })
+        );
+
+        // The file header says the file is longer than the bytes we have
+        let file = two_by_two(32, true);
+        assert_eq!(
+            Bmp::from_bytes(&file[..file.len() - 1]).err(),
+            Some(BmpError::Truncated {
+                needed: file.len(),
+                available: file.len() - 1
+            })
+        );
+
+        // Not even the info header is there
+        let mut file = file[..20].to_vec();
+        file[2..6].copy_from_slice(&20u32.to_le_bytes());
+        assert!(matches!(
+            Bmp::from_bytes(&file),
+            Err(BmpError::Truncated { available: 20, .. })
+        ));
+    }
+
+    #[test]
+    fn palette_index_out_of_range() {
+        // Only two colors are in the table, but a pixel uses the third one
+        let file = Image {
+            width: 2,
+            height: 1,
+            bit_count: 8,
+            compression: compression::RGB,
+            masks: None,
+            palette: &[[0; 4], [0xFF; 4]],
+            rows: &[&[1, 2, 0, 0]],
+        }
+        .encode();
+        assert_eq!(
+            Bmp::from_bytes(&file).err(),
+            Some(BmpError::InvalidColorIndex(2))
+        );
+
+        // The padding is not made of pixels, so it can hold anything
+        let file = Image {
+            width: 2,
+            height: 1,
+            bit_count: 8,
+            compression: compression::RGB,
+            masks: None,
+            palette: &[[0; 4], [0xFF; 4]],
+            rows: &[&[1, 0, 0xEE, 0xEE]],
+        }
+        .encode();
+        assert!(Bmp::from_bytes(&file).is_ok());
+    }
+
+    #[test]
+    fn dimensions() {
+        let max = MAX_BMP_DIMENSION as i32;
+        for (width, height) in [
+            (0, 1),
+            (1, 0),
+            (-1, 1),
+            (max + 1, 1),
+            (1, max + 1),
+            (1, -max - 1),
+        ] {
+            let file = Image {
+                width,
+                height,
+                bit_count: 24,
+                compression: compression::RGB,
+                masks: None,
+                palette: &[],
+                rows: &[&[0; 4]],
+            }
+            .encode();
+            assert_eq!(
+                Bmp::from_bytes(&file).err(),
+                Some(BmpError::InvalidDimensions { width, height })
+            );
+        }
+
+        // The largest dimensions are accepted as long as the pixels are there
+        let file = Image {
+            width: max,
+            height: -1,
+            bit_count: 8,
+            compression: compression::RGB,
+            masks: None,
+            palette: &[[0; 4]],
+            rows: &[&vec![0; max as usize]],
+        }
+        .encode();
+        assert_eq!(Bmp::from_bytes(&file).unwrap().width(), MAX_BMP_DIMENSION);
+    }
+
+    #[test]
+    fn unsupported_images() {
+        let mut file = two_by_two(24, false);
+        file[0] = b'b';
+        assert_eq!(
+            Bmp::from_bytes(&file).err(),
+            Some(BmpError::InvalidSignature)
+        );
+
+        let encode = |bit_count, compression| {
+            Image {
+                width: 2,
+                height: 2,
+                bit_count,
+                compression,
+                masks: None,
+                palette: &[],
+                rows: &[&[0; 8], &[0; 8]],
+            }
+            .encode()
+        };
+        assert_eq!(
+            Bmp::from_bytes(&encode(16, compression::RGB)).err(),
+            Some(BmpError::UnsupportedBitCount(16))
+        );
+        assert_eq!(
+            Bmp::from_bytes(&encode(24, compression::BITFIELDS)).err(),
+            Some(BmpError::UnsupportedBitCount(24))
+        );
+        // Run-length encoded
+        assert_eq!(
+            Bmp::from_bytes(&encode(8, 1)).err(),
+            Some(BmpError::UnsupportedCompression(1))
+        );
+
+        // A BITMAPCOREHEADER
+        let mut file = two_by_two(24, false);
+        file[14..18].copy_from_slice(&12u32.to_le_bytes());
+        assert_eq!(
+            Bmp::from_bytes(&file).err(),
+            Some(BmpError::UnsupportedHeader(12))
+        );
+    }
+}
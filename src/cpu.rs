@@ -1,27 +1,146 @@
 use core::arch::asm;
 use bitflags::bitflags;
 
+pub mod cpuid;
+
+use cpuid::{Cpu, ExtLeaf1Edx, Features, Leaf1Edx, Leaf7Edx, XsaveExtensions};
+
+/// Writes the byte `value` to the I/O port `port`
+///
+/// # Safety
+///
+/// Writing to a port can have any side effect on the device behind it, which the caller must
+/// own.
 pub unsafe fn outb(port: u16, value: u8) {
     asm!(
-        "out dx, {value}",
-        value = in(reg_byte) value,
+        "out dx, al",
+        in("al") value,
         in("dx") port,
     );
 }
 
+/// Reads a byte from the I/O port `port`
+///
+/// # Safety
+///
+/// Reading from a port can have side effects on the device behind it, such as acknowledging
+/// an interrupt, which the caller must own.
 pub unsafe fn inb(port: u16) -> u8 {
     let value;
     asm!(
-        "in {value}, dx",
-        value = out(reg_byte) value,
+        "in al, dx",
+        out("al") value,
+        in("dx") port,
+    );
+    value
+}
+
+/// Writes the word `value` to the I/O port `port`
+///
+/// # Safety
+///
+/// Writing to a port can have any side effect on the device behind it, which the caller must
+/// own.
+pub unsafe fn outw(port: u16, value: u16) {
+    asm!(
+        "out dx, ax",
+        in("ax") value,
+        in("dx") port,
+    );
+}
+
+/// Reads a word from the I/O port `port`
+///
+/// # Safety
+///
+/// Reading from a port can have side effects on the device behind it, such as acknowledging
+/// an interrupt, which the caller must own.
+pub unsafe fn inw(port: u16) -> u16 {
+    let value;
+    asm!(
+        "in ax, dx",
+        out("ax") value,
         in("dx") port,
     );
     value
 }
 
+/// Writes the double word `value` to the I/O port `port`
+///
+/// # Safety
+///
+/// Writing to a port can have any side effect on the device behind it, which the caller must
+/// own.
+pub unsafe fn outl(port: u16, value: u32) {
+    asm!(
+        "out dx, eax",
+        in("eax") value,
+        in("dx") port,
+    );
+}
+
+/// Reads a double word from the I/O port `port`
+///
+/// # Safety
+///
+/// Reading from a port can have side effects on the device behind it, such as acknowledging
+/// an interrupt, which the caller must own.
+pub unsafe fn inl(port: u16) -> u32 {
+    let value;
+    asm!(
+        "in eax, dx",
+        out("eax") value,
+        in("dx") port,
+    );
+    value
+}
+
+/// Writes the cache line holding `addr` back to memory and invalidates it, such that devices that
+/// do not snoop the caches see what was written there
+///
+/// # Safety
+///
+/// `addr` must be mapped.
+pub unsafe fn clflush(addr: usize) {
+    asm!(
+        "clflush [{addr}]",
+        addr = in(reg) addr,
+    );
+}
+
+/// Orders every load and store before the fence with the ones after it, including `clflush`
+///
+/// # Safety
+///
+/// This has no requirement, and is only unsafe for consistency with `clflush`.
+pub unsafe fn mfence() {
+    asm!("mfence");
+}
+
+/// Resets the processor by loading an empty Interrupt Descriptor Table and raising an exception.
+/// Since no handler can be found, the processor ends up triple faulting.
+///
+/// # Safety
+///
+/// Everything that is not in non-volatile storage is lost.
+pub unsafe fn triple_fault() -> ! {
+    // An IDT descriptor with a limit and base of 0
+    let idt_descriptor = [0u8; 10];
+    asm!(
+        "lidt [{idt}]",
+        "int3",
+        idt = in(reg) idt_descriptor.as_ptr(),
+        options(noreturn),
+    );
+}
+
 /// Typically, Control Registers are the same size as the underlying mode that they run on,
 /// (either 32-bits or 64-bits). Since we do not care about 32-bits right now, we will take the
 /// full value.
+///
+/// # Safety
+///
+/// The processor must be running at privilege level 0.
 pub unsafe fn cr0() -> CR0 {
     let value: u64;
     asm!(
@@ -35,6 +154,11 @@ pub unsafe fn cr0() -> CR0 {
 /// guarantee about calling this function and receiving a valid result, so you should check that
 /// you are on a proper platform and in a proper CPU mode yourself. Since we are only targeting
 /// IA32e, we will return a u64.
+///
+/// # Safety
+///
+/// The processor must be running at privilege level 0, and must implement the MSR, otherwise
+/// this raises a general protection fault. `read_msr` checks the latter.
 pub unsafe fn rdmsr(ecx: u32) -> u64 {
     let eax: u32;
     let edx: u32;
@@ -50,8 +174,45 @@ pub unsafe fn rdmsr(ecx: u32) -> u64 {
     value
 }
 
+/// Returns whether the processor has the model-specific register `ecx`, according to the CPUID
+/// feature that comes with it. Registers we do not know how to check for are reported as missing.
+pub fn msr_supported(ecx: u32) -> bool {
+    let features = Features::read(&Cpu);
+    if !features.leaf1_edx.contains(Leaf1Edx::MSR) {
+        return false;
+    }
+
+    match ecx {
+        msr_reg_addr::IA32_APIC_BASE => features.leaf1_edx.contains(Leaf1Edx::APIC),
+        msr_reg_addr::IA32_ARCH_CAPABILITIES => {
+            features.leaf7_edx.contains(Leaf7Edx::ARCH_CAPABILITIES)
+        }
+        msr_reg_addr::IA32_PAT => features.leaf1_edx.contains(Leaf1Edx::PAT),
+        msr_reg_addr::IA32_XSS => cpuid::xsave_info(&Cpu)
+            .is_some_and(|xsave| xsave.extensions.contains(XsaveExtensions::XSAVES)),
+        msr_reg_addr::IA32_EFER => features
+            .ext_leaf1_edx
+            .intersects(ExtLeaf1Edx::LM | ExtLeaf1Edx::NX | ExtLeaf1Edx::SYSCALL),
+        _ => false,
+    }
+}
+
+/// Reads the model-specific register specified in `ecx`, if `msr_supported` says the processor
+/// has it. Unlike `rdmsr`, this cannot fault on a missing register.
+pub fn read_msr(ecx: u32) -> Option<u64> {
+    msr_supported(ecx).then(|| unsafe { rdmsr(ecx) })
+}
+
 /// Module that contains constants representing register addresses for Model Specific Registers.
 pub mod msr_reg_addr {
+    // Local APIC base address and enable
+    pub const IA32_APIC_BASE: u32 = 0x0000_001B;
+    // Enumeration of the architectural features and vulnerabilities of the processor
+    pub const IA32_ARCH_CAPABILITIES: u32 = 0x0000_010A;
+    // Page Attribute Table
+    pub const IA32_PAT: u32 = 0x0000_0277;
+    // Supervisor state components enabled for XSAVES
+    pub const IA32_XSS: u32 = 0x0000_0DA0;
     // Extended feature Enables
     pub const IA32_EFER: u32 = 0xC000_0080;
 }
@@ -0,0 +1,1231 @@
+//! Module that identifies the processor through the CPUID instruction: who made it, what it
+//! supports, its caches, how its logical processors are laid out and which hypervisor, if any, it
+//! runs under. The leaves are read from a `CpuidSource`, which is the processor itself outside of
+//! the tests.
+use bitflags::bitflags;
+use core::arch::asm;
+use core::fmt;
+
+/// The maximum number of cache descriptors we enumerate
+pub const MAX_CACHE_DESCRIPTORS: u32 = 16;
+
+/// The maximum number of topology levels we enumerate
+pub const MAX_TOPOLOGY_LEVELS: u32 = 8;
+
+/// Leaves of the CPUID instruction
+pub mod leaf {
+    pub const VENDOR: u32 = 0x0000_0000;
+    pub const FEATURES: u32 = 0x0000_0001;
+    pub const CACHE_PARAMETERS: u32 = 0x0000_0004;
+    pub const EXTENDED_FEATURES: u32 = 0x0000_0007;
+    pub const TOPOLOGY: u32 = 0x0000_000B;
+    pub const XSAVE: u32 = 0x0000_000D;
+    pub const TOPOLOGY_V2: u32 = 0x0000_001F;
+    pub const HYPERVISOR: u32 = 0x4000_0000;
+    pub const EXTENDED_MAX: u32 = 0x8000_0000;
+    pub const EXTENDED_SIGNATURE: u32 = 0x8000_0001;
+    pub const BRAND_STRING: u32 = 0x8000_0002;
+    pub const AMD_CACHE_TOPOLOGY: u32 = 0x8000_001D;
+}
+
+/// The registers returned by the CPUID instruction
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct CpuidResult {
+    pub eax: u32,
+    pub ebx: u32,
+    pub ecx: u32,
+    pub edx: u32,
+}
+
+/// Executes CPUID for the `leaf` and `subleaf`. Leaves above the maximum supported one return
+/// the data of the highest basic leaf, so callers must check the maximum leaf first.
+pub fn cpuid(leaf: u32, subleaf: u32) -> CpuidResult {
+    let eax: u32;
+    let ebx: u64;
+    let ecx: u32;
+    let edx: u32;
+
+    // SAFETY: CPUID is available on every x86_64 processor and has no side effect. RBX is used
+    // by LLVM, so it is saved around the instruction.
+    unsafe {
+        asm!(
+            "mov {ebx}, rbx",
+            "cpuid",
+            "xchg {ebx}, rbx",
+            ebx = out(reg) ebx,
+            inout("eax") leaf => eax,
+            inout("ecx") subleaf => ecx,
+            out("edx") edx,
+            options(nostack, preserves_flags),
+        );
+    }
+
+    CpuidResult {
+        eax,
+        ebx: ebx as u32,
+        ecx,
+        edx,
+    }
+}
+
+/// Something that answers CPUID queries
+pub trait CpuidSource {
+    /// Returns the registers CPUID gives for the `leaf` and `subleaf`
+    fn cpuid(&self, leaf: u32, subleaf: u32) -> CpuidResult;
+}
+
+/// The processor we run on, which answers CPUID queries by executing the instruction
+#[derive(Debug, Default, Clone, Copy)]
+pub struct Cpu;
+
+impl CpuidSource for Cpu {
+    fn cpuid(&self, leaf: u32, subleaf: u32) -> CpuidResult {
+        cpuid(leaf, subleaf)
+    }
+}
+
+/// Returns the highest basic leaf
+pub fn max_leaf(cpu: &impl CpuidSource) -> u32 {
+    cpu.cpuid(leaf::VENDOR, 0).eax
+}
+
+/// Returns the highest extended leaf, or 0 if there are none
+pub fn max_extended_leaf(cpu: &impl CpuidSource) -> u32 {
+    let max = cpu.cpuid(leaf::EXTENDED_MAX, 0).eax;
+    if max >= leaf::EXTENDED_MAX {
+        max
+    } else {
+        0
+    }
+}
+
+// Reads `leaf` if it is at most the highest leaf of its range
+fn cpuid_checked(cpu: &impl CpuidSource, leaf: u32, subleaf: u32) -> Option<CpuidResult> {
+    let max = if leaf >= leaf::EXTENDED_MAX {
+        max_extended_leaf(cpu)
+    } else {
+        max_leaf(cpu)
+    };
+
+    (leaf <= max).then(|| cpu.cpuid(leaf, subleaf))
+}
+
+/// An ASCII string made of CPUID registers, such as the vendor or the brand string
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub struct CpuString<const N: usize>([u8; N]);
+
+impl<const N: usize> CpuString<N> {
+    // Builds the string from the bytes of `registers`, in order
+    fn from_registers(registers: &[u32]) -> Self {
+        let mut bytes = [0; N];
+        for (chunk, register) in bytes.chunks_mut(4).zip(registers) {
+            chunk.copy_from_slice(&register.to_le_bytes()[..chunk.len()]);
+        }
+        Self(bytes)
+    }
+
+    /// Returns the string without its null padding and its surrounding spaces, or an empty string
+    /// if it is not ASCII
+    pub fn as_str(&self) -> &str {
+        let len = self.0.iter().position(|&byte| byte == 0).unwrap_or(N);
+        core::str::from_utf8(&self.0[..len]).unwrap_or("").trim()
+    }
+}
+
+impl<const N: usize> fmt::Debug for CpuString<N> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(self.as_str(), f)
+    }
+}
+
+/// The manufacturer of the processor
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Vendor {
+    Intel,
+    Amd,
+    /// Any other vendor, whose string is given by `vendor_string`
+    Other,
+}
+
+/// Returns the 12 characters vendor string, such as "GenuineIntel"
+pub fn vendor_string(cpu: &impl CpuidSource) -> CpuString<12> {
+    let regs = cpu.cpuid(leaf::VENDOR, 0);
+    CpuString::from_registers(&[regs.ebx, regs.edx, regs.ecx])
+}
+
+/// Returns the manufacturer of the processor
+pub fn vendor(cpu: &impl CpuidSource) -> Vendor {
+    match vendor_string(cpu).as_str() {
+        "GenuineIntel" => Vendor::Intel,
+        "AuthenticAMD" | "HygonGenuine" => Vendor::Amd,
+        _ => Vendor::Other,
+    }
+}
+
+/// Returns the brand string, such as "Intel(R) Core(TM) i7-8700 CPU @ 3.20GHz", if the processor
+/// has one
+pub fn brand_string(cpu: &impl CpuidSource) -> Option<CpuString<48>> {
+    if max_extended_leaf(cpu) < leaf::BRAND_STRING + 2 {
+        return None;
+    }
+
+    let mut registers = [0; 12];
+    for (idx, chunk) in registers.chunks_mut(4).enumerate() {
+        let regs = cpu.cpuid(leaf::BRAND_STRING + idx as u32, 0);
+        chunk.copy_from_slice(&[regs.eax, regs.ebx, regs.ecx, regs.edx]);
+    }
+
+    Some(CpuString::from_registers(&registers))
+}
+
+/// The family, model and stepping of the processor, with the extended family and model already
+/// folded in
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Signature {
+    pub family: u16,
+    pub model: u8,
+    pub stepping: u8,
+}
+
+/// Returns the signature of the processor
+pub fn signature(cpu: &impl CpuidSource) -> Signature {
+    let eax = cpu.cpuid(leaf::FEATURES, 0).eax;
+    let stepping = (eax & 0xF) as u8;
+    let base_model = (eax >> 4 & 0xF) as u8;
+    let base_family = (eax >> 8 & 0xF) as u16;
+    let ext_model = (eax >> 16 & 0xF) as u8;
+    let ext_family = (eax >> 20 & 0xFF) as u16;
+
+    // The extended fields only count for the families that ran out of room in the base fields
+    let family = match base_family {
+        0xF => base_family + ext_family,
+        _ => base_family,
+    };
+    let model = match base_family {
+        0x6 | 0xF => ext_model << 4 | base_model,
+        _ => base_model,
+    };
+
+    Signature {
+        family,
+        model,
+        stepping,
+    }
+}
+
+bitflags! {
+    /// Features reported in ECX by leaf 1
+    #[repr(transparent)]
+    #[derive(Default, Clone, Copy, Debug, PartialEq, Eq, Hash)]
+    pub struct Leaf1Ecx: u32 {
+        const SSE3 = 1 << 0;
+        const PCLMULQDQ = 1 << 1;
+        const DTES64 = 1 << 2;
+        const MONITOR = 1 << 3;
+        const DS_CPL = 1 << 4;
+        const VMX = 1 << 5;
+        const SMX = 1 << 6;
+        const EST = 1 << 7;
+        const TM2 = 1 << 8;
+        const SSSE3 = 1 << 9;
+        const CNXT_ID = 1 << 10;
+        const SDBG = 1 << 11;
+        const FMA = 1 << 12;
+        const CX16 = 1 << 13;
+        const XTPR = 1 << 14;
+        const PDCM = 1 << 15;
+        const PCID = 1 << 17;
+        const DCA = 1 << 18;
+        const SSE4_1 = 1 << 19;
+        const SSE4_2 = 1 << 20;
+        const X2APIC = 1 << 21;
+        const MOVBE = 1 << 22;
+        const POPCNT = 1 << 23;
+        const TSC_DEADLINE = 1 << 24;
+        const AES = 1 << 25;
+        const XSAVE = 1 << 26;
+        /// The OS enabled XSETBV and XGETBV through CR4
+        const OSXSAVE = 1 << 27;
+        const AVX = 1 << 28;
+        const F16C = 1 << 29;
+        const RDRAND = 1 << 30;
+        /// We are running under a hypervisor
+        const HYPERVISOR = 1 << 31;
+    }
+}
+
+bitflags! {
+    /// Features reported in EDX by leaf 1
+    #[repr(transparent)]
+    #[derive(Default, Clone, Copy, Debug, PartialEq, Eq, Hash)]
+    pub struct Leaf1Edx: u32 {
+        const FPU = 1 << 0;
+        const VME = 1 << 1;
+        const DE = 1 << 2;
+        const PSE = 1 << 3;
+        const TSC = 1 << 4;
+        /// RDMSR and WRMSR are supported
+        const MSR = 1 << 5;
+        const PAE = 1 << 6;
+        const MCE = 1 << 7;
+        const CX8 = 1 << 8;
+        const APIC = 1 << 9;
+        const SEP = 1 << 11;
+        const MTRR = 1 << 12;
+        const PGE = 1 << 13;
+        const MCA = 1 << 14;
+        const CMOV = 1 << 15;
+        const PAT = 1 << 16;
+        const PSE36 = 1 << 17;
+        const PSN = 1 << 18;
+        const CLFSH = 1 << 19;
+        const DS = 1 << 21;
+        const ACPI = 1 << 22;
+        const MMX = 1 << 23;
+        const FXSR = 1 << 24;
+        const SSE = 1 << 25;
+        const SSE2 = 1 << 26;
+        const SS = 1 << 27;
+        const HTT = 1 << 28;
+        const TM = 1 << 29;
+        const PBE = 1 << 31;
+    }
+}
+
+bitflags! {
+    /// Features reported in EBX by leaf 7, subleaf 0
+    #[repr(transparent)]
+    #[derive(Default, Clone, Copy, Debug, PartialEq, Eq, Hash)]
+    pub struct Leaf7Ebx: u32 {
+        const FSGSBASE = 1 << 0;
+        const TSC_ADJUST = 1 << 1;
+        const SGX = 1 << 2;
+        const BMI1 = 1 << 3;
+        const HLE = 1 << 4;
+        const AVX2 = 1 << 5;
+        const FDP_EXCPTN_ONLY = 1 << 6;
+        const SMEP = 1 << 7;
+        const BMI2 = 1 << 8;
+        const ERMS = 1 << 9;
+        const INVPCID = 1 << 10;
+        const RTM = 1 << 11;
+        const RDT_M = 1 << 12;
+        const FPU_CS_DS_DEPRECATED = 1 << 13;
+        const MPX = 1 << 14;
+        const RDT_A = 1 << 15;
+        const AVX512F = 1 << 16;
+        const AVX512DQ = 1 << 17;
+        const RDSEED = 1 << 18;
+        const ADX = 1 << 19;
+        const SMAP = 1 << 20;
+        const AVX512_IFMA = 1 << 21;
+        const CLFLUSHOPT = 1 << 23;
+        const CLWB = 1 << 24;
+        const PT = 1 << 25;
+        const AVX512PF = 1 << 26;
+        const AVX512ER = 1 << 27;
+        const AVX512CD = 1 << 28;
+        const SHA = 1 << 29;
+        const AVX512BW = 1 << 30;
+        const AVX512VL = 1 << 31;
+    }
+}
+
+bitflags! {
+    /// Features reported in ECX by leaf 7, subleaf 0
+    #[repr(transparent)]
+    #[derive(Default, Clone, Copy, Debug, PartialEq, Eq, Hash)]
+    pub struct Leaf7Ecx: u32 {
+        const PREFETCHWT1 = 1 << 0;
+        const AVX512_VBMI = 1 << 1;
+        const UMIP = 1 << 2;
+        const PKU = 1 << 3;
+        const OSPKE = 1 << 4;
+        const WAITPKG = 1 << 5;
+        const AVX512_VBMI2 = 1 << 6;
+        const CET_SS = 1 << 7;
+        const GFNI = 1 << 8;
+        const VAES = 1 << 9;
+        const VPCLMULQDQ = 1 << 10;
+        const AVX512_VNNI = 1 << 11;
+        const AVX512_BITALG = 1 << 12;
+        const TME = 1 << 13;
+        const AVX512_VPOPCNTDQ = 1 << 14;
+        /// 5-level paging
+        const LA57 = 1 << 16;
+        const RDPID = 1 << 22;
+        const KL = 1 << 23;
+        const BUS_LOCK_DETECT = 1 << 24;
+        const CLDEMOTE = 1 << 25;
+        const MOVDIRI = 1 << 27;
+        const MOVDIR64B = 1 << 28;
+        const ENQCMD = 1 << 29;
+        const SGX_LC = 1 << 30;
+        const PKS = 1 << 31;
+    }
+}
+
+bitflags! {
+    /// Features reported in EDX by leaf 7, subleaf 0
+    #[repr(transparent)]
+    #[derive(Default, Clone, Copy, Debug, PartialEq, Eq, Hash)]
+    pub struct Leaf7Edx: u32 {
+        const AVX512_4VNNIW = 1 << 2;
+        const AVX512_4FMAPS = 1 << 3;
+        const FSRM = 1 << 4;
+        const UINTR = 1 << 5;
+        const AVX512_VP2INTERSECT = 1 << 8;
+        const SRBDS_CTRL = 1 << 9;
+        const MD_CLEAR = 1 << 10;
+        const RTM_ALWAYS_ABORT = 1 << 11;
+        const SERIALIZE = 1 << 14;
+        const HYBRID = 1 << 15;
+        const TSXLDTRK = 1 << 16;
+        const PCONFIG = 1 << 18;
+        const ARCH_LBR = 1 << 19;
+        const CET_IBT = 1 << 20;
+        const AMX_BF16 = 1 << 22;
+        const AVX512_FP16 = 1 << 23;
+        const AMX_TILE = 1 << 24;
+        const AMX_INT8 = 1 << 25;
+        const IBRS_IBPB = 1 << 26;
+        const STIBP = 1 << 27;
+        const L1D_FLUSH = 1 << 28;
+        /// The IA32_ARCH_CAPABILITIES MSR is supported
+        const ARCH_CAPABILITIES = 1 << 29;
+        /// The IA32_CORE_CAPABILITIES MSR is supported
+        const CORE_CAPABILITIES = 1 << 30;
+        const SSBD = 1 << 31;
+    }
+}
+
+bitflags! {
+    /// Features reported in EAX by leaf 7, subleaf 1
+    #[repr(transparent)]
+    #[derive(Default, Clone, Copy, Debug, PartialEq, Eq, Hash)]
+    pub struct Leaf7Sub1Eax: u32 {
+        const SHA512 = 1 << 0;
+        const SM3 = 1 << 1;
+        const SM4 = 1 << 2;
+        const RAO_INT = 1 << 3;
+        const AVX_VNNI = 1 << 4;
+        const AVX512_BF16 = 1 << 5;
+        const LASS = 1 << 6;
+        const CMPCCXADD = 1 << 7;
+        const ARCH_PERFMON_EXT = 1 << 8;
+        const FZLRM = 1 << 10;
+        const FSRS = 1 << 11;
+        const FSRCS = 1 << 12;
+        const FRED = 1 << 17;
+        const LKGS = 1 << 18;
+        const WRMSRNS = 1 << 19;
+        const AMX_FP16 = 1 << 21;
+        const HRESET = 1 << 22;
+        const AVX_IFMA = 1 << 23;
+        const LAM = 1 << 26;
+        const MSRLIST = 1 << 27;
+    }
+}
+
+bitflags! {
+    /// Features reported in ECX by leaf 0x80000001
+    #[repr(transparent)]
+    #[derive(Default, Clone, Copy, Debug, PartialEq, Eq, Hash)]
+    pub struct ExtLeaf1Ecx: u32 {
+        const LAHF_LM = 1 << 0;
+        const CMP_LEGACY = 1 << 1;
+        const SVM = 1 << 2;
+        const EXTAPIC = 1 << 3;
+        const CR8_LEGACY = 1 << 4;
+        const LZCNT = 1 << 5;
+        const SSE4A = 1 << 6;
+        const MISALIGNSSE = 1 << 7;
+        const PREFETCHW = 1 << 8;
+        const OSVW = 1 << 9;
+        const IBS = 1 << 10;
+        const XOP = 1 << 11;
+        const SKINIT = 1 << 12;
+        const WDT = 1 << 13;
+        const LWP = 1 << 15;
+        const FMA4 = 1 << 16;
+        const TCE = 1 << 17;
+        const TBM = 1 << 21;
+        /// Leaf 0x8000001D describes the caches
+        const TOPOEXT = 1 << 22;
+        const PERFCTR_CORE = 1 << 23;
+        const PERFCTR_NB = 1 << 24;
+        const DBX = 1 << 26;
+        const PERFTSC = 1 << 27;
+        const PCX_L2I = 1 << 28;
+        const MONITORX = 1 << 29;
+    }
+}
+
+bitflags! {
+    /// Features reported in EDX by leaf 0x80000001
+    #[repr(transparent)]
+    #[derive(Default, Clone, Copy, Debug, PartialEq, Eq, Hash)]
+    pub struct ExtLeaf1Edx: u32 {
+        const SYSCALL = 1 << 11;
+        const MP = 1 << 19;
+        /// No-execute pages, enabled through IA32_EFER
+        const NX = 1 << 20;
+        const MMXEXT = 1 << 22;
+        const FXSR_OPT = 1 << 25;
+        const PDPE1GB = 1 << 26;
+        const RDTSCP = 1 << 27;
+        /// Long mode, and with it the IA32_EFER MSR
+        const LM = 1 << 29;
+        const AMD_3DNOWEXT = 1 << 30;
+        const AMD_3DNOW = 1 << 31;
+    }
+}
+
+/// All the feature flags of the processor. Leaves the processor does not have read as no
+/// features.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Features {
+    pub leaf1_ecx: Leaf1Ecx,
+    pub leaf1_edx: Leaf1Edx,
+    pub leaf7_ebx: Leaf7Ebx,
+    pub leaf7_ecx: Leaf7Ecx,
+    pub leaf7_edx: Leaf7Edx,
+    pub leaf7_1_eax: Leaf7Sub1Eax,
+    pub ext_leaf1_ecx: ExtLeaf1Ecx,
+    pub ext_leaf1_edx: ExtLeaf1Edx,
+}
+
+impl Features {
+    /// Reads the feature flags of the processor `cpu`
+    pub fn read(cpu: &impl CpuidSource) -> Self {
+        let mut features = Self::default();
+
+        if let Some(regs) = cpuid_checked(cpu, leaf::FEATURES, 0) {
+            features.leaf1_ecx = Leaf1Ecx::from_bits_retain(regs.ecx);
+            features.leaf1_edx = Leaf1Edx::from_bits_retain(regs.edx);
+        }
+        if let Some(regs) = cpuid_checked(cpu, leaf::EXTENDED_FEATURES, 0) {
+            features.leaf7_ebx = Leaf7Ebx::from_bits_retain(regs.ebx);
+            features.leaf7_ecx = Leaf7Ecx::from_bits_retain(regs.ecx);
+            features.leaf7_edx = Leaf7Edx::from_bits_retain(regs.edx);
+
+            // EAX of subleaf 0 gives the highest subleaf
+            if regs.eax >= 1 {
+                let regs = cpu.cpuid(leaf::EXTENDED_FEATURES, 1);
+                features.leaf7_1_eax = Leaf7Sub1Eax::from_bits_retain(regs.eax);
+            }
+        }
+        if let Some(regs) = cpuid_checked(cpu, leaf::EXTENDED_SIGNATURE, 0) {
+            features.ext_leaf1_ecx = ExtLeaf1Ecx::from_bits_retain(regs.ecx);
+            features.ext_leaf1_edx = ExtLeaf1Edx::from_bits_retain(regs.edx);
+        }
+
+        features
+    }
+}
+
+bitflags! {
+    /// State components managed by XSAVE, as laid out in XCR0 and IA32_XSS
+    #[repr(transparent)]
+    #[derive(Default, Clone, Copy, Debug, PartialEq, Eq, Hash)]
+    pub struct XsaveComponents: u64 {
+        const X87 = 1 << 0;
+        const SSE = 1 << 1;
+        const AVX = 1 << 2;
+        const BNDREGS = 1 << 3;
+        const BNDCSR = 1 << 4;
+        const OPMASK = 1 << 5;
+        const ZMM_HI256 = 1 << 6;
+        const HI16_ZMM = 1 << 7;
+        const PT = 1 << 8;
+        const PKRU = 1 << 9;
+        const PASID = 1 << 10;
+        const CET_U = 1 << 11;
+        const CET_S = 1 << 12;
+        const HDC = 1 << 13;
+        const UINTR = 1 << 14;
+        const LBR = 1 << 15;
+        const HWP = 1 << 16;
+        const TILECFG = 1 << 17;
+        const TILEDATA = 1 << 18;
+    }
+}
+
+bitflags! {
+    /// Extensions of XSAVE reported in EAX by leaf 0xD, subleaf 1
+    #[repr(transparent)]
+    #[derive(Default, Clone, Copy, Debug, PartialEq, Eq, Hash)]
+    pub struct XsaveExtensions: u32 {
+        const XSAVEOPT = 1 << 0;
+        const XSAVEC = 1 << 1;
+        const XGETBV_ECX1 = 1 << 2;
+        /// XSAVES, XRSTORS and the IA32_XSS MSR are supported
+        const XSAVES = 1 << 3;
+        const XFD = 1 << 4;
+    }
+}
+
+/// What XSAVE can save, and how much room it needs
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct XsaveInfo {
+    /// The components that can be enabled in XCR0
+    pub xcr0_supported: XsaveComponents,
+    /// The components that can be enabled in IA32_XSS
+    pub xss_supported: XsaveComponents,
+    pub extensions: XsaveExtensions,
+    /// The size of the XSAVE area for the components currently enabled in XCR0
+    pub enabled_size: u32,
+    /// The size of the XSAVE area for all the components XCR0 supports
+    pub max_size: u32,
+}
+
+/// Returns what XSAVE supports, if the processor has it
+pub fn xsave_info(cpu: &impl CpuidSource) -> Option<XsaveInfo> {
+    if !Features::read(cpu).leaf1_ecx.contains(Leaf1Ecx::XSAVE) {
+        return None;
+    }
+
+    let main = cpuid_checked(cpu, leaf::XSAVE, 0)?;
+    let ext = cpu.cpuid(leaf::XSAVE, 1);
+
+    Some(XsaveInfo {
+        xcr0_supported: XsaveComponents::from_bits_retain(
+            (main.edx as u64) << 32 | main.eax as u64,
+        ),
+        xss_supported: XsaveComponents::from_bits_retain((ext.edx as u64) << 32 | ext.ecx as u64),
+        extensions: XsaveExtensions::from_bits_retain(ext.eax),
+        enabled_size: main.ebx,
+        max_size: main.ecx,
+    })
+}
+
+/// The kind of cache a descriptor describes
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CacheType {
+    Data,
+    Instruction,
+    Unified,
+    Other(u8),
+}
+
+/// A cache, as described by leaf 4 on Intel processors and leaf 0x8000001D on AMD processors
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CacheDescriptor {
+    pub level: u8,
+    pub cache_type: CacheType,
+    pub fully_associative: bool,
+    pub ways: u32,
+    pub partitions: u32,
+    pub line_size: u32,
+    pub sets: u32,
+    /// The maximum number of logical processors sharing the cache
+    pub shared_by: u32,
+}
+
+impl CacheDescriptor {
+    // Decodes the registers of one subleaf, or returns `None` past the last cache
+    fn new(regs: CpuidResult) -> Option<Self> {
+        let cache_type = match regs.eax & 0x1F {
+            0 => return None,
+            1 => CacheType::Data,
+            2 => CacheType::Instruction,
+            3 => CacheType::Unified,
+            other => CacheType::Other(other as u8),
+        };
+
+        // All the counts are reported minus one
+        Some(Self {
+            level: (regs.eax >> 5 & 0x7) as u8,
+            cache_type,
+            fully_associative: regs.eax & 1 << 9 != 0,
+            ways: (regs.ebx >> 22) + 1,
+            partitions: (regs.ebx >> 12 & 0x3FF) + 1,
+            line_size: (regs.ebx & 0xFFF) + 1,
+            sets: regs.ecx + 1,
+            shared_by: (regs.eax >> 14 & 0xFFF) + 1,
+        })
+    }
+
+    /// Returns the size of the cache, in bytes
+    pub fn size(&self) -> u64 {
+        self.ways as u64 * self.partitions as u64 * self.line_size as u64 * self.sets as u64
+    }
+}
+
+/// Returns the caches of the processor, from the leaf its vendor describes them in. Processors
+/// with neither leaf have no caches reported.
+pub fn caches(cpu: &impl CpuidSource) -> impl Iterator<Item = CacheDescriptor> + '_ {
+    let cache_leaf = match vendor(cpu) {
+        Vendor::Amd => Features::read(cpu)
+            .ext_leaf1_ecx
+            .contains(ExtLeaf1Ecx::TOPOEXT)
+            .then_some(leaf::AMD_CACHE_TOPOLOGY),
+        _ => Some(leaf::CACHE_PARAMETERS),
+    }
+    .filter(|&cache_leaf| cpuid_checked(cpu, cache_leaf, 0).is_some());
+
+    cache_leaf
+        .into_iter()
+        .flat_map(move |cache_leaf| {
+            (0..MAX_CACHE_DESCRIPTORS).map(move |subleaf| cpu.cpuid(cache_leaf, subleaf))
+        })
+        .map_while(CacheDescriptor::new)
+}
+
+/// The kind of a level of the processor topology
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TopologyLevelType {
+    /// Logical processors sharing a core
+    Smt,
+    Core,
+    Module,
+    Tile,
+    Die,
+    Other(u8),
+}
+
+/// A level of the processor topology, as described by leaf 0x1F or leaf 0xB
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TopologyLevel {
+    pub level_type: TopologyLevelType,
+    /// The number of bits to shift the x2APIC ID right by, to get the ID of the next level up
+    pub shift: u8,
+    /// The number of logical processors at this level, as configured by the firmware
+    pub logical_processors: u16,
+    /// The x2APIC ID of the processor we run on
+    pub x2apic_id: u32,
+}
+
+impl TopologyLevel {
+    // Decodes the registers of one subleaf, or returns `None` past the last level
+    fn new(regs: CpuidResult) -> Option<Self> {
+        let level_type = match regs.ecx >> 8 & 0xFF {
+            0 => return None,
+            1 => TopologyLevelType::Smt,
+            2 => TopologyLevelType::Core,
+            3 => TopologyLevelType::Module,
+            4 => TopologyLevelType::Tile,
+            5 => TopologyLevelType::Die,
+            other => TopologyLevelType::Other(other as u8),
+        };
+
+        Some(Self {
+            level_type,
+            shift: (regs.eax & 0x1F) as u8,
+            logical_processors: regs.ebx as u16,
+            x2apic_id: regs.edx,
+        })
+    }
+}
+
+/// Returns the levels of the processor topology, from the lowest one up. Leaf 0x1F is preferred
+/// over leaf 0xB, which does not know of modules, tiles and dies.
+pub fn topology(cpu: &impl CpuidSource) -> impl Iterator<Item = TopologyLevel> + '_ {
+    // A leaf is valid when its first subleaf reports processors
+    let topology_leaf = [leaf::TOPOLOGY_V2, leaf::TOPOLOGY]
+        .into_iter()
+        .find(|&topology_leaf| {
+            cpuid_checked(cpu, topology_leaf, 0).is_some_and(|regs| regs.ebx != 0)
+        });
+
+    topology_leaf
+        .into_iter()
+        .flat_map(move |topology_leaf| {
+            (0..MAX_TOPOLOGY_LEVELS).map(move |subleaf| cpu.cpuid(topology_leaf, subleaf))
+        })
+        .map_while(TopologyLevel::new)
+}
+
+/// Hypervisors we know of
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HypervisorVendor {
+    Kvm,
+    HyperV,
+    VMware,
+    Xen,
+    /// QEMU without KVM
+    Tcg,
+    Other,
+}
+
+/// The hypervisor we run under, as described by the leaves starting at 0x40000000
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Hypervisor {
+    /// The highest hypervisor leaf
+    pub max_leaf: u32,
+    pub vendor_string: CpuString<12>,
+}
+
+impl Hypervisor {
+    pub fn vendor(&self) -> HypervisorVendor {
+        match self.vendor_string.as_str() {
+            "KVMKVMKVM" => HypervisorVendor::Kvm,
+            "Microsoft Hv" => HypervisorVendor::HyperV,
+            "VMwareVMware" => HypervisorVendor::VMware,
+            "XenVMMXenVMM" => HypervisorVendor::Xen,
+            "TCGTCGTCGTCG" => HypervisorVendor::Tcg,
+            _ => HypervisorVendor::Other,
+        }
+    }
+
+    /// Reads the hypervisor leaf `0x40000000 + offset` of `cpu`, if the hypervisor has it.
+    /// Their meaning depends on the hypervisor.
+    pub fn leaf(&self, cpu: &impl CpuidSource, offset: u32, subleaf: u32) -> Option<CpuidResult> {
+        let hypervisor_leaf = leaf::HYPERVISOR.checked_add(offset)?;
+        (hypervisor_leaf <= self.max_leaf).then(|| cpu.cpuid(hypervisor_leaf, subleaf))
+    }
+}
+
+/// Returns the hypervisor we run under, if any
+pub fn hypervisor(cpu: &impl CpuidSource) -> Option<Hypervisor> {
+    if !Features::read(cpu).leaf1_ecx.contains(Leaf1Ecx::HYPERVISOR) {
+        return None;
+    }
+
+    let regs = cpu.cpuid(leaf::HYPERVISOR, 0);
+    // Some hypervisors leave the maximum leaf at zero, while still having the vendor leaf
+    let max_leaf = regs.eax.max(leaf::HYPERVISOR);
+
+    Some(Hypervisor {
+        max_leaf,
+        vendor_string: CpuString::from_registers(&[regs.ebx, regs.ecx, regs.edx]),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A processor whose CPUID leaves were recorded, as `(leaf, subleaf, [eax, ebx, ecx, edx])`.
+    // The leaves and subleaves that were not recorded read as zeroes.
+    struct Recorded(Vec<(u32, u32, [u32; 4])>);
+
+    impl CpuidSource for Recorded {
+        fn cpuid(&self, leaf: u32, subleaf: u32) -> CpuidResult {
+            let [eax, ebx, ecx, edx] = self
+                .0
+                .iter()
+                .find(|record| (record.0, record.1) == (leaf, subleaf))
+                .map_or([0; 4], |record| record.2);
+            CpuidResult { eax, ebx, ecx, edx }
+        }
+    }
+
+    impl Recorded {
+        // Returns a copy of the processor with the registers of `leaf` and `subleaf` replaced
+        fn with(&self, leaf: u32, subleaf: u32, regs: [u32; 4]) -> Self {
+            let mut records = self.0.clone();
+            records.retain(|record| (record.0, record.1) != (leaf, subleaf));
+            records.push((leaf, subleaf, regs));
+            Self(records)
+        }
+    }
+
+    // A Sapphire Rapids Xeon with one logical processor, running under KVM
+    fn xeon() -> Recorded {
+        Recorded(vec![
+            (
+                0x0000_0000,
+                0,
+                [0x0000_0020, 0x756e_6547, 0x6c65_746e, 0x4965_6e69],
+            ),
+            (
+                0x0000_0001,
+                0,
+                [0x0008_06f8, 0x0001_0800, 0xfffa_3203, 0x0f8b_fbff],
+            ),
+            (
+                0x0000_0004,
+                0,
+                [0x0000_0121, 0x02c0_003f, 0x0000_003f, 0x0000_0000],
+            ),
+            (
+                0x0000_0004,
+                1,
+                [0x0000_0122, 0x01c0_003f, 0x0000_003f, 0x0000_0000],
+            ),
+            (
+                0x0000_0004,
+                2,
+                [0x0000_0143, 0x03c0_003f, 0x0000_07ff, 0x0000_0000],
+            ),
+            (
+                0x0000_0004,
+                3,
+                [0x0000_0163, 0x0380_003f, 0x0001_bfff, 0x0000_0004],
+            ),
+            (
+                0x0000_0007,
+                0,
+                [0x0000_0002, 0xf1bf_27eb, 0x1b41_5fde, 0xbfd1_4410],
+            ),
+            (
+                0x0000_0007,
+                1,
+                [0x0000_1c30, 0x0000_0000, 0x0000_0000, 0x0000_0000],
+            ),
+            (
+                0x0000_000b,
+                0,
+                [0x0000_0000, 0x0000_0001, 0x0000_0100, 0x0000_0000],
+            ),
+            (
+                0x0000_000b,
+                1,
+                [0x0000_0005, 0x0000_0001, 0x0000_0201, 0x0000_0000],
+            ),
+            (
+                0x0000_000b,
+                2,
+                [0x0000_0000, 0x0000_0000, 0x0000_0002, 0x0000_0000],
+            ),
+            (
+                0x0000_000b,
+                3,
+                [0x0000_0000, 0x0000_0000, 0x0000_0003, 0x0000_0000],
+            ),
+            (
+                0x0000_000d,
+                0,
+                [0x0006_02e7, 0x0000_2b00, 0x0000_2b00, 0x0000_0000],
+            ),
+            (
+                0x0000_000d,
+                1,
+                [0x0000_001f, 0x0000_2a00, 0x0000_1800, 0x0000_0000],
+            ),
+            (
+                0x0000_001f,
+                0,
+                [0x0000_0000, 0x0000_0001, 0x0000_0100, 0x0000_0000],
+            ),
+            (
+                0x0000_001f,
+                1,
+                [0x0000_0005, 0x0000_0001, 0x0000_0201, 0x0000_0000],
+            ),
+            (
+                0x0000_001f,
+                2,
+                [0x0000_0000, 0x0000_0000, 0x0000_0002, 0x0000_0000],
+            ),
+            (
+                0x0000_001f,
+                3,
+                [0x0000_0000, 0x0000_0000, 0x0000_0003, 0x0000_0000],
+            ),
+            (
+                0x4000_0000,
+                0,
+                [0x4000_0001, 0x4b4d_564b, 0x564b_4d56, 0x0000_004d],
+            ),
+            (
+                0x4000_0001,
+                0,
+                [0x0100_7efb, 0x0000_0000, 0x0000_0000, 0x0000_0000],
+            ),
+            (
+                0x8000_0000,
+                0,
+                [0x8000_0008, 0x0000_0000, 0x0000_0000, 0x0000_0000],
+            ),
+            (
+                0x8000_0001,
+                0,
+                [0x0000_0000, 0x0000_0000, 0x0000_0121, 0x2c10_0800],
+            ),
+            (
+                0x8000_0002,
+                0,
+                [0x6574_6e49, 0x2952_286c, 0x6f65_5820, 0x2952_286e],
+            ),
+            (
+                0x8000_0003,
+                0,
+                [0x6f72_5020, 0x7373_6563, 0x0000_726f, 0x0000_0000],
+            ),
+            (
+                0x8000_0004,
+                0,
+                [0x0000_0000, 0x0000_0000, 0x0000_0000, 0x0000_0000],
+            ),
+        ])
+    }
+
+    // A Zen 2 Ryzen, family 0x17 model 0x71, on bare metal. Its caches are described by leaf
+    // 0x8000001D, and its brand string is padded with spaces.
+    fn ryzen() -> Recorded {
+        Recorded(vec![
+            (
+                0x0000_0000,
+                0,
+                [0x0000_0010, 0x6874_7541, 0x444d_4163, 0x6974_6e65],
+            ),
+            (
+                0x0000_0001,
+                0,
+                [0x0087_0f10, 0x0018_0800, 0x7ed8_320b, 0x178b_fbff],
+            ),
+            (
+                0x8000_0000,
+                0,
+                [0x8000_0020, 0x6874_7541, 0x444d_4163, 0x6974_6e65],
+            ),
+            (
+                0x8000_0001,
+                0,
+                [0x0087_0f10, 0x2000_0000, 0x75c2_37ff, 0x2fd3_fbff],
+            ),
+            (
+                0x8000_0002,
+                0,
+                [0x2044_4d41, 0x657a_7952, 0x2039_206e, 0x3030_3933],
+            ),
+            (
+                0x8000_0003,
+                0,
+                [0x3231_2058, 0x726f_432d, 0x7250_2065, 0x7365_636f],
+            ),
+            (
+                0x8000_0004,
+                0,
+                [0x2072_6f73, 0x2020_2020, 0x2020_2020, 0x0020_2020],
+            ),
+            (
+                0x8000_001d,
+                0,
+                [0x0000_4121, 0x01c0_003f, 0x0000_003f, 0x0000_0000],
+            ),
+            (
+                0x8000_001d,
+                1,
+                [0x0000_4122, 0x01c0_003f, 0x0000_003f, 0x0000_0000],
+            ),
+            (
+                0x8000_001d,
+                2,
+                [0x0000_4143, 0x01c0_003f, 0x0000_03ff, 0x0000_0002],
+            ),
+            (
+                0x8000_001d,
+                3,
+                [0x0003_c163, 0x03c0_003f, 0x0000_3fff, 0x0000_0001],
+            ),
+        ])
+    }
+
+    #[test]
+    fn identification() {
+        let xeon = xeon();
+        assert_eq!(vendor(&xeon), Vendor::Intel);
+        assert_eq!(vendor_string(&xeon).as_str(), "GenuineIntel");
+        assert_eq!(
+            (max_leaf(&xeon), max_extended_leaf(&xeon)),
+            (0x20, 0x8000_0008)
+        );
+        let ryzen = ryzen();
+        assert_eq!(vendor(&ryzen), Vendor::Amd);
+
+        // The brand strings lose their null and space padding
+        let brand = brand_string(&xeon).unwrap();
+        assert_eq!(brand.as_str(), "Intel(R) Xeon(R) Processor");
+        assert_eq!(format!("{:?}", brand), "\"Intel(R) Xeon(R) Processor\"");
+        let brand = brand_string(&ryzen).unwrap();
+        assert_eq!(brand.as_str(), "AMD Ryzen 9 3900X 12-Core Processor");
+        let no_brand = xeon.with(leaf::EXTENDED_MAX, 0, [leaf::BRAND_STRING + 1, 0, 0, 0]);
+        assert_eq!(brand_string(&no_brand), None);
+        let not_ascii = xeon.with(leaf::BRAND_STRING, 0, [0xFFFF_FFFF, 0, 0, 0]);
+        assert_eq!(brand_string(&not_ascii).unwrap().as_str(), "");
+
+        // Family 6 takes the extended model, family 0xF the extended family as well
+        let expected = |family, model, stepping| Signature {
+            family,
+            model,
+            stepping,
+        };
+        assert_eq!(signature(&xeon), expected(0x6, 0x8F, 8));
+        assert_eq!(signature(&ryzen), expected(0x17, 0x71, 0));
+        let pentium = xeon.with(leaf::FEATURES, 0, [0x0000_0543, 0, 0, 0]);
+        assert_eq!(signature(&pentium), expected(0x5, 0x4, 3));
+        let ignored_extensions = xeon.with(leaf::FEATURES, 0, [0x0ff8_0543, 0, 0, 0]);
+        assert_eq!(signature(&ignored_extensions), expected(0x5, 0x4, 3));
+    }
+
+    #[test]
+    fn features() {
+        let features = Features::read(&xeon());
+        assert!(features
+            .leaf1_ecx
+            .contains(Leaf1Ecx::HYPERVISOR | Leaf1Ecx::X2APIC));
+        assert!(features
+            .leaf7_ebx
+            .contains(Leaf7Ebx::AVX512F | Leaf7Ebx::SHA));
+        assert!(features
+            .leaf7_edx
+            .contains(Leaf7Edx::AMX_TILE | Leaf7Edx::SERIALIZE));
+        assert!(features
+            .leaf7_1_eax
+            .contains(Leaf7Sub1Eax::AVX_VNNI | Leaf7Sub1Eax::AVX512_BF16));
+        assert!(features
+            .ext_leaf1_edx
+            .contains(ExtLeaf1Edx::LM | ExtLeaf1Edx::NX));
+
+        // Subleaf 1 of leaf 7 is only read when subleaf 0 says it exists
+        let features = Features::read(&ryzen());
+        assert!(features.ext_leaf1_ecx.contains(ExtLeaf1Ecx::TOPOEXT));
+        assert!(!features.leaf1_ecx.contains(Leaf1Ecx::HYPERVISOR));
+        assert_eq!(features.leaf7_1_eax, Leaf7Sub1Eax::empty());
+        let no_subleaf = xeon().with(leaf::EXTENDED_FEATURES, 0, [0, 0, 0, 0]);
+        assert_eq!(
+            Features::read(&no_subleaf).leaf7_1_eax,
+            Leaf7Sub1Eax::empty()
+        );
+
+        let xsave = xsave_info(&xeon()).unwrap();
+        assert_eq!(
+            xsave.xcr0_supported,
+            XsaveComponents::X87
+                | XsaveComponents::SSE
+                | XsaveComponents::AVX
+                | XsaveComponents::OPMASK
+                | XsaveComponents::ZMM_HI256
+                | XsaveComponents::HI16_ZMM
+                | XsaveComponents::PKRU
+                | XsaveComponents::TILECFG
+                | XsaveComponents::TILEDATA
+        );
+        assert_eq!(
+            xsave.xss_supported,
+            XsaveComponents::CET_U | XsaveComponents::CET_S
+        );
+        assert!(xsave.extensions.contains(XsaveExtensions::XSAVES));
+        assert_eq!((xsave.enabled_size, xsave.max_size), (0x2B00, 0x2B00));
+        assert_eq!(xsave_info(&xeon().with(leaf::FEATURES, 0, [0; 4])), None);
+    }
+
+    #[test]
+    fn cache_descriptors() {
+        let cache = |level, cache_type, ways, sets, shared_by| CacheDescriptor {
+            level,
+            cache_type,
+            fully_associative: false,
+            ways,
+            partitions: 1,
+            line_size: 64,
+            sets,
+            shared_by,
+        };
+
+        // Intel processors describe them in leaf 4, up to the first null cache type
+        let found: Vec<_> = caches(&xeon()).collect();
+        assert_eq!(
+            found,
+            [
+                cache(1, CacheType::Data, 12, 64, 1),
+                cache(1, CacheType::Instruction, 8, 64, 1),
+                cache(2, CacheType::Unified, 16, 2048, 1),
+                cache(3, CacheType::Unified, 15, 114_688, 1),
+            ]
+        );
+        let sizes: Vec<_> = found.iter().map(CacheDescriptor::size).collect();
+        assert_eq!(sizes, [48 << 10, 32 << 10, 2 << 20, 105 << 20]);
+
+        // AMD processors in leaf 0x8000001D, when they have the topology extensions
+        let found: Vec<_> = caches(&ryzen()).collect();
+        assert_eq!(
+            found,
+            [
+                cache(1, CacheType::Data, 8, 64, 2),
+                cache(1, CacheType::Instruction, 8, 64, 2),
+                cache(2, CacheType::Unified, 8, 1024, 2),
+                cache(3, CacheType::Unified, 16, 16384, 16),
+            ]
+        );
+        assert_eq!(found[3].size(), 16 << 20);
+        let no_topoext = ryzen().with(leaf::EXTENDED_SIGNATURE, 0, [0; 4]);
+        assert_eq!(caches(&no_topoext).count(), 0);
+        let old_max_leaf = xeon().with(
+            leaf::VENDOR,
+            0,
+            [0x2, 0x756e_6547, 0x6c65_746e, 0x4965_6e69],
+        );
+        assert_eq!(caches(&old_max_leaf).count(), 0);
+    }
+
+    #[test]
+    fn topology_levels() {
+        let levels = [
+            TopologyLevel {
+                level_type: TopologyLevelType::Smt,
+                shift: 0,
+                logical_processors: 1,
+                x2apic_id: 0,
+            },
+            TopologyLevel {
+                level_type: TopologyLevelType::Core,
+                shift: 5,
+                logical_processors: 1,
+                x2apic_id: 0,
+            },
+        ];
+
+        // The walk stops at the first level of type 0, whatever follows
+        let xeon = xeon();
+        assert_eq!(topology(&xeon).collect::<Vec<_>>(), levels);
+        let die = xeon.with(leaf::TOPOLOGY_V2, 2, [0x7, 0x1, 0x502, 0x0]);
+        let after_stop = die.with(leaf::TOPOLOGY_V2, 1, [0, 0, 0x1, 0]);
+        assert_eq!(topology(&after_stop).count(), 1);
+        let levels_v2: Vec<_> = topology(&die).map(|level| level.level_type).collect();
+        assert_eq!(
+            levels_v2,
+            [
+                TopologyLevelType::Smt,
+                TopologyLevelType::Core,
+                TopologyLevelType::Die
+            ]
+        );
+
+        // Leaf 0xB stands in for leaf 0x1F when the processor does not have it, or when it reports
+        // no processors
+        let without_v2 = xeon.with(
+            leaf::VENDOR,
+            0,
+            [0x1E, 0x756e_6547, 0x6c65_746e, 0x4965_6e69],
+        );
+        assert_eq!(topology(&without_v2).collect::<Vec<_>>(), levels);
+        let empty_v2 = die.with(leaf::TOPOLOGY_V2, 0, [0; 4]);
+        assert_eq!(topology(&empty_v2).collect::<Vec<_>>(), levels);
+        assert_eq!(topology(&ryzen()).count(), 0);
+    }
+
+    #[test]
+    fn hypervisors() {
+        let kvm = hypervisor(&xeon()).unwrap();
+        assert_eq!(kvm.vendor(), HypervisorVendor::Kvm);
+        assert_eq!(kvm.vendor_string.as_str(), "KVMKVMKVM");
+        assert_eq!(kvm.max_leaf, 0x4000_0001);
+        assert_eq!(
+            kvm.leaf(&xeon(), 1, 0),
+            Some(CpuidResult {
+                eax: 0x0100_7efb,
+                ..Default::default()
+            })
+        );
+        assert_eq!(kvm.leaf(&xeon(), 2, 0), None);
+        assert_eq!(kvm.leaf(&xeon(), u32::MAX, 0), None);
+
+        // A maximum leaf of zero still has the vendor leaf
+        let zero_max = xeon().with(
+            leaf::HYPERVISOR,
+            0,
+            [0, 0x7263_694d, 0x666f_736f, 0x7648_2074],
+        );
+        let hyperv = hypervisor(&zero_max).unwrap();
+        assert_eq!(hyperv.vendor(), HypervisorVendor::HyperV);
+        assert_eq!(hyperv.max_leaf, leaf::HYPERVISOR);
+        assert_eq!(hyperv.leaf(&zero_max, 1, 0), None);
+
+        // Without the hypervisor bit, the hypervisor leaves are not looked at
+        assert_eq!(hypervisor(&ryzen()), None);
+    }
+}
@@ -1,15 +1,17 @@
 //! Module that acts as a central point for FFI bindings from the UEFI API
 pub mod acpi;
 pub mod boot_services;
+pub mod gop;
 pub mod malloc;
+pub mod mem_attr;
+pub mod runtime_services;
 pub mod status;
 
 pub use boot_services::exit_boot_services;
 pub use status::*;
-use acpi::EFI_ACPI_20_TABLE_GUID;
 use boot_services::EfiBootServicesTable;
+use runtime_services::EfiRuntimeServicesTable;
 use core::sync::atomic::{AtomicPtr, Ordering};
-use crate::print;
 
 // Signature, that resides as the first field in the UEFI System Table. We check this to make sure
 // we actually are in an UEFI system
@@ -26,8 +28,14 @@ type _EfiEvent = usize;
 pub type EfiStatus = usize;
 
 /// Takes the `system_table` pointer given as input and places it into the global
-/// `EFI_SYSTEM_TABLE`, if the global stores a null pointer.
-pub fn initialize_system_table(system_table: *mut EfiSystemTable) {
+/// `EFI_SYSTEM_TABLE`, if the global stores a null pointer. Fails with the status given by
+/// `initialize_runtime_services` if its runtime services table cannot be used.
+///
+/// # Safety
+///
+/// `system_table` must be the pointer to the EFI System Table the firmware passed to the image
+/// entry point.
+pub unsafe fn initialize_system_table(system_table: *mut EfiSystemTable) -> Result<(), EfiStatus> {
     // Get the signature reported by UEFI system table
     let signature = unsafe { (*system_table).hdr.signature() };
 
@@ -44,6 +52,9 @@ pub fn initialize_system_table(system_table: *mut EfiSystemTable) {
             Ordering::SeqCst,
         )
         .unwrap();
+
+    // Runtime services outlive the boot services, so we keep a separate handle to them
+    unsafe { runtime_services::initialize_runtime_services(system_table) }
 }
 
 // Takes a `str` slice as input and displays it in the default UEFI ConsoleOut device
@@ -173,7 +184,7 @@ pub struct EfiSystemTable {
     // StandardErrorHandle.
     _std_err: usize,
     // A pointer to the EFI Runtime Services Table.
-    _runtime_services: usize,
+    runtime_services: *const EfiRuntimeServicesTable,
     // A pointer to the EFI Boot Services Table.
     boot_services: *const EfiBootServicesTable,
     // The number of system configuration tables in the buffer ConfigurationTable.
@@ -184,7 +195,7 @@ pub struct EfiSystemTable {
 }
 
 // TODO: We should wrap these types intead so it is stronger typed
-type EfiGuid = u128;
+pub type EfiGuid = u128;
 
 #[derive(Debug)]
 #[repr(packed, C)]
@@ -195,48 +206,33 @@ pub struct EfiConfigurationTableEntry {
     pub vendor_table: usize,
 }
 
-/// Reads the EfiConfigurationTable from the EfiSystemTable
-pub fn read_config_table() {
+/// Looks up the configuration table identified by `guid` and returns the address of its vendor
+/// table, if the firmware installed one.
+pub fn find_config_table(guid: EfiGuid) -> Option<usize> {
     // Get a handle to the EfiSystemTable
     let sys_table = EFI_SYSTEM_TABLE.load(Ordering::SeqCst);
 
-    // If the handle is null, just return
+    // If the handle is null, there is no configuration table to look into
     if sys_table.is_null() {
-        //print!("Is Null\n");
-        return;
+        return None;
     }
-    print!("Is Not Null!\n");
 
-    // Compute the size of a single entry from the configuration table
-    let config_table_entry_size = core::mem::size_of::<EfiConfigurationTableEntry>();
-
-    // Get a handle to the configuration table
+    // Get a handle to the configuration table and the number of entries it holds
     let config_table = unsafe { (*sys_table).config_table };
-
-    // Get the number of table entries
     let ntable_entries = unsafe { (*sys_table).ntable_entries };
 
-    // We start at entry 0
-    let mut entry_idx = 0;
-
-    // Loop until we still have entries to parse
-    while entry_idx < ntable_entries {
+    (0..ntable_entries).find_map(|entry_idx| {
         // Compute the entry's address
-        let entry_addr = config_table as usize + entry_idx * config_table_entry_size;
+        let entry_addr =
+            config_table as usize + entry_idx * core::mem::size_of::<EfiConfigurationTableEntry>();
         // Read the entry and convert it to the appropriate structure
         let table_entry =
             unsafe { core::ptr::read_unaligned(entry_addr as *const EfiConfigurationTableEntry) };
-        // Get the vendor guid
-        let guid = table_entry.vendor_guid;
-
-        if guid == EFI_ACPI_20_TABLE_GUID {
-            crate::print!("Found ACPI 2.0 table: {:x?}\n", guid);
-            acpi::read_rsdp(table_entry.vendor_table);
-        }
+        // Copy the guid, as the structure is packed
+        let entry_guid = table_entry.vendor_guid;
 
-        // Go to the next entry
-        entry_idx += 1;
-    }
+        (entry_guid == guid).then_some(table_entry.vendor_table)
+    })
 }
 
 /// The Simple Text Output Protocol defines the minimum requirements for a text-based `ConsoleOut`
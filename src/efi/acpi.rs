@@ -1,22 +1,112 @@
-// TODO: Verify checksum for the RSDP structure
-// TODO: Verify ext_checksum for the RSDP structure
+pub mod aml;
+mod bgrt;
+mod dbg2;
+mod dmar;
+mod dump;
+mod events;
+mod facs;
+mod fadt;
+mod gas;
 mod header;
-mod madt;
+mod hpet;
+pub mod madt;
+mod mcfg;
+mod mode;
+mod numa;
+mod parse;
+mod phys;
+mod power;
 mod rsdt;
+mod slit;
+mod spcr;
+mod srat;
+mod tables;
+#[cfg(test)]
+mod tests;
 mod xsdt;
 
 use crate::print;
+use core::sync::atomic::{AtomicU8, Ordering};
+pub use bgrt::{
+    BgrtImageError, BgrtImageType, BgrtOrientation, BGRT, MAX_BOOT_IMAGE_SIZE,
+};
+pub use dbg2::{
+    debug_port_type, usb_subtype, DebugDevice, DebugTransport, DBG2, MAX_DBG2_ADDRESSES,
+    MAX_DBG2_DEVICES, MAX_DBG2_NAMESPACE_LENGTH,
+};
+pub use dmar::{
+    Atsr, DeviceScope, DeviceScopeType, DmarFlags, Drhd, Rmrr, DMAR, MAX_ATSR_UNITS,
+    MAX_DEVICE_SCOPES, MAX_DRHD_UNITS, MAX_RMRR_REGIONS, MAX_SCOPE_PATH,
+};
+pub use dump::{dump_table, dump_tables};
+pub use events::{EventError, FixedEvent, FixedEventCallback, FixedEventHandler};
+pub use facs::{FacsFlags, FacsOspmFlags, GlobalLock, GlobalLockError, GlobalLockState, FACS};
+pub use fadt::{FixedFeatureFlags, IaPcBootArchFlags, FADT};
+pub use gas::{address_space, AddressSpace, GasError, GenericAddress, GenericAddressStructure};
 pub use header::DescriptionHeader;
+pub use hpet::{PageProtection, HPET};
 pub use madt::MADT;
+pub use mode::{disable_acpi_mode, enable_acpi_mode, is_acpi_mode, AcpiModeError};
+pub use mcfg::{McfgEntry, MAX_MCFG_ENTRIES, MCFG};
+pub use numa::{NumaRegion, NumaTopology, MAX_NUMA_DOMAINS, REMOTE_DISTANCE};
+pub use parse::{checksum, read, read_prefix, FromBytes, ParseError};
+pub use phys::{phys_atomic_u32, phys_slice, rsdp_bytes, table_bytes, MAX_TABLE_LENGTH};
+#[cfg(any(test, fuzzing))]
+pub use phys::{with_phys_memory, with_shared_phys_memory, PhysRegion, PhysWords};
+pub use power::{find_s5, poweroff, reboot, PowerError};
 pub use xsdt::XSDT;
 pub use rsdt::RSDT;
+pub use spcr::{serial_interface, SpcrFlowControl, SPCR};
+pub use slit::{LOCAL_DISTANCE, MAX_LOCALITIES, SLIT, UNREACHABLE_DISTANCE};
+pub use srat::{
+    MemoryAffinity, MemoryAffinityFlags, ProcessorAffinity, MAX_SRAT_MEMORY_RANGES,
+    MAX_SRAT_PROCESSORS, SRAT,
+};
+pub use tables::{AcpiTable, AcpiTables, TableInfo, MAX_ACPI_TABLES};
 
 /// GUID for the ACPI 2.0 vendor table, which is the RSDP structure, as reported by UEFI System
 /// Table
 pub const EFI_ACPI_20_TABLE_GUID: u128 = 0x81883cc7_800022bc_11d3e4f1_8868e871;
 
+/// GUID for the ACPI 1.0 vendor table, which is the revision 0 RSDP structure, as reported by UEFI
+/// System Table on firmware that only supports ACPI 1.0
+pub const EFI_ACPI_10_TABLE_GUID: u128 = 0x4dc13f27_9000169a_11d32d88_eb9d2d30;
+
+/// Returns the address of the RSDP from the EFI configuration table, preferring the ACPI 2.0 one
+/// and falling back to the ACPI 1.0 one
+pub fn find_rsdp() -> Option<usize> {
+    crate::efi::find_config_table(EFI_ACPI_20_TABLE_GUID)
+        .or_else(|| crate::efi::find_config_table(EFI_ACPI_10_TABLE_GUID))
+}
+
+/// What to do with an ACPI structure whose checksum is not valid
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum ChecksumPolicy {
+    /// Report the structure and keep on using it
+    Warn = 0,
+    /// Report the structure and ignore it
+    Reject = 1,
+}
+
+// The policy currently in use, stored as a `ChecksumPolicy` discriminant
+static CHECKSUM_POLICY: AtomicU8 = AtomicU8::new(ChecksumPolicy::Warn as u8);
+
+/// Sets the policy applied to ACPI structures with an invalid checksum
+pub fn set_checksum_policy(policy: ChecksumPolicy) {
+    CHECKSUM_POLICY.store(policy as u8, Ordering::SeqCst);
+}
+
+/// Returns the policy applied to ACPI structures with an invalid checksum
+pub fn checksum_policy() -> ChecksumPolicy {
+    match CHECKSUM_POLICY.load(Ordering::SeqCst) {
+        0 => ChecksumPolicy::Warn,
+        _ => ChecksumPolicy::Reject,
+    }
+}
+
 /// Root System Description Pointer Structure
-#[derive(Debug)]
+#[derive(Debug, Default, Clone, Copy)]
 #[repr(C, packed)]
 pub struct RSDP {
     /// The "RSD PTR" signature, that must contain a trailing blank character
@@ -46,26 +136,132 @@ pub struct RSDP {
     reserved: [u8; 3],
 }
 
-/// Tries to read and return an `RSDP` structure from the `addr` pointer
-pub fn read_rsdp(addr: usize) -> RSDP {
-    let rsdp = unsafe { core::ptr::read_unaligned(addr as *const RSDP) };
+unsafe impl FromBytes for RSDP {}
+
+/// The number of bytes covered by the ACPI 1.0 RSDP checksum
+pub const RSDP_V1_LENGTH: usize = 20;
 
-    let signature = core::str::from_utf8(&rsdp.signature).unwrap();
-    assert!("RSD PTR " == signature);
+/// The signature the RSDP starts with, including its trailing blank character
+const RSDP_SIGNATURE: &[u8; 8] = b"RSD PTR ";
 
-    read_acpi_table(rsdp.xsdt_addr as usize);
-    read_acpi_table(rsdp.rsdt_addr as usize);
+impl RSDP {
+    /// Reads the RSDP from `bytes`. Revision 2 and above structures must hold as many bytes as
+    /// their length says, while the fields of ACPI 1.0 structures past the first 20 bytes are
+    /// left as zero.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, ParseError> {
+        let v1 = bytes.get(..RSDP_V1_LENGTH).ok_or(ParseError::Truncated {
+            needed: RSDP_V1_LENGTH,
+            available: bytes.len(),
+        })?;
+        let mut rsdp: RSDP = read_prefix(v1);
+        if &rsdp.signature != RSDP_SIGNATURE {
+            return Err(ParseError::InvalidSignature);
+        }
+
+        if rsdp.revision >= 2 {
+            rsdp = read(bytes, 0)?;
+            let length = rsdp.length as usize;
+            if length < core::mem::size_of::<RSDP>() || length > bytes.len() {
+                return Err(ParseError::InvalidLength(length));
+            }
+        }
+
+        Ok(rsdp)
+    }
+
+    pub fn revision(&self) -> u8 {
+        self.revision
+    }
+    pub fn rsdt_addr(&self) -> u32 {
+        self.rsdt_addr
+    }
+    /// The XSDT address is only available starting with revision 2
+    pub fn xsdt_addr(&self) -> u64 {
+        if self.revision < 2 {
+            return 0;
+        }
+        self.xsdt_addr
+    }
 
-    rsdp
+    /// Verifies both the ACPI 1.0 checksum and, for revision 2 and above, the extended checksum
+    /// of the RSDP held by `bytes`
+    pub fn is_checksum_valid(&self, bytes: &[u8]) -> bool {
+        let valid = |length: usize| bytes.get(..length).is_some_and(|rsdp| checksum(rsdp) == 0);
+
+        valid(RSDP_V1_LENGTH) && (self.revision < 2 || valid(self.length as usize))
+    }
+}
+
+/// Tries to read and return an `RSDP` structure from the `addr` pointer. Returns `None` if the
+/// RSDP is malformed, or if its checksum is invalid and the `ChecksumPolicy` says to reject it.
+/// Use `AcpiTables::new` to find the tables the RSDP leads to.
+pub fn read_rsdp(addr: usize) -> Option<RSDP> {
+    let parsed = rsdp_bytes(addr).and_then(|bytes| Ok((RSDP::from_bytes(bytes)?, bytes)));
+    let (rsdp, bytes) = match parsed {
+        Ok(parsed) => parsed,
+        Err(err) => {
+            print!("Cannot read the RSDP at {:#x}: {:?}\n", addr, err);
+            return None;
+        }
+    };
+
+    if !rsdp.is_checksum_valid(bytes) {
+        print!("Invalid checksum for the RSDP at {:#x}\n", addr);
+
+        if checksum_policy() == ChecksumPolicy::Reject {
+            return None;
+        }
+    }
+
+    Some(rsdp)
 }
 
-/// Reads an ACPI table
+/// Validates the checksum of `table`, whose header is `header`, reporting it if the checksum is
+/// invalid. Returns whether the table should still be used, according to the current
+/// `ChecksumPolicy`.
+pub fn check_table(table: &[u8], header: &DescriptionHeader) -> bool {
+    if header.is_checksum_valid(table) {
+        return true;
+    }
+
+    let signature = header.signature;
+    let oem_table_id = header.oem_table_id();
+    print!(
+        "Invalid checksum for table {:?} (OEM Table ID {:?}) at {:#x}\n",
+        core::str::from_utf8(&signature).unwrap_or("????"),
+        core::str::from_utf8(&oem_table_id).unwrap_or("????????"),
+        table.as_ptr() as usize,
+    );
+
+    checksum_policy() == ChecksumPolicy::Warn
+}
+
+/// Reads the system description table found at `addr`, returning its bytes and its header.
+/// Returns `None`, after reporting it, if the table is malformed, or if its checksum is invalid and
+/// the `ChecksumPolicy` says to reject it.
+pub fn read_table(addr: usize) -> Option<(&'static [u8], DescriptionHeader)> {
+    let parsed =
+        table_bytes(addr).and_then(|table| Ok((table, DescriptionHeader::from_bytes(table)?)));
+
+    match parsed {
+        Ok((table, header)) => check_table(table, &header).then_some((table, header)),
+        Err(err) => {
+            print!("Cannot read the ACPI table at {:#x}: {:?}\n", addr, err);
+            None
+        }
+    }
+}
+
+/// Reads an ACPI table and prints what we know about it. Tables we do not have a parser for are
+/// only reported.
 pub fn read_acpi_table(addr: usize) {
-    // Read the table's header
-    let header = DescriptionHeader::from_addr(addr);
+    // Read the table's header and its bytes, checking its length and checksum
+    let Some((table, header)) = read_table(addr) else {
+        return;
+    };
 
     // Convert the signature into a `str` if possible
-    let signature = core::str::from_utf8(&header.signature).unwrap();
+    let signature = core::str::from_utf8(&header.signature).unwrap_or("????");
     // Copy the length into a variable, because Rust cannot use it, as it was unaligned.
     let length = header.length;
     print!(
@@ -73,57 +269,134 @@ pub fn read_acpi_table(addr: usize) {
         signature, length
     );
 
-    // TODO: Maybe transform this match in a list? Static list with function pointers?
     match signature {
-        "XSDT" => {
-            let maybe_xsdt = XSDT::from_header(addr, header);
-
-            if let Some(xsdt) = maybe_xsdt {
-                // Now that we got the XSDT, we can read the other tables, it refers to
-                for table_addr in xsdt.entries.into_iter() {
-                    read_acpi_table(table_addr as usize);
+        "XSDT" => match XSDT::from_bytes(table) {
+            Ok(xsdt) => {
+                print!("XSDT refers to {} tables\n", xsdt.entries.into_iter().count());
+            }
+            Err(err) => {
+                print!("Invalid XSDT: {:?}\n", err);
+            }
+        },
+        "RSDT" => match RSDT::from_bytes(table) {
+            Ok(rsdt) => {
+                print!("RSDT refers to {} tables\n", rsdt.entries.into_iter().count());
+            }
+            Err(err) => {
+                print!("Invalid RSDT: {:?}\n", err);
+            }
+        },
+        // This is the Fixed ACPI Description Table (FADT)
+        "FACP" => match FADT::from_bytes(table) {
+            Ok(fadt) => {
+                print!("{:#?}\n", fadt);
+            }
+            Err(err) => {
+                print!("Invalid FADT: {:?}\n", err);
+            }
+        },
+        // This is the Multiple APIC Description Table
+        "APIC" => match MADT::from_bytes(table) {
+            Ok(madt) => {
+                print!("{:#?}\n", madt);
+                for int_ctrl in madt.int_ctrls() {
+                    print!("{:#?}\n", int_ctrl);
                 }
             }
-        }
-        "RSDT" => {
-            let maybe_rsdt = RSDT::from_header(addr, header);
-
-            if let Some(rsdt) = maybe_rsdt {
-                // Now that we got the RSDT, we can read the other tables, it refers to
-                for table_addr in rsdt.entries.into_iter() {
-                    read_acpi_table(table_addr as usize);
+            Err(err) => {
+                print!("Invalid MADT: {:?}\n", err);
+            }
+        },
+        // This is the PCI Express memory mapped configuration space description table
+        "MCFG" => match MCFG::from_bytes(table) {
+            Ok(mcfg) => {
+                print!("{:#x?}\n", mcfg);
+            }
+            Err(err) => {
+                print!("Invalid MCFG: {:?}\n", err);
+            }
+        },
+        // Replacement for Event Timer Description Table, which is now obsolete
+        "HPET" => match HPET::from_bytes(table) {
+            Ok(hpet) => {
+                print!("{:#?}\n", hpet);
+            }
+            Err(err) => {
+                print!("Invalid HPET: {:?}\n", err);
+            }
+        },
+        // This is the System Resource Affinity Table, which gives the NUMA node of the processors
+        // and memory
+        "SRAT" => match SRAT::from_bytes(table) {
+            Ok(srat) => {
+                print!("{:#?}\n", srat);
+                for processor in srat.processors() {
+                    print!("{:?}\n", processor);
+                }
+                for memory_range in srat.memory_ranges() {
+                    print!("{:x?}\n", memory_range);
                 }
             }
-        }
-        "FACP" => {
-            // This is the Fixed ACPI Description Table (FADT) and it is way to fucking long to be
-            // parsed at this moment. Please come back
-        }
-        "APIC" => {
-            // This is the Multiple APIC Description Table
-            let maybe_madt = MADT::from_header(addr, header);
-
-            if let Some(_madt) = maybe_madt {
-                print!("Parsed MADT\n");
+            Err(err) => {
+                print!("Invalid SRAT: {:?}\n", err);
             }
-        }
-        "HPET" => {
-            // Replacement for Event Timer Description Table, which is now obsolete
-        }
+        },
+        // This is the System Locality Information Table, which gives the distances between NUMA
+        // nodes
+        "SLIT" => match SLIT::from_bytes(table) {
+            Ok(slit) => {
+                print!("{:#?}\n", slit);
+            }
+            Err(err) => {
+                print!("Invalid SLIT: {:?}\n", err);
+            }
+        },
+        // This is the Serial Port Console Redirection Table
+        "SPCR" => match SPCR::from_bytes(table) {
+            Ok(spcr) => {
+                print!("{:#?}\n", spcr);
+            }
+            Err(err) => {
+                print!("Invalid SPCR: {:?}\n", err);
+            }
+        },
+        // This is the Debug Port Table 2, which lists the ports set aside for debugging
+        "DBG2" => match DBG2::from_bytes(table) {
+            Ok(dbg2) => {
+                print!("{:#?}\n", dbg2);
+            }
+            Err(err) => {
+                print!("Invalid DBG2: {:?}\n", err);
+            }
+        },
+        // This is the DMA Remapping Reporting Table, which describes the VT-d remapping units
+        "DMAR" => match DMAR::from_bytes(table) {
+            Ok(dmar) => {
+                print!("{:#x?}\n", dmar);
+            }
+            Err(err) => {
+                print!("Invalid DMAR: {:?}\n", err);
+            }
+        },
         "WAET" => {
             // Windows ACPI Emulated Devices Table
         }
-        "BGRT" => {
-            // Boot Graphics Resource Table is an optional table that provides a mechanism to
-            // indicate that an image was drawn on the screen during boot, and some information
-            // about the image.
-        }
+        // Boot Graphics Resource Table is an optional table that provides a mechanism to
+        // indicate that an image was drawn on the screen during boot, and some information
+        // about the image.
+        "BGRT" => match BGRT::from_bytes(table) {
+            Ok(bgrt) => {
+                print!("{:#?}\n", bgrt);
+            }
+            Err(err) => {
+                print!("Invalid BGRT: {:?}\n", err);
+            }
+        },
         &_ => {
             print!(
-                "Parsing for table {:?} at addr {:x?} not yet implemented!!!\n",
+                "Parsing for table {:?} at addr {:x?} not yet implemented\n",
                 signature, addr
             );
-            todo!();
         }
     };
 }
@@ -0,0 +1,75 @@
+//! Module that parses and runs the ACPI Machine Language (AML) bytecode, found in the DSDT and
+//! the SSDTs. Loading these definition blocks builds the ACPI namespace, whose objects and
+//! control methods can then be evaluated.
+mod device;
+mod interp;
+mod name;
+mod namespace;
+mod prt;
+mod region;
+mod resource;
+mod value;
+
+use crate::efi::acpi::{AcpiTables, GasError};
+pub use device::{pnp_id, DeviceId, DeviceInfo, DeviceStatus, IdString, MAX_CIDS};
+pub use interp::{Interpreter, InterpreterGuard};
+pub use name::{AmlName, NameSeg};
+pub use namespace::{Builtin, FieldKind, FieldUnit, Namespace, Node, NodeId, NodePath, Object};
+pub use prt::{PciRoute, PrtEntry};
+pub use resource::{
+    resources, AddressKind, AddressSpace, InterruptMode, Resource, ResourceIter, MAX_EXTENDED_IRQS,
+};
+pub use value::{AmlValue, Bytes, Elements, IndexTarget};
+
+/// Reasons for which AML code could not be loaded or evaluated
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AmlError {
+    /// The code ended in the middle of an object
+    UnexpectedEnd,
+    /// A name is not properly encoded
+    InvalidName,
+    /// The namespace has no object with the requested name
+    NameNotFound,
+    /// There is no room left in the namespace for new objects
+    NamespaceFull,
+    /// There is no room left to store strings, buffers or packages
+    OutOfMemory,
+    /// The opcode is not defined, or not supported by the interpreter
+    UnknownOpcode(u16),
+    /// An operand does not have a type the operator accepts
+    TypeMismatch,
+    /// An operand has a value the operator does not accept
+    InvalidArgument,
+    /// A field belongs to an operation region whose address space we cannot access
+    UnsupportedRegion(u8),
+    /// A field could not be accessed through its operation region
+    InvalidRegion(GasError),
+    /// Too many control methods were nested
+    MethodDepthExceeded,
+    /// A `While` loop ran for too long
+    LoopTimeout,
+    /// The AML code ran a `Fatal` operator
+    Fatal { fatal_type: u8, code: u32, arg: u64 },
+    /// The interpreter is already in use
+    Busy,
+    /// The FADT does not point to a DSDT
+    NoDsdt,
+    /// A definition block was rejected, because of its checksum
+    InvalidTable,
+    /// There is neither a PM timer nor an HPET to run the `Timer` operator with
+    NoTimer,
+}
+
+impl From<GasError> for AmlError {
+    fn from(value: GasError) -> Self {
+        Self::InvalidRegion(value)
+    }
+}
+
+/// Loads the DSDT and the SSDTs found through `tables` into the namespace, returning the number of
+/// objects the namespace holds
+pub fn load_namespace(tables: &AcpiTables) -> Result<usize, AmlError> {
+    let mut interpreter = Interpreter::lock().ok_or(AmlError::Busy)?;
+    interpreter.load_tables(tables)?;
+    Ok(interpreter.namespace().len())
+}
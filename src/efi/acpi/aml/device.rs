@@ -0,0 +1,307 @@
+//! Module that enumerates the devices of the namespace, along with their identification objects,
+//! status and current resources
+use crate::efi::acpi::aml::{
+    interp::Interpreter,
+    namespace::{NodeId, Object, MAX_NODES},
+    resource::{resources, ResourceIter},
+    value::AmlValue,
+    AmlError,
+};
+use crate::print;
+use bitflags::bitflags;
+use core::fmt;
+
+/// The maximum number of compatible IDs kept for a device
+pub const MAX_CIDS: usize = 4;
+
+/// Hardware IDs of the devices that are commonly looked for
+pub mod pnp_id {
+    /// 16550 compatible serial port
+    pub const COM_PORT: &str = "PNP0501";
+    /// AT real time clock
+    pub const RTC: &str = "PNP0B00";
+    /// High Precision Event Timer
+    pub const HPET: &str = "PNP0103";
+    /// PCI root bridge
+    pub const PCI_ROOT_BRIDGE: &str = "PNP0A03";
+    /// PCI Express root bridge, which is also compatible with `PCI_ROOT_BRIDGE`
+    pub const PCIE_ROOT_BRIDGE: &str = "PNP0A08";
+}
+
+bitflags! {
+    /// The status of a device, as returned by `_STA`
+    #[repr(transparent)]
+    #[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+    pub struct DeviceStatus: u32 {
+        const PRESENT = 1 << 0;
+        const ENABLED = 1 << 1;
+        const SHOWN_IN_UI = 1 << 2;
+        const FUNCTIONING = 1 << 3;
+        const BATTERY_PRESENT = 1 << 4;
+    }
+}
+
+/// A short identification string, like a PNP ID or a string `_UID`. Longer strings are truncated.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub struct IdString {
+    bytes: [u8; 16],
+    len: u8,
+}
+
+impl IdString {
+    fn new(data: &[u8]) -> Self {
+        let mut bytes = [0; 16];
+        // Strings from AML code may hold their NUL terminator
+        let data = data.split(|&byte| byte == 0).next().unwrap_or(&[]);
+        let len = data.len().min(bytes.len());
+        bytes[..len].copy_from_slice(&data[..len]);
+
+        Self {
+            bytes,
+            len: len as u8,
+        }
+    }
+
+    // Decodes a compressed EISA ID, as produced by `EISAID("PNP0501")`
+    fn from_eisa_id(value: u32) -> Self {
+        const HEX: &[u8; 16] = b"0123456789ABCDEF";
+
+        let id = value.swap_bytes();
+        let mut text = [0u8; 7];
+        for (idx, shift) in [26, 21, 16].into_iter().enumerate() {
+            text[idx] = b'@' + (id >> shift & 0x1F) as u8;
+        }
+        for (idx, shift) in [12, 8, 4, 0].into_iter().enumerate() {
+            text[3 + idx] = HEX[(id >> shift & 0xF) as usize];
+        }
+
+        Self::new(&text)
+    }
+
+    pub fn as_str(&self) -> &str {
+        core::str::from_utf8(&self.bytes[..self.len as usize]).unwrap_or("")
+    }
+}
+
+impl fmt::Debug for IdString {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:?}", self.as_str())
+    }
+}
+
+/// A device identification, as returned by `_HID`, `_CID` or `_UID`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DeviceId {
+    Text(IdString),
+    Integer(u64),
+}
+
+impl DeviceId {
+    /// Returns whether the ID is the string `id`
+    pub fn is(&self, id: &str) -> bool {
+        matches!(self, DeviceId::Text(text) if text.as_str() == id)
+    }
+}
+
+impl fmt::Display for DeviceId {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            DeviceId::Text(text) => f.write_str(text.as_str()),
+            DeviceId::Integer(value) => write!(f, "{:#x}", value),
+        }
+    }
+}
+
+/// The identification and status of a device of the namespace
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DeviceInfo {
+    pub node: NodeId,
+    /// The hardware ID, from `_HID`
+    pub hid: Option<DeviceId>,
+    /// The compatible IDs, from `_CID`. Only the first `MAX_CIDS` are kept.
+    pub cids: [Option<DeviceId>; MAX_CIDS],
+    /// The unique ID, from `_UID`, which tells apart devices with the same hardware ID
+    pub uid: Option<DeviceId>,
+    /// The address on the parent bus, from `_ADR`. For PCI devices, the device number is in the
+    /// high word and the function number in the low word.
+    pub adr: Option<u64>,
+    pub status: DeviceStatus,
+}
+
+impl DeviceInfo {
+    /// Returns whether the hardware ID or one of the compatible IDs is `id`
+    pub fn matches(&self, id: &str) -> bool {
+        self.hid.is_some_and(|hid| hid.is(id)) || self.cids.iter().flatten().any(|cid| cid.is(id))
+    }
+
+    /// Returns whether the device is present, as told by `_STA`
+    pub fn is_present(&self) -> bool {
+        self.status.contains(DeviceStatus::PRESENT)
+    }
+}
+
+impl Interpreter {
+    // Evaluates the child `name` of `node`, returning `None` if it does not exist
+    fn evaluate_child(
+        &mut self,
+        node: NodeId,
+        name: [u8; 4],
+    ) -> Result<Option<AmlValue>, AmlError> {
+        match self.namespace.child(node, name) {
+            Some(child) => self.invoke(child, &[]).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    // Converts the value of `_HID`, `_CID` or `_UID` to a device ID
+    fn device_id(&mut self, value: AmlValue, eisa: bool) -> Result<DeviceId, AmlError> {
+        match value {
+            AmlValue::String(bytes) => Ok(DeviceId::Text(IdString::new(self.bytes.get(bytes)))),
+            value => {
+                let value = self.to_integer(value)?;
+                if eisa {
+                    Ok(DeviceId::Text(IdString::from_eisa_id(value as u32)))
+                } else {
+                    Ok(DeviceId::Integer(value))
+                }
+            }
+        }
+    }
+
+    /// Returns whether `node` is a PCI or PCI Express root bridge, as told by its `_HID` or `_CID`
+    pub(super) fn is_pci_root(&mut self, node: NodeId) -> Result<bool, AmlError> {
+        let is_root = |id: DeviceId| {
+            id.is(pnp_id::PCI_ROOT_BRIDGE) || id.is(pnp_id::PCIE_ROOT_BRIDGE)
+        };
+
+        if let Some(value) = self.evaluate_child(node, *b"_HID")? {
+            if is_root(self.device_id(value, true)?) {
+                return Ok(true);
+            }
+        }
+        match self.evaluate_child(node, *b"_CID")? {
+            Some(AmlValue::Package(ids)) => {
+                for idx in 0..ids.len() {
+                    let id = self.values.get(ids)[idx];
+                    if is_root(self.device_id(id, true)?) {
+                        return Ok(true);
+                    }
+                }
+                Ok(false)
+            }
+            Some(value) => Ok(is_root(self.device_id(value, true)?)),
+            None => Ok(false),
+        }
+    }
+
+    /// Evaluates the identification objects and the status of the device `node`. Devices without
+    /// `_STA` are present and functioning.
+    pub fn device_info(&mut self, node: NodeId) -> Result<DeviceInfo, AmlError> {
+        let mut info = DeviceInfo {
+            node,
+            hid: None,
+            cids: [None; MAX_CIDS],
+            uid: None,
+            adr: None,
+            status: DeviceStatus::from_bits_retain(0xF),
+        };
+
+        if let Some(value) = self.evaluate_child(node, *b"_STA")? {
+            info.status = DeviceStatus::from_bits_retain(self.to_integer(value)? as u32);
+        }
+        if let Some(value) = self.evaluate_child(node, *b"_HID")? {
+            info.hid = Some(self.device_id(value, true)?);
+        }
+        if let Some(value) = self.evaluate_child(node, *b"_UID")? {
+            info.uid = Some(self.device_id(value, false)?);
+        }
+        if let Some(value) = self.evaluate_child(node, *b"_ADR")? {
+            info.adr = Some(self.to_integer(value)?);
+        }
+
+        match self.evaluate_child(node, *b"_CID")? {
+            // A device may have several compatible IDs, given as a package
+            Some(AmlValue::Package(ids)) => {
+                for idx in 0..ids.len().min(MAX_CIDS) {
+                    let id = self.values.get(ids)[idx];
+                    info.cids[idx] = Some(self.device_id(id, true)?);
+                }
+            }
+            Some(value) => info.cids[0] = Some(self.device_id(value, true)?),
+            None => {}
+        }
+
+        Ok(info)
+    }
+
+    /// Calls `f` on every device of the namespace, in the order they were defined. The children
+    /// of devices that are neither present nor functioning are skipped, as their status cannot
+    /// be trusted. Values created while evaluating a device are released once `f` returns.
+    pub fn for_each_device(&mut self, mut f: impl FnMut(&mut Interpreter, &DeviceInfo)) {
+        // The nodes below a device that is not there. Parents are always defined before their
+        // children, so a single pass is enough.
+        let mut hidden = [0u64; MAX_NODES / 64];
+        let is_hidden =
+            |hidden: &[u64], id: NodeId| hidden[id.0 as usize / 64] >> (id.0 % 64) & 1 != 0;
+
+        let mut idx = 1;
+        while idx < self.namespace.len() {
+            let id = NodeId(idx as u16);
+            idx += 1;
+
+            let node = self.namespace.node(id);
+            if is_hidden(&hidden, node.parent()) {
+                hidden[id.0 as usize / 64] |= 1 << (id.0 % 64);
+                continue;
+            }
+            if !matches!(node.object(), Object::Device) {
+                continue;
+            }
+
+            match self.device_info(id) {
+                Ok(info) => {
+                    if !info
+                        .status
+                        .intersects(DeviceStatus::PRESENT | DeviceStatus::FUNCTIONING)
+                    {
+                        hidden[id.0 as usize / 64] |= 1 << (id.0 % 64);
+                    }
+                    f(self, &info);
+                }
+                Err(err) => {
+                    print!(
+                        "Cannot evaluate the device {}: {:?}\n",
+                        self.namespace.path(id),
+                        err
+                    );
+                }
+            }
+            self.release_values();
+        }
+    }
+
+    /// Fills `devices` with the present devices whose hardware ID or compatible IDs match `id`.
+    /// Returns the number of devices found, which is at most `devices.len()`.
+    pub fn find_devices(&mut self, id: &str, devices: &mut [DeviceInfo]) -> usize {
+        let mut count = 0;
+        self.for_each_device(|_, info| {
+            if info.is_present() && info.matches(id) && count < devices.len() {
+                devices[count] = *info;
+                count += 1;
+            }
+        });
+        count
+    }
+
+    /// Evaluates the `_CRS` of the device `node`, returning an iterator over the resources it
+    /// currently uses
+    pub fn current_resources(&mut self, node: NodeId) -> Result<ResourceIter<'_>, AmlError> {
+        let value = self
+            .evaluate_child(node, *b"_CRS")?
+            .ok_or(AmlError::NameNotFound)?;
+        match value {
+            AmlValue::Buffer(bytes) => Ok(resources(self.bytes.get(bytes))),
+            _ => Err(AmlError::TypeMismatch),
+        }
+    }
+}
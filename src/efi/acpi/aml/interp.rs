@@ -0,0 +1,2231 @@
+//! Module that holds the AML interpreter, which loads definition blocks into the namespace and
+//! evaluates their objects and control methods
+use crate::efi::acpi::aml::{
+    name::{self, AmlName},
+    namespace::{Builtin, FieldKind, FieldUnit, Namespace, NodeId, Object},
+    value::{AmlValue, Arena, Bytes, IndexTarget},
+    AmlError,
+};
+use crate::efi::acpi::{address_space, read_table, AcpiTables, DescriptionHeader, FADT};
+use crate::hpet::Hpet;
+use crate::pm_timer::PmTimer;
+use crate::print;
+use core::cell::UnsafeCell;
+use core::cmp::Ordering as CmpOrdering;
+use core::fmt::{self, Write};
+use core::mem::size_of;
+use core::ops::{Deref, DerefMut};
+use core::sync::atomic::{AtomicBool, Ordering};
+
+/// The number of bytes available to store strings and buffers
+pub const MAX_BYTES: usize = 0x20000;
+/// The number of values available to store package elements
+pub const MAX_VALUES: usize = 0x2000;
+/// How deeply control methods can call each other
+const MAX_METHOD_DEPTH: usize = 16;
+/// How many times the body of a `While` loop can run before we give up on it
+const MAX_LOOP_ITERATIONS: usize = 0x10000;
+/// The value returned by the `Revision` operator
+const INTERPRETER_REVISION: u64 = 1;
+
+/// AML opcodes, as encoded in the bytecode
+mod opcode {
+    pub const ZERO: u8 = 0x00;
+    pub const ONE: u8 = 0x01;
+    pub const ALIAS: u8 = 0x06;
+    pub const NAME: u8 = 0x08;
+    pub const BYTE_PREFIX: u8 = 0x0A;
+    pub const WORD_PREFIX: u8 = 0x0B;
+    pub const DWORD_PREFIX: u8 = 0x0C;
+    pub const STRING_PREFIX: u8 = 0x0D;
+    pub const QWORD_PREFIX: u8 = 0x0E;
+    pub const SCOPE: u8 = 0x10;
+    pub const BUFFER: u8 = 0x11;
+    pub const PACKAGE: u8 = 0x12;
+    pub const VAR_PACKAGE: u8 = 0x13;
+    pub const METHOD: u8 = 0x14;
+    pub const EXTERNAL: u8 = 0x15;
+    pub const EXT_PREFIX: u8 = 0x5B;
+    pub const LOCAL0: u8 = 0x60;
+    pub const LOCAL7: u8 = 0x67;
+    pub const ARG0: u8 = 0x68;
+    pub const ARG6: u8 = 0x6E;
+    pub const STORE: u8 = 0x70;
+    pub const REF_OF: u8 = 0x71;
+    pub const ADD: u8 = 0x72;
+    pub const CONCAT: u8 = 0x73;
+    pub const SUBTRACT: u8 = 0x74;
+    pub const INCREMENT: u8 = 0x75;
+    pub const DECREMENT: u8 = 0x76;
+    pub const MULTIPLY: u8 = 0x77;
+    pub const DIVIDE: u8 = 0x78;
+    pub const SHIFT_LEFT: u8 = 0x79;
+    pub const SHIFT_RIGHT: u8 = 0x7A;
+    pub const AND: u8 = 0x7B;
+    pub const NAND: u8 = 0x7C;
+    pub const OR: u8 = 0x7D;
+    pub const NOR: u8 = 0x7E;
+    pub const XOR: u8 = 0x7F;
+    pub const NOT: u8 = 0x80;
+    pub const FIND_SET_LEFT_BIT: u8 = 0x81;
+    pub const FIND_SET_RIGHT_BIT: u8 = 0x82;
+    pub const DEREF_OF: u8 = 0x83;
+    pub const CONCAT_RES: u8 = 0x84;
+    pub const MOD: u8 = 0x85;
+    pub const NOTIFY: u8 = 0x86;
+    pub const SIZE_OF: u8 = 0x87;
+    pub const INDEX: u8 = 0x88;
+    pub const MATCH: u8 = 0x89;
+    pub const CREATE_DWORD_FIELD: u8 = 0x8A;
+    pub const CREATE_WORD_FIELD: u8 = 0x8B;
+    pub const CREATE_BYTE_FIELD: u8 = 0x8C;
+    pub const CREATE_BIT_FIELD: u8 = 0x8D;
+    pub const OBJECT_TYPE: u8 = 0x8E;
+    pub const CREATE_QWORD_FIELD: u8 = 0x8F;
+    pub const LAND: u8 = 0x90;
+    pub const LOR: u8 = 0x91;
+    pub const LNOT: u8 = 0x92;
+    pub const LEQUAL: u8 = 0x93;
+    pub const LGREATER: u8 = 0x94;
+    pub const LLESS: u8 = 0x95;
+    pub const TO_BUFFER: u8 = 0x96;
+    pub const TO_DECIMAL_STRING: u8 = 0x97;
+    pub const TO_HEX_STRING: u8 = 0x98;
+    pub const TO_INTEGER: u8 = 0x99;
+    pub const TO_STRING: u8 = 0x9C;
+    pub const COPY_OBJECT: u8 = 0x9D;
+    pub const MID: u8 = 0x9E;
+    pub const CONTINUE: u8 = 0x9F;
+    pub const IF: u8 = 0xA0;
+    pub const ELSE: u8 = 0xA1;
+    pub const WHILE: u8 = 0xA2;
+    pub const NOOP: u8 = 0xA3;
+    pub const RETURN: u8 = 0xA4;
+    pub const BREAK: u8 = 0xA5;
+    pub const BREAKPOINT: u8 = 0xCC;
+    pub const ONES: u8 = 0xFF;
+}
+
+/// AML opcodes that follow the `EXT_PREFIX` byte
+mod ext_opcode {
+    pub const MUTEX: u8 = 0x01;
+    pub const EVENT: u8 = 0x02;
+    pub const COND_REF_OF: u8 = 0x12;
+    pub const CREATE_FIELD: u8 = 0x13;
+    pub const LOAD_TABLE: u8 = 0x1F;
+    pub const LOAD: u8 = 0x20;
+    pub const STALL: u8 = 0x21;
+    pub const SLEEP: u8 = 0x22;
+    pub const ACQUIRE: u8 = 0x23;
+    pub const SIGNAL: u8 = 0x24;
+    pub const WAIT: u8 = 0x25;
+    pub const RESET: u8 = 0x26;
+    pub const RELEASE: u8 = 0x27;
+    pub const FROM_BCD: u8 = 0x28;
+    pub const TO_BCD: u8 = 0x29;
+    pub const UNLOAD: u8 = 0x2A;
+    pub const REVISION: u8 = 0x30;
+    pub const DEBUG: u8 = 0x31;
+    pub const FATAL: u8 = 0x32;
+    pub const TIMER: u8 = 0x33;
+    pub const OP_REGION: u8 = 0x80;
+    pub const FIELD: u8 = 0x81;
+    pub const DEVICE: u8 = 0x82;
+    pub const PROCESSOR: u8 = 0x83;
+    pub const POWER_RES: u8 = 0x84;
+    pub const THERMAL_ZONE: u8 = 0x85;
+    pub const INDEX_FIELD: u8 = 0x86;
+    pub const BANK_FIELD: u8 = 0x87;
+    pub const DATA_REGION: u8 = 0x88;
+}
+
+/// The interfaces `\_OSI` reports as supported. Firmware commonly enables features based on the
+/// Windows version, so we answer like a recent Windows does.
+const SUPPORTED_INTERFACES: &[&[u8]] = &[
+    b"Windows 2000",
+    b"Windows 2001",
+    b"Windows 2001 SP1",
+    b"Windows 2001.1",
+    b"Windows 2001 SP2",
+    b"Windows 2001.1 SP1",
+    b"Windows 2006",
+    b"Windows 2006 SP1",
+    b"Windows 2006.1",
+    b"Windows 2009",
+    b"Windows 2012",
+    b"Windows 2013",
+    b"Windows 2015",
+    b"Windows 2016",
+    b"Windows 2017",
+    b"Windows 2017.2",
+    b"Windows 2018",
+    b"Windows 2018.2",
+    b"Windows 2019",
+    b"Windows 2020",
+    b"Windows 2021",
+    b"Windows 2022",
+    b"Module Device",
+    b"Processor Device",
+    b"3.0 Thermal Model",
+    b"Extended Address Space Descriptor",
+    b"Processor Aggregator Device",
+];
+
+/// A position in a block of AML code
+#[derive(Clone, Copy)]
+struct Cursor {
+    aml: &'static [u8],
+    pos: usize,
+}
+
+impl Cursor {
+    fn new(aml: &'static [u8]) -> Self {
+        Self { aml, pos: 0 }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.pos >= self.aml.len()
+    }
+
+    fn peek(&self) -> Result<u8, AmlError> {
+        self.aml
+            .get(self.pos)
+            .copied()
+            .ok_or(AmlError::UnexpectedEnd)
+    }
+
+    fn peek_at(&self, offset: usize) -> Option<u8> {
+        self.aml.get(self.pos + offset).copied()
+    }
+
+    fn skip(&mut self, count: usize) {
+        self.pos += count;
+    }
+
+    fn byte(&mut self) -> Result<u8, AmlError> {
+        let byte = self.peek()?;
+        self.pos += 1;
+        Ok(byte)
+    }
+
+    fn bytes(&mut self, len: usize) -> Result<&'static [u8], AmlError> {
+        let aml = self.aml;
+        let bytes = aml
+            .get(self.pos..self.pos + len)
+            .ok_or(AmlError::UnexpectedEnd)?;
+        self.pos += len;
+        Ok(bytes)
+    }
+
+    fn word(&mut self) -> Result<u16, AmlError> {
+        Ok(u16::from_le_bytes(self.bytes(2)?.try_into().unwrap()))
+    }
+
+    fn dword(&mut self) -> Result<u32, AmlError> {
+        Ok(u32::from_le_bytes(self.bytes(4)?.try_into().unwrap()))
+    }
+
+    fn qword(&mut self) -> Result<u64, AmlError> {
+        Ok(u64::from_le_bytes(self.bytes(8)?.try_into().unwrap()))
+    }
+
+    fn remaining(&self) -> &'static [u8] {
+        let aml = self.aml;
+        &aml[self.pos.min(aml.len())..]
+    }
+
+    /// Parses a NameString, returning it along with its encoding
+    fn name_with_bytes(&mut self) -> Result<(AmlName, &'static [u8]), AmlError> {
+        let (name, size) = AmlName::parse(self.remaining())?;
+        Ok((name, self.bytes(size)?))
+    }
+
+    fn name(&mut self) -> Result<AmlName, AmlError> {
+        self.name_with_bytes().map(|(name, _)| name)
+    }
+
+    /// Parses a PkgLength, returning the length it encodes
+    fn pkg_length(&mut self) -> Result<usize, AmlError> {
+        let lead = self.byte()?;
+        let follow = (lead >> 6) as usize;
+        if follow == 0 {
+            return Ok((lead & 0x3F) as usize);
+        }
+
+        // When bytes follow, only the lower 4 bits of the lead byte are part of the length
+        let mut len = (lead & 0x0F) as usize;
+        for idx in 0..follow {
+            len |= (self.byte()? as usize) << (4 + 8 * idx);
+        }
+        Ok(len)
+    }
+
+    /// Returns where the term at the cursor ends, if it is one whose length is given by a
+    /// PkgLength
+    fn package_end(&self) -> Option<usize> {
+        let mut cur = *self;
+        let has_pkg_length = match cur.byte().ok()? {
+            opcode::SCOPE
+            | opcode::BUFFER
+            | opcode::PACKAGE
+            | opcode::VAR_PACKAGE
+            | opcode::METHOD
+            | opcode::IF
+            | opcode::ELSE
+            | opcode::WHILE => true,
+            opcode::EXT_PREFIX => matches!(
+                cur.byte().ok()?,
+                ext_opcode::FIELD
+                    | ext_opcode::DEVICE
+                    | ext_opcode::PROCESSOR
+                    | ext_opcode::POWER_RES
+                    | ext_opcode::THERMAL_ZONE
+                    | ext_opcode::INDEX_FIELD
+                    | ext_opcode::BANK_FIELD
+            ),
+            _ => false,
+        };
+        if !has_pkg_length {
+            return None;
+        }
+
+        cur.package().ok()?;
+        Some(cur.pos)
+    }
+
+    /// Parses a PkgLength and returns a cursor over the rest of the package, moving this cursor
+    /// to the end of the package
+    fn package(&mut self) -> Result<Cursor, AmlError> {
+        let start = self.pos;
+        let len = self.pkg_length()?;
+        let end = start + len;
+        if end > self.aml.len() || end < self.pos {
+            return Err(AmlError::UnexpectedEnd);
+        }
+
+        let aml = self.aml;
+        let body = Cursor {
+            aml: &aml[..end],
+            pos: self.pos,
+        };
+        self.pos = end;
+        Ok(body)
+    }
+}
+
+/// The state of a running control method
+struct Frame {
+    /// The scope relative to which names are resolved
+    scope: NodeId,
+    locals: [AmlValue; 8],
+    args: [AmlValue; 7],
+    /// The value given to `Return`
+    ret: AmlValue,
+}
+
+impl Frame {
+    fn new(scope: NodeId) -> Self {
+        Self {
+            scope,
+            locals: [AmlValue::Uninitialized; 8],
+            args: [AmlValue::Uninitialized; 7],
+            ret: AmlValue::Uninitialized,
+        }
+    }
+}
+
+/// What to do once a term has been executed
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Flow {
+    Next,
+    Return,
+    Break,
+    Continue,
+}
+
+/// Where the result of an operator is stored
+#[derive(Debug, Clone, Copy)]
+enum Target {
+    /// The result is discarded
+    Null,
+    /// The result is printed
+    Debug,
+    Local(usize),
+    Arg(usize),
+    Node(NodeId),
+    Index {
+        target: IndexTarget,
+        index: u32,
+    },
+}
+
+/// Formats text into a fixed size buffer, dropping what does not fit
+struct TextBuffer {
+    buf: [u8; 64],
+    len: usize,
+}
+
+impl TextBuffer {
+    fn new() -> Self {
+        Self {
+            buf: [0; 64],
+            len: 0,
+        }
+    }
+
+    fn as_bytes(&self) -> &[u8] {
+        &self.buf[..self.len]
+    }
+}
+
+impl Write for TextBuffer {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        let len = s.len().min(self.buf.len() - self.len);
+        self.buf[self.len..self.len + len].copy_from_slice(&s.as_bytes()[..len]);
+        self.len += len;
+        Ok(())
+    }
+}
+
+// The counter the `Timer` operator reads
+#[derive(Clone, Copy)]
+enum ClockSource {
+    PmTimer(PmTimer),
+    Hpet(Hpet),
+}
+
+// A clock counting up from its first read, without wrapping around like the counters do
+#[derive(Clone, Copy)]
+struct Clock {
+    source: ClockSource,
+    // The value of the counter when the clock was last read, if it was
+    last: Option<u64>,
+    // The number of ticks counted since the first read
+    ticks: u64,
+}
+
+impl Clock {
+    // Sets up a clock on the PM timer, or on the HPET on platforms without one. The counters are
+    // not touched until the clock is read.
+    fn from_acpi(tables: &AcpiTables) -> Option<Self> {
+        let source = match PmTimer::from_acpi(tables) {
+            Ok(timer) => ClockSource::PmTimer(timer),
+            Err(_) => ClockSource::Hpet(Hpet::from_acpi(tables).ok()?),
+        };
+
+        Some(Self {
+            source,
+            last: None,
+            ticks: 0,
+        })
+    }
+
+    // Returns the time elapsed since the first read, in 100 ns units. It is only right if the
+    // clock is read at least once every wrap around period of the counter.
+    fn read_100ns(&mut self) -> u64 {
+        let (now, ns) = match self.source {
+            ClockSource::PmTimer(timer) => {
+                let now = timer.counter() as u64;
+                let elapsed = self
+                    .last
+                    .map_or(0, |last| timer.ticks_between(last as u32, now as u32));
+                self.ticks += elapsed as u64;
+                (now, timer.ticks_to_ns(self.ticks))
+            }
+            ClockSource::Hpet(hpet) => {
+                // The main counter does not run until the HPET is enabled
+                if self.last.is_none() {
+                    hpet.enable();
+                }
+                let now = hpet.counter();
+                self.ticks += self.last.map_or(0, |last| hpet.ticks_between(last, now));
+                (now, hpet.ticks_to_ns(self.ticks))
+            }
+        };
+
+        self.last = Some(now);
+        ns / 100
+    }
+}
+
+/// The AML interpreter, holding the namespace and the storage of all the values AML code
+/// manipulates
+pub struct Interpreter {
+    pub(super) namespace: Namespace,
+    pub(super) bytes: Arena<u8, MAX_BYTES>,
+    pub(super) values: Arena<AmlValue, MAX_VALUES>,
+    // Whether integers are 64 bits wide, which is the case starting with revision 2 of the DSDT
+    wide_integers: bool,
+    // Number of control methods being run
+    depth: usize,
+    // Nodes from this one on were created by the control methods being run, and are removed once
+    // the outermost method returns
+    transient_base: usize,
+    // The clock of the `Timer` operator, set up when the tables are loaded
+    clock: Option<Clock>,
+}
+
+// The interpreter is too large for the stack, so there is a single one, in a static
+struct InterpreterCell(UnsafeCell<Interpreter>);
+
+// Accesses to the interpreter are serialized by `INTERPRETER_LOCKED`
+unsafe impl Sync for InterpreterCell {}
+
+static INTERPRETER: InterpreterCell = InterpreterCell(UnsafeCell::new(Interpreter::new()));
+static INTERPRETER_LOCKED: AtomicBool = AtomicBool::new(false);
+
+/// Exclusive access to the interpreter, as returned by `Interpreter::lock`. Values returned by
+/// the interpreter are only valid while the guard is held, or until `release_values` is called.
+pub struct InterpreterGuard {
+    interpreter: &'static mut Interpreter,
+}
+
+impl Deref for InterpreterGuard {
+    type Target = Interpreter;
+
+    fn deref(&self) -> &Interpreter {
+        self.interpreter
+    }
+}
+
+impl DerefMut for InterpreterGuard {
+    fn deref_mut(&mut self) -> &mut Interpreter {
+        self.interpreter
+    }
+}
+
+impl Drop for InterpreterGuard {
+    fn drop(&mut self) {
+        self.interpreter.release_values();
+        INTERPRETER_LOCKED.store(false, Ordering::Release);
+    }
+}
+
+impl Interpreter {
+    const fn new() -> Self {
+        Self {
+            namespace: Namespace::new(),
+            bytes: Arena::new(0),
+            values: Arena::new(AmlValue::Uninitialized),
+            wide_integers: true,
+            depth: 0,
+            transient_base: usize::MAX,
+            clock: None,
+        }
+    }
+
+    /// Returns exclusive access to the interpreter, or `None` if it is already in use
+    pub fn lock() -> Option<InterpreterGuard> {
+        if INTERPRETER_LOCKED.swap(true, Ordering::Acquire) {
+            return None;
+        }
+
+        Some(InterpreterGuard {
+            interpreter: unsafe { &mut *INTERPRETER.0.get() },
+        })
+    }
+
+    pub fn namespace(&self) -> &Namespace {
+        &self.namespace
+    }
+
+    /// Empties the namespace, such that every test loads its tables from scratch
+    #[cfg(test)]
+    pub(crate) fn reset(&mut self) {
+        *self = Self::new();
+    }
+
+    /// Frees the strings, buffers and packages created while evaluating objects. Values returned
+    /// by previous evaluations must not be used afterwards.
+    pub fn release_values(&mut self) {
+        self.bytes.release_temporaries();
+        self.values.release_temporaries();
+    }
+
+    // Creates the objects every namespace starts with
+    fn add_predefined(&mut self) -> Result<(), AmlError> {
+        if !self.namespace.is_empty() {
+            return Ok(());
+        }
+
+        for scope in [b"_GPE", b"_PR_", b"_SB_", b"_SI_", b"_TZ_"] {
+            self.namespace.add(NodeId::ROOT, *scope, Object::Scope)?;
+        }
+
+        self.namespace
+            .add(NodeId::ROOT, *b"_OSI", Object::Builtin(Builtin::Osi, 1))?;
+        let os = self.bytes.copy_bytes(b"Microsoft Windows NT", true)?;
+        self.namespace
+            .add(NodeId::ROOT, *b"_OS_", Object::Name(AmlValue::String(os)))?;
+        self.namespace
+            .add(NodeId::ROOT, *b"_REV", Object::Name(AmlValue::Integer(2)))?;
+        self.namespace.add(NodeId::ROOT, *b"_GL_", Object::Mutex)?;
+
+        Ok(())
+    }
+
+    /// Loads the DSDT, as found through the FADT, and all the SSDTs into the namespace. The SSDTs
+    /// are loaded even if the DSDT cannot be, in which case its error is returned once they are.
+    /// SSDTs that fail to load are reported and skipped.
+    pub fn load_tables(&mut self, tables: &AcpiTables) -> Result<(), AmlError> {
+        self.clock = Clock::from_acpi(tables);
+
+        let dsdt_result = tables
+            .find::<FADT>()
+            .and_then(|fadt| fadt.dsdt_addr())
+            .ok_or(AmlError::NoDsdt)
+            .and_then(|dsdt_addr| self.load_table(dsdt_addr as usize));
+
+        for ssdt in tables.find_all(b"SSDT") {
+            if let Err(err) = self.load_table(ssdt.addr()) {
+                print!("Cannot load the SSDT at {:#x}: {:?}\n", ssdt.addr(), err);
+            }
+        }
+
+        dsdt_result
+    }
+
+    /// Loads the definition block found at `addr` into the namespace. Terms that fail to run are
+    /// reported and skipped, the objects defined by the other terms being kept. An error is only
+    /// returned when the table cannot be read, or when the length of a failing term cannot be
+    /// told, in which case the terms that follow it in its scope are left out.
+    pub fn load_table(&mut self, addr: usize) -> Result<(), AmlError> {
+        let (table, header) = read_table(addr).ok_or(AmlError::InvalidTable)?;
+
+        // The revision of the DSDT sets the width of integers for all definition blocks
+        if &header.signature == b"DSDT" {
+            self.wide_integers = header.revision() >= 2;
+        }
+
+        self.add_predefined()?;
+
+        let aml = &table[size_of::<DescriptionHeader>()..];
+
+        let mut frame = Frame::new(NodeId::ROOT);
+        let result = self.exec_term_list(&mut frame, Cursor::new(aml));
+        self.release_values();
+
+        result.map(|_| ())
+    }
+
+    /// Looks `path` up, from the root of the namespace
+    pub fn lookup(&self, path: &str) -> Option<NodeId> {
+        let name: AmlName = path.parse().ok()?;
+        self.namespace.lookup(NodeId::ROOT, &name)
+    }
+
+    /// Looks the child `name` of `parent` up. Unlike single segment names in AML code, the parent
+    /// scopes are not searched.
+    pub fn child(&self, parent: NodeId, name: &str) -> Option<NodeId> {
+        let name: AmlName = name.parse().ok()?;
+        match name.segs() {
+            [seg] if name.is_single_seg() => self.namespace.child(parent, *seg),
+            _ => self.namespace.lookup(parent, &name),
+        }
+    }
+
+    /// Evaluates the object at `path`, running it with `args` if it is a control method
+    pub fn evaluate(&mut self, path: &str, args: &[AmlValue]) -> Result<AmlValue, AmlError> {
+        let node = self.lookup(path).ok_or(AmlError::NameNotFound)?;
+        self.evaluate_node(node, args)
+    }
+
+    /// Evaluates the object `node`, running it with `args` if it is a control method
+    pub fn evaluate_node(&mut self, node: NodeId, args: &[AmlValue]) -> Result<AmlValue, AmlError> {
+        self.invoke(node, args)
+    }
+
+    /// Converts `value` to an integer
+    pub fn integer(&mut self, value: AmlValue) -> Result<u64, AmlError> {
+        self.to_integer(value)
+    }
+
+    /// Returns the contents of a string value
+    pub fn string(&self, value: AmlValue) -> Option<&[u8]> {
+        match value {
+            AmlValue::String(bytes) => Some(self.bytes.get(bytes)),
+            _ => None,
+        }
+    }
+
+    /// Returns the contents of a buffer value
+    pub fn buffer(&self, value: AmlValue) -> Option<&[u8]> {
+        match value {
+            AmlValue::Buffer(bytes) => Some(self.bytes.get(bytes)),
+            _ => None,
+        }
+    }
+
+    /// Returns the elements of a package value
+    pub fn package(&self, value: AmlValue) -> Option<&[AmlValue]> {
+        match value {
+            AmlValue::Package(elements) => Some(self.values.get(elements)),
+            _ => None,
+        }
+    }
+
+    /// Resolves a value that names an object: a reference, a name from a package or a path
+    /// string relative to `scope`
+    pub fn resolve(&self, scope: NodeId, value: AmlValue) -> Option<NodeId> {
+        match value {
+            AmlValue::Reference(node) => Some(node),
+            AmlValue::Name { path, scope } => {
+                let (name, _) = AmlName::parse(self.bytes.get(path)).ok()?;
+                self.namespace.lookup(scope, &name)
+            }
+            AmlValue::String(bytes) => {
+                let path = core::str::from_utf8(self.bytes.get(bytes)).ok()?;
+                let name: AmlName = path.parse().ok()?;
+                self.namespace.lookup(scope, &name)
+            }
+            _ => None,
+        }
+    }
+
+    // Returns the mask of the bits an integer holds
+    fn ones(&self) -> u64 {
+        if self.wide_integers {
+            u64::MAX
+        } else {
+            u32::MAX as u64
+        }
+    }
+
+    pub(super) fn integer_bits(&self) -> u32 {
+        if self.wide_integers {
+            64
+        } else {
+            32
+        }
+    }
+
+    // Returns whether the values stored in `node` must outlive the control methods being run
+    fn is_persistent_node(&self, node: NodeId) -> bool {
+        (node.0 as usize) < self.transient_base
+    }
+
+    // Runs `node`, if it is a control method, or returns its value
+    pub(super) fn invoke(&mut self, node: NodeId, args: &[AmlValue]) -> Result<AmlValue, AmlError> {
+        let (code, argc) = match *self.namespace.node(node).object() {
+            Object::Method { code, flags } => (code, (flags & 0b111) as usize),
+            Object::Builtin(Builtin::Osi, _) => return self.osi(args.first().copied()),
+            Object::Alias(target) => return self.invoke(target, args),
+            _ => return self.read_node(node),
+        };
+
+        if self.depth >= MAX_METHOD_DEPTH {
+            return Err(AmlError::MethodDepthExceeded);
+        }
+
+        let mut frame = Frame::new(node);
+        for (arg, value) in frame.args.iter_mut().zip(args.iter().take(argc)) {
+            *arg = *value;
+        }
+
+        // Objects created by the method only live until it returns
+        let namespace_len = self.namespace.len();
+        if self.depth == 0 {
+            self.transient_base = namespace_len;
+        }
+
+        self.depth += 1;
+        let result = self.exec_term_list(&mut frame, Cursor::new(code));
+        self.depth -= 1;
+
+        self.namespace.truncate(namespace_len);
+        if self.depth == 0 {
+            self.transient_base = usize::MAX;
+        }
+
+        result.map(|_| frame.ret)
+    }
+
+    // Implements `\_OSI`, returning Ones if the `interface` string is supported
+    fn osi(&mut self, interface: Option<AmlValue>) -> Result<AmlValue, AmlError> {
+        let interface = interface
+            .and_then(|value| self.string(value))
+            .ok_or(AmlError::TypeMismatch)?;
+
+        let supported = SUPPORTED_INTERFACES.contains(&interface);
+        Ok(AmlValue::Integer(if supported { self.ones() } else { 0 }))
+    }
+
+    // Returns the value of the object `node`. Objects that do not hold a value are returned as
+    // references to themselves.
+    pub(super) fn read_node(&mut self, node: NodeId) -> Result<AmlValue, AmlError> {
+        match *self.namespace.node(node).object() {
+            Object::Name(value) => Ok(value),
+            Object::Field(field) => self.read_field(field),
+            Object::BufferField {
+                data,
+                bit_offset,
+                bit_len,
+            } => self.read_buffer_field(data, bit_offset, bit_len),
+            Object::Alias(target) => self.read_node(target),
+            Object::Method { .. } | Object::Builtin(..) => self.invoke(node, &[]),
+            Object::External { .. } => Err(AmlError::NameNotFound),
+            _ => Ok(AmlValue::Reference(node)),
+        }
+    }
+
+    // Runs all the terms of `cur`
+    fn exec_term_list(&mut self, frame: &mut Frame, mut cur: Cursor) -> Result<Flow, AmlError> {
+        while !cur.is_empty() {
+            let start = cur;
+            let flow = match self.exec_term(frame, &mut cur) {
+                Ok(flow) => flow,
+                // While loading a table, a term that fails is skipped, such that the objects
+                // after it still get defined. This needs to know where the term ends, which its
+                // package length tells if it has one. Otherwise the term was decoded up to where
+                // it failed, unless the error is about its encoding.
+                Err(err) if self.depth == 0 => {
+                    let decoded = !matches!(
+                        err,
+                        AmlError::UnexpectedEnd
+                            | AmlError::InvalidName
+                            | AmlError::UnknownOpcode(_)
+                    );
+                    match start.package_end() {
+                        Some(end) => cur.pos = end,
+                        None if decoded && cur.pos > start.pos => {}
+                        None => return Err(err),
+                    }
+                    print!(
+                        "Skipping the AML term at offset {:#x}, opcode {:#04x}: {:?}\n",
+                        start.pos, start.aml[start.pos], err
+                    );
+                    self.release_values();
+                    continue;
+                }
+                Err(err) => return Err(err),
+            };
+
+            // Outside of control methods, the values created by a term are not used by the
+            // following ones
+            if self.depth == 0 {
+                self.release_values();
+            }
+
+            if flow != Flow::Next {
+                return Ok(flow);
+            }
+        }
+        Ok(Flow::Next)
+    }
+
+    // Runs the terms of `body`, with `scope` as the current scope
+    fn exec_scope(
+        &mut self,
+        frame: &mut Frame,
+        scope: NodeId,
+        body: Cursor,
+    ) -> Result<Flow, AmlError> {
+        let previous = frame.scope;
+        frame.scope = scope;
+        let result = self.exec_term_list(frame, body);
+        frame.scope = previous;
+        result
+    }
+
+    // Creates `object`, named `name` relative to the current scope
+    fn add_object(
+        &mut self,
+        frame: &Frame,
+        name: &AmlName,
+        object: Object,
+    ) -> Result<NodeId, AmlError> {
+        let (parent, seg) = self.namespace.parent_for(frame.scope, name)?;
+        self.namespace.add(parent, seg, object)
+    }
+
+    // Runs a single term, which is either an object definition, a statement or an expression
+    // whose result is discarded
+    fn exec_term(&mut self, frame: &mut Frame, cur: &mut Cursor) -> Result<Flow, AmlError> {
+        match cur.peek()? {
+            opcode::ALIAS => {
+                cur.skip(1);
+                let source = cur.name()?;
+                let alias = cur.name()?;
+                let target = self
+                    .namespace
+                    .lookup(frame.scope, &source)
+                    .ok_or(AmlError::NameNotFound)?;
+                self.add_object(frame, &alias, Object::Alias(target))?;
+            }
+            opcode::NAME => {
+                cur.skip(1);
+                let name = cur.name()?;
+                let value = self.eval_term_arg(frame, cur)?;
+                let node = self.add_object(frame, &name, Object::Name(AmlValue::Uninitialized))?;
+                let value = self.copy_value(value, self.is_persistent_node(node))?;
+                self.namespace.set_object(node, Object::Name(value));
+            }
+            opcode::SCOPE => {
+                cur.skip(1);
+                let mut body = cur.package()?;
+                let name = body.name()?;
+                let scope = self
+                    .namespace
+                    .lookup(frame.scope, &name)
+                    .ok_or(AmlError::NameNotFound)?;
+                return self.exec_scope(frame, scope, body);
+            }
+            opcode::METHOD => {
+                cur.skip(1);
+                let mut body = cur.package()?;
+                let name = body.name()?;
+                let flags = body.byte()?;
+                let code = body.remaining();
+                self.add_object(frame, &name, Object::Method { code, flags })?;
+            }
+            opcode::EXTERNAL => {
+                cur.skip(1);
+                let name = cur.name()?;
+                let object_type = cur.byte()?;
+                let argc = cur.byte()?;
+                // The object may be defined in a table that is not loaded yet, so its parent
+                // scopes may not exist either
+                if let Ok((parent, seg)) = self.namespace.parent_for(frame.scope, &name) {
+                    self.namespace
+                        .add(parent, seg, Object::External { object_type, argc })?;
+                }
+            }
+            opcode::CREATE_BIT_FIELD => {
+                cur.skip(1);
+                self.def_create_field(frame, cur, Some(1), true)?;
+            }
+            opcode::CREATE_BYTE_FIELD => {
+                cur.skip(1);
+                self.def_create_field(frame, cur, Some(8), false)?;
+            }
+            opcode::CREATE_WORD_FIELD => {
+                cur.skip(1);
+                self.def_create_field(frame, cur, Some(16), false)?;
+            }
+            opcode::CREATE_DWORD_FIELD => {
+                cur.skip(1);
+                self.def_create_field(frame, cur, Some(32), false)?;
+            }
+            opcode::CREATE_QWORD_FIELD => {
+                cur.skip(1);
+                self.def_create_field(frame, cur, Some(64), false)?;
+            }
+            opcode::IF => {
+                cur.skip(1);
+                let mut body = cur.package()?;
+                let predicate = self.eval_integer(frame, &mut body)?;
+
+                // Find the optional Else that follows, and skip over it
+                let else_body = if cur.peek().ok() == Some(opcode::ELSE) {
+                    cur.skip(1);
+                    Some(cur.package()?)
+                } else {
+                    None
+                };
+
+                if predicate != 0 {
+                    return self.exec_term_list(frame, body);
+                } else if let Some(else_body) = else_body {
+                    return self.exec_term_list(frame, else_body);
+                }
+            }
+            opcode::ELSE => {
+                // An Else that does not follow an If
+                cur.skip(1);
+                cur.package()?;
+            }
+            opcode::WHILE => {
+                cur.skip(1);
+                let body = cur.package()?;
+                return self.exec_while(frame, body);
+            }
+            opcode::RETURN => {
+                cur.skip(1);
+                frame.ret = self.eval_term_arg(frame, cur)?;
+                return Ok(Flow::Return);
+            }
+            opcode::BREAK => {
+                cur.skip(1);
+                return Ok(Flow::Break);
+            }
+            opcode::CONTINUE => {
+                cur.skip(1);
+                return Ok(Flow::Continue);
+            }
+            opcode::NOOP | opcode::BREAKPOINT => cur.skip(1),
+            opcode::NOTIFY => {
+                cur.skip(1);
+                // We do not install notify handlers, so notifications are dropped
+                self.parse_target(frame, cur)?;
+                self.eval_integer(frame, cur)?;
+            }
+            opcode::EXT_PREFIX => return self.exec_ext_term(frame, cur),
+            _ => {
+                self.eval_term_arg(frame, cur)?;
+            }
+        }
+
+        Ok(Flow::Next)
+    }
+
+    // Runs a term whose opcode starts with the extended opcode prefix
+    fn exec_ext_term(&mut self, frame: &mut Frame, cur: &mut Cursor) -> Result<Flow, AmlError> {
+        let op = cur.peek_at(1).ok_or(AmlError::UnexpectedEnd)?;
+
+        match op {
+            ext_opcode::MUTEX => {
+                cur.skip(2);
+                let name = cur.name()?;
+                cur.byte()?;
+                self.add_object(frame, &name, Object::Mutex)?;
+            }
+            ext_opcode::EVENT => {
+                cur.skip(2);
+                let name = cur.name()?;
+                self.add_object(frame, &name, Object::Event)?;
+            }
+            ext_opcode::CREATE_FIELD => {
+                cur.skip(2);
+                self.def_create_field(frame, cur, None, true)?;
+            }
+            ext_opcode::OP_REGION => {
+                cur.skip(2);
+                let name = cur.name()?;
+                let space = cur.byte()?;
+                let offset = self.eval_integer(frame, cur)?;
+                let length = self.eval_integer(frame, cur)?;
+                self.add_object(
+                    frame,
+                    &name,
+                    Object::OpRegion {
+                        space,
+                        offset,
+                        length,
+                    },
+                )?;
+            }
+            ext_opcode::DATA_REGION => {
+                cur.skip(2);
+                self.def_data_region(frame, cur)?;
+            }
+            ext_opcode::FIELD => {
+                cur.skip(2);
+                let mut body = cur.package()?;
+                let name = body.name()?;
+                let region = self
+                    .namespace
+                    .lookup(frame.scope, &name)
+                    .ok_or(AmlError::NameNotFound)?;
+                let flags = body.byte()?;
+                self.def_field_list(frame, &mut body, FieldKind::Region(region), flags)?;
+            }
+            ext_opcode::INDEX_FIELD => {
+                cur.skip(2);
+                let mut body = cur.package()?;
+                let index_name = body.name()?;
+                let data_name = body.name()?;
+                let index = self
+                    .namespace
+                    .lookup(frame.scope, &index_name)
+                    .ok_or(AmlError::NameNotFound)?;
+                let data = self
+                    .namespace
+                    .lookup(frame.scope, &data_name)
+                    .ok_or(AmlError::NameNotFound)?;
+                let flags = body.byte()?;
+                self.def_field_list(frame, &mut body, FieldKind::Index { index, data }, flags)?;
+            }
+            ext_opcode::BANK_FIELD => {
+                cur.skip(2);
+                let mut body = cur.package()?;
+                let region_name = body.name()?;
+                let bank_name = body.name()?;
+                let region = self
+                    .namespace
+                    .lookup(frame.scope, &region_name)
+                    .ok_or(AmlError::NameNotFound)?;
+                let bank = self
+                    .namespace
+                    .lookup(frame.scope, &bank_name)
+                    .ok_or(AmlError::NameNotFound)?;
+                let value = self.eval_integer(frame, &mut body)?;
+                let flags = body.byte()?;
+                let kind = FieldKind::Bank {
+                    region,
+                    bank,
+                    value,
+                };
+                self.def_field_list(frame, &mut body, kind, flags)?;
+            }
+            ext_opcode::DEVICE | ext_opcode::THERMAL_ZONE => {
+                cur.skip(2);
+                let mut body = cur.package()?;
+                let name = body.name()?;
+                let object = match op {
+                    ext_opcode::DEVICE => Object::Device,
+                    _ => Object::ThermalZone,
+                };
+                let node = self.add_object(frame, &name, object)?;
+                return self.exec_scope(frame, node, body);
+            }
+            ext_opcode::PROCESSOR => {
+                cur.skip(2);
+                let mut body = cur.package()?;
+                let name = body.name()?;
+                let id = body.byte()?;
+                let pblk_addr = body.dword()?;
+                let pblk_len = body.byte()?;
+                let processor = Object::Processor {
+                    id,
+                    pblk_addr,
+                    pblk_len,
+                };
+                let node = self.add_object(frame, &name, processor)?;
+                return self.exec_scope(frame, node, body);
+            }
+            ext_opcode::POWER_RES => {
+                cur.skip(2);
+                let mut body = cur.package()?;
+                let name = body.name()?;
+                // The system level and the resource order
+                body.byte()?;
+                body.word()?;
+                let node = self.add_object(frame, &name, Object::PowerResource)?;
+                return self.exec_scope(frame, node, body);
+            }
+            ext_opcode::STALL => {
+                cur.skip(2);
+                let microseconds = self.eval_integer(frame, cur)?;
+                delay_us(microseconds);
+            }
+            ext_opcode::SLEEP => {
+                cur.skip(2);
+                let milliseconds = self.eval_integer(frame, cur)?;
+                delay_us(milliseconds.saturating_mul(1000));
+            }
+            ext_opcode::SIGNAL | ext_opcode::RESET | ext_opcode::RELEASE => {
+                // There is a single thread running AML code, so events and mutexes are no-ops
+                cur.skip(2);
+                self.parse_target(frame, cur)?;
+            }
+            ext_opcode::FATAL => {
+                cur.skip(2);
+                let fatal_type = cur.byte()?;
+                let code = cur.dword()?;
+                let arg = self.eval_integer(frame, cur)?;
+                return Err(AmlError::Fatal {
+                    fatal_type,
+                    code,
+                    arg,
+                });
+            }
+            ext_opcode::LOAD | ext_opcode::LOAD_TABLE | ext_opcode::UNLOAD => {
+                return Err(AmlError::UnknownOpcode(0x5B00 | op as u16));
+            }
+            _ => {
+                self.eval_term_arg(frame, cur)?;
+            }
+        }
+
+        Ok(Flow::Next)
+    }
+
+    // Runs the `While` loop whose predicate and body are in `body`
+    fn exec_while(&mut self, frame: &mut Frame, body: Cursor) -> Result<Flow, AmlError> {
+        for _ in 0..MAX_LOOP_ITERATIONS {
+            let mut cur = body;
+            if self.eval_integer(frame, &mut cur)? == 0 {
+                return Ok(Flow::Next);
+            }
+
+            match self.exec_term_list(frame, cur)? {
+                Flow::Break => return Ok(Flow::Next),
+                Flow::Return => return Ok(Flow::Return),
+                Flow::Next | Flow::Continue => {}
+            }
+        }
+
+        Err(AmlError::LoopTimeout)
+    }
+
+    // Creates the fields of a `Field`, `IndexField` or `BankField` list
+    fn def_field_list(
+        &mut self,
+        frame: &Frame,
+        body: &mut Cursor,
+        kind: FieldKind,
+        mut flags: u8,
+    ) -> Result<(), AmlError> {
+        let mut bit_offset = 0u32;
+
+        while !body.is_empty() {
+            match body.peek()? {
+                // ReservedField
+                0x00 => {
+                    body.skip(1);
+                    bit_offset += body.pkg_length()? as u32;
+                }
+                // AccessField, which changes the access type of the following fields
+                0x01 => {
+                    body.skip(1);
+                    let access_type = body.byte()?;
+                    body.byte()?;
+                    flags = (flags & !0xF) | (access_type & 0xF);
+                }
+                // ConnectField, only used by GPIO and serial bus regions
+                0x02 => {
+                    body.skip(1);
+                    if body.peek()? == opcode::BUFFER {
+                        body.skip(1);
+                        body.package()?;
+                    } else {
+                        body.name()?;
+                    }
+                }
+                // ExtendedAccessField
+                0x03 => {
+                    body.skip(1);
+                    let access_type = body.byte()?;
+                    body.bytes(2)?;
+                    flags = (flags & !0xF) | (access_type & 0xF);
+                }
+                _ => {
+                    let name = body.bytes(4)?.try_into().unwrap();
+                    let bit_len = body.pkg_length()? as u32;
+                    let field = FieldUnit {
+                        kind,
+                        bit_offset,
+                        bit_len,
+                        flags,
+                    };
+                    self.namespace
+                        .add(frame.scope, name, Object::Field(field))?;
+                    bit_offset += bit_len;
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    // Creates a buffer field. `bit_len` is `None` for `CreateField`, which gives the size of the
+    // field as an operand, and `bit_index` tells whether the index operand counts bits or bytes.
+    fn def_create_field(
+        &mut self,
+        frame: &mut Frame,
+        cur: &mut Cursor,
+        bit_len: Option<u32>,
+        bit_index: bool,
+    ) -> Result<(), AmlError> {
+        let source = self.eval_term_arg(frame, cur)?;
+        let index = self.eval_integer(frame, cur)? as u32;
+        let bit_len = match bit_len {
+            Some(bit_len) => bit_len,
+            None => self.eval_integer(frame, cur)? as u32,
+        };
+        let name = cur.name()?;
+
+        let data = match source {
+            AmlValue::Buffer(data) => data,
+            _ => return Err(AmlError::TypeMismatch),
+        };
+        let bit_offset = if bit_index { index } else { index * 8 };
+        if bit_offset as usize + bit_len as usize > data.len() * 8 {
+            return Err(AmlError::InvalidArgument);
+        }
+
+        let field = Object::BufferField {
+            data,
+            bit_offset,
+            bit_len,
+        };
+        self.add_object(frame, &name, field)?;
+        Ok(())
+    }
+
+    // Creates a `DataTableRegion`, which is a system memory region covering an ACPI table
+    fn def_data_region(&mut self, frame: &mut Frame, cur: &mut Cursor) -> Result<(), AmlError> {
+        let name = cur.name()?;
+        let signature = self.eval_term_arg(frame, cur)?;
+        let oem_id = self.eval_term_arg(frame, cur)?;
+        let oem_table_id = self.eval_term_arg(frame, cur)?;
+
+        let signature = self.string(signature).ok_or(AmlError::TypeMismatch)?;
+        let oem_id = self.string(oem_id).ok_or(AmlError::TypeMismatch)?;
+        let oem_table_id = self.string(oem_table_id).ok_or(AmlError::TypeMismatch)?;
+
+        // Empty OEM IDs match any table
+        let matches = |field: &[u8], wanted: &[u8]| {
+            wanted.is_empty()
+                || field
+                    .iter()
+                    .take_while(|byte| **byte != 0)
+                    .eq(wanted.iter())
+        };
+
+        let tables = AcpiTables::get().ok_or(AmlError::NameNotFound)?;
+        let table = tables
+            .iter()
+            .find(|table| {
+                let header = table.header();
+                table.signature().as_slice() == signature
+                    && matches(&header.oem_id(), oem_id)
+                    && matches(&header.oem_table_id(), oem_table_id)
+            })
+            .ok_or(AmlError::NameNotFound)?;
+
+        let region = Object::OpRegion {
+            space: address_space::SYSTEM_MEMORY,
+            offset: table.addr() as u64,
+            length: table.length() as u64,
+        };
+        self.add_object(frame, &name, region)?;
+        Ok(())
+    }
+
+    fn eval_integer(&mut self, frame: &mut Frame, cur: &mut Cursor) -> Result<u64, AmlError> {
+        let value = self.eval_term_arg(frame, cur)?;
+        self.to_integer(value)
+    }
+
+    // Evaluates a TermArg: a data object, a local or argument, a name, or an expression
+    fn eval_term_arg(&mut self, frame: &mut Frame, cur: &mut Cursor) -> Result<AmlValue, AmlError> {
+        let op = cur.peek()?;
+        if op != opcode::ZERO && name::is_name_start(op) {
+            return self.eval_name(frame, cur);
+        }
+        cur.skip(1);
+
+        let value = match op {
+            opcode::ZERO => AmlValue::Integer(0),
+            opcode::ONE => AmlValue::Integer(1),
+            opcode::ONES => AmlValue::Integer(self.ones()),
+            opcode::BYTE_PREFIX => AmlValue::Integer(cur.byte()? as u64),
+            opcode::WORD_PREFIX => AmlValue::Integer(cur.word()? as u64),
+            opcode::DWORD_PREFIX => AmlValue::Integer(cur.dword()? as u64),
+            opcode::QWORD_PREFIX => AmlValue::Integer(cur.qword()? & self.ones()),
+            opcode::STRING_PREFIX => {
+                let len = cur
+                    .remaining()
+                    .iter()
+                    .position(|byte| *byte == 0)
+                    .ok_or(AmlError::UnexpectedEnd)?;
+                let string = cur.bytes(len)?;
+                cur.skip(1);
+                AmlValue::String(self.bytes.copy_bytes(string, false)?)
+            }
+            opcode::BUFFER => {
+                let mut body = cur.package()?;
+                let size = self.eval_integer(frame, &mut body)? as usize;
+                let init = body.remaining();
+                let data = self.bytes.alloc_bytes(size.max(init.len()), false)?;
+                self.bytes.get_mut(data)[..init.len()].copy_from_slice(init);
+                AmlValue::Buffer(data)
+            }
+            opcode::PACKAGE | opcode::VAR_PACKAGE => {
+                let mut body = cur.package()?;
+                let count = match op {
+                    opcode::PACKAGE => body.byte()? as usize,
+                    _ => self.eval_integer(frame, &mut body)? as usize,
+                };
+                let elements = self.values.alloc_elements(count, false)?;
+                let mut idx = 0;
+                while !body.is_empty() {
+                    let element = self.eval_package_element(frame, &mut body)?;
+                    if idx < count {
+                        self.values.get_mut(elements)[idx] = element;
+                    }
+                    idx += 1;
+                }
+                AmlValue::Package(elements)
+            }
+            opcode::LOCAL0..=opcode::LOCAL7 => frame.locals[(op - opcode::LOCAL0) as usize],
+            opcode::ARG0..=opcode::ARG6 => frame.args[(op - opcode::ARG0) as usize],
+            opcode::STORE => {
+                let value = self.eval_term_arg(frame, cur)?;
+                let target = self.parse_target(frame, cur)?;
+                self.store(frame, target, value)?;
+                value
+            }
+            opcode::COPY_OBJECT => {
+                let value = self.eval_term_arg(frame, cur)?;
+                let target = self.parse_target(frame, cur)?;
+                self.copy_object(frame, target, value)?;
+                value
+            }
+            opcode::ADD
+            | opcode::SUBTRACT
+            | opcode::MULTIPLY
+            | opcode::SHIFT_LEFT
+            | opcode::SHIFT_RIGHT
+            | opcode::AND
+            | opcode::NAND
+            | opcode::OR
+            | opcode::NOR
+            | opcode::XOR
+            | opcode::MOD => self.eval_binary(frame, cur, op)?,
+            opcode::NOT
+            | opcode::FIND_SET_LEFT_BIT
+            | opcode::FIND_SET_RIGHT_BIT
+            | opcode::TO_INTEGER
+            | opcode::TO_BUFFER
+            | opcode::TO_DECIMAL_STRING
+            | opcode::TO_HEX_STRING => self.eval_unary(frame, cur, op as u16)?,
+            opcode::INCREMENT | opcode::DECREMENT => {
+                let target = self.parse_target(frame, cur)?;
+                let value = self.read_target(frame, target)?;
+                let value = self.to_integer(value)?;
+                let result = match op {
+                    opcode::INCREMENT => value.wrapping_add(1),
+                    _ => value.wrapping_sub(1),
+                };
+                let result = AmlValue::Integer(result & self.ones());
+                self.store(frame, target, result)?;
+                result
+            }
+            opcode::DIVIDE => {
+                let dividend = self.eval_integer(frame, cur)?;
+                let divisor = self.eval_integer(frame, cur)?;
+                let remainder_target = self.parse_target(frame, cur)?;
+                let quotient_target = self.parse_target(frame, cur)?;
+                if divisor == 0 {
+                    return Err(AmlError::InvalidArgument);
+                }
+                let quotient = AmlValue::Integer(dividend / divisor);
+                self.store(
+                    frame,
+                    remainder_target,
+                    AmlValue::Integer(dividend % divisor),
+                )?;
+                self.store(frame, quotient_target, quotient)?;
+                quotient
+            }
+            opcode::LAND | opcode::LOR => {
+                let lhs = self.eval_integer(frame, cur)? != 0;
+                let rhs = self.eval_integer(frame, cur)? != 0;
+                let result = match op {
+                    opcode::LAND => lhs && rhs,
+                    _ => lhs || rhs,
+                };
+                self.boolean(result)
+            }
+            opcode::LNOT => {
+                let operand = self.eval_integer(frame, cur)?;
+                self.boolean(operand == 0)
+            }
+            opcode::LEQUAL | opcode::LGREATER | opcode::LLESS => {
+                let lhs = self.eval_term_arg(frame, cur)?;
+                let rhs = self.eval_term_arg(frame, cur)?;
+                let ordering = self.compare(lhs, rhs)?;
+                let result = match op {
+                    opcode::LEQUAL => ordering == CmpOrdering::Equal,
+                    opcode::LGREATER => ordering == CmpOrdering::Greater,
+                    _ => ordering == CmpOrdering::Less,
+                };
+                self.boolean(result)
+            }
+            opcode::CONCAT => {
+                let lhs = self.eval_term_arg(frame, cur)?;
+                let rhs = self.eval_term_arg(frame, cur)?;
+                let target = self.parse_target(frame, cur)?;
+                let result = self.concat(lhs, rhs)?;
+                self.store(frame, target, result)?;
+                result
+            }
+            opcode::CONCAT_RES => {
+                let lhs = self.eval_term_arg(frame, cur)?;
+                let rhs = self.eval_term_arg(frame, cur)?;
+                let target = self.parse_target(frame, cur)?;
+                let result = self.concat_resources(lhs, rhs)?;
+                self.store(frame, target, result)?;
+                result
+            }
+            opcode::TO_STRING => {
+                let source = self.eval_term_arg(frame, cur)?;
+                let length = self.eval_integer(frame, cur)? as usize;
+                let target = self.parse_target(frame, cur)?;
+                let bytes = match source {
+                    AmlValue::Buffer(bytes) => bytes,
+                    _ => return Err(AmlError::TypeMismatch),
+                };
+                // The string stops at the first null character
+                let data = self.bytes.get(bytes);
+                let len = data
+                    .iter()
+                    .take(length)
+                    .position(|byte| *byte == 0)
+                    .unwrap_or(data.len().min(length));
+                let result = AmlValue::String(self.bytes.duplicate(bytes.sub(0, len), false)?);
+                self.store(frame, target, result)?;
+                result
+            }
+            opcode::MID => {
+                let source = self.eval_term_arg(frame, cur)?;
+                let index = self.eval_integer(frame, cur)? as usize;
+                let length = self.eval_integer(frame, cur)? as usize;
+                let target = self.parse_target(frame, cur)?;
+                let result = match source {
+                    AmlValue::String(bytes) => {
+                        AmlValue::String(self.bytes.duplicate(bytes.sub(index, length), false)?)
+                    }
+                    AmlValue::Buffer(bytes) => {
+                        AmlValue::Buffer(self.bytes.duplicate(bytes.sub(index, length), false)?)
+                    }
+                    _ => return Err(AmlError::TypeMismatch),
+                };
+                self.store(frame, target, result)?;
+                result
+            }
+            opcode::REF_OF => {
+                let target = self.parse_target(frame, cur)?;
+                self.reference_to(target)?
+            }
+            opcode::DEREF_OF => {
+                let reference = self.eval_term_arg(frame, cur)?;
+                self.deref(frame.scope, reference)?
+            }
+            opcode::INDEX => {
+                let source = self.eval_term_arg(frame, cur)?;
+                let index = self.eval_integer(frame, cur)?;
+                let target = self.parse_target(frame, cur)?;
+                let (target_ref, len) = match source {
+                    AmlValue::Buffer(bytes) | AmlValue::String(bytes) => {
+                        (IndexTarget::Buffer(bytes), bytes.len())
+                    }
+                    AmlValue::Package(elements) => (IndexTarget::Package(elements), elements.len()),
+                    _ => return Err(AmlError::TypeMismatch),
+                };
+                if index >= len as u64 {
+                    return Err(AmlError::InvalidArgument);
+                }
+                let result = AmlValue::Index {
+                    target: target_ref,
+                    index: index as u32,
+                };
+                self.store(frame, target, result)?;
+                result
+            }
+            opcode::MATCH => self.eval_match(frame, cur)?,
+            opcode::SIZE_OF => {
+                let target = self.parse_target(frame, cur)?;
+                let size = match self.read_target(frame, target)? {
+                    AmlValue::String(bytes) | AmlValue::Buffer(bytes) => bytes.len(),
+                    AmlValue::Package(elements) => elements.len(),
+                    _ => return Err(AmlError::TypeMismatch),
+                };
+                AmlValue::Integer(size as u64)
+            }
+            opcode::OBJECT_TYPE => {
+                let target = self.parse_target(frame, cur)?;
+                AmlValue::Integer(self.object_type(frame, target))
+            }
+            opcode::EXT_PREFIX => return self.eval_ext_term_arg(frame, cur),
+            _ => return Err(AmlError::UnknownOpcode(op as u16)),
+        };
+
+        Ok(value)
+    }
+
+    // Evaluates a TermArg whose opcode starts with the extended opcode prefix
+    fn eval_ext_term_arg(
+        &mut self,
+        frame: &mut Frame,
+        cur: &mut Cursor,
+    ) -> Result<AmlValue, AmlError> {
+        let op = cur.byte()?;
+
+        let value = match op {
+            ext_opcode::COND_REF_OF => {
+                // The operand is usually a name that may not exist, which is not an error here
+                let exists = match cur.peek()? {
+                    byte if byte != opcode::ZERO && name::is_name_start(byte) => {
+                        let mut lookahead = *cur;
+                        let name = lookahead.name()?;
+                        self.namespace.lookup(frame.scope, &name).is_some()
+                    }
+                    _ => true,
+                };
+
+                if !exists {
+                    cur.name()?;
+                    self.parse_target(frame, cur)?;
+                    return Ok(AmlValue::Integer(0));
+                }
+
+                let source = self.parse_target(frame, cur)?;
+                let target = self.parse_target(frame, cur)?;
+                let reference = self.reference_to(source)?;
+                self.store(frame, target, reference)?;
+                AmlValue::Integer(self.ones())
+            }
+            ext_opcode::ACQUIRE => {
+                self.parse_target(frame, cur)?;
+                cur.word()?;
+                // There is a single thread running AML code, so the mutex is always acquired
+                AmlValue::Integer(0)
+            }
+            ext_opcode::WAIT => {
+                self.parse_target(frame, cur)?;
+                self.eval_integer(frame, cur)?;
+                AmlValue::Integer(0)
+            }
+            ext_opcode::FROM_BCD | ext_opcode::TO_BCD => {
+                self.eval_unary(frame, cur, 0x5B00 | op as u16)?
+            }
+            ext_opcode::REVISION => AmlValue::Integer(INTERPRETER_REVISION),
+            ext_opcode::TIMER => {
+                let clock = self.clock.as_mut().ok_or(AmlError::NoTimer)?;
+                AmlValue::Integer(clock.read_100ns())
+            }
+            _ => return Err(AmlError::UnknownOpcode(0x5B00 | op as u16)),
+        };
+
+        Ok(value)
+    }
+
+    // Evaluates a name, running the object it refers to if it is a control method
+    fn eval_name(&mut self, frame: &mut Frame, cur: &mut Cursor) -> Result<AmlValue, AmlError> {
+        let name = cur.name()?;
+        let node = self
+            .namespace
+            .lookup(frame.scope, &name)
+            .ok_or(AmlError::NameNotFound)?;
+
+        let argc = match self.namespace.node(node).object().argc() {
+            Some(argc) => argc,
+            None => return self.read_node(node),
+        };
+
+        let mut args = [AmlValue::Uninitialized; 7];
+        for arg in args.iter_mut().take(argc) {
+            *arg = self.eval_term_arg(frame, cur)?;
+        }
+
+        self.invoke(node, &args[..argc])
+    }
+
+    // Evaluates a package element. Names are not evaluated, but stored as references.
+    fn eval_package_element(
+        &mut self,
+        frame: &mut Frame,
+        cur: &mut Cursor,
+    ) -> Result<AmlValue, AmlError> {
+        let op = cur.peek()?;
+        if op == opcode::ZERO || !name::is_name_start(op) {
+            return self.eval_term_arg(frame, cur);
+        }
+
+        let (name, encoded) = cur.name_with_bytes()?;
+        match self.namespace.lookup(frame.scope, &name) {
+            Some(node) => Ok(AmlValue::Reference(node)),
+            // The object may be defined later on, so we keep its name
+            None => Ok(AmlValue::Name {
+                path: self.bytes.copy_bytes(encoded, false)?,
+                scope: frame.scope,
+            }),
+        }
+    }
+
+    // Evaluates an operator taking two integer operands and a target
+    fn eval_binary(
+        &mut self,
+        frame: &mut Frame,
+        cur: &mut Cursor,
+        op: u8,
+    ) -> Result<AmlValue, AmlError> {
+        let lhs = self.eval_integer(frame, cur)?;
+        let rhs = self.eval_integer(frame, cur)?;
+        let target = self.parse_target(frame, cur)?;
+
+        let result = match op {
+            opcode::ADD => lhs.wrapping_add(rhs),
+            opcode::SUBTRACT => lhs.wrapping_sub(rhs),
+            opcode::MULTIPLY => lhs.wrapping_mul(rhs),
+            opcode::SHIFT_LEFT => lhs
+                .checked_shl(rhs as u32)
+                .filter(|_| rhs < 64)
+                .unwrap_or(0),
+            opcode::SHIFT_RIGHT => lhs
+                .checked_shr(rhs as u32)
+                .filter(|_| rhs < 64)
+                .unwrap_or(0),
+            opcode::AND => lhs & rhs,
+            opcode::NAND => !(lhs & rhs),
+            opcode::OR => lhs | rhs,
+            opcode::NOR => !(lhs | rhs),
+            opcode::XOR => lhs ^ rhs,
+            opcode::MOD => lhs.checked_rem(rhs).ok_or(AmlError::InvalidArgument)?,
+            _ => unreachable!(),
+        };
+
+        let result = AmlValue::Integer(result & self.ones());
+        self.store(frame, target, result)?;
+        Ok(result)
+    }
+
+    // Evaluates an operator taking one operand and a target. Extended opcodes are passed with
+    // their prefix, in the high byte.
+    fn eval_unary(
+        &mut self,
+        frame: &mut Frame,
+        cur: &mut Cursor,
+        op: u16,
+    ) -> Result<AmlValue, AmlError> {
+        let operand = self.eval_term_arg(frame, cur)?;
+        let target = self.parse_target(frame, cur)?;
+        let ones = self.ones();
+
+        let result = match op {
+            op if op == opcode::NOT as u16 => AmlValue::Integer(!self.to_integer(operand)? & ones),
+            op if op == opcode::FIND_SET_LEFT_BIT as u16 => {
+                let value = self.to_integer(operand)?;
+                AmlValue::Integer(match value {
+                    0 => 0,
+                    value => (u64::BITS - value.leading_zeros()) as u64,
+                })
+            }
+            op if op == opcode::FIND_SET_RIGHT_BIT as u16 => {
+                let value = self.to_integer(operand)?;
+                AmlValue::Integer(match value {
+                    0 => 0,
+                    value => (value.trailing_zeros() + 1) as u64,
+                })
+            }
+            op if op == opcode::TO_INTEGER as u16 => match operand {
+                AmlValue::String(bytes) => {
+                    AmlValue::Integer(parse_integer(self.bytes.get(bytes), true) & ones)
+                }
+                _ => AmlValue::Integer(self.to_integer(operand)?),
+            },
+            op if op == opcode::TO_BUFFER as u16 => match operand {
+                AmlValue::String(bytes) => {
+                    // The terminating null character is part of the buffer
+                    let data = self.bytes.alloc_bytes(bytes.len() + 1, false)?;
+                    self.bytes.copy_into(bytes, data);
+                    AmlValue::Buffer(data)
+                }
+                _ => AmlValue::Buffer(self.to_buffer(operand)?),
+            },
+            op if op == opcode::TO_DECIMAL_STRING as u16 || op == opcode::TO_HEX_STRING as u16 => {
+                let decimal = op == opcode::TO_DECIMAL_STRING as u16;
+                AmlValue::String(self.to_string(operand, decimal)?)
+            }
+            op if op == 0x5B00 | ext_opcode::FROM_BCD as u16 => {
+                let mut value = self.to_integer(operand)?;
+                let mut result = 0;
+                let mut scale = 1u64;
+                while value != 0 {
+                    result += (value & 0xF) * scale;
+                    scale = scale.wrapping_mul(10);
+                    value >>= 4;
+                }
+                AmlValue::Integer(result & ones)
+            }
+            op if op == 0x5B00 | ext_opcode::TO_BCD as u16 => {
+                let mut value = self.to_integer(operand)?;
+                let mut result = 0;
+                let mut shift = 0;
+                while value != 0 && shift < 64 {
+                    result |= (value % 10) << shift;
+                    shift += 4;
+                    value /= 10;
+                }
+                AmlValue::Integer(result & ones)
+            }
+            _ => return Err(AmlError::UnknownOpcode(op)),
+        };
+
+        self.store(frame, target, result)?;
+        Ok(result)
+    }
+
+    // Evaluates `Match`, which returns the index of the first package element that satisfies
+    // two comparisons, or Ones if there is none
+    fn eval_match(&mut self, frame: &mut Frame, cur: &mut Cursor) -> Result<AmlValue, AmlError> {
+        let package = self.eval_term_arg(frame, cur)?;
+        let op1 = cur.byte()?;
+        let operand1 = self.eval_integer(frame, cur)?;
+        let op2 = cur.byte()?;
+        let operand2 = self.eval_integer(frame, cur)?;
+        let start = self.eval_integer(frame, cur)? as usize;
+
+        let elements = match package {
+            AmlValue::Package(elements) => elements,
+            _ => return Err(AmlError::TypeMismatch),
+        };
+
+        let matches = |op: u8, element: u64, operand: u64| match op {
+            // MTR
+            0 => true,
+            // MEQ
+            1 => element == operand,
+            // MLE
+            2 => element <= operand,
+            // MLT
+            3 => element < operand,
+            // MGE
+            4 => element >= operand,
+            // MGT
+            5 => element > operand,
+            _ => false,
+        };
+
+        for idx in start..elements.len() {
+            // Elements that are not integers never match
+            let element = match self.values.get(elements)[idx] {
+                AmlValue::Integer(value) => value,
+                _ => continue,
+            };
+
+            if matches(op1, element, operand1) && matches(op2, element, operand2) {
+                return Ok(AmlValue::Integer(idx as u64));
+            }
+        }
+
+        Ok(AmlValue::Integer(self.ones()))
+    }
+
+    fn boolean(&self, value: bool) -> AmlValue {
+        AmlValue::Integer(if value { self.ones() } else { 0 })
+    }
+
+    // Parses a Target or a SuperName
+    fn parse_target(&mut self, frame: &mut Frame, cur: &mut Cursor) -> Result<Target, AmlError> {
+        let op = cur.peek()?;
+
+        let target = match op {
+            name::NULL_NAME => {
+                cur.skip(1);
+                Target::Null
+            }
+            opcode::EXT_PREFIX if cur.peek_at(1) == Some(ext_opcode::DEBUG) => {
+                cur.skip(2);
+                Target::Debug
+            }
+            opcode::LOCAL0..=opcode::LOCAL7 => {
+                cur.skip(1);
+                Target::Local((op - opcode::LOCAL0) as usize)
+            }
+            opcode::ARG0..=opcode::ARG6 => {
+                cur.skip(1);
+                Target::Arg((op - opcode::ARG0) as usize)
+            }
+            opcode::DEREF_OF => {
+                // The target is the object the reference refers to
+                cur.skip(1);
+                let reference = self.eval_term_arg(frame, cur)?;
+                self.value_to_target(frame.scope, reference)?
+            }
+            op if name::is_name_start(op) => {
+                let name = cur.name()?;
+                let node = self
+                    .namespace
+                    .lookup(frame.scope, &name)
+                    .ok_or(AmlError::NameNotFound)?;
+                Target::Node(node)
+            }
+            _ => {
+                let reference = self.eval_term_arg(frame, cur)?;
+                self.value_to_target(frame.scope, reference)?
+            }
+        };
+
+        Ok(target)
+    }
+
+    // Converts a reference value to the target it refers to
+    fn value_to_target(&self, scope: NodeId, value: AmlValue) -> Result<Target, AmlError> {
+        match value {
+            AmlValue::Index { target, index } => Ok(Target::Index { target, index }),
+            value => self
+                .resolve(scope, value)
+                .map(Target::Node)
+                .ok_or(AmlError::TypeMismatch),
+        }
+    }
+
+    // Returns the value a target holds, dereferencing arguments that hold references
+    fn read_target(&mut self, frame: &Frame, target: Target) -> Result<AmlValue, AmlError> {
+        match target {
+            Target::Local(idx) => Ok(frame.locals[idx]),
+            Target::Arg(idx) => match frame.args[idx] {
+                AmlValue::Reference(node) => self.read_node(node),
+                AmlValue::Index { target, index } => self.read_index(target, index),
+                value => Ok(value),
+            },
+            Target::Node(node) => self.read_node(node),
+            Target::Index { target, index } => self.read_index(target, index),
+            Target::Null | Target::Debug => Err(AmlError::TypeMismatch),
+        }
+    }
+
+    // Returns a reference to a target, as done by `RefOf`
+    fn reference_to(&self, target: Target) -> Result<AmlValue, AmlError> {
+        match target {
+            Target::Node(node) => Ok(AmlValue::Reference(node)),
+            Target::Index { target, index } => Ok(AmlValue::Index { target, index }),
+            _ => Err(AmlError::TypeMismatch),
+        }
+    }
+
+    // Returns the type code of a target, as done by `ObjectType`
+    fn object_type(&self, frame: &Frame, target: Target) -> u64 {
+        match target {
+            Target::Node(node) => self.namespace.node(node).object().type_code(),
+            Target::Local(idx) => frame.locals[idx].type_code(),
+            Target::Arg(idx) => match frame.args[idx] {
+                AmlValue::Reference(node) => self.namespace.node(node).object().type_code(),
+                value => value.type_code(),
+            },
+            Target::Index {
+                target: IndexTarget::Buffer(_),
+                ..
+            } => 14,
+            Target::Index {
+                target: IndexTarget::Package(elements),
+                index,
+            } => self.values.get(elements)[index as usize].type_code(),
+            Target::Debug => 16,
+            Target::Null => 0,
+        }
+    }
+
+    // Returns the value a reference refers to, as done by `DerefOf`
+    fn deref(&mut self, scope: NodeId, reference: AmlValue) -> Result<AmlValue, AmlError> {
+        match reference {
+            AmlValue::Index { target, index } => self.read_index(target, index),
+            value => {
+                let node = self.resolve(scope, value).ok_or(AmlError::TypeMismatch)?;
+                self.read_node(node)
+            }
+        }
+    }
+
+    // Returns a single element of a buffer or a package
+    fn read_index(&self, target: IndexTarget, index: u32) -> Result<AmlValue, AmlError> {
+        match target {
+            IndexTarget::Buffer(bytes) => self
+                .bytes
+                .get(bytes)
+                .get(index as usize)
+                .map(|byte| AmlValue::Integer(*byte as u64))
+                .ok_or(AmlError::InvalidArgument),
+            IndexTarget::Package(elements) => self
+                .values
+                .get(elements)
+                .get(index as usize)
+                .copied()
+                .ok_or(AmlError::InvalidArgument),
+        }
+    }
+
+    // Stores `value` into `target`, converting it to the type of the target when it is a named
+    // object of a fixed type
+    fn store(
+        &mut self,
+        frame: &mut Frame,
+        target: Target,
+        value: AmlValue,
+    ) -> Result<(), AmlError> {
+        match target {
+            Target::Null => {}
+            Target::Debug => self.print_debug(value),
+            Target::Local(idx) => frame.locals[idx] = self.copy_value(value, false)?,
+            Target::Arg(idx) => match frame.args[idx] {
+                // Arguments that hold references store through the reference
+                AmlValue::Reference(node) => self.store_node(node, value)?,
+                AmlValue::Index { target, index } => self.store_index(target, index, value)?,
+                _ => frame.args[idx] = self.copy_value(value, false)?,
+            },
+            Target::Node(node) => self.store_node(node, value)?,
+            Target::Index { target, index } => self.store_index(target, index, value)?,
+        }
+        Ok(())
+    }
+
+    // Stores `value` into the named object `node`
+    pub(super) fn store_node(&mut self, node: NodeId, value: AmlValue) -> Result<(), AmlError> {
+        let persistent = self.is_persistent_node(node);
+
+        let object = match *self.namespace.node(node).object() {
+            Object::Name(AmlValue::Integer(_)) => {
+                Object::Name(AmlValue::Integer(self.to_integer(value)?))
+            }
+            // Buffers keep their size, the value is truncated or zero-extended
+            Object::Name(AmlValue::Buffer(data)) => {
+                let source = self.to_buffer(value)?;
+                self.bytes.copy_into(source, data);
+                return Ok(());
+            }
+            Object::Name(AmlValue::String(_)) => {
+                let string = self.to_string(value, false)?;
+                Object::Name(AmlValue::String(self.bytes.duplicate(string, persistent)?))
+            }
+            Object::Name(_) => Object::Name(self.copy_value(value, persistent)?),
+            Object::Field(field) => return self.write_field(field, value),
+            Object::BufferField {
+                data,
+                bit_offset,
+                bit_len,
+            } => return self.write_buffer_field(data, bit_offset, bit_len, value),
+            Object::Alias(target) => return self.store_node(target, value),
+            _ => return Err(AmlError::TypeMismatch),
+        };
+
+        self.namespace.set_object(node, object);
+        Ok(())
+    }
+
+    // Stores `value` into a single element of a buffer or a package
+    fn store_index(
+        &mut self,
+        target: IndexTarget,
+        index: u32,
+        value: AmlValue,
+    ) -> Result<(), AmlError> {
+        match target {
+            IndexTarget::Buffer(bytes) => {
+                let byte = self.to_integer(value)? as u8;
+                *self
+                    .bytes
+                    .get_mut(bytes)
+                    .get_mut(index as usize)
+                    .ok_or(AmlError::InvalidArgument)? = byte;
+            }
+            IndexTarget::Package(elements) => {
+                if index as usize >= elements.len() {
+                    return Err(AmlError::InvalidArgument);
+                }
+                let persistent = self.values.is_persistent(elements);
+                let value = self.copy_value(value, persistent)?;
+                self.values.get_mut(elements)[index as usize] = value;
+            }
+        }
+        Ok(())
+    }
+
+    // Stores `value` into `target` without any conversion, as done by `CopyObject`
+    fn copy_object(
+        &mut self,
+        frame: &mut Frame,
+        target: Target,
+        value: AmlValue,
+    ) -> Result<(), AmlError> {
+        match target {
+            Target::Node(node) => {
+                let value = self.copy_value(value, self.is_persistent_node(node))?;
+                self.namespace.set_object(node, Object::Name(value));
+                Ok(())
+            }
+            target => self.store(frame, target, value),
+        }
+    }
+
+    // Returns a deep copy of `value`, whose strings, buffers and packages are allocated as
+    // persistent or temporary
+    fn copy_value(&mut self, value: AmlValue, persistent: bool) -> Result<AmlValue, AmlError> {
+        let copy = match value {
+            AmlValue::String(bytes) => AmlValue::String(self.bytes.duplicate(bytes, persistent)?),
+            AmlValue::Buffer(bytes) => AmlValue::Buffer(self.bytes.duplicate(bytes, persistent)?),
+            AmlValue::Name { path, scope } => AmlValue::Name {
+                path: self.bytes.duplicate(path, persistent)?,
+                scope,
+            },
+            AmlValue::Package(elements) => {
+                let copy = self.values.alloc_elements(elements.len(), persistent)?;
+                for idx in 0..elements.len() {
+                    let element = self.values.get(elements)[idx];
+                    let element = self.copy_value(element, persistent)?;
+                    self.values.get_mut(copy)[idx] = element;
+                }
+                AmlValue::Package(copy)
+            }
+            value => value,
+        };
+        Ok(copy)
+    }
+
+    // Converts `value` to an integer, reading the first bytes of strings and buffers
+    #[allow(clippy::wrong_self_convention)]
+    pub(super) fn to_integer(&mut self, value: AmlValue) -> Result<u64, AmlError> {
+        let integer = match value {
+            AmlValue::Integer(value) => value,
+            // Strings are implicitly converted as hexadecimal numbers
+            AmlValue::String(bytes) => parse_integer(self.bytes.get(bytes), false),
+            AmlValue::Buffer(bytes) => {
+                let data = self.bytes.get(bytes);
+                let len = data.len().min(self.integer_bits() as usize / 8);
+                data[..len]
+                    .iter()
+                    .rev()
+                    .fold(0, |value, byte| value << 8 | *byte as u64)
+            }
+            AmlValue::Index { target, index } => {
+                let element = self.read_index(target, index)?;
+                return self.to_integer(element);
+            }
+            AmlValue::Reference(_) | AmlValue::Name { .. } => {
+                let value = self.deref(NodeId::ROOT, value)?;
+                if matches!(value, AmlValue::Reference(_)) {
+                    return Err(AmlError::TypeMismatch);
+                }
+                return self.to_integer(value);
+            }
+            AmlValue::Uninitialized | AmlValue::Package(_) => return Err(AmlError::TypeMismatch),
+        };
+        Ok(integer & self.ones())
+    }
+
+    // Converts `value` to a buffer. Integers are converted to their little endian bytes.
+    #[allow(clippy::wrong_self_convention)]
+    pub(super) fn to_buffer(&mut self, value: AmlValue) -> Result<Bytes, AmlError> {
+        match value {
+            AmlValue::Buffer(bytes) | AmlValue::String(bytes) => Ok(bytes),
+            value => {
+                let integer = self.to_integer(value)?;
+                let len = self.integer_bits() as usize / 8;
+                self.bytes.copy_bytes(&integer.to_le_bytes()[..len], false)
+            }
+        }
+    }
+
+    // Converts `value` to a string. Integers are converted to hexadecimal, unless `decimal` is
+    // set, and buffers to a list of their bytes.
+    #[allow(clippy::wrong_self_convention)]
+    fn to_string(&mut self, value: AmlValue, decimal: bool) -> Result<Bytes, AmlError> {
+        let mut text = TextBuffer::new();
+
+        match value {
+            AmlValue::String(bytes) => return Ok(bytes),
+            AmlValue::Buffer(bytes) => {
+                // Long buffers are truncated to what fits in the text buffer
+                for (idx, byte) in self.bytes.get(bytes).iter().enumerate() {
+                    let separator = if idx == 0 { "" } else { "," };
+                    if decimal {
+                        let _ = write!(text, "{}{}", separator, byte);
+                    } else {
+                        let _ = write!(text, "{}0x{:02X}", separator, byte);
+                    }
+                }
+            }
+            value => {
+                let integer = self.to_integer(value)?;
+                if decimal {
+                    let _ = write!(text, "{}", integer);
+                } else {
+                    let digits = self.integer_bits() as usize / 4;
+                    let _ = write!(text, "{:0width$X}", integer, width = digits);
+                }
+            }
+        }
+
+        self.bytes.copy_bytes(text.as_bytes(), false)
+    }
+
+    // Compares two values. The second one is converted to the type of the first one.
+    fn compare(&mut self, lhs: AmlValue, rhs: AmlValue) -> Result<CmpOrdering, AmlError> {
+        match lhs {
+            AmlValue::String(lhs) => {
+                let rhs = self.to_string(rhs, false)?;
+                Ok(self.bytes.get(lhs).cmp(self.bytes.get(rhs)))
+            }
+            AmlValue::Buffer(lhs) => {
+                let rhs = self.to_buffer(rhs)?;
+                Ok(self.bytes.get(lhs).cmp(self.bytes.get(rhs)))
+            }
+            lhs => {
+                let lhs = self.to_integer(lhs)?;
+                let rhs = self.to_integer(rhs)?;
+                Ok(lhs.cmp(&rhs))
+            }
+        }
+    }
+
+    // Concatenates two values, whose result has the type of the first one. Integers are
+    // concatenated into a buffer.
+    fn concat(&mut self, lhs: AmlValue, rhs: AmlValue) -> Result<AmlValue, AmlError> {
+        let (lhs, rhs, is_string) = match lhs {
+            AmlValue::String(lhs) => (lhs, self.to_string(rhs, false)?, true),
+            lhs => {
+                let lhs = self.to_buffer(lhs)?;
+                (lhs, self.to_buffer(rhs)?, false)
+            }
+        };
+
+        let result = self.concat_bytes(lhs, rhs)?;
+        Ok(if is_string {
+            AmlValue::String(result)
+        } else {
+            AmlValue::Buffer(result)
+        })
+    }
+
+    // Concatenates two resource templates, keeping only the end tag of the second one
+    fn concat_resources(&mut self, lhs: AmlValue, rhs: AmlValue) -> Result<AmlValue, AmlError> {
+        let (lhs, rhs) = match (lhs, rhs) {
+            (AmlValue::Buffer(lhs), AmlValue::Buffer(rhs)) => (lhs, rhs),
+            _ => return Err(AmlError::TypeMismatch),
+        };
+
+        // Drop the two bytes end tag of the first template
+        let lhs = lhs.sub(0, lhs.len().saturating_sub(2));
+        let result = self.concat_bytes(lhs, rhs)?;
+        Ok(AmlValue::Buffer(result))
+    }
+
+    // Allocates a temporary range of bytes holding `lhs` followed by `rhs`
+    fn concat_bytes(&mut self, lhs: Bytes, rhs: Bytes) -> Result<Bytes, AmlError> {
+        let result = self.bytes.alloc_bytes(lhs.len() + rhs.len(), false)?;
+        self.bytes.copy_into(lhs, result);
+        self.bytes.copy_into(rhs, result.sub(lhs.len(), rhs.len()));
+        Ok(result)
+    }
+
+    // Prints a value stored into the Debug object
+    fn print_debug(&self, value: AmlValue) {
+        match value {
+            AmlValue::Integer(integer) => {
+                print!("AML debug: {:#x}\n", integer);
+            }
+            AmlValue::String(bytes) => {
+                let string = core::str::from_utf8(self.bytes.get(bytes)).unwrap_or("<invalid>");
+                print!("AML debug: {:?}\n", string);
+            }
+            AmlValue::Buffer(bytes) => {
+                print!("AML debug: {:x?}\n", self.bytes.get(bytes));
+            }
+            AmlValue::Reference(node) => {
+                print!("AML debug: reference to {}\n", self.namespace.path(node));
+            }
+            value => {
+                print!("AML debug: {:?}\n", value);
+            }
+        }
+    }
+}
+
+// Parses the integer at the start of `text`, in hexadecimal unless `allow_decimal` is set and
+// the text does not start with `0x`
+fn parse_integer(text: &[u8], allow_decimal: bool) -> u64 {
+    let (digits, radix) = match text {
+        [b'0', b'x' | b'X', rest @ ..] => (rest, 16),
+        text if allow_decimal => (text, 10),
+        text => (text, 16),
+    };
+
+    digits
+        .iter()
+        .map_while(|byte| (*byte as char).to_digit(radix))
+        .fold(0u64, |value, digit| {
+            value.wrapping_mul(radix as u64).wrapping_add(digit as u64)
+        })
+}
+
+// Busy-waits for about `microseconds`
+fn delay_us(microseconds: u64) {
+    // Reading an I/O port takes about a microsecond, which makes the delay independent of the
+    // speed of the processor
+    for _ in 0..microseconds {
+        unsafe { crate::cpu::inb(0x80) };
+    }
+}
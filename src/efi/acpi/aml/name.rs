@@ -0,0 +1,202 @@
+//! Module that parses AML names and paths
+use crate::efi::acpi::aml::AmlError;
+use core::fmt;
+use core::str::FromStr;
+
+/// A single 4 character name segment, like `_SB_` or `PCI0`
+pub type NameSeg = [u8; 4];
+
+/// The maximum number of segments a path can hold
+pub const MAX_NAME_SEGS: usize = 16;
+
+/// Prefix of a name path that starts from the root of the namespace
+pub const ROOT_CHAR: u8 = b'\\';
+/// Prefix of a name path that starts from the parent of the current scope
+pub const PARENT_PREFIX_CHAR: u8 = b'^';
+/// Prefix of a name path made of two segments
+pub const DUAL_NAME_PREFIX: u8 = 0x2E;
+/// Prefix of a name path made of a variable number of segments
+pub const MULTI_NAME_PREFIX: u8 = 0x2F;
+/// An empty name
+pub const NULL_NAME: u8 = 0x00;
+
+/// Returns whether `byte` can start a NameString
+pub fn is_name_start(byte: u8) -> bool {
+    is_lead_name_char(byte)
+        || matches!(
+            byte,
+            ROOT_CHAR | PARENT_PREFIX_CHAR | DUAL_NAME_PREFIX | MULTI_NAME_PREFIX
+        )
+}
+
+// Returns whether `byte` can be the first character of a name segment
+fn is_lead_name_char(byte: u8) -> bool {
+    byte.is_ascii_uppercase() || byte == b'_'
+}
+
+// Returns whether `byte` can be any but the first character of a name segment
+fn is_name_char(byte: u8) -> bool {
+    is_lead_name_char(byte) || byte.is_ascii_digit()
+}
+
+/// A parsed NameString, which is either absolute, or relative to some scope
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub struct AmlName {
+    // Whether the path starts from the root of the namespace
+    absolute: bool,
+    // Number of parent prefixes, each of them moving one scope up
+    parents: u8,
+    // The segments of the path
+    segs: [NameSeg; MAX_NAME_SEGS],
+    // Number of populated segments in `segs`
+    nsegs: u8,
+}
+
+impl AmlName {
+    /// Parses a NameString from the start of `aml`, returning the name and its encoded size
+    pub fn parse(aml: &[u8]) -> Result<(Self, usize), AmlError> {
+        let mut name = AmlName {
+            absolute: false,
+            parents: 0,
+            segs: [[0; 4]; MAX_NAME_SEGS],
+            nsegs: 0,
+        };
+        let mut idx = 0;
+
+        match aml.first() {
+            Some(&ROOT_CHAR) => {
+                name.absolute = true;
+                idx += 1;
+            }
+            Some(&PARENT_PREFIX_CHAR) => {
+                while aml.get(idx) == Some(&PARENT_PREFIX_CHAR) {
+                    name.parents += 1;
+                    idx += 1;
+                }
+            }
+            Some(_) => {}
+            None => return Err(AmlError::UnexpectedEnd),
+        }
+
+        let nsegs = match *aml.get(idx).ok_or(AmlError::UnexpectedEnd)? {
+            NULL_NAME => {
+                idx += 1;
+                0
+            }
+            DUAL_NAME_PREFIX => {
+                idx += 1;
+                2
+            }
+            MULTI_NAME_PREFIX => {
+                let count = *aml.get(idx + 1).ok_or(AmlError::UnexpectedEnd)?;
+                idx += 2;
+                count as usize
+            }
+            _ => 1,
+        };
+
+        if nsegs > MAX_NAME_SEGS {
+            return Err(AmlError::InvalidName);
+        }
+
+        for seg_idx in 0..nsegs {
+            let seg: NameSeg = aml
+                .get(idx..idx + 4)
+                .ok_or(AmlError::UnexpectedEnd)?
+                .try_into()
+                .unwrap();
+
+            if !is_lead_name_char(seg[0]) || !seg[1..].iter().all(|byte| is_name_char(*byte)) {
+                return Err(AmlError::InvalidName);
+            }
+
+            name.segs[seg_idx] = seg;
+            idx += 4;
+        }
+        name.nsegs = nsegs as u8;
+
+        Ok((name, idx))
+    }
+
+    pub fn is_absolute(&self) -> bool {
+        self.absolute
+    }
+    pub fn parents(&self) -> u8 {
+        self.parents
+    }
+    pub fn segs(&self) -> &[NameSeg] {
+        &self.segs[..self.nsegs as usize]
+    }
+
+    /// Returns whether the name is a single segment relative to the current scope, in which case
+    /// lookups also search the parent scopes
+    pub fn is_single_seg(&self) -> bool {
+        !self.absolute && self.parents == 0 && self.nsegs == 1
+    }
+}
+
+impl FromStr for AmlName {
+    type Err = AmlError;
+
+    /// Parses a path written in ASL notation, like `\_SB.PCI0._CRS` or `^_STA`. Segments shorter
+    /// than 4 characters are padded with underscores.
+    fn from_str(path: &str) -> Result<Self, AmlError> {
+        let mut name = AmlName {
+            absolute: false,
+            parents: 0,
+            segs: [[0; 4]; MAX_NAME_SEGS],
+            nsegs: 0,
+        };
+
+        let mut rest = path.as_bytes();
+        if let Some((&ROOT_CHAR, tail)) = rest.split_first() {
+            name.absolute = true;
+            rest = tail;
+        }
+        while let Some((&PARENT_PREFIX_CHAR, tail)) = rest.split_first() {
+            name.parents += 1;
+            rest = tail;
+        }
+
+        if rest.is_empty() {
+            return Ok(name);
+        }
+
+        for part in rest.split(|byte| *byte == b'.') {
+            if part.is_empty() || part.len() > 4 || name.nsegs as usize == MAX_NAME_SEGS {
+                return Err(AmlError::InvalidName);
+            }
+
+            let mut seg = [b'_'; 4];
+            seg[..part.len()].copy_from_slice(part);
+            name.segs[name.nsegs as usize] = seg;
+            name.nsegs += 1;
+        }
+
+        Ok(name)
+    }
+}
+
+impl fmt::Display for AmlName {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.absolute {
+            write!(f, "\\")?;
+        }
+        for _ in 0..self.parents {
+            write!(f, "^")?;
+        }
+        for (idx, seg) in self.segs().iter().enumerate() {
+            if idx != 0 {
+                write!(f, ".")?;
+            }
+            write!(f, "{}", core::str::from_utf8(seg).unwrap_or("????"))?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Debug for AmlName {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self)
+    }
+}
@@ -0,0 +1,393 @@
+//! Module that holds the ACPI namespace, the tree of all the objects defined by the definition
+//! blocks
+use crate::efi::acpi::aml::{
+    name::{AmlName, NameSeg},
+    value::{AmlValue, Bytes},
+    AmlError,
+};
+use core::fmt;
+
+/// The maximum number of objects the namespace can hold
+pub const MAX_NODES: usize = 2048;
+
+/// Identifies an object of the namespace
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct NodeId(pub(crate) u16);
+
+impl NodeId {
+    /// The root of the namespace, `\`
+    pub const ROOT: NodeId = NodeId(0);
+}
+
+/// Methods that the interpreter implements itself, instead of running AML code
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Builtin {
+    /// `\_OSI`, through which AML code queries the interfaces supported by the OS
+    Osi,
+}
+
+/// Where the contents of a field unit live
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FieldKind {
+    /// The field is directly part of an operation region
+    Region(NodeId),
+    /// The field is accessed by writing its offset to the `index` field and then accessing the
+    /// `data` field
+    Index { index: NodeId, data: NodeId },
+    /// The field is part of an operation region, once `value` is written to the `bank` field
+    Bank {
+        region: NodeId,
+        bank: NodeId,
+        value: u64,
+    },
+}
+
+/// A field unit, as defined by `Field`, `IndexField` or `BankField`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FieldUnit {
+    pub kind: FieldKind,
+    /// Offset of the field, in bits, from the start of its region
+    pub bit_offset: u32,
+    /// Size of the field in bits
+    pub bit_len: u32,
+    /// The field flags, holding the access type, lock rule and update rule
+    pub flags: u8,
+}
+
+impl FieldUnit {
+    /// Returns the width, in bits, of the accesses used for this field
+    pub fn access_bits(&self) -> u32 {
+        match self.flags & 0xF {
+            // WordAcc
+            2 => 16,
+            // DWordAcc
+            3 => 32,
+            // QWordAcc
+            4 => 64,
+            // AnyAcc, ByteAcc and BufferAcc
+            _ => 8,
+        }
+    }
+
+    /// Returns the update rule: 0 to preserve, 1 to write as ones and 2 to write as zeros the
+    /// bits of an access that are not part of the field
+    pub fn update_rule(&self) -> u8 {
+        (self.flags >> 5) & 0b11
+    }
+}
+
+/// An object from the namespace
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Object {
+    /// A scope that only holds other objects, like `\_SB`
+    Scope,
+    Device,
+    Processor {
+        id: u8,
+        pblk_addr: u32,
+        pblk_len: u8,
+    },
+    PowerResource,
+    ThermalZone,
+    /// A named value, as defined by `Name`
+    Name(AmlValue),
+    /// A control method, whose code runs with the method's node as the scope
+    Method {
+        code: &'static [u8],
+        flags: u8,
+    },
+    /// A method implemented by the interpreter
+    Builtin(Builtin, u8),
+    /// An object that is declared by `External`, and defined by another table
+    External {
+        object_type: u8,
+        argc: u8,
+    },
+    /// An operation region, whose `space` is one of the `address_space` ids
+    OpRegion {
+        space: u8,
+        offset: u64,
+        length: u64,
+    },
+    Field(FieldUnit),
+    /// A field of a buffer, as created by the `Create*Field` operators
+    BufferField {
+        data: Bytes,
+        bit_offset: u32,
+        bit_len: u32,
+    },
+    Mutex,
+    Event,
+    Alias(NodeId),
+}
+
+impl Object {
+    /// Returns the ACPI object type code, as reported by the `ObjectType` operator
+    pub fn type_code(&self) -> u64 {
+        match self {
+            Self::Scope => 0,
+            Self::Name(value) => value.type_code(),
+            Self::Field(_) => 5,
+            Self::Device => 6,
+            Self::Event => 7,
+            Self::Method { .. } | Self::Builtin(..) => 8,
+            Self::Mutex => 9,
+            Self::OpRegion { .. } => 10,
+            Self::PowerResource => 11,
+            Self::Processor { .. } => 12,
+            Self::ThermalZone => 13,
+            Self::BufferField { .. } => 14,
+            Self::External { object_type, .. } => *object_type as u64,
+            Self::Alias(_) => 0,
+        }
+    }
+
+    /// Returns the number of arguments a method takes, or `None` if the object is not a method
+    pub fn argc(&self) -> Option<usize> {
+        match self {
+            Self::Method { flags, .. } => Some((flags & 0b111) as usize),
+            Self::Builtin(_, argc) => Some(*argc as usize),
+            // Methods declared as external give us their argument count
+            Self::External {
+                object_type: 8,
+                argc,
+            } => Some(*argc as usize),
+            _ => None,
+        }
+    }
+
+    /// Returns whether the object can hold other objects
+    pub fn is_scope(&self) -> bool {
+        matches!(
+            self,
+            Self::Scope
+                | Self::Device
+                | Self::Processor { .. }
+                | Self::PowerResource
+                | Self::ThermalZone
+                | Self::Method { .. }
+        )
+    }
+}
+
+/// A single object of the namespace
+#[derive(Debug, Clone, Copy)]
+pub struct Node {
+    name: NameSeg,
+    parent: NodeId,
+    object: Object,
+}
+
+impl Node {
+    pub fn name(&self) -> NameSeg {
+        self.name
+    }
+    pub fn parent(&self) -> NodeId {
+        self.parent
+    }
+    pub fn object(&self) -> &Object {
+        &self.object
+    }
+}
+
+const INIT_NODE: Node = Node {
+    name: *b"\\___",
+    parent: NodeId::ROOT,
+    object: Object::Scope,
+};
+
+/// The ACPI namespace. Nodes are only ever appended, except for the ones created by a method,
+/// which are removed once the method returns.
+pub struct Namespace {
+    nodes: [Node; MAX_NODES],
+    nnodes: usize,
+}
+
+impl Default for Namespace {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Namespace {
+    /// Creates a namespace only holding the root scope
+    pub const fn new() -> Self {
+        Self {
+            nodes: [INIT_NODE; MAX_NODES],
+            nnodes: 1,
+        }
+    }
+
+    /// Returns the node `id`
+    pub fn node(&self, id: NodeId) -> &Node {
+        &self.nodes[id.0 as usize]
+    }
+
+    /// Replaces the object held by node `id`
+    pub fn set_object(&mut self, id: NodeId, object: Object) {
+        self.nodes[id.0 as usize].object = object;
+    }
+
+    /// Returns the number of nodes, which can be used to remove all the nodes created afterwards
+    /// through `truncate`
+    pub fn len(&self) -> usize {
+        self.nnodes
+    }
+
+    /// Returns whether the namespace only holds the root scope
+    pub fn is_empty(&self) -> bool {
+        self.nnodes <= 1
+    }
+
+    /// Removes all the nodes created after the namespace had `len` nodes
+    pub fn truncate(&mut self, len: usize) {
+        self.nnodes = self.nnodes.min(len.max(1));
+    }
+
+    /// Returns an iterator over all the nodes
+    pub fn iter(&self) -> impl Iterator<Item = (NodeId, &Node)> {
+        self.nodes[..self.nnodes]
+            .iter()
+            .enumerate()
+            .map(|(idx, node)| (NodeId(idx as u16), node))
+    }
+
+    /// Returns the children of `parent`, in the order they were defined
+    pub fn children(&self, parent: NodeId) -> impl Iterator<Item = (NodeId, &Node)> {
+        self.iter()
+            .skip(1)
+            .filter(move |(_, node)| node.parent == parent)
+    }
+
+    /// Returns the child of `parent` named `name`
+    pub fn child(&self, parent: NodeId, name: NameSeg) -> Option<NodeId> {
+        self.children(parent)
+            .find(|(_, node)| node.name == name)
+            .map(|(id, _)| id)
+    }
+
+    /// Adds `object` named `name` under `parent`. If an object with that name already exists, it
+    /// is kept, unless it is a placeholder: a plain scope or an external declaration.
+    pub fn add(
+        &mut self,
+        parent: NodeId,
+        name: NameSeg,
+        object: Object,
+    ) -> Result<NodeId, AmlError> {
+        if let Some(existing) = self.child(parent, name) {
+            let is_placeholder = matches!(
+                self.node(existing).object,
+                Object::Scope | Object::External { .. }
+            );
+            if is_placeholder && !matches!(object, Object::Scope | Object::External { .. }) {
+                self.set_object(existing, object);
+            }
+            return Ok(existing);
+        }
+
+        let slot = self
+            .nodes
+            .get_mut(self.nnodes)
+            .ok_or(AmlError::NamespaceFull)?;
+        *slot = Node {
+            name,
+            parent,
+            object,
+        };
+        self.nnodes += 1;
+
+        Ok(NodeId(self.nnodes as u16 - 1))
+    }
+
+    // Walks `name`'s prefixes from `scope`, returning the scope its segments start from
+    fn start_scope(&self, scope: NodeId, name: &AmlName) -> Option<NodeId> {
+        if name.is_absolute() {
+            return Some(NodeId::ROOT);
+        }
+
+        let mut current = scope;
+        for _ in 0..name.parents() {
+            if current == NodeId::ROOT {
+                return None;
+            }
+            current = self.node(current).parent;
+        }
+        Some(current)
+    }
+
+    /// Looks `name` up, relative to `scope`. Single segment names are also searched for in all
+    /// the scopes above `scope`, as required by the namespace search rules.
+    pub fn lookup(&self, scope: NodeId, name: &AmlName) -> Option<NodeId> {
+        if name.is_single_seg() {
+            let seg = name.segs()[0];
+            let mut current = scope;
+            loop {
+                if let Some(found) = self.child(current, seg) {
+                    return Some(found);
+                }
+                if current == NodeId::ROOT {
+                    return None;
+                }
+                current = self.node(current).parent;
+            }
+        }
+
+        let mut current = self.start_scope(scope, name)?;
+        for seg in name.segs() {
+            current = self.child(current, *seg)?;
+        }
+        Some(current)
+    }
+
+    /// Resolves the scope in which an object called `name` has to be created, relative to
+    /// `scope`, returning it with the object's own name
+    pub fn parent_for(&self, scope: NodeId, name: &AmlName) -> Result<(NodeId, NameSeg), AmlError> {
+        let (last, init) = name.segs().split_last().ok_or(AmlError::InvalidName)?;
+
+        let mut current = self.start_scope(scope, name).ok_or(AmlError::InvalidName)?;
+        for seg in init {
+            current = self.child(current, *seg).ok_or(AmlError::NameNotFound)?;
+        }
+
+        Ok((current, *last))
+    }
+
+    /// Returns a value that displays the absolute path of `id`
+    pub fn path(&self, id: NodeId) -> NodePath<'_> {
+        NodePath {
+            namespace: self,
+            id,
+        }
+    }
+}
+
+/// Displays the absolute path of a node, like `\_SB_.PCI0`
+pub struct NodePath<'a> {
+    namespace: &'a Namespace,
+    id: NodeId,
+}
+
+impl fmt::Display for NodePath<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // Collect the segments from the node up to the root
+        let mut segs = [[0u8; 4]; 32];
+        let mut nsegs = 0;
+        let mut current = self.id;
+
+        while current != NodeId::ROOT && nsegs < segs.len() {
+            let node = self.namespace.node(current);
+            segs[nsegs] = node.name;
+            nsegs += 1;
+            current = node.parent;
+        }
+
+        write!(f, "\\")?;
+        for (idx, seg) in segs[..nsegs].iter().rev().enumerate() {
+            if idx != 0 {
+                write!(f, ".")?;
+            }
+            write!(f, "{}", core::str::from_utf8(seg).unwrap_or("????"))?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,86 @@
+//! Module that decodes the PCI routing tables returned by `_PRT`, which tell how the interrupt
+//! pins of PCI devices are connected to the interrupt controllers
+use crate::efi::acpi::aml::{interp::Interpreter, namespace::NodeId, value::AmlValue, AmlError};
+
+/// Where an interrupt pin is routed
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PciRoute {
+    /// The pin is hardwired to this global system interrupt
+    Gsi(u32),
+    /// The pin is connected to the interrupt link device `link`. `index` selects the interrupt
+    /// amongst the resources of the link device.
+    Link { link: NodeId, index: u32 },
+}
+
+impl Default for PciRoute {
+    fn default() -> Self {
+        Self::Gsi(0)
+    }
+}
+
+/// A single entry of a PCI routing table
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct PrtEntry {
+    /// The address of the device, with the device number in the high word. The low word is
+    /// 0xFFFF, as entries apply to all the functions of the device.
+    pub address: u64,
+    /// The interrupt pin: 0 for INTA#, 1 for INTB#, 2 for INTC# and 3 for INTD#
+    pub pin: u8,
+    pub route: PciRoute,
+}
+
+impl PrtEntry {
+    /// Returns the device number the entry applies to
+    pub fn device(&self) -> u8 {
+        (self.address >> 16) as u8
+    }
+}
+
+impl Interpreter {
+    /// Evaluates the `_PRT` of the PCI bridge `bridge`, filling `entries` with its routing table.
+    /// Returns the number of entries written, which is at most `entries.len()`.
+    pub fn pci_routing_table(
+        &mut self,
+        bridge: NodeId,
+        entries: &mut [PrtEntry],
+    ) -> Result<usize, AmlError> {
+        let prt = self
+            .namespace
+            .child(bridge, *b"_PRT")
+            .ok_or(AmlError::NameNotFound)?;
+        let table = self.evaluate_node(prt, &[])?;
+        let rows = match table {
+            AmlValue::Package(rows) => rows,
+            _ => return Err(AmlError::TypeMismatch),
+        };
+
+        let mut count = 0;
+        for (idx, entry) in entries.iter_mut().enumerate().take(rows.len()) {
+            let row = match self.values.get(rows)[idx] {
+                AmlValue::Package(row) if row.len() >= 4 => row,
+                _ => return Err(AmlError::TypeMismatch),
+            };
+            let [address, pin, source, source_index] =
+                [0, 1, 2, 3].map(|field| self.values.get(row)[field]);
+
+            let source_index = self.to_integer(source_index)? as u32;
+            let route = match source {
+                // A null source means the pin is hardwired to a GSI
+                AmlValue::Integer(0) => PciRoute::Gsi(source_index),
+                source => PciRoute::Link {
+                    link: self.resolve(bridge, source).ok_or(AmlError::NameNotFound)?,
+                    index: source_index,
+                },
+            };
+
+            *entry = PrtEntry {
+                address: self.to_integer(address)?,
+                pin: self.to_integer(pin)? as u8,
+                route,
+            };
+            count += 1;
+        }
+
+        Ok(count)
+    }
+}
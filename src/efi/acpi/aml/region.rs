@@ -0,0 +1,354 @@
+//! Module that accesses field units and buffer fields, reading and writing operation regions
+//! through the Generic Address Structure accessors
+use crate::efi::acpi::aml::{
+    interp::Interpreter,
+    namespace::{FieldKind, FieldUnit, NodeId, Object},
+    value::{AmlValue, Bytes},
+    AmlError,
+};
+use crate::efi::acpi::{address_space, GenericAddress, GenericAddressStructure};
+
+/// The largest field, in bytes, that can be read or written at once
+const MAX_FIELD_BYTES: usize = 256;
+
+// Returns a mask with the lower `bits` bits set
+fn low_mask(bits: u32) -> u64 {
+    if bits >= u64::BITS {
+        u64::MAX
+    } else {
+        (1 << bits) - 1
+    }
+}
+
+// Reads `len` bits, up to 64, starting at bit `pos` of `buf`. Bits past the end of `buf` are 0.
+fn get_bits(buf: &[u8], pos: usize, len: usize) -> u64 {
+    (0..len).fold(0, |value, idx| {
+        let bit = pos + idx;
+        let set = buf
+            .get(bit / 8)
+            .is_some_and(|byte| byte >> (bit % 8) & 1 != 0);
+        value | (set as u64) << idx
+    })
+}
+
+// Writes the lower `len` bits, up to 64, of `value` starting at bit `pos` of `buf`
+fn set_bits(buf: &mut [u8], pos: usize, len: usize, value: u64) {
+    for idx in 0..len {
+        let bit = pos + idx;
+        if let Some(byte) = buf.get_mut(bit / 8) {
+            *byte &= !(1 << (bit % 8));
+            *byte |= ((value >> idx & 1) as u8) << (bit % 8);
+        }
+    }
+}
+
+impl Interpreter {
+    // Returns a field's value as an integer if it fits, or as a buffer otherwise
+    fn field_value(&mut self, data: &[u8], bit_len: u32) -> Result<AmlValue, AmlError> {
+        if bit_len <= self.integer_bits() {
+            return Ok(AmlValue::Integer(get_bits(data, 0, bit_len as usize)));
+        }
+
+        let len = (bit_len as usize).div_ceil(8);
+        Ok(AmlValue::Buffer(
+            self.bytes.copy_bytes(&data[..len], false)?,
+        ))
+    }
+
+    // Copies the bytes of `value` into `out`, integers being stored in little endian
+    fn value_bytes(&mut self, value: AmlValue, out: &mut [u8]) -> Result<(), AmlError> {
+        match value {
+            AmlValue::Buffer(bytes) | AmlValue::String(bytes) => {
+                let data = self.bytes.get(bytes);
+                let len = data.len().min(out.len());
+                out[..len].copy_from_slice(&data[..len]);
+            }
+            value => {
+                let integer = self.to_integer(value)?.to_le_bytes();
+                let len = integer.len().min(out.len());
+                out[..len].copy_from_slice(&integer[..len]);
+            }
+        }
+        Ok(())
+    }
+
+    /// Reads a field unit, one access unit at a time
+    pub(super) fn read_field(&mut self, field: FieldUnit) -> Result<AmlValue, AmlError> {
+        let len = (field.bit_len as usize).div_ceil(8);
+        if len > MAX_FIELD_BYTES {
+            return Err(AmlError::InvalidArgument);
+        }
+        if field.bit_len == 0 {
+            return Ok(AmlValue::Integer(0));
+        }
+
+        let mut data = [0u8; MAX_FIELD_BYTES];
+        let access_bits = field.access_bits();
+        let end = field.bit_offset + field.bit_len;
+
+        for unit in field.bit_offset / access_bits..=(end - 1) / access_bits {
+            let unit_start = unit * access_bits;
+            // The part of the access unit that belongs to the field
+            let low = field.bit_offset.max(unit_start);
+            let high = end.min(unit_start + access_bits);
+
+            let raw = self.access_unit(&field, unit_start / 8, access_bits, None)?;
+            set_bits(
+                &mut data,
+                (low - field.bit_offset) as usize,
+                (high - low) as usize,
+                raw >> (low - unit_start),
+            );
+        }
+
+        self.field_value(&data, field.bit_len)
+    }
+
+    /// Writes a field unit, one access unit at a time. The bits of the access units that are not
+    /// part of the field are handled according to the field's update rule.
+    pub(super) fn write_field(
+        &mut self,
+        field: FieldUnit,
+        value: AmlValue,
+    ) -> Result<(), AmlError> {
+        if (field.bit_len as usize).div_ceil(8) > MAX_FIELD_BYTES {
+            return Err(AmlError::InvalidArgument);
+        }
+        if field.bit_len == 0 {
+            return Ok(());
+        }
+
+        let mut data = [0u8; MAX_FIELD_BYTES];
+        self.value_bytes(value, &mut data)?;
+
+        let access_bits = field.access_bits();
+        let unit_mask = low_mask(access_bits);
+        let end = field.bit_offset + field.bit_len;
+
+        for unit in field.bit_offset / access_bits..=(end - 1) / access_bits {
+            let unit_start = unit * access_bits;
+            let low = field.bit_offset.max(unit_start);
+            let high = end.min(unit_start + access_bits);
+
+            let shift = low - unit_start;
+            let mask = low_mask(high - low) << shift;
+            let bits = get_bits(
+                &data,
+                (low - field.bit_offset) as usize,
+                (high - low) as usize,
+            ) << shift;
+
+            let unit_value = if mask == unit_mask {
+                bits
+            } else {
+                match field.update_rule() {
+                    // Preserve
+                    0 => {
+                        let current =
+                            self.access_unit(&field, unit_start / 8, access_bits, None)?;
+                        (current & !mask) | bits
+                    }
+                    // WriteAsOnes
+                    1 => (unit_mask & !mask) | bits,
+                    // WriteAsZeros
+                    _ => bits,
+                }
+            };
+
+            self.access_unit(&field, unit_start / 8, access_bits, Some(unit_value))?;
+        }
+
+        Ok(())
+    }
+
+    // Reads, or writes if `write` is set, a single access unit of a field unit, `byte_offset`
+    // bytes from the start of its region
+    fn access_unit(
+        &mut self,
+        field: &FieldUnit,
+        byte_offset: u32,
+        access_bits: u32,
+        write: Option<u64>,
+    ) -> Result<u64, AmlError> {
+        match field.kind {
+            FieldKind::Region(region) => {
+                self.access_region(region, byte_offset as u64, access_bits, write)
+            }
+            FieldKind::Bank {
+                region,
+                bank,
+                value,
+            } => {
+                // Select the bank first
+                self.store_node(bank, AmlValue::Integer(value))?;
+                self.access_region(region, byte_offset as u64, access_bits, write)
+            }
+            FieldKind::Index { index, data } => {
+                self.store_node(index, AmlValue::Integer(byte_offset as u64))?;
+                match write {
+                    Some(value) => {
+                        self.store_node(data, AmlValue::Integer(value))?;
+                        Ok(0)
+                    }
+                    None => {
+                        let value = self.read_node(data)?;
+                        self.to_integer(value)
+                    }
+                }
+            }
+        }
+    }
+
+    // Reads, or writes if `write` is set, `access_bits` bits at `offset` bytes into `region`
+    fn access_region(
+        &mut self,
+        region: NodeId,
+        offset: u64,
+        access_bits: u32,
+        write: Option<u64>,
+    ) -> Result<u64, AmlError> {
+        let (space, base, length) = match *self.namespace.node(region).object() {
+            Object::OpRegion {
+                space,
+                offset,
+                length,
+            } => (space, offset, length),
+            _ => return Err(AmlError::TypeMismatch),
+        };
+
+        let end = offset.checked_add(access_bits as u64 / 8);
+        if end.is_none_or(|end| end > length) {
+            return Err(AmlError::InvalidArgument);
+        }
+        let address = base.checked_add(offset).ok_or(AmlError::InvalidArgument)?;
+
+        let address = match space {
+            address_space::SYSTEM_MEMORY | address_space::SYSTEM_IO => address,
+            address_space::PCI_CONFIG => {
+                // The configuration space offset only has 16 bits in a Generic Address Structure
+                if address > 0xFFFF {
+                    return Err(AmlError::InvalidArgument);
+                }
+                // The function is the one of the device the region belongs to, encoded in the
+                // same way as in a Generic Address Structure
+                let adr = self.pci_adr(region)?;
+                (adr >> 16 & 0xFFFF) << 32 | (adr & 0xFFFF) << 16 | address
+            }
+            space => return Err(AmlError::UnsupportedRegion(space)),
+        };
+
+        let gas = GenericAddressStructure {
+            address_space_id: space,
+            register_bit_width: access_bits as u8,
+            register_bit_offset: 0,
+            access_size: (access_bits / 8).trailing_zeros() as u8 + 1,
+            address,
+        };
+        let reg = GenericAddress::new(gas)?;
+
+        unsafe {
+            match write {
+                Some(value) => {
+                    reg.write(value);
+                    Ok(0)
+                }
+                None => Ok(reg.read()),
+            }
+        }
+    }
+
+    // Returns the `_ADR` of the device a PCI configuration region belongs to. Generic Address
+    // Structures only reach bus 0 of segment 0, so the device must sit right below a root bridge
+    // whose `_SEG` and `_BBN` are 0. Regions on other buses are not supported.
+    fn pci_adr(&mut self, region: NodeId) -> Result<u64, AmlError> {
+        let unsupported = AmlError::UnsupportedRegion(address_space::PCI_CONFIG);
+        let mut scope = self.namespace.node(region).parent();
+        let mut adr = None;
+
+        loop {
+            if self.is_pci_root(scope)? {
+                for name in [*b"_SEG", *b"_BBN"] {
+                    if let Some(node) = self.namespace.child(scope, name) {
+                        let value = self.invoke(node, &[])?;
+                        if self.to_integer(value)? != 0 {
+                            return Err(unsupported);
+                        }
+                    }
+                }
+                // A region of the root bridge itself is in the host bridge's function
+                return Ok(adr.unwrap_or(0));
+            }
+
+            if let Some(node) = self.namespace.child(scope, *b"_ADR") {
+                // Another device with an address is a bridge, and the region is behind it
+                if adr.is_some() {
+                    return Err(unsupported);
+                }
+                let value = self.invoke(node, &[])?;
+                adr = Some(self.to_integer(value)?);
+            }
+
+            // Without a root bridge, the bus of the device is unknown
+            if scope == NodeId::ROOT {
+                return Err(unsupported);
+            }
+            scope = self.namespace.node(scope).parent();
+        }
+    }
+
+    /// Reads a field of a buffer
+    pub(super) fn read_buffer_field(
+        &mut self,
+        data: Bytes,
+        bit_offset: u32,
+        bit_len: u32,
+    ) -> Result<AmlValue, AmlError> {
+        let len = (bit_len as usize).div_ceil(8);
+        if len > MAX_FIELD_BYTES {
+            return Err(AmlError::InvalidArgument);
+        }
+
+        let mut field = [0u8; MAX_FIELD_BYTES];
+        let source = self.bytes.get(data);
+        for pos in (0..bit_len as usize).step_by(64) {
+            let chunk = (bit_len as usize - pos).min(64);
+            set_bits(
+                &mut field,
+                pos,
+                chunk,
+                get_bits(source, bit_offset as usize + pos, chunk),
+            );
+        }
+
+        self.field_value(&field, bit_len)
+    }
+
+    /// Writes a field of a buffer
+    pub(super) fn write_buffer_field(
+        &mut self,
+        data: Bytes,
+        bit_offset: u32,
+        bit_len: u32,
+        value: AmlValue,
+    ) -> Result<(), AmlError> {
+        if (bit_len as usize).div_ceil(8) > MAX_FIELD_BYTES {
+            return Err(AmlError::InvalidArgument);
+        }
+
+        let mut field = [0u8; MAX_FIELD_BYTES];
+        self.value_bytes(value, &mut field)?;
+
+        let target = self.bytes.get_mut(data);
+        for pos in (0..bit_len as usize).step_by(64) {
+            let chunk = (bit_len as usize - pos).min(64);
+            set_bits(
+                target,
+                bit_offset as usize + pos,
+                chunk,
+                get_bits(&field, pos, chunk),
+            );
+        }
+
+        Ok(())
+    }
+}
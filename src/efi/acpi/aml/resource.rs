@@ -0,0 +1,343 @@
+//! Module that decodes resource templates, the buffers returned by `_CRS` and `_PRS` that describe
+//! the I/O ports, memory ranges and interrupts a device uses
+use crate::efi::acpi::aml::AmlError;
+use crate::efi::acpi::GenericAddressStructure;
+
+/// The number of interrupts kept from an Extended Interrupt descriptor
+pub const MAX_EXTENDED_IRQS: usize = 8;
+
+/// How an interrupt is signaled
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct InterruptMode {
+    /// Edge triggered if set, level triggered otherwise
+    pub edge_triggered: bool,
+    /// Active low if set, active high otherwise
+    pub active_low: bool,
+    /// Whether the interrupt can be shared with other devices
+    pub shared: bool,
+    /// Whether the interrupt can wake the system
+    pub wake_capable: bool,
+}
+
+/// The kind of resource described by an address space descriptor
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AddressKind {
+    Memory,
+    Io,
+    BusNumber,
+    Other(u8),
+}
+
+impl From<u8> for AddressKind {
+    fn from(value: u8) -> Self {
+        match value {
+            0 => Self::Memory,
+            1 => Self::Io,
+            2 => Self::BusNumber,
+            value => Self::Other(value),
+        }
+    }
+}
+
+/// A range described by a Word, DWord, QWord or Extended Address Space descriptor
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AddressSpace {
+    pub kind: AddressKind,
+    /// The general flags, telling whether the device consumes or produces the range, and whether
+    /// its bounds are fixed
+    pub flags: u8,
+    /// Flags whose meaning depends on `kind`, like the cacheability of memory ranges
+    pub type_flags: u8,
+    pub granularity: u64,
+    pub min: u64,
+    pub max: u64,
+    /// Offset between the addresses on the primary side of a bridge and the secondary side
+    pub translation: u64,
+    pub length: u64,
+}
+
+/// A single resource descriptor of a resource template
+#[derive(Debug, Clone, Copy)]
+pub enum Resource {
+    /// Legacy ISA interrupts, as a mask of the IRQs the device can use
+    Irq {
+        irqs: u16,
+        mode: InterruptMode,
+    },
+    /// ISA DMA channels, as a mask of the channels the device can use
+    Dma {
+        channels: u8,
+        flags: u8,
+    },
+    /// A range of I/O ports, which may be relocated between `min` and `max`
+    Io {
+        decode_16bit: bool,
+        min: u16,
+        max: u16,
+        alignment: u8,
+        length: u8,
+    },
+    /// A range of I/O ports at a fixed address, decoded on 10 bits
+    FixedIo {
+        base: u16,
+        length: u8,
+    },
+    /// A 24-bit memory range, as used by ISA devices
+    Memory24 {
+        writable: bool,
+        min: u32,
+        max: u32,
+        alignment: u32,
+        length: u32,
+    },
+    /// A 32-bit memory range, which may be relocated between `min` and `max`
+    Memory32 {
+        writable: bool,
+        min: u32,
+        max: u32,
+        alignment: u32,
+        length: u32,
+    },
+    /// A 32-bit memory range at a fixed address
+    FixedMemory32 {
+        writable: bool,
+        base: u32,
+        length: u32,
+    },
+    AddressSpace(AddressSpace),
+    /// Interrupts that are global system interrupts, unless `resource_source` is set. Only the
+    /// first `MAX_EXTENDED_IRQS` interrupts are kept.
+    ExtendedIrq {
+        mode: InterruptMode,
+        /// Whether the interrupts are produced by the device, instead of consumed
+        producer: bool,
+        count: u8,
+        interrupts: [u32; MAX_EXTENDED_IRQS],
+    },
+    GenericRegister(GenericAddressStructure),
+    /// Starts a set of alternative resources, only found in `_PRS`
+    StartDependent,
+    /// Ends the sets of alternative resources
+    EndDependent,
+    /// A descriptor we do not decode, with its tag
+    Other(u8),
+}
+
+/// Iterates over the descriptors of a resource template, up to its end tag
+pub struct ResourceIter<'a> {
+    data: &'a [u8],
+    pos: usize,
+    done: bool,
+}
+
+/// Returns an iterator over the descriptors of the resource template `data`
+pub fn resources(data: &[u8]) -> ResourceIter<'_> {
+    ResourceIter {
+        data,
+        pos: 0,
+        done: false,
+    }
+}
+
+// Reads little endian integers from the body of a descriptor. Fields past the end of the body
+// read as zero.
+fn read_u16(body: &[u8], offset: usize) -> u16 {
+    read_le(body, offset, 2) as u16
+}
+fn read_u32(body: &[u8], offset: usize) -> u32 {
+    read_le(body, offset, 4) as u32
+}
+fn read_u64(body: &[u8], offset: usize) -> u64 {
+    read_le(body, offset, 8)
+}
+fn read_le(body: &[u8], offset: usize, size: usize) -> u64 {
+    (0..size).rev().fold(0, |value, idx| {
+        value << 8 | *body.get(offset + idx).unwrap_or(&0) as u64
+    })
+}
+
+/// Small resource descriptor types
+mod small {
+    pub const IRQ: u8 = 0x04;
+    pub const DMA: u8 = 0x05;
+    pub const START_DEPENDENT: u8 = 0x06;
+    pub const END_DEPENDENT: u8 = 0x07;
+    pub const IO: u8 = 0x08;
+    pub const FIXED_IO: u8 = 0x09;
+    pub const END_TAG: u8 = 0x0F;
+}
+
+/// Large resource descriptor types
+mod large {
+    pub const MEMORY24: u8 = 0x01;
+    pub const GENERIC_REGISTER: u8 = 0x02;
+    pub const MEMORY32: u8 = 0x05;
+    pub const FIXED_MEMORY32: u8 = 0x06;
+    pub const DWORD_ADDRESS_SPACE: u8 = 0x07;
+    pub const WORD_ADDRESS_SPACE: u8 = 0x08;
+    pub const EXTENDED_IRQ: u8 = 0x09;
+    pub const QWORD_ADDRESS_SPACE: u8 = 0x0A;
+    pub const EXTENDED_ADDRESS_SPACE: u8 = 0x0B;
+}
+
+// Decodes the body of a small descriptor
+fn decode_small(kind: u8, body: &[u8]) -> Resource {
+    match kind {
+        small::IRQ => {
+            // Without the information byte, the interrupt is edge triggered and active high
+            let info = body.get(2).copied().unwrap_or(0x01);
+            Resource::Irq {
+                irqs: read_u16(body, 0),
+                mode: InterruptMode {
+                    edge_triggered: info & 0x01 != 0,
+                    active_low: info & 0x08 != 0,
+                    shared: info & 0x10 != 0,
+                    wake_capable: info & 0x20 != 0,
+                },
+            }
+        }
+        small::DMA => Resource::Dma {
+            channels: body.first().copied().unwrap_or(0),
+            flags: body.get(1).copied().unwrap_or(0),
+        },
+        small::START_DEPENDENT => Resource::StartDependent,
+        small::END_DEPENDENT => Resource::EndDependent,
+        small::IO => Resource::Io {
+            decode_16bit: body.first().is_some_and(|info| info & 1 != 0),
+            min: read_u16(body, 1),
+            max: read_u16(body, 3),
+            alignment: body.get(5).copied().unwrap_or(0),
+            length: body.get(6).copied().unwrap_or(0),
+        },
+        small::FIXED_IO => Resource::FixedIo {
+            base: read_u16(body, 0) & 0x3FF,
+            length: body.get(2).copied().unwrap_or(0),
+        },
+        kind => Resource::Other(kind << 3),
+    }
+}
+
+// Decodes the body of a large descriptor
+fn decode_large(kind: u8, body: &[u8]) -> Resource {
+    let writable = body.first().is_some_and(|info| info & 1 != 0);
+
+    match kind {
+        large::MEMORY24 => Resource::Memory24 {
+            writable,
+            // 24-bit memory ranges are given in 256 bytes units
+            min: (read_u16(body, 1) as u32) << 8,
+            max: (read_u16(body, 3) as u32) << 8,
+            alignment: read_u16(body, 5) as u32,
+            length: (read_u16(body, 7) as u32) << 8,
+        },
+        large::MEMORY32 => Resource::Memory32 {
+            writable,
+            min: read_u32(body, 1),
+            max: read_u32(body, 5),
+            alignment: read_u32(body, 9),
+            length: read_u32(body, 13),
+        },
+        large::FIXED_MEMORY32 => Resource::FixedMemory32 {
+            writable,
+            base: read_u32(body, 1),
+            length: read_u32(body, 5),
+        },
+        large::WORD_ADDRESS_SPACE => address_space(body, 3, 2),
+        large::DWORD_ADDRESS_SPACE => address_space(body, 3, 4),
+        large::QWORD_ADDRESS_SPACE => address_space(body, 3, 8),
+        // The extended descriptor has a revision and a reserved byte before its ranges
+        large::EXTENDED_ADDRESS_SPACE => address_space(body, 5, 8),
+        large::EXTENDED_IRQ => {
+            let flags = body.first().copied().unwrap_or(0);
+            let count = body.get(1).copied().unwrap_or(0);
+            let mut interrupts = [0; MAX_EXTENDED_IRQS];
+            for (idx, interrupt) in interrupts.iter_mut().enumerate().take(count as usize) {
+                *interrupt = read_u32(body, 2 + idx * 4);
+            }
+
+            Resource::ExtendedIrq {
+                mode: InterruptMode {
+                    edge_triggered: flags & 0x02 != 0,
+                    active_low: flags & 0x04 != 0,
+                    shared: flags & 0x08 != 0,
+                    wake_capable: flags & 0x10 != 0,
+                },
+                producer: flags & 0x01 == 0,
+                count,
+                interrupts,
+            }
+        }
+        large::GENERIC_REGISTER => {
+            let gas = GenericAddressStructure {
+                address_space_id: body.first().copied().unwrap_or(0),
+                register_bit_width: body.get(1).copied().unwrap_or(0),
+                register_bit_offset: body.get(2).copied().unwrap_or(0),
+                access_size: body.get(3).copied().unwrap_or(0),
+                address: read_u64(body, 4),
+            };
+            Resource::GenericRegister(gas)
+        }
+        kind => Resource::Other(0x80 | kind),
+    }
+}
+
+// Decodes an address space descriptor, whose ranges start at `offset` into `body` and are `size`
+// bytes wide
+fn address_space(body: &[u8], offset: usize, size: usize) -> Resource {
+    let field = |idx: usize| read_le(body, offset + idx * size, size);
+
+    Resource::AddressSpace(AddressSpace {
+        kind: AddressKind::from(body.first().copied().unwrap_or(0)),
+        flags: body.get(1).copied().unwrap_or(0),
+        type_flags: body.get(2).copied().unwrap_or(0),
+        granularity: field(0),
+        min: field(1),
+        max: field(2),
+        translation: field(3),
+        length: field(4),
+    })
+}
+
+impl<'a> Iterator for ResourceIter<'a> {
+    type Item = Result<Resource, AmlError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done || self.pos >= self.data.len() {
+            return None;
+        }
+
+        let tag = self.data[self.pos];
+        let (kind, body_start, body_len) = if tag & 0x80 == 0 {
+            // Small descriptor: the type and the length are both in the tag
+            ((tag >> 3) & 0xF, self.pos + 1, (tag & 0x7) as usize)
+        } else {
+            let len = match self.data.get(self.pos + 1..self.pos + 3) {
+                Some(len) => u16::from_le_bytes(len.try_into().unwrap()) as usize,
+                None => {
+                    self.done = true;
+                    return Some(Err(AmlError::UnexpectedEnd));
+                }
+            };
+            (tag & 0x7F, self.pos + 3, len)
+        };
+
+        let body = match self.data.get(body_start..body_start + body_len) {
+            Some(body) => body,
+            None => {
+                self.done = true;
+                return Some(Err(AmlError::UnexpectedEnd));
+            }
+        };
+        self.pos = body_start + body_len;
+
+        if tag & 0x80 == 0 {
+            if kind == small::END_TAG {
+                self.done = true;
+                return None;
+            }
+            Some(Ok(decode_small(kind, body)))
+        } else {
+            Some(Ok(decode_large(kind, body)))
+        }
+    }
+}
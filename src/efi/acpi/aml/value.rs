@@ -0,0 +1,204 @@
+//! Module that holds the values AML code works with, and the arenas that store their contents
+use crate::efi::acpi::aml::{namespace::NodeId, AmlError};
+
+/// A range of bytes in the interpreter's byte arena, holding the contents of a string or a buffer
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Bytes {
+    start: u32,
+    len: u32,
+}
+
+impl Bytes {
+    pub fn len(&self) -> usize {
+        self.len as usize
+    }
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Returns the range of bytes that starts `offset` bytes into this one
+    pub fn sub(&self, offset: usize, len: usize) -> Self {
+        let offset = offset.min(self.len as usize);
+        Self {
+            start: self.start + offset as u32,
+            len: len.min(self.len as usize - offset) as u32,
+        }
+    }
+}
+
+/// A range of values in the interpreter's value arena, holding the elements of a package
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Elements {
+    start: u32,
+    len: u32,
+}
+
+impl Elements {
+    pub fn len(&self) -> usize {
+        self.len as usize
+    }
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+}
+
+/// What an `Index` reference points into
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IndexTarget {
+    Buffer(Bytes),
+    Package(Elements),
+}
+
+/// A value, as manipulated by AML code. Strings, buffers and packages are handles into the arenas
+/// of the interpreter that created them.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum AmlValue {
+    #[default]
+    Uninitialized,
+    Integer(u64),
+    String(Bytes),
+    Buffer(Bytes),
+    Package(Elements),
+    /// A reference to a namespace object, as created by `RefOf`, or by naming an object in a
+    /// package
+    Reference(NodeId),
+    /// A name from a package that did not exist yet when the package was built, relative to
+    /// `scope`. It is resolved when used.
+    Name {
+        path: Bytes,
+        scope: NodeId,
+    },
+    /// A reference to a single element of a buffer or a package, as created by `Index`
+    Index {
+        target: IndexTarget,
+        index: u32,
+    },
+}
+
+impl AmlValue {
+    /// Returns the ACPI object type code, as reported by the `ObjectType` operator
+    pub fn type_code(&self) -> u64 {
+        match self {
+            Self::Uninitialized => 0,
+            Self::Integer(_) => 1,
+            Self::String(_) => 2,
+            Self::Buffer(_) => 3,
+            Self::Package(_) => 4,
+            Self::Reference(_) | Self::Name { .. } | Self::Index { .. } => 0x14,
+        }
+    }
+}
+
+/// A fixed size arena, from which persistent allocations grow from the start and temporary ones
+/// from the end. Temporary allocations are all released at once.
+pub struct Arena<T: Copy, const N: usize> {
+    data: [T; N],
+    // End of the persistent allocations
+    low: usize,
+    // Start of the temporary allocations
+    high: usize,
+}
+
+impl<T: Copy, const N: usize> Arena<T, N> {
+    pub const fn new(init: T) -> Self {
+        Self {
+            data: [init; N],
+            low: 0,
+            high: N,
+        }
+    }
+
+    // Reserves `len` elements, filled with `init`, returning the start of the allocation
+    fn alloc(&mut self, len: usize, persistent: bool, init: T) -> Result<u32, AmlError> {
+        if self.high - self.low < len {
+            return Err(AmlError::OutOfMemory);
+        }
+
+        let start = if persistent {
+            self.low += len;
+            self.low - len
+        } else {
+            self.high -= len;
+            self.high
+        };
+
+        self.data[start..start + len].fill(init);
+        Ok(start as u32)
+    }
+
+    // Returns whether the allocation that starts at `start` is persistent
+    fn is_persistent_at(&self, start: u32) -> bool {
+        (start as usize) < self.low
+    }
+
+    /// Frees all the temporary allocations
+    pub fn release_temporaries(&mut self) {
+        self.high = N;
+    }
+}
+
+impl<const N: usize> Arena<u8, N> {
+    /// Allocates a zeroed range of `len` bytes
+    pub fn alloc_bytes(&mut self, len: usize, persistent: bool) -> Result<Bytes, AmlError> {
+        let start = self.alloc(len, persistent, 0)?;
+        Ok(Bytes {
+            start,
+            len: len as u32,
+        })
+    }
+
+    /// Allocates a range of bytes holding a copy of `data`
+    pub fn copy_bytes(&mut self, data: &[u8], persistent: bool) -> Result<Bytes, AmlError> {
+        let bytes = self.alloc_bytes(data.len(), persistent)?;
+        self.get_mut(bytes).copy_from_slice(data);
+        Ok(bytes)
+    }
+
+    /// Allocates a new range of bytes, holding a copy of the `bytes` range
+    pub fn duplicate(&mut self, bytes: Bytes, persistent: bool) -> Result<Bytes, AmlError> {
+        let copy = self.alloc_bytes(bytes.len(), persistent)?;
+        let src = bytes.start as usize;
+        self.data
+            .copy_within(src..src + bytes.len(), copy.start as usize);
+        Ok(copy)
+    }
+
+    /// Copies as much of `src` as fits into `dst`, zeroing the rest of `dst`
+    pub fn copy_into(&mut self, src: Bytes, dst: Bytes) {
+        let len = src.len().min(dst.len());
+        let src = src.start as usize;
+        self.data.copy_within(src..src + len, dst.start as usize);
+        self.get_mut(dst)[len..].fill(0);
+    }
+
+    pub fn get(&self, bytes: Bytes) -> &[u8] {
+        &self.data[bytes.start as usize..(bytes.start + bytes.len) as usize]
+    }
+
+    pub fn get_mut(&mut self, bytes: Bytes) -> &mut [u8] {
+        &mut self.data[bytes.start as usize..(bytes.start + bytes.len) as usize]
+    }
+}
+
+impl<const N: usize> Arena<AmlValue, N> {
+    /// Allocates `len` uninitialized package elements
+    pub fn alloc_elements(&mut self, len: usize, persistent: bool) -> Result<Elements, AmlError> {
+        let start = self.alloc(len, persistent, AmlValue::Uninitialized)?;
+        Ok(Elements {
+            start,
+            len: len as u32,
+        })
+    }
+
+    pub fn is_persistent(&self, elements: Elements) -> bool {
+        self.is_persistent_at(elements.start)
+    }
+
+    pub fn get(&self, elements: Elements) -> &[AmlValue] {
+        &self.data[elements.start as usize..(elements.start + elements.len) as usize]
+    }
+
+    pub fn get_mut(&mut self, elements: Elements) -> &mut [AmlValue] {
+        &mut self.data[elements.start as usize..(elements.start + elements.len) as usize]
+    }
+}
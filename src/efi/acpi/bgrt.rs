@@ -0,0 +1,172 @@
+use crate::bmp::{Bmp, BmpError};
+use crate::efi::acpi::{phys_slice, read, AcpiTable, DescriptionHeader, FromBytes, ParseError};
+use crate::efi::EfiStatus;
+use core::fmt;
+use core::mem::size_of;
+
+/// The largest boot logo we accept, such that a corrupted BMP header does not make us read
+/// through all of memory
+pub const MAX_BOOT_IMAGE_SIZE: usize = 32 << 20;
+
+/// Boot Graphics Resource Table.
+/// It tells that the firmware drew an image, usually the vendor logo, on the screen during boot,
+/// where the image is in memory and where it was drawn. The image is held in boot services data
+/// memory, so it must be used before that memory is reclaimed.
+pub struct BGRT {
+    header: DescriptionHeader,
+    fields: BgrtFields,
+}
+
+// The fields that follow the header
+#[derive(Clone, Copy)]
+#[repr(C, packed)]
+struct BgrtFields {
+    // Version of the structure, which must be 1
+    version: u16,
+    // Whether the image is displayed in bit 0, and its orientation offset in bits 1 and 2
+    status: u8,
+    // The format of the image, 0 being a BMP
+    image_type: u8,
+    // The physical address of the image
+    image_addr: u64,
+    // The position of the top left corner of the image on the screen
+    image_offset_x: u32,
+    image_offset_y: u32,
+}
+
+unsafe impl FromBytes for BgrtFields {}
+
+/// The rotation the image was drawn with, clockwise
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BgrtOrientation {
+    None,
+    Rotated90,
+    Rotated180,
+    Rotated270,
+}
+
+/// The format of the boot image
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BgrtImageType {
+    Bitmap,
+    Reserved(u8),
+}
+
+/// Reasons for which the boot image cannot be used
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BgrtImageError {
+    /// The image is not a BMP
+    UnsupportedImageType(u8),
+    /// The image is at the null address
+    NullAddress,
+    /// The BMP size is above `MAX_BOOT_IMAGE_SIZE`
+    TooLarge(usize),
+    /// The BMP is malformed
+    Bmp(BmpError),
+    /// No memory could be allocated to copy the image out of boot services memory, for the given
+    /// EFI status
+    OutOfMemory(EfiStatus),
+}
+
+impl From<BmpError> for BgrtImageError {
+    fn from(err: BmpError) -> Self {
+        Self::Bmp(err)
+    }
+}
+
+// The signature found in the first 4 bytes from the BGRT table
+const BGRT_SIGNATURE: &[u8; 4] = b"BGRT";
+
+// Offset of the file size in the BMP file header
+const BMP_FILE_SIZE_OFFSET: usize = 2;
+
+impl BGRT {
+    /// Reads the BGRT from the bytes of the entire table
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, ParseError> {
+        let (header, table) = DescriptionHeader::parse_table(bytes, BGRT_SIGNATURE)?;
+        let fields = read(table, size_of::<DescriptionHeader>())?;
+
+        Ok(BGRT { header, fields })
+    }
+
+    pub fn version(&self) -> u16 {
+        self.fields.version
+    }
+
+    /// Returns whether the image is currently on the screen. This is no longer the case once the
+    /// screen was cleared or its mode changed.
+    pub fn is_displayed(&self) -> bool {
+        self.fields.status & 1 != 0
+    }
+
+    pub fn orientation(&self) -> BgrtOrientation {
+        match self.fields.status >> 1 & 0b11 {
+            0 => BgrtOrientation::None,
+            1 => BgrtOrientation::Rotated90,
+            2 => BgrtOrientation::Rotated180,
+            _ => BgrtOrientation::Rotated270,
+        }
+    }
+
+    pub fn image_type(&self) -> BgrtImageType {
+        match self.fields.image_type {
+            0 => BgrtImageType::Bitmap,
+            image_type => BgrtImageType::Reserved(image_type),
+        }
+    }
+
+    /// Returns the physical address of the image
+    pub fn image_addr(&self) -> u64 {
+        self.fields.image_addr
+    }
+
+    /// Returns the position of the top left corner of the image on the screen, as X and Y
+    /// offsets in pixels
+    pub fn image_offset(&self) -> (u32, u32) {
+        (self.fields.image_offset_x, self.fields.image_offset_y)
+    }
+
+    /// Reads and validates the BMP image the table points to. The memory holding it must not have
+    /// been reclaimed yet, which is why the image cannot outlive the table: use `BootLogo` to keep
+    /// it around.
+    pub fn image(&self) -> Result<Bmp<'_>, BgrtImageError> {
+        if let BgrtImageType::Reserved(image_type) = self.image_type() {
+            return Err(BgrtImageError::UnsupportedImageType(image_type));
+        }
+
+        let addr = self.image_addr() as usize;
+        // SAFETY: the firmware keeps the image in boot services data memory, which is mapped
+        let file_header = unsafe { phys_slice(addr, BMP_FILE_SIZE_OFFSET + size_of::<u32>()) }
+            .map_err(|_| BgrtImageError::NullAddress)?;
+        let size = u32::from_le_bytes(file_header[BMP_FILE_SIZE_OFFSET..].try_into().unwrap());
+        let size = size as usize;
+        if size > MAX_BOOT_IMAGE_SIZE {
+            return Err(BgrtImageError::TooLarge(size));
+        }
+
+        let bytes = unsafe { phys_slice(addr, size) }.map_err(|_| BgrtImageError::NullAddress)?;
+        Ok(Bmp::from_bytes(bytes)?)
+    }
+}
+
+impl fmt::Debug for BGRT {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("BGRT")
+            .field("Revision", &self.header.revision())
+            .field("Version", &self.version())
+            .field("Displayed", &self.is_displayed())
+            .field("Orientation", &self.orientation())
+            .field("Image Type", &self.image_type())
+            .field("Image Address", &format_args!("{:#x}", self.image_addr()))
+            .field("Image Offset", &self.image_offset())
+            .finish()
+    }
+}
+
+impl AcpiTable for BGRT {
+    const SIGNATURE: &'static [u8; 4] = BGRT_SIGNATURE;
+
+    fn from_bytes(bytes: &[u8]) -> Result<Self, ParseError> {
+        BGRT::from_bytes(bytes)
+    }
+}
@@ -0,0 +1,284 @@
+use crate::efi::acpi::{
+    read, serial_interface, AcpiTable, DescriptionHeader, FromBytes, GenericAddressStructure,
+    ParseError,
+};
+use crate::print;
+use core::fmt;
+use core::mem::size_of;
+
+/// The maximum number of debug devices kept from the DBG2
+pub const MAX_DBG2_DEVICES: usize = 8;
+
+/// The maximum number of base address registers kept for each debug device
+pub const MAX_DBG2_ADDRESSES: usize = 4;
+
+/// The maximum length of the ACPI namespace path of a debug device, without its null terminator
+pub const MAX_DBG2_NAMESPACE_LENGTH: usize = 64;
+
+/// Debug port types, each having its own subtypes
+pub mod debug_port_type {
+    /// Serial port, with the `serial_interface` types as subtypes
+    pub const SERIAL: u16 = 0x8000;
+    /// IEEE 1394 (FireWire) host controller
+    pub const IEEE1394: u16 = 0x8001;
+    /// USB host controller
+    pub const USB: u16 = 0x8002;
+    /// Network controller, with its PCI vendor ID as subtype
+    pub const NET: u16 = 0x8003;
+}
+
+/// Subtypes of the USB debug ports
+pub mod usb_subtype {
+    pub const XHCI: u16 = 0x0000;
+    pub const EHCI: u16 = 0x0001;
+}
+
+/// A transport a debugger or a log sink can use, as advertised by the firmware
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DebugTransport {
+    /// A serial port with the given `serial_interface` type
+    Serial(u16),
+    Ieee1394,
+    Xhci,
+    Ehci,
+    /// A network controller from the vendor with the given PCI vendor ID
+    Net(u16),
+    /// A port type or subtype we do not know
+    Other { port_type: u16, port_subtype: u16 },
+}
+
+impl DebugTransport {
+    /// Decodes the transport from the port type and subtype of a debug device
+    pub fn new(port_type: u16, port_subtype: u16) -> Self {
+        match (port_type, port_subtype) {
+            (debug_port_type::SERIAL, _) => Self::Serial(port_subtype),
+            (debug_port_type::IEEE1394, 0) => Self::Ieee1394,
+            (debug_port_type::USB, usb_subtype::XHCI) => Self::Xhci,
+            (debug_port_type::USB, usb_subtype::EHCI) => Self::Ehci,
+            (debug_port_type::NET, _) => Self::Net(port_subtype),
+            _ => Self::Other {
+                port_type,
+                port_subtype,
+            },
+        }
+    }
+}
+
+/// A debug device described by the DBG2, along with where its registers are
+#[derive(Clone, Copy)]
+pub struct DebugDevice {
+    port_type: u16,
+    port_subtype: u16,
+    // The register blocks of the device, along with their size in bytes
+    addresses: [(GenericAddressStructure, u32); MAX_DBG2_ADDRESSES],
+    // Number of populated entries in `addresses`
+    naddresses: usize,
+    // The ACPI namespace path of the device, without its null terminator
+    namespace: [u8; MAX_DBG2_NAMESPACE_LENGTH],
+    // Number of bytes of `namespace` in use
+    namespace_length: usize,
+}
+
+impl DebugDevice {
+    pub fn port_type(&self) -> u16 {
+        self.port_type
+    }
+    pub fn port_subtype(&self) -> u16 {
+        self.port_subtype
+    }
+
+    /// Returns the transport the device provides
+    pub fn transport(&self) -> DebugTransport {
+        DebugTransport::new(self.port_type, self.port_subtype)
+    }
+
+    /// Returns whether the device is a serial port driven like a 16550 UART, which is what
+    /// `SerialWriter` knows how to drive
+    pub fn is_16550_compatible(&self) -> bool {
+        self.port_type == debug_port_type::SERIAL
+            && serial_interface::is_16550_compatible(self.port_subtype)
+    }
+
+    /// Returns the register blocks of the device, each with its size in bytes
+    pub fn addresses(&self) -> impl Iterator<Item = &(GenericAddressStructure, u32)> {
+        self.addresses[..self.naddresses].iter()
+    }
+
+    /// Returns the first register block of the device, which is the only one serial ports have
+    pub fn base_addr(&self) -> Option<GenericAddressStructure> {
+        self.addresses().next().map(|(gas, _)| *gas)
+    }
+
+    /// Returns the ACPI namespace path of the device, or `None` if the device is not in the
+    /// namespace, which the table tells with a "." path
+    pub fn namespace_path(&self) -> Option<&str> {
+        let path = core::str::from_utf8(&self.namespace[..self.namespace_length]).ok()?;
+        (path != "." && !path.is_empty()).then_some(path)
+    }
+}
+
+impl fmt::Debug for DebugDevice {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("DebugDevice")
+            .field("Transport", &self.transport())
+            .field("Namespace Path", &self.namespace_path())
+            .field("Addresses", &&self.addresses[..self.naddresses])
+            .finish()
+    }
+}
+
+// Debug Device Information Structure, which is followed by its base address registers, the size
+// of each of them, its namespace string and its OEM data, all at offsets from its start
+#[derive(Clone, Copy)]
+#[repr(C, packed)]
+struct DebugDeviceInfo {
+    revision: u8,
+    length: u16,
+    number_of_addresses: u8,
+    namespace_string_length: u16,
+    namespace_string_offset: u16,
+    oem_data_length: u16,
+    oem_data_offset: u16,
+    port_type: u16,
+    port_subtype: u16,
+    reserved: u16,
+    base_address_register_offset: u16,
+    address_size_offset: u16,
+}
+
+unsafe impl FromBytes for DebugDeviceInfo {}
+
+/// Debug Port Table 2.
+/// It describes the devices the firmware sets aside for debugging: serial ports, USB and IEEE 1394
+/// host controllers and network controllers. Unlike the SPCR, which gives the console, these are
+/// the ports a kernel debugger or a log sink should use.
+pub struct DBG2 {
+    header: DescriptionHeader,
+    // The debug devices, in the order they appear in the table
+    devices: [Option<DebugDevice>; MAX_DBG2_DEVICES],
+    // Number of populated entries in `devices`
+    ndevices: usize,
+}
+
+// The signature found in the first 4 bytes from the DBG2 table
+const DBG2_SIGNATURE: &[u8; 4] = b"DBG2";
+
+impl DBG2 {
+    /// Reads the DBG2 from the bytes of the entire table, keeping up to `MAX_DBG2_DEVICES`
+    /// devices. Devices whose structure, registers or namespace string go past the end of the
+    /// table are rejected.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, ParseError> {
+        let (header, table) = DescriptionHeader::parse_table(bytes, DBG2_SIGNATURE)?;
+
+        let devices_offset = read::<u32>(table, size_of::<DescriptionHeader>())? as usize;
+        let ndevices: u32 = read(table, size_of::<DescriptionHeader>() + 4)?;
+
+        let mut dbg2 = DBG2 {
+            header,
+            devices: [None; MAX_DBG2_DEVICES],
+            ndevices: 0,
+        };
+
+        let mut offset = devices_offset;
+        for _ in 0..ndevices {
+            let info: DebugDeviceInfo = read(table, offset)?;
+            let length = info.length as usize;
+            if length < size_of::<DebugDeviceInfo>() {
+                return Err(ParseError::InvalidLength(length));
+            }
+            let entry = table
+                .get(offset..offset + length)
+                .ok_or(ParseError::Truncated {
+                    needed: offset + length,
+                    available: table.len(),
+                })?;
+
+            if dbg2.ndevices == MAX_DBG2_DEVICES {
+                print!(
+                    "The DBG2 holds more than {} devices, ignoring the rest\n",
+                    MAX_DBG2_DEVICES
+                );
+                break;
+            }
+            dbg2.devices[dbg2.ndevices] = Some(Self::parse_device(&info, entry)?);
+            dbg2.ndevices += 1;
+
+            offset += length;
+        }
+
+        Ok(dbg2)
+    }
+
+    // Reads the device described by `info`, whose whole structure is `entry`
+    fn parse_device(info: &DebugDeviceInfo, entry: &[u8]) -> Result<DebugDevice, ParseError> {
+        let mut device = DebugDevice {
+            port_type: info.port_type,
+            port_subtype: info.port_subtype,
+            addresses: [(GenericAddressStructure::default(), 0); MAX_DBG2_ADDRESSES],
+            naddresses: 0,
+            namespace: [0; MAX_DBG2_NAMESPACE_LENGTH],
+            namespace_length: 0,
+        };
+
+        let addresses_offset = info.base_address_register_offset as usize;
+        let sizes_offset = info.address_size_offset as usize;
+        let naddresses = (info.number_of_addresses as usize).min(MAX_DBG2_ADDRESSES);
+        for (idx, address) in device.addresses.iter_mut().enumerate().take(naddresses) {
+            let gas = read(entry, addresses_offset + idx * size_of::<GenericAddressStructure>())?;
+            let size = read(entry, sizes_offset + idx * size_of::<u32>())?;
+            *address = (gas, size);
+        }
+        device.naddresses = naddresses;
+
+        // The namespace string is null terminated, and its length includes the terminator
+        let namespace_offset = info.namespace_string_offset as usize;
+        let namespace_length = info.namespace_string_length as usize;
+        let namespace = entry
+            .get(namespace_offset..namespace_offset + namespace_length)
+            .ok_or(ParseError::Truncated {
+                needed: namespace_offset + namespace_length,
+                available: entry.len(),
+            })?;
+        let namespace = namespace.split(|&byte| byte == 0).next().unwrap_or(&[]);
+        if namespace.len() > MAX_DBG2_NAMESPACE_LENGTH {
+            return Err(ParseError::InvalidLength(namespace.len()));
+        }
+        device.namespace[..namespace.len()].copy_from_slice(namespace);
+        device.namespace_length = namespace.len();
+
+        Ok(device)
+    }
+
+    /// Returns all the debug devices, in the order they appear in the table
+    pub fn devices(&self) -> impl Iterator<Item = &DebugDevice> {
+        self.devices[..self.ndevices].iter().flatten()
+    }
+
+    /// Returns the debug devices that provide the `transport`
+    pub fn find(&self, transport: DebugTransport) -> impl Iterator<Item = &DebugDevice> {
+        self.devices().filter(move |device| device.transport() == transport)
+    }
+
+    /// Returns the serial debug ports that `SerialWriter` can drive, in the order the firmware
+    /// lists them
+    pub fn serial_ports(&self) -> impl Iterator<Item = &DebugDevice> {
+        self.devices().filter(|device| device.is_16550_compatible())
+    }
+}
+
+impl fmt::Debug for DBG2 {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("DBG2")
+            .field("Revision", &self.header.revision())
+            .field("Devices", &&self.devices[..self.ndevices])
+            .finish()
+    }
+}
+
+impl AcpiTable for DBG2 {
+    const SIGNATURE: &'static [u8; 4] = DBG2_SIGNATURE;
+
+    fn from_bytes(bytes: &[u8]) -> Result<Self, ParseError> {
+        DBG2::from_bytes(bytes)
+    }
+}
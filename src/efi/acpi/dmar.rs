@@ -0,0 +1,463 @@
+use crate::efi::acpi::{read, AcpiTable, DescriptionHeader, FromBytes, ParseError};
+use crate::pci::PciAddress;
+use bitflags::bitflags;
+use core::fmt;
+use core::mem::size_of;
+
+/// The maximum number of remapping hardware units kept from the DMAR, which is enough for servers
+/// with several units on each socket
+pub const MAX_DRHD_UNITS: usize = 32;
+
+/// The maximum number of reserved memory regions kept from the DMAR
+pub const MAX_RMRR_REGIONS: usize = 16;
+
+/// The maximum number of root port ATS capability structures kept from the DMAR, which is one for
+/// each PCI segment group at most
+pub const MAX_ATSR_UNITS: usize = 4;
+
+/// The maximum number of device scopes kept for each structure of the DMAR
+pub const MAX_DEVICE_SCOPES: usize = 16;
+
+/// The maximum number of hops kept in the path of a device scope, which is the depth of the PCI
+/// hierarchy below the start bus
+pub const MAX_SCOPE_PATH: usize = 8;
+
+bitflags! {
+    #[repr(transparent)]
+    #[derive(Default, Clone, Copy, Debug, PartialEq, Eq, Hash)]
+    pub struct DmarFlags: u8 {
+        /// The platform supports interrupt remapping
+        const INTR_REMAP = 1 << 0;
+        /// The firmware asks the OS not to enable x2APIC mode
+        const X2APIC_OPT_OUT = 1 << 1;
+        /// The firmware set up DMA protection for the devices it uses, and supports the OS
+        /// taking over without a window of unprotected DMA
+        const DMA_CTRL_PLATFORM_OPT_IN = 1 << 2;
+    }
+}
+
+/// The kinds of device a device scope refers to
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DeviceScopeType {
+    /// A single PCI endpoint
+    PciEndpoint,
+    /// A PCI-PCI bridge and every device below it
+    PciSubHierarchy,
+    /// An I/O APIC, whose ID is the enumeration ID
+    IoApic,
+    /// An HPET capable of MSI, whose HPET number is the enumeration ID
+    HpetMsi,
+    /// An ACPI namespace device, whose ANDD number is the enumeration ID
+    AcpiNamespaceDevice,
+    Reserved(u8),
+}
+
+impl From<u8> for DeviceScopeType {
+    fn from(value: u8) -> Self {
+        match value {
+            1 => Self::PciEndpoint,
+            2 => Self::PciSubHierarchy,
+            3 => Self::IoApic,
+            4 => Self::HpetMsi,
+            5 => Self::AcpiNamespaceDevice,
+            _ => Self::Reserved(value),
+        }
+    }
+}
+
+/// A device, or a hierarchy of devices, a structure of the DMAR applies to. The device is given as
+/// a path from a bus: the first hop is the device and function on the start bus, and every
+/// following hop is a device and function on the secondary bus of the bridge before it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DeviceScope {
+    pub scope_type: DeviceScopeType,
+    /// The I/O APIC ID, HPET number or ACPI device number, depending on `scope_type`
+    pub enumeration_id: u8,
+    pub start_bus: u8,
+    // The hops of the path, as device and function pairs
+    path: [(u8, u8); MAX_SCOPE_PATH],
+    // Number of populated entries in `path`
+    path_length: usize,
+}
+
+impl DeviceScope {
+    /// Returns the hops of the path, as device and function pairs
+    pub fn path(&self) -> &[(u8, u8)] {
+        &self.path[..self.path_length]
+    }
+
+    /// Returns the PCI function the scope refers to, on the segment group `segment`. The
+    /// secondary bus of each bridge along the path is read with `secondary_bus`. Returns `None` if
+    /// the path is empty, or if a bridge along it has no secondary bus.
+    pub fn resolve(
+        &self,
+        segment: u16,
+        mut secondary_bus: impl FnMut(PciAddress) -> Option<u8>,
+    ) -> Option<PciAddress> {
+        let (&(device, function), rest) = self.path().split_first()?;
+        let mut addr = PciAddress::new(segment, self.start_bus, device, function);
+
+        for &(device, function) in rest {
+            let bus = secondary_bus(addr)?;
+            addr = PciAddress::new(segment, bus, device, function);
+        }
+
+        Some(addr)
+    }
+}
+
+/// DMA Remapping Hardware Unit Definition: a remapping unit, with the devices whose DMA it
+/// translates
+#[derive(Debug, Clone, Copy)]
+pub struct Drhd {
+    /// Physical address of the registers of the unit
+    pub register_base: u64,
+    /// Size of the registers of the unit, in 4 KiB pages
+    pub register_pages: u32,
+    pub segment: u16,
+    /// The unit handles every device of its segment group that is not handled by another unit. The
+    /// device scopes then only list the I/O APICs and HPETs.
+    pub include_pci_all: bool,
+    scopes: [Option<DeviceScope>; MAX_DEVICE_SCOPES],
+    // Number of populated entries in `scopes`
+    nscopes: usize,
+}
+
+impl Drhd {
+    pub fn scopes(&self) -> impl Iterator<Item = &DeviceScope> {
+        self.scopes[..self.nscopes].iter().flatten()
+    }
+}
+
+/// Reserved Memory Region Reporting: a range of memory that devices keep on using for DMA after
+/// the firmware hands over, like the USB controllers emulating a legacy keyboard. It must stay
+/// mapped, at the same address, for the devices in its scopes.
+#[derive(Debug, Clone, Copy)]
+pub struct Rmrr {
+    pub segment: u16,
+    /// Address of the first byte of the range, aligned on 4 KiB
+    pub base: u64,
+    /// Address of the last byte of the range
+    pub limit: u64,
+    scopes: [Option<DeviceScope>; MAX_DEVICE_SCOPES],
+    // Number of populated entries in `scopes`
+    nscopes: usize,
+}
+
+impl Rmrr {
+    pub fn scopes(&self) -> impl Iterator<Item = &DeviceScope> {
+        self.scopes[..self.nscopes].iter().flatten()
+    }
+
+    /// Returns whether the range holds `addr`
+    pub fn contains(&self, addr: u64) -> bool {
+        (self.base..=self.limit).contains(&addr)
+    }
+}
+
+/// Root Port ATS Capability Reporting: the root ports of a segment group that support Address
+/// Translation Services
+#[derive(Debug, Clone, Copy)]
+pub struct Atsr {
+    pub segment: u16,
+    /// Every root port of the segment group supports ATS, and the scopes are empty
+    pub all_ports: bool,
+    scopes: [Option<DeviceScope>; MAX_DEVICE_SCOPES],
+    // Number of populated entries in `scopes`
+    nscopes: usize,
+}
+
+impl Atsr {
+    pub fn scopes(&self) -> impl Iterator<Item = &DeviceScope> {
+        self.scopes[..self.nscopes].iter().flatten()
+    }
+}
+
+// The header of every remapping structure
+#[derive(Clone, Copy)]
+#[repr(C, packed)]
+struct RemappingHeader {
+    entry_type: u16,
+    length: u16,
+}
+
+unsafe impl FromBytes for RemappingHeader {}
+
+// DMA Remapping Hardware Unit Definition Structure, followed by its device scopes
+#[derive(Clone, Copy)]
+#[repr(C, packed)]
+struct RawDrhd {
+    header: RemappingHeader,
+    flags: u8,
+    // The size of the registers is 2^size pages, starting with revision 3 of the table
+    size: u8,
+    segment: u16,
+    register_base: u64,
+}
+
+unsafe impl FromBytes for RawDrhd {}
+
+// Reserved Memory Region Reporting Structure, followed by its device scopes
+#[derive(Clone, Copy)]
+#[repr(C, packed)]
+struct RawRmrr {
+    header: RemappingHeader,
+    reserved: u16,
+    segment: u16,
+    base: u64,
+    limit: u64,
+}
+
+unsafe impl FromBytes for RawRmrr {}
+
+// Root Port ATS Capability Reporting Structure, followed by its device scopes
+#[derive(Clone, Copy)]
+#[repr(C, packed)]
+struct RawAtsr {
+    header: RemappingHeader,
+    flags: u8,
+    reserved: u8,
+    segment: u16,
+}
+
+unsafe impl FromBytes for RawAtsr {}
+
+// Device Scope Structure, followed by its path
+#[derive(Clone, Copy)]
+#[repr(C, packed)]
+struct RawDeviceScope {
+    scope_type: u8,
+    length: u8,
+    reserved: u16,
+    enumeration_id: u8,
+    start_bus: u8,
+}
+
+unsafe impl FromBytes for RawDeviceScope {}
+
+mod dmar_entry_type {
+    pub const DRHD: u16 = 0;
+    pub const RMRR: u16 = 1;
+    pub const ATSR: u16 = 2;
+}
+
+// The signature found in the first 4 bytes from the DMAR table
+const DMAR_SIGNATURE: &[u8; 4] = b"DMAR";
+
+// The number of reserved bytes after the flags of the table
+const DMAR_RESERVED: usize = 10;
+
+/// DMA Remapping Reporting Table.
+/// It describes the Intel VT-d remapping hardware units of the platform, which translate the
+/// addresses devices use for DMA, and which devices each of them handles. It also lists the
+/// memory that devices keep on using after boot, which must stay reachable through the
+/// translation.
+pub struct DMAR {
+    header: DescriptionHeader,
+    // The width of the physical addresses DMA can target, in bits
+    host_address_width: u8,
+    flags: DmarFlags,
+    drhds: [Option<Drhd>; MAX_DRHD_UNITS],
+    // Number of populated entries in `drhds`
+    ndrhds: usize,
+    rmrrs: [Option<Rmrr>; MAX_RMRR_REGIONS],
+    // Number of populated entries in `rmrrs`
+    nrmrrs: usize,
+    atsrs: [Option<Atsr>; MAX_ATSR_UNITS],
+    // Number of populated entries in `atsrs`
+    natsrs: usize,
+}
+
+impl DMAR {
+    /// Reads the DMAR from the bytes of the entire table, which may hold up to `MAX_DRHD_UNITS`
+    /// units, `MAX_RMRR_REGIONS` reserved regions and `MAX_ATSR_UNITS` ATS structures. Structures
+    /// we do not use, like the RHSA, ANDD and SATC, are skipped. Structures that are shorter than
+    /// their header, or that go past the end of the table, are rejected. So are tables with more
+    /// structures than we have room for, as leaving a unit or a reserved region out would leave
+    /// devices without translation, or cut them off from memory they use.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, ParseError> {
+        let (header, table) = DescriptionHeader::parse_table(bytes, DMAR_SIGNATURE)?;
+
+        let host_address_width: u8 = read(table, size_of::<DescriptionHeader>())?;
+        let flags = DmarFlags::from_bits_retain(read(table, size_of::<DescriptionHeader>() + 1)?);
+
+        let mut dmar = DMAR {
+            header,
+            host_address_width: host_address_width.saturating_add(1),
+            flags,
+            drhds: [None; MAX_DRHD_UNITS],
+            ndrhds: 0,
+            rmrrs: [None; MAX_RMRR_REGIONS],
+            nrmrrs: 0,
+            atsrs: [None; MAX_ATSR_UNITS],
+            natsrs: 0,
+        };
+
+        let mut offset = size_of::<DescriptionHeader>() + 2 + DMAR_RESERVED;
+        while offset < table.len() {
+            let entry_header: RemappingHeader = read(table, offset)?;
+            let length = entry_header.length as usize;
+            if length < size_of::<RemappingHeader>() {
+                return Err(ParseError::InvalidLength(length));
+            }
+            let entry = table
+                .get(offset..offset + length)
+                .ok_or(ParseError::Truncated {
+                    needed: offset + length,
+                    available: table.len(),
+                })?;
+
+            match entry_header.entry_type {
+                dmar_entry_type::DRHD => {
+                    let raw: RawDrhd = read(entry, 0)?;
+                    let (scopes, nscopes) = parse_scopes(&entry[size_of::<RawDrhd>()..])?;
+                    let register_pages = if dmar.header.revision() >= 3 {
+                        1u32.checked_shl(raw.size as u32 & 0xF).unwrap_or(1)
+                    } else {
+                        1
+                    };
+                    let drhd = Drhd {
+                        register_base: raw.register_base,
+                        register_pages,
+                        segment: raw.segment,
+                        include_pci_all: raw.flags & 1 != 0,
+                        scopes,
+                        nscopes,
+                    };
+                    push(&mut dmar.drhds, &mut dmar.ndrhds, drhd)?;
+                }
+                dmar_entry_type::RMRR => {
+                    let raw: RawRmrr = read(entry, 0)?;
+                    let (scopes, nscopes) = parse_scopes(&entry[size_of::<RawRmrr>()..])?;
+                    let rmrr = Rmrr {
+                        segment: raw.segment,
+                        base: raw.base,
+                        limit: raw.limit,
+                        scopes,
+                        nscopes,
+                    };
+                    push(&mut dmar.rmrrs, &mut dmar.nrmrrs, rmrr)?;
+                }
+                dmar_entry_type::ATSR => {
+                    let raw: RawAtsr = read(entry, 0)?;
+                    let (scopes, nscopes) = parse_scopes(&entry[size_of::<RawAtsr>()..])?;
+                    let atsr = Atsr {
+                        segment: raw.segment,
+                        all_ports: raw.flags & 1 != 0,
+                        scopes,
+                        nscopes,
+                    };
+                    push(&mut dmar.atsrs, &mut dmar.natsrs, atsr)?;
+                }
+                _ => {}
+            }
+
+            offset += length;
+        }
+
+        Ok(dmar)
+    }
+
+    /// Returns the width of the physical addresses DMA can target, in bits
+    pub fn host_address_width(&self) -> u8 {
+        self.host_address_width
+    }
+
+    pub fn flags(&self) -> DmarFlags {
+        self.flags
+    }
+
+    /// Returns the remapping hardware units, in the order they appear in the table. Units with
+    /// `include_pci_all` come last in their segment group.
+    pub fn drhds(&self) -> impl Iterator<Item = &Drhd> {
+        self.drhds[..self.ndrhds].iter().flatten()
+    }
+
+    /// Returns the reserved memory regions, in the order they appear in the table
+    pub fn rmrrs(&self) -> impl Iterator<Item = &Rmrr> {
+        self.rmrrs[..self.nrmrrs].iter().flatten()
+    }
+
+    /// Returns the root port ATS capability structures, in the order they appear in the table
+    pub fn atsrs(&self) -> impl Iterator<Item = &Atsr> {
+        self.atsrs[..self.natsrs].iter().flatten()
+    }
+}
+
+// Adds `value` to the `count` populated entries of `array`, failing if `array` is full
+fn push<T>(array: &mut [Option<T>], count: &mut usize, value: T) -> Result<(), ParseError> {
+    let limit = array.len();
+    let slot = array
+        .get_mut(*count)
+        .ok_or(ParseError::TooManyEntries { limit })?;
+    *slot = Some(value);
+    *count += 1;
+
+    Ok(())
+}
+
+// Reads the device scopes held by `bytes`, which may hold up to `MAX_DEVICE_SCOPES` of them
+fn parse_scopes(
+    bytes: &[u8],
+) -> Result<([Option<DeviceScope>; MAX_DEVICE_SCOPES], usize), ParseError> {
+    let mut scopes = [None; MAX_DEVICE_SCOPES];
+    let mut nscopes = 0;
+
+    let mut offset = 0;
+    while offset < bytes.len() {
+        let raw: RawDeviceScope = read(bytes, offset)?;
+        let length = raw.length as usize;
+        if length < size_of::<RawDeviceScope>()
+            || !(length - size_of::<RawDeviceScope>()).is_multiple_of(2)
+        {
+            return Err(ParseError::InvalidLength(length));
+        }
+        let hops = bytes
+            .get(offset + size_of::<RawDeviceScope>()..offset + length)
+            .ok_or(ParseError::Truncated {
+                needed: offset + length,
+                available: bytes.len(),
+            })?;
+        if hops.len() / 2 > MAX_SCOPE_PATH {
+            return Err(ParseError::InvalidLength(length));
+        }
+
+        let mut scope = DeviceScope {
+            scope_type: DeviceScopeType::from(raw.scope_type),
+            enumeration_id: raw.enumeration_id,
+            start_bus: raw.start_bus,
+            path: [(0, 0); MAX_SCOPE_PATH],
+            path_length: hops.len() / 2,
+        };
+        for (hop, pair) in scope.path.iter_mut().zip(hops.chunks_exact(2)) {
+            *hop = (pair[0], pair[1]);
+        }
+
+        push(&mut scopes, &mut nscopes, scope)?;
+
+        offset += length;
+    }
+
+    Ok((scopes, nscopes))
+}
+
+impl fmt::Debug for DMAR {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("DMAR")
+            .field("Revision", &self.header.revision())
+            .field("Host Address Width", &self.host_address_width())
+            .field("Flags", &self.flags())
+            .field("DRHD", &&self.drhds[..self.ndrhds])
+            .field("RMRR", &&self.rmrrs[..self.nrmrrs])
+            .field("ATSR", &&self.atsrs[..self.natsrs])
+            .finish()
+    }
+}
+
+impl AcpiTable for DMAR {
+    const SIGNATURE: &'static [u8; 4] = DMAR_SIGNATURE;
+
+    fn from_bytes(bytes: &[u8]) -> Result<Self, ParseError> {
+        DMAR::from_bytes(bytes)
+    }
+}
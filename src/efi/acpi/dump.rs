@@ -0,0 +1,157 @@
+//! Module that dumps the ACPI tables over the serial port, in the text format produced by
+//! `acpidump`, such that `acpixtract` can turn the captured output back into binary tables
+use crate::efi::acpi::{rsdp_bytes, table_bytes, AcpiTable, AcpiTables, FADT};
+use crate::print;
+use core::fmt;
+
+/// The number of bytes shown on each line of a dump
+const BYTES_PER_LINE: usize = 16;
+
+/// Dumps every table reachable from the RSDP the registry `tables` was built from: the tables
+/// referred to by the root table, the DSDT and FACS referred to by the FADT, the root tables
+/// themselves and the RSDP. Tables are dumped even if their checksum is invalid, as they are
+/// meant to be looked at.
+pub fn dump_tables(tables: &AcpiTables) {
+    for table in tables.iter() {
+        dump_table(table.signature_str(), table.addr(), table.bytes());
+
+        // The DSDT and the FACS are only referred to by the FADT
+        if &table.signature() == FADT::SIGNATURE {
+            let Ok(fadt) = FADT::from_bytes(table.bytes()) else {
+                continue;
+            };
+            for addr in [fadt.dsdt_addr(), fadt.facs_addr()].into_iter().flatten() {
+                dump_table_at(addr as usize);
+            }
+        }
+    }
+
+    let rsdp = tables.rsdp();
+    if rsdp.xsdt_addr() != 0 {
+        dump_table_at(rsdp.xsdt_addr() as usize);
+    }
+    if rsdp.rsdt_addr() != 0 {
+        dump_table_at(rsdp.rsdt_addr() as usize);
+    }
+
+    match rsdp_bytes(tables.rsdp_addr()) {
+        Ok(bytes) => dump_table("RSDP", tables.rsdp_addr(), bytes),
+        Err(err) => {
+            print!(
+                "Cannot dump the RSDP at {:#x}: {:?}\n",
+                tables.rsdp_addr(),
+                err
+            );
+        }
+    }
+}
+
+// Dumps the table found at `addr`, whose length is read from its header. The FACS does not have
+// a full description header, but its signature and length are at the same place.
+fn dump_table_at(addr: usize) {
+    match table_bytes(addr) {
+        Ok(bytes) => {
+            let signature = bytes
+                .get(..4)
+                .and_then(|sig| core::str::from_utf8(sig).ok());
+            dump_table(signature.unwrap_or("????"), addr, bytes);
+        }
+        Err(err) => {
+            print!("Cannot dump the ACPI table at {:#x}: {:?}\n", addr, err);
+        }
+    }
+}
+
+/// Dumps the `bytes` of the table `signature` found at `addr`: a line naming the table and giving
+/// its address, followed by its bytes and a blank line
+pub fn dump_table(signature: &str, addr: usize, bytes: &[u8]) {
+    // Printing cannot fail
+    let _ = write_table(&mut PrintWriter, signature, addr, bytes);
+}
+
+// Writes text with `print!`, such that the dumps go wherever the rest of the output goes
+struct PrintWriter;
+
+impl fmt::Write for PrintWriter {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        print!("{}", s);
+        Ok(())
+    }
+}
+
+// Writes the dump of the `bytes` of the table `signature` found at `addr` to `out`
+fn write_table(
+    out: &mut impl fmt::Write,
+    signature: &str,
+    addr: usize,
+    bytes: &[u8],
+) -> fmt::Result {
+    write!(out, "{} @ 0x{:016X}\n", signature, addr)?;
+
+    for (idx, line) in bytes.chunks(BYTES_PER_LINE).enumerate() {
+        write_line(out, idx * BYTES_PER_LINE, line)?;
+    }
+
+    write!(out, "\n")
+}
+
+// Writes the bytes of a single line, in hexadecimal and then as ASCII, with the unprintable
+// characters replaced by dots. Short lines are padded such that the ASCII columns line up.
+fn write_line(out: &mut impl fmt::Write, offset: usize, line: &[u8]) -> fmt::Result {
+    // `acpidump` prints offsets with at least 4 digits, right aligned on 8 characters
+    if offset < 0x1000 {
+        write!(out, "    {:04X}:", offset)?;
+    } else {
+        write!(out, "{:8X}:", offset)?;
+    }
+
+    for byte in line {
+        write!(out, " {:02X}", byte)?;
+    }
+    for _ in line.len()..BYTES_PER_LINE {
+        write!(out, "   ")?;
+    }
+
+    write!(out, "  ")?;
+    for &byte in line {
+        let ascii = if (0x20..0x7F).contains(&byte) {
+            byte as char
+        } else {
+            '.'
+        };
+        write!(out, "{}", ascii)?;
+    }
+    write!(out, "\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::Firecracker;
+
+    #[test]
+    fn acpidump_format() {
+        // The 60 bytes of the Firecracker MCFG make 3 full lines and a partial one, whose ASCII
+        // column still lines up
+        let mut dump = String::new();
+        let mcfg = Firecracker::table("mcfg");
+        write_table(&mut dump, "MCFG", 0x7FB7_E000, mcfg).unwrap();
+        let expected = concat!(
+            "MCFG @ 0x000000007FB7E000\n",
+            "    0000: 4D 43 46 47 3C 00 00 00 01 7F 46 49 52 45 43 4B  MCFG<.....FIRECK\n",
+            "    0010: 46 43 4D 56 4D 43 46 47 00 00 00 00 46 43 41 54  FCMVMCFG....FCAT\n",
+            "    0020: 19 01 24 20 00 00 00 00 00 00 00 00 00 00 C0 EE  ..$ ............\n",
+            "    0030: 00 00 00 00 00 00 00 00 00 00 00 00              ............\n",
+            "\n",
+        );
+        assert_eq!(dump, expected);
+
+        // Offsets past 4 digits still end at the same column
+        let mut line = String::new();
+        write_line(&mut line, 0x1_0000, b"DSDT").unwrap();
+        assert_eq!(
+            line,
+            "   10000: 44 53 44 54                                      DSDT\n"
+        );
+    }
+}
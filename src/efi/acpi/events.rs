@@ -0,0 +1,348 @@
+//! Module that handles the ACPI fixed events, such as the power button being pressed or the RTC
+//! alarm going off. The chipset latches them in the PM1 status registers and, for the enabled
+//! ones, raises the SCI. We do not take the SCI as an interrupt: the status registers are polled
+//! instead, and the events we handle are kept disabled such that they never raise an SCI that
+//! nothing would acknowledge.
+use crate::efi::acpi::{
+    AcpiTables, FixedFeatureFlags, GasError, GenericAddress, GenericAddressStructure, FADT,
+};
+use bitflags::bitflags;
+
+bitflags! {
+    /// The fixed events, as laid out in both the PM1 status and enable registers
+    #[repr(transparent)]
+    #[derive(Default, Clone, Copy, Debug, PartialEq, Eq, Hash)]
+    pub struct FixedEvent: u16 {
+        /// The most significant bit of the PM timer changed
+        const TIMER = 1 << 0;
+        /// The firmware released the global lock while we were waiting for it
+        const GLOBAL = 1 << 5;
+        const POWER_BUTTON = 1 << 8;
+        const SLEEP_BUTTON = 1 << 9;
+        /// The RTC alarm went off
+        const RTC = 1 << 10;
+    }
+}
+
+/// A function called with the fixed event it was registered for, once that event happened
+pub type FixedEventCallback = fn(FixedEvent);
+
+/// Reasons for which the fixed events cannot be handled as asked
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EventError {
+    /// There is no FADT amongst the ACPI tables
+    NoFadt,
+    /// The platform is hardware-reduced ACPI, which has no fixed event registers
+    HardwareReduced,
+    /// The FADT does not describe the PM1a event block, or gives it an invalid length
+    NoEventBlock,
+    /// One of the registers could not be accessed
+    InvalidRegister(GasError),
+    /// These events are not fixed events on this platform, usually because they are handled as
+    /// control method devices instead
+    Unsupported(FixedEvent),
+}
+
+impl From<GasError> for EventError {
+    fn from(value: GasError) -> Self {
+        Self::InvalidRegister(value)
+    }
+}
+
+/// The status and enable registers of a PM1 event block
+#[derive(Debug, Clone, Copy)]
+struct EventRegisters {
+    // Write 1 to clear register of the events that happened
+    status: GenericAddress,
+    // Register of the events that raise the SCI
+    enable: GenericAddress,
+}
+
+impl EventRegisters {
+    // Splits the event block `blk`, which is `len` bytes long, into its status register and its
+    // enable register, which each take half of the block
+    fn new(blk: GenericAddressStructure, len: u8) -> Result<Self, EventError> {
+        let half = len / 2;
+        if half == 0 {
+            return Err(EventError::NoEventBlock);
+        }
+
+        // The access size of the block may be larger than the registers, so it is left for
+        // `decode` to derive from their width
+        let register = |offset: u8| GenericAddressStructure {
+            address_space_id: blk.address_space_id,
+            register_bit_width: half * 8,
+            register_bit_offset: 0,
+            access_size: 0,
+            address: blk.address + offset as u64,
+        };
+
+        Ok(Self {
+            status: register(0).decode()?,
+            enable: register(half).decode()?,
+        })
+    }
+}
+
+/// The fixed events of the platform, along with the callbacks to call when they happen
+pub struct FixedEventHandler {
+    // The registers of the PM1a and, if present, PM1b event blocks. Both must be read and
+    // written for each access.
+    blocks: [Option<EventRegisters>; 2],
+    // The events that are fixed events on this platform
+    supported: FixedEvent,
+    // The events that have a callback
+    registered: FixedEvent,
+    // The callback of each event, by bit position
+    callbacks: [Option<FixedEventCallback>; u16::BITS as usize],
+}
+
+impl FixedEventHandler {
+    /// Sets up the handling of the fixed events described by `fadt`. Events are left as they
+    /// are until a callback is registered for them.
+    pub fn new(fadt: &FADT) -> Result<Self, EventError> {
+        if fadt.is_hw_reduced() {
+            return Err(EventError::HardwareReduced);
+        }
+
+        let len = fadt.pm1_evt_len();
+        let pm1a = fadt.pm1a_evt_blk().ok_or(EventError::NoEventBlock)?;
+        let pm1a = EventRegisters::new(pm1a, len)?;
+        let pm1b = match fadt.pm1b_evt_blk() {
+            Some(pm1b) => Some(EventRegisters::new(pm1b, len)?),
+            None => None,
+        };
+
+        // The buttons and the RTC may be control method devices, signaled through a GPE instead
+        let flags = fadt.flags();
+        let mut supported = FixedEvent::TIMER | FixedEvent::GLOBAL;
+        supported.set(
+            FixedEvent::POWER_BUTTON,
+            !flags.contains(FixedFeatureFlags::PWR_BUTTON),
+        );
+        supported.set(
+            FixedEvent::SLEEP_BUTTON,
+            !flags.contains(FixedFeatureFlags::SLP_BUTTON),
+        );
+        supported.set(FixedEvent::RTC, !flags.contains(FixedFeatureFlags::FIX_RTC));
+
+        Ok(Self {
+            blocks: [Some(pm1a), pm1b],
+            supported,
+            registered: FixedEvent::empty(),
+            callbacks: [None; u16::BITS as usize],
+        })
+    }
+
+    /// Sets up the handling of the fixed events described by the FADT of the ACPI tables
+    pub fn from_acpi(tables: &AcpiTables) -> Result<Self, EventError> {
+        let fadt = tables.find::<FADT>().ok_or(EventError::NoFadt)?;
+        Self::new(&fadt)
+    }
+
+    // Returns the registers of the event blocks that are present
+    fn blocks(&self) -> impl Iterator<Item = &EventRegisters> {
+        self.blocks.iter().flatten()
+    }
+
+    /// Returns the events that are fixed events on this platform
+    pub fn supported(&self) -> FixedEvent {
+        self.supported
+    }
+
+    /// Returns the events that happened, whether they are enabled or not
+    pub fn status(&self) -> FixedEvent {
+        let status = self.blocks().fold(0, |status, block| {
+            status | unsafe { block.status.read() } as u16
+        });
+        FixedEvent::from_bits_truncate(status)
+    }
+
+    /// Returns the events that raise the SCI
+    pub fn enabled(&self) -> FixedEvent {
+        let enable = self.blocks().fold(0, |enable, block| {
+            enable | unsafe { block.enable.read() } as u16
+        });
+        FixedEvent::from_bits_truncate(enable)
+    }
+
+    // Clears the enable bits of `events`, preserving the other ones
+    fn disable(&self, events: FixedEvent) {
+        for block in self.blocks() {
+            unsafe {
+                let enable = FixedEvent::from_bits_retain(block.enable.read() as u16);
+                block.enable.write((enable - events).bits() as u64);
+            }
+        }
+    }
+
+    /// Clears the status of `events`, which is required before they can raise the SCI again
+    pub fn acknowledge(&self, events: FixedEvent) {
+        // The status bits are cleared by writing ones, so writing zeroes leaves the others alone
+        for block in self.blocks() {
+            unsafe { block.status.write(events.bits() as u64) };
+        }
+    }
+
+    /// Calls `callback` from `poll_events` whenever one of `events` happened. The events are
+    /// disabled, such that they do not raise the SCI, as they are only polled for. Occurrences
+    /// from before the registration are discarded.
+    pub fn register(
+        &mut self,
+        events: FixedEvent,
+        callback: FixedEventCallback,
+    ) -> Result<(), EventError> {
+        if !self.supported.contains(events) {
+            return Err(EventError::Unsupported(events - self.supported));
+        }
+
+        for event in events.iter() {
+            self.callbacks[event.bits().trailing_zeros() as usize] = Some(callback);
+        }
+        self.registered |= events;
+        self.disable(events);
+        self.acknowledge(events);
+
+        Ok(())
+    }
+
+    /// Removes the callbacks of `events`, which are no longer polled for
+    pub fn unregister(&mut self, events: FixedEvent) {
+        let events = events & self.supported;
+
+        self.registered -= events;
+        for event in events.iter() {
+            self.callbacks[event.bits().trailing_zeros() as usize] = None;
+        }
+    }
+
+    /// Polls the fixed events: the registered events that happened are acknowledged and their
+    /// callbacks are called. The SCI is not routed to an interrupt handler, so nothing happens
+    /// unless this is called periodically, like from the condition of `PmTimer::wait_until`.
+    /// Returns the events that were handled.
+    pub fn poll_events(&self) -> FixedEvent {
+        let pending = self.status() & self.registered;
+        if pending.is_empty() {
+            return pending;
+        }
+
+        // Acknowledge first, such that an event that happens again while its callback runs is
+        // not lost
+        self.acknowledge(pending);
+        for event in pending.iter() {
+            if let Some(callback) = self.callbacks[event.bits().trailing_zeros() as usize] {
+                callback(event);
+            }
+        }
+
+        pending
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::efi::acpi::address_space;
+    use crate::test_support::{fadt_with_block, fix_checksum, gas, gas_bytes, Machine, Q35};
+    use std::sync::atomic::{AtomicU16, Ordering};
+
+    // The events `pm1_callback` was called with
+    static PM1_CALLED: AtomicU16 = AtomicU16::new(0);
+
+    fn pm1_callback(event: FixedEvent) {
+        PM1_CALLED.fetch_or(event.bits(), Ordering::SeqCst);
+    }
+
+    #[test]
+    fn pm1_event_masking() {
+        // The status and enable registers of the PM1a and PM1b event blocks, in system memory
+        let pm1a = [AtomicU16::new(0), AtomicU16::new(0)];
+        let pm1b = [AtomicU16::new(0), AtomicU16::new(0)];
+        let blk = |registers: &[AtomicU16; 2]| {
+            gas(
+                address_space::SYSTEM_MEMORY,
+                32,
+                0,
+                3,
+                registers.as_ptr() as u64,
+            )
+        };
+        let status = |registers: &[AtomicU16; 2]| registers[0].load(Ordering::SeqCst);
+        let enable = |registers: &[AtomicU16; 2]| registers[1].load(Ordering::SeqCst);
+
+        // The power button is a control method device, the sleep button and the RTC fixed events
+        let flags = FixedFeatureFlags::PWR_BUTTON;
+        let mut facp = Q35::table("facp").to_vec();
+        facp[112..116].copy_from_slice(&flags.bits().to_le_bytes());
+        facp[148..160].copy_from_slice(&gas_bytes(&blk(&pm1a)));
+        facp[160..172].copy_from_slice(&gas_bytes(&blk(&pm1b)));
+        fix_checksum(&mut facp, 9);
+        let fadt = FADT::from_bytes(&facp).unwrap();
+        assert_eq!(fadt.pm1_evt_len(), 4);
+        let mut events = FixedEventHandler::new(&fadt).unwrap();
+        let fixed =
+            FixedEvent::TIMER | FixedEvent::GLOBAL | FixedEvent::SLEEP_BUTTON | FixedEvent::RTC;
+        assert_eq!(events.supported(), fixed);
+
+        // The bits that are not fixed events, like WAK_STS and the reserved ones, are masked out of
+        // the events of both blocks
+        pm1a[0].store(0x8001, Ordering::SeqCst);
+        pm1b[0].store(0x0400 | 0x0800, Ordering::SeqCst);
+        pm1a[1].store(0x4000 | 0x0400, Ordering::SeqCst);
+        assert_eq!(events.status(), FixedEvent::TIMER | FixedEvent::RTC);
+        assert_eq!(events.enabled(), FixedEvent::RTC);
+
+        // Registering disables the events in both blocks, as they are polled for, keeping the other
+        // enable bits, and clears their status by writing ones only for them
+        assert_eq!(
+            events.register(FixedEvent::POWER_BUTTON | FixedEvent::RTC, pm1_callback),
+            Err(EventError::Unsupported(FixedEvent::POWER_BUTTON))
+        );
+        events
+            .register(FixedEvent::SLEEP_BUTTON | FixedEvent::RTC, pm1_callback)
+            .unwrap();
+        assert_eq!((enable(&pm1a), enable(&pm1b)), (0x4000, 0x0000));
+        assert_eq!((status(&pm1a), status(&pm1b)), (0x0600, 0x0600));
+        assert_eq!(events.enabled(), FixedEvent::empty());
+
+        // Only the events that are both registered and pending are acknowledged and handed to their
+        // callbacks
+        pm1a[0].store(0x8001 | 0x0400, Ordering::SeqCst);
+        pm1b[0].store(0x0020, Ordering::SeqCst);
+        assert_eq!(events.poll_events(), FixedEvent::RTC);
+        assert_eq!(PM1_CALLED.swap(0, Ordering::SeqCst), FixedEvent::RTC.bits());
+        assert_eq!((status(&pm1a), status(&pm1b)), (0x0400, 0x0400));
+
+        // With nothing pending, the status registers are left alone
+        pm1a[0].store(0x8001, Ordering::SeqCst);
+        pm1b[0].store(0x0020, Ordering::SeqCst);
+        assert_eq!(events.poll_events(), FixedEvent::empty());
+        assert_eq!(PM1_CALLED.load(Ordering::SeqCst), 0);
+        assert_eq!((status(&pm1a), status(&pm1b)), (0x8001, 0x0020));
+
+        // Unregistering only stops polling for the given events, and leaves them disabled
+        events.unregister(FixedEvent::RTC | FixedEvent::POWER_BUTTON);
+        pm1b[0].store(0x0400 | 0x0200, Ordering::SeqCst);
+        assert_eq!(events.poll_events(), FixedEvent::SLEEP_BUTTON);
+        assert_eq!(
+            PM1_CALLED.swap(0, Ordering::SeqCst),
+            FixedEvent::SLEEP_BUTTON.bits()
+        );
+        assert_eq!((enable(&pm1a), enable(&pm1b)), (0x4000, 0x0000));
+
+        // Hardware-reduced platforms have no PM1 event blocks, and a block must hold both registers
+        let hw_reduced = FixedFeatureFlags::HW_REDUCED_ACPI;
+        let fadt = fadt_with_block(hw_reduced, 148, blk(&pm1a));
+        assert_eq!(
+            FixedEventHandler::new(&fadt).err(),
+            Some(EventError::HardwareReduced)
+        );
+        facp[88] = 1;
+        fix_checksum(&mut facp, 9);
+        let fadt = FADT::from_bytes(&facp).unwrap();
+        assert_eq!(
+            FixedEventHandler::new(&fadt).err(),
+            Some(EventError::NoEventBlock)
+        );
+    }
+}
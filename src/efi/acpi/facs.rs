@@ -0,0 +1,433 @@
+//! Module that parses the Firmware ACPI Control Structure (FACS) and implements the global lock
+//! it holds, which the OS and the firmware use to share hardware
+use crate::efi::acpi::{
+    phys_atomic_u32, read, table_bytes, AcpiTables, FromBytes, GasError, GenericAddress,
+    ParseError, FADT,
+};
+use crate::pm_timer::PmTimer;
+use bitflags::bitflags;
+use core::fmt;
+use core::mem::size_of;
+use core::sync::atomic::{AtomicU32, Ordering};
+
+// The signature found in the first 4 bytes from the FACS
+const FACS_SIGNATURE: &[u8; 4] = b"FACS";
+
+/// Firmware ACPI Control Structure.
+/// It lives in read/write memory, and is shared by the firmware and the OS. It holds the
+/// hardware signature, which changes with the hardware configuration, the vector the firmware
+/// jumps to when waking up from a sleep state, and the global lock.
+pub struct FACS {
+    // Physical address of the structure, which the global lock is accessed through
+    addr: usize,
+    fields: FacsFields,
+}
+
+// The whole structure, as of version 2
+#[derive(Clone, Copy)]
+#[repr(C, packed)]
+struct FacsFields {
+    signature: [u8; 4],
+    // Length of the structure, which is at least 64 bytes
+    length: u32,
+    // Value calculated by the firmware from the hardware configuration at boot
+    hardware_signature: u32,
+    // 32-bit physical address the firmware jumps to in real mode when waking up
+    firmware_waking_vector: u32,
+    // The global lock, shared between the OS and the firmware
+    global_lock: u32,
+    // Firmware control structure feature flags
+    flags: u32,
+    // 64-bit physical address the firmware jumps to when waking up, in preference to
+    // `firmware_waking_vector`
+    x_firmware_waking_vector: u64,
+    // Version of the structure
+    version: u8,
+    reserved0: [u8; 3],
+    // OSPM enabled firmware control structure flags
+    ospm_flags: u32,
+    reserved1: [u8; 24],
+}
+
+unsafe impl FromBytes for FacsFields {}
+
+bitflags! {
+    #[repr(transparent)]
+    #[derive(Default, Clone, Copy, Debug, PartialEq, Eq, Hash)]
+    pub struct FacsFlags: u32 {
+        /// The platform supports the S4BIOS_REQ command of the SMI command port
+        const S4BIOS = 1 << 0;
+        /// The firmware can wake up in 64-bit mode through the extended waking vector
+        const WAKE_64BIT_SUPPORTED = 1 << 1;
+    }
+}
+
+bitflags! {
+    #[repr(transparent)]
+    #[derive(Default, Clone, Copy, Debug, PartialEq, Eq, Hash)]
+    pub struct FacsOspmFlags: u32 {
+        /// The OS asks the firmware to wake up in 64-bit mode through the extended waking vector
+        const WAKE_64BIT = 1 << 0;
+    }
+}
+
+bitflags! {
+    /// The state of the global lock
+    #[repr(transparent)]
+    #[derive(Default, Clone, Copy, Debug, PartialEq, Eq, Hash)]
+    pub struct GlobalLockState: u32 {
+        /// Someone is waiting for the lock, and must be told when it is released
+        const PENDING = 1 << 0;
+        /// The lock is owned, by the OS or by the firmware
+        const OWNED = 1 << 1;
+    }
+}
+
+/// Offset of the global lock in the FACS
+const GLOBAL_LOCK_OFFSET: usize = 16;
+
+impl FACS {
+    /// Reads the FACS from the bytes of the entire structure, found at the physical address
+    /// `addr`
+    pub fn from_bytes(bytes: &[u8], addr: usize) -> Result<Self, ParseError> {
+        let fields: FacsFields = read(bytes, 0)?;
+        if &fields.signature != FACS_SIGNATURE {
+            return Err(ParseError::InvalidSignature);
+        }
+
+        let length = fields.length as usize;
+        if length < size_of::<FacsFields>() {
+            return Err(ParseError::InvalidLength(length));
+        }
+
+        Ok(FACS { addr, fields })
+    }
+
+    /// Reads the FACS found at the physical address `addr`. Its signature and length are where
+    /// those of a description header are.
+    pub fn from_addr(addr: usize) -> Result<Self, ParseError> {
+        Self::from_bytes(table_bytes(addr)?, addr)
+    }
+
+    /// Reads the FACS the FADT points to
+    pub fn from_fadt(fadt: &FADT) -> Result<Self, ParseError> {
+        let addr = fadt.facs_addr().ok_or(ParseError::NullAddress)?;
+        Self::from_addr(addr as usize)
+    }
+
+    /// Returns the physical address of the structure
+    pub fn addr(&self) -> usize {
+        self.addr
+    }
+
+    pub fn length(&self) -> u32 {
+        self.fields.length
+    }
+
+    pub fn version(&self) -> u8 {
+        self.fields.version
+    }
+
+    /// Returns the hardware signature, which the firmware changes when the hardware configuration
+    /// changed since the last boot
+    pub fn hardware_signature(&self) -> u32 {
+        self.fields.hardware_signature
+    }
+
+    /// Returns the 32-bit waking vector, which the firmware jumps to in real mode
+    pub fn firmware_waking_vector(&self) -> u32 {
+        self.fields.firmware_waking_vector
+    }
+
+    /// Returns the 64-bit waking vector, which is used instead of the 32-bit one when it is not
+    /// zero. Only version 1 and later structures have it.
+    pub fn x_firmware_waking_vector(&self) -> Option<u64> {
+        let vector = self.fields.x_firmware_waking_vector;
+        (self.version() >= 1 && vector != 0).then_some(vector)
+    }
+
+    pub fn flags(&self) -> FacsFlags {
+        FacsFlags::from_bits_retain(self.fields.flags)
+    }
+
+    /// Returns the flags set by the OS. Only version 2 and later structures have them.
+    pub fn ospm_flags(&self) -> FacsOspmFlags {
+        match self.version() {
+            0 | 1 => FacsOspmFlags::empty(),
+            _ => FacsOspmFlags::from_bits_retain(self.fields.ospm_flags),
+        }
+    }
+
+    // Returns the global lock, in the FACS itself rather than in our copy of it
+    fn global_lock(&self) -> Result<&'static AtomicU32, ParseError> {
+        // SAFETY: the FACS was read from this address, and is never reclaimed. The firmware only
+        // accesses the lock atomically.
+        unsafe { phys_atomic_u32(self.addr + GLOBAL_LOCK_OFFSET) }
+    }
+
+    /// Returns the current state of the global lock, or `None` if the lock cannot be accessed
+    pub fn global_lock_state(&self) -> Option<GlobalLockState> {
+        let lock = self.global_lock().ok()?;
+        Some(GlobalLockState::from_bits_truncate(lock.load(Ordering::Acquire)))
+    }
+}
+
+impl fmt::Debug for FACS {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("FACS")
+            .field("Version", &self.version())
+            .field(
+                "Hardware Signature",
+                &format_args!("{:#x}", self.hardware_signature()),
+            )
+            .field(
+                "Firmware Waking Vector",
+                &format_args!("{:#x}", self.firmware_waking_vector()),
+            )
+            .field("X Firmware Waking Vector", &self.x_firmware_waking_vector())
+            .field("Global Lock", &self.global_lock_state())
+            .field("Flags", &self.flags())
+            .field("OSPM Flags", &self.ospm_flags())
+            .finish()
+    }
+}
+
+/// Reasons for which the global lock cannot be used
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GlobalLockError {
+    /// There is no FADT amongst the ACPI tables
+    NoFadt,
+    /// The FACS, or the lock in it, could not be read
+    InvalidFacs(ParseError),
+    /// The PM1 control register could not be accessed
+    InvalidRegister(GasError),
+    /// The firmware did not release the lock in time
+    Timeout,
+}
+
+impl From<GasError> for GlobalLockError {
+    fn from(value: GasError) -> Self {
+        Self::InvalidRegister(value)
+    }
+}
+
+/// The GBL_RLS bit of the PM1 control registers, which tells the firmware that the global lock it
+/// is waiting for was released
+const GBL_RLS: u64 = 1 << 2;
+
+/// The SLP_EN bit of the PM1 control registers, which must not be set while writing GBL_RLS
+const SLP_EN: u64 = 1 << 13;
+
+/// Number of attempts at taking the lock when there is no PM timer to time them with
+const GLOBAL_LOCK_SPINS: u32 = 10_000_000;
+
+/// The global lock, which the OS takes before accessing hardware that the firmware also uses,
+/// such as the embedded controller. When the firmware owns the lock, we mark it as pending and
+/// the firmware raises the GLOBAL fixed event once it released it.
+pub struct GlobalLock {
+    lock: &'static AtomicU32,
+    // The PM1a and, if present, PM1b control registers, where GBL_RLS is
+    pm1_cnt: [Option<GenericAddress>; 2],
+    // The timer used to time out `acquire`, if the platform has one
+    timer: Option<PmTimer>,
+}
+
+impl GlobalLock {
+    /// Sets up the global lock of `facs`, to be released through the PM1 control registers of
+    /// `fadt`
+    pub fn new(facs: &FACS, fadt: &FADT) -> Result<Self, GlobalLockError> {
+        let pm1a_cnt = match fadt.pm1a_cnt_blk() {
+            Some(pm1a_cnt) => Some(pm1a_cnt.decode()?),
+            None => None,
+        };
+        let pm1b_cnt = match fadt.pm1b_cnt_blk() {
+            Some(pm1b_cnt) => Some(pm1b_cnt.decode()?),
+            None => None,
+        };
+
+        Ok(Self {
+            lock: facs.global_lock().map_err(GlobalLockError::InvalidFacs)?,
+            pm1_cnt: [pm1a_cnt, pm1b_cnt],
+            timer: PmTimer::new(fadt).ok(),
+        })
+    }
+
+    /// Sets up the global lock of the FACS the FADT of the ACPI tables points to
+    pub fn from_acpi(tables: &AcpiTables) -> Result<Self, GlobalLockError> {
+        let fadt = tables.find::<FADT>().ok_or(GlobalLockError::NoFadt)?;
+        let facs = FACS::from_fadt(&fadt).map_err(GlobalLockError::InvalidFacs)?;
+        Self::new(&facs, &fadt)
+    }
+
+    /// Tries to take the lock once. If the firmware owns it, the lock is marked as pending such
+    /// that the firmware tells us when it released it, and this returns false.
+    pub fn try_acquire(&self) -> bool {
+        let mut old = self.lock.load(Ordering::Relaxed);
+
+        loop {
+            let state = GlobalLockState::from_bits_retain(old);
+            let mut new = (state - GlobalLockState::PENDING) | GlobalLockState::OWNED;
+            if state.contains(GlobalLockState::OWNED) {
+                new |= GlobalLockState::PENDING;
+            }
+
+            match self.lock.compare_exchange_weak(
+                old,
+                new.bits(),
+                Ordering::AcqRel,
+                Ordering::Relaxed,
+            ) {
+                Ok(_) => return !new.contains(GlobalLockState::PENDING),
+                Err(current) => old = current,
+            }
+        }
+    }
+
+    /// Takes the lock, waiting up to `timeout_ns` nanoseconds for the firmware to release it.
+    /// Rather than waiting for the GLOBAL fixed event, we keep trying to take the lock.
+    pub fn acquire(&self, timeout_ns: u64) -> Result<(), GlobalLockError> {
+        let acquired = match &self.timer {
+            Some(timer) => {
+                self.try_acquire() || timer.wait_until(timeout_ns, || self.try_acquire())
+            }
+            None => (0..GLOBAL_LOCK_SPINS).any(|_| {
+                core::hint::spin_loop();
+                self.try_acquire()
+            }),
+        };
+
+        match acquired {
+            true => Ok(()),
+            false => Err(GlobalLockError::Timeout),
+        }
+    }
+
+    /// Releases the lock, which must be owned by us. If the firmware is waiting for it, it is
+    /// told through GBL_RLS.
+    pub fn release(&self) {
+        let owned = GlobalLockState::OWNED | GlobalLockState::PENDING;
+        let old = self.lock.fetch_and(!owned.bits(), Ordering::AcqRel);
+
+        if GlobalLockState::from_bits_retain(old).contains(GlobalLockState::PENDING) {
+            for pm1_cnt in self.pm1_cnt.iter().flatten() {
+                unsafe {
+                    let value = pm1_cnt.read() & !SLP_EN;
+                    pm1_cnt.write(value | GBL_RLS);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::efi::acpi::{address_space, with_phys_memory, with_shared_phys_memory};
+    use crate::test_support::{fix_checksum, gas, gas_bytes, Machine, Q35};
+    use std::sync::atomic::AtomicU16;
+
+    #[test]
+    fn global_lock_transitions() {
+        const OWNED: u32 = 1 << 1;
+        const PENDING: u32 = 1 << 0;
+        const SCI_EN: u16 = 1 << 0;
+        const GBL_RLS: u16 = 1 << 2;
+        const SLP_EN: u16 = 1 << 13;
+
+        // The q35 FACS, whose global lock is shared with the firmware
+        let facs_addr = Q35::addr("facs");
+        let lock = [AtomicU32::new(0)];
+        let memory = [(facs_addr, Q35::table("facs"))];
+        let shared = [(facs_addr + 16, lock.as_slice())];
+        let lock = &lock[0];
+
+        // The X_PM1a_CNT_BLK in system memory, and no PM timer such that `acquire` spins
+        let pm1a_cnt = AtomicU16::new(0);
+        let blk = gas(
+            address_space::SYSTEM_MEMORY,
+            16,
+            0,
+            2,
+            pm1a_cnt.as_ptr() as u64,
+        );
+        let mut facp = Q35::table("facp").to_vec();
+        facp[76..80].fill(0);
+        facp[208..220].fill(0);
+        facp[172..184].copy_from_slice(&gas_bytes(&blk));
+        fix_checksum(&mut facp, 9);
+        let fadt = FADT::from_bytes(&facp).unwrap();
+
+        with_shared_phys_memory(&memory, &shared, || {
+            let facs = FACS::from_addr(facs_addr).unwrap();
+            let ours = GlobalLock::new(&facs, &fadt).unwrap();
+            let firmware = GlobalLock::new(&facs, &fadt).unwrap();
+            let state = || facs.global_lock_state().unwrap();
+
+            // A free lock is taken at once
+            assert_eq!(state(), GlobalLockState::empty());
+            assert!(ours.try_acquire());
+            assert_eq!(lock.load(Ordering::SeqCst), OWNED);
+
+            // Taking an owned lock marks it pending, and fails
+            assert!(!firmware.try_acquire());
+            assert_eq!(lock.load(Ordering::SeqCst), OWNED | PENDING);
+            assert!(!firmware.try_acquire());
+            assert_eq!(state(), GlobalLockState::OWNED | GlobalLockState::PENDING);
+
+            // Releasing a pending lock frees it and sets GBL_RLS, without entering a sleep state
+            pm1a_cnt.store(SLP_EN | SCI_EN, Ordering::SeqCst);
+            ours.release();
+            assert_eq!(lock.load(Ordering::SeqCst), 0);
+            assert_eq!(pm1a_cnt.load(Ordering::SeqCst), SCI_EN | GBL_RLS);
+
+            // Releasing a lock nobody waits for leaves the control register alone
+            pm1a_cnt.store(SCI_EN, Ordering::SeqCst);
+            assert!(firmware.try_acquire());
+            firmware.release();
+            assert_eq!(lock.load(Ordering::SeqCst), 0);
+            assert_eq!(pm1a_cnt.load(Ordering::SeqCst), SCI_EN);
+
+            // A pending bit left on a free lock is cleared by the owner, and the reserved bits are
+            // kept
+            lock.store(0xF0 | PENDING, Ordering::SeqCst);
+            assert!(ours.try_acquire());
+            assert_eq!(lock.load(Ordering::SeqCst), 0xF0 | OWNED);
+            assert_eq!(state(), GlobalLockState::OWNED);
+            ours.release();
+            assert_eq!(lock.load(Ordering::SeqCst), 0xF0);
+
+            // Without a PM timer, `acquire` gives up after a fixed number of attempts
+            assert_eq!(ours.acquire(1_000_000), Ok(()));
+            assert_eq!(firmware.acquire(1_000_000), Err(GlobalLockError::Timeout));
+            assert_eq!(lock.load(Ordering::SeqCst), 0xF0 | OWNED | PENDING);
+            ours.release();
+            assert!(format!("{:?}", facs).contains("Global Lock: Some(GlobalLockState(0x0))"));
+        });
+
+        // A lock that is not aligned cannot be shared with the firmware, and one out of the shared
+        // memory cannot be shared at all
+        let unaligned = [(facs_addr + 2, Q35::table("facs"))];
+        with_shared_phys_memory(&unaligned, &shared, || {
+            let facs = FACS::from_addr(facs_addr + 2).unwrap();
+            assert_eq!(facs.global_lock_state(), None);
+            assert_eq!(
+                GlobalLock::new(&facs, &fadt).err(),
+                Some(GlobalLockError::InvalidFacs(ParseError::Misaligned(
+                    facs_addr + 18
+                )))
+            );
+        });
+        with_phys_memory(&memory, || {
+            let facs = FACS::from_addr(facs_addr).unwrap();
+            assert_eq!(facs.global_lock_state(), None);
+            assert!(matches!(
+                GlobalLock::new(&facs, &fadt).err(),
+                Some(GlobalLockError::InvalidFacs(ParseError::Truncated { .. }))
+            ));
+            assert_eq!(
+                unsafe { phys_atomic_u32(0) }.err(),
+                Some(ParseError::NullAddress)
+            );
+        });
+    }
+}
@@ -0,0 +1,438 @@
+//! Module that parses the Fixed ACPI Description Table (FADT)
+use crate::efi::acpi::{
+    read_prefix, AcpiTable, DescriptionHeader, FromBytes, GenericAddressStructure, ParseError,
+};
+use bitflags::bitflags;
+use core::fmt;
+use core::mem::size_of;
+
+/// The Fixed ACPI Description Table defines various fixed hardware ACPI information vital to an
+/// ACPI-compatible OS, such as the base address for the hardware register blocks. It also holds
+/// the physical addresses of the FACS and the DSDT.
+///
+/// The table grew with every ACPI revision. Fields that are past the end of the table reported by
+/// firmware are read as zero, such that revision 1 to 6 tables are all handled the same way.
+pub struct FADT {
+    header: DescriptionHeader,
+    fields: FadtFields,
+}
+
+/// All the fields of the FADT that follow its header, as of revision 6
+#[derive(Debug, Default, Clone, Copy)]
+#[repr(C, packed)]
+struct FadtFields {
+    // Physical memory address of the FACS
+    firmware_ctrl: u32,
+    // Physical memory address of the DSDT
+    dsdt: u32,
+    // Eliminated in ACPI 2.0. Used to be the interrupt model
+    reserved0: u8,
+    // The power management profile preferred by the OEM
+    preferred_pm_profile: u8,
+    // System vector the SCI interrupt is wired to in 8259 mode, or the global system interrupt
+    // number of the SCI in APIC mode
+    sci_int: u16,
+    // System port address of the SMI Command Port. Zero on systems that do not support System
+    // Management mode
+    smi_cmd: u32,
+    // The value to write to `smi_cmd` to disable SMI ownership of the ACPI hardware registers
+    acpi_enable: u8,
+    // The value to write to `smi_cmd` to re-enable SMI ownership of the ACPI hardware registers
+    acpi_disable: u8,
+    // The value to write to `smi_cmd` to enter the S4BIOS state
+    s4bios_req: u8,
+    // If non-zero, the value OSPM writes to `smi_cmd` to assume processor performance state
+    // control responsibility
+    pstate_cnt: u8,
+    // System port address of the PM1a Event Register Block
+    pm1a_evt_blk: u32,
+    // System port address of the PM1b Event Register Block
+    pm1b_evt_blk: u32,
+    // System port address of the PM1a Control Register Block
+    pm1a_cnt_blk: u32,
+    // System port address of the PM1b Control Register Block
+    pm1b_cnt_blk: u32,
+    // System port address of the PM2 Control Register Block
+    pm2_cnt_blk: u32,
+    // System port address of the Power Management Timer Control Register Block
+    pm_tmr_blk: u32,
+    // System port address of General-Purpose Event 0 Register Block
+    gpe0_blk: u32,
+    // System port address of General-Purpose Event 1 Register Block
+    gpe1_blk: u32,
+    // Number of bytes decoded by `pm1a_evt_blk` and, if supported, `pm1b_evt_blk`
+    pm1_evt_len: u8,
+    // Number of bytes decoded by `pm1a_cnt_blk` and, if supported, `pm1b_cnt_blk`
+    pm1_cnt_len: u8,
+    // Number of bytes decoded by `pm2_cnt_blk`
+    pm2_cnt_len: u8,
+    // Number of bytes decoded by `pm_tmr_blk`. Must be 4
+    pm_tmr_len: u8,
+    // Length of the register whose address is given by `gpe0_blk`
+    gpe0_blk_len: u8,
+    // Length of the register whose address is given by `gpe1_blk`
+    gpe1_blk_len: u8,
+    // Offset within the ACPI general-purpose event model where GPE1 based events start
+    gpe1_base: u8,
+    // If non-zero, the value OSPM writes to `smi_cmd` to indicate OS support for the _CST object
+    cst_cnt: u8,
+    // The worst-case hardware latency, in microseconds, to enter and exit a C2 state
+    p_lvl2_lat: u16,
+    // The worst-case hardware latency, in microseconds, to enter and exit a C3 state
+    p_lvl3_lat: u16,
+    // Ignored if the WBINVD flag is set
+    flush_size: u16,
+    // Ignored if the WBINVD flag is set
+    flush_stride: u16,
+    // The zero-based index of where the processor's duty cycle setting is within the processor's
+    // P_CNT register
+    duty_offset: u8,
+    // The bit width of the processor's duty cycle setting value in the P_CNT register
+    duty_width: u8,
+    // The RTC CMOS RAM index to the day-of-month alarm value
+    day_alrm: u8,
+    // The RTC CMOS RAM index to the month of year alarm value
+    mon_alrm: u8,
+    // The RTC CMOS RAM index to the century of data value
+    century: u8,
+    // IA-PC Boot Architecture Flags
+    iapc_boot_arch: IaPcBootArchFlags,
+    // Must be 0
+    reserved1: u8,
+    // Fixed feature flags
+    flags: FixedFeatureFlags,
+    //
+    // End of the ACPI 1.0 table
+    //
+    // The address of the reset register
+    reset_reg: GenericAddressStructure,
+    // Indicates the value to write to the `reset_reg` port to reset the system
+    reset_value: u8,
+    // ARM Boot Architecture Flags
+    arm_boot_arch: u16,
+    // Minor version of this FADT structure
+    fadt_minor_version: u8,
+    // Extended physical address of the FACS
+    x_firmware_ctrl: u64,
+    // Extended physical address of the DSDT
+    x_dsdt: u64,
+    // Extended address of the PM1a Event Register Block
+    x_pm1a_evt_blk: GenericAddressStructure,
+    // Extended address of the PM1b Event Register Block
+    x_pm1b_evt_blk: GenericAddressStructure,
+    // Extended address of the PM1a Control Register Block
+    x_pm1a_cnt_blk: GenericAddressStructure,
+    // Extended address of the PM1b Control Register Block
+    x_pm1b_cnt_blk: GenericAddressStructure,
+    // Extended address of the PM2 Control Register Block
+    x_pm2_cnt_blk: GenericAddressStructure,
+    // Extended address of the Power Management Timer Control Register Block
+    x_pm_tmr_blk: GenericAddressStructure,
+    // Extended address of the General-Purpose Event 0 Register Block
+    x_gpe0_blk: GenericAddressStructure,
+    // Extended address of the General-Purpose Event 1 Register Block
+    x_gpe1_blk: GenericAddressStructure,
+    //
+    // End of the ACPI 3.0 table
+    //
+    // The address of the Sleep register, for hardware-reduced ACPI systems
+    sleep_control_reg: GenericAddressStructure,
+    // The address of the Sleep status register, for hardware-reduced ACPI systems
+    sleep_status_reg: GenericAddressStructure,
+    //
+    // End of the ACPI 5.0 table
+    //
+    // 64-bit identifier of the hypervisor vendor
+    hypervisor_vendor_identity: u64,
+}
+
+unsafe impl FromBytes for FadtFields {}
+
+bitflags! {
+    #[repr(transparent)]
+    #[derive(Default, Clone, Copy, Debug, PartialEq, Eq, Hash)]
+    pub struct IaPcBootArchFlags: u16 {
+        /// The motherboard supports user-visible devices on the LPC or ISA bus
+        const LEGACY_DEVICES = 1 << 0;
+        /// The motherboard contains support for a port 60 and 64 based keyboard controller,
+        /// usually implemented as an 8042 or equivalent micro-controller
+        const I8042 = 1 << 1;
+        /// OSPM must not blindly probe the VGA hardware, as it is not present
+        const VGA_NOT_PRESENT = 1 << 2;
+        /// OSPM must not enable Message Signaled Interrupts on this platform
+        const MSI_NOT_SUPPORTED = 1 << 3;
+        /// OSPM must not enable OSPM ASPM control on this platform
+        const PCIE_ASPM_CONTROLS = 1 << 4;
+        /// OSPM must not assume the presence of the CMOS RTC
+        const CMOS_RTC_NOT_PRESENT = 1 << 5;
+    }
+}
+
+bitflags! {
+    #[repr(transparent)]
+    #[derive(Default, Clone, Copy, Debug, PartialEq, Eq, Hash)]
+    pub struct FixedFeatureFlags: u32 {
+        /// The WBINVD instruction correctly flushes the processor caches
+        const WBINVD = 1 << 0;
+        /// WBINVD flushes all caches and maintains memory coherency, but does not guarantee the
+        /// caches are invalidated
+        const WBINVD_FLUSH = 1 << 1;
+        /// C1 power state is supported on all processors
+        const PROC_C1 = 1 << 2;
+        /// The C2 power state is configured to work on a uniprocessor and multiprocessor system
+        const P_LVL2_UP = 1 << 3;
+        /// The power button is handled as a control method device, instead of a fixed feature
+        const PWR_BUTTON = 1 << 4;
+        /// The sleep button is handled as a control method device, instead of a fixed feature
+        const SLP_BUTTON = 1 << 5;
+        /// The RTC wake status is not supported in fixed register space
+        const FIX_RTC = 1 << 6;
+        /// The RTC alarm function can wake the system from the S4 state
+        const RTC_S4 = 1 << 7;
+        /// The PM timer is a 32-bit value, otherwise it is a 24-bit value
+        const TMR_VAL_EXT = 1 << 8;
+        /// The system can support docking
+        const DCK_CAP = 1 << 9;
+        /// The system supports system reset via the `reset_reg`
+        const RESET_REG_SUP = 1 << 10;
+        /// The system type contains no internal expansion capabilities and the case is sealed
+        const SEALED_CASE = 1 << 11;
+        /// The system cannot detect the monitor or keyboard / mouse devices
+        const HEADLESS = 1 << 12;
+        /// OSPM must execute a processor native instruction after writing the SLP_TYPx register
+        const CPU_SW_SLP = 1 << 13;
+        /// The platform supports the PCIEXP_WAKE_STS and PCIEXP_WAKE_EN bits
+        const PCI_EXP_WAK = 1 << 14;
+        /// OSPM should use a platform provided timer to drive any monotonically non-decreasing
+        /// counters
+        const USE_PLATFORM_CLOCK = 1 << 15;
+        /// The contents of the RTC_STS flag is valid when waking the system from S4
+        const S4_RTC_STS_VALID = 1 << 16;
+        /// The platform is compatible with remote power-on
+        const REMOTE_POWER_ON_CAPABLE = 1 << 17;
+        /// All local APICs must be configured for the cluster destination model
+        const FORCE_APIC_CLUSTER_MODEL = 1 << 18;
+        /// All local xAPICs must be configured for physical destination mode
+        const FORCE_APIC_PHYSICAL_DESTINATION_MODE = 1 << 19;
+        /// ACPI hardware is not available on this platform
+        const HW_REDUCED_ACPI = 1 << 20;
+        /// The platform is able to achieve power savings in S0 similar to or better than those
+        /// typically achieved in S3
+        const LOW_POWER_S0_IDLE_CAPABLE = 1 << 21;
+        /// Whether the CPU caches and any other caches that are coherent with them, are
+        /// considered by the platform to be persistent
+        const PERSISTENT_CPU_CACHES = 0b11 << 22;
+    }
+}
+
+// The signature found in the first 4 bytes from the FADT table
+const FADT_SIGNATURE: &[u8; 4] = b"FACP";
+
+impl FADT {
+    /// Read the Fixed ACPI Description Table from the bytes of the entire table. Only the fields
+    /// that fit in the length reported by its header are read, the others being left as zero.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, ParseError> {
+        let (header, table) = DescriptionHeader::parse_table(bytes, FADT_SIGNATURE)?;
+        let fields = read_prefix(&table[size_of::<DescriptionHeader>()..]);
+
+        Ok(FADT { header, fields })
+    }
+
+    /// Returns the revision of the table
+    pub fn revision(&self) -> u8 {
+        self.header.revision()
+    }
+
+    /// Returns the physical address of the FACS, preferring the 64-bit field. Hardware-reduced
+    /// ACPI platforms may not have a FACS, in which case this returns `None`.
+    pub fn facs_addr(&self) -> Option<u64> {
+        let x_firmware_ctrl = self.fields.x_firmware_ctrl;
+        let firmware_ctrl = self.fields.firmware_ctrl as u64;
+        [x_firmware_ctrl, firmware_ctrl].into_iter().find(|addr| *addr != 0)
+    }
+
+    /// Returns the physical address of the DSDT, preferring the 64-bit field
+    pub fn dsdt_addr(&self) -> Option<u64> {
+        let x_dsdt = self.fields.x_dsdt;
+        let dsdt = self.fields.dsdt as u64;
+        [x_dsdt, dsdt].into_iter().find(|addr| *addr != 0)
+    }
+
+    /// Returns the power management profile preferred by the OEM
+    pub fn preferred_pm_profile(&self) -> u8 {
+        self.fields.preferred_pm_profile
+    }
+
+    /// Returns the interrupt the SCI is wired to
+    pub fn sci_int(&self) -> u16 {
+        self.fields.sci_int
+    }
+
+    /// Returns the SMI Command Port, if the platform supports System Management Mode
+    pub fn smi_cmd(&self) -> Option<u32> {
+        let smi_cmd = self.fields.smi_cmd;
+        (smi_cmd != 0).then_some(smi_cmd)
+    }
+
+    /// Returns the value to write to the SMI Command Port to hand over the ACPI hardware
+    /// registers to the OS
+    pub fn acpi_enable(&self) -> u8 {
+        self.fields.acpi_enable
+    }
+
+    /// Returns the value to write to the SMI Command Port to hand back the ACPI hardware
+    /// registers to the firmware
+    pub fn acpi_disable(&self) -> u8 {
+        self.fields.acpi_disable
+    }
+
+    // Picks the extended register block, if present, otherwise builds one from the legacy I/O
+    // port block
+    fn register_block(
+        x_blk: GenericAddressStructure,
+        blk: u32,
+        len: u8,
+    ) -> Option<GenericAddressStructure> {
+        if x_blk.is_present() {
+            return Some(x_blk);
+        }
+
+        (blk != 0).then(|| GenericAddressStructure::from_io_block(blk, len))
+    }
+
+    /// Returns the PM1a Event Register Block
+    pub fn pm1a_evt_blk(&self) -> Option<GenericAddressStructure> {
+        let f = self.fields;
+        Self::register_block(f.x_pm1a_evt_blk, f.pm1a_evt_blk, f.pm1_evt_len)
+    }
+
+    /// Returns the PM1b Event Register Block
+    pub fn pm1b_evt_blk(&self) -> Option<GenericAddressStructure> {
+        let f = self.fields;
+        Self::register_block(f.x_pm1b_evt_blk, f.pm1b_evt_blk, f.pm1_evt_len)
+    }
+
+    /// Returns the number of bytes decoded by each PM1 Event Register Block, whose first half
+    /// holds the status register and whose second half holds the enable register
+    pub fn pm1_evt_len(&self) -> u8 {
+        self.fields.pm1_evt_len
+    }
+
+    /// Returns the PM1a Control Register Block
+    pub fn pm1a_cnt_blk(&self) -> Option<GenericAddressStructure> {
+        let f = self.fields;
+        Self::register_block(f.x_pm1a_cnt_blk, f.pm1a_cnt_blk, f.pm1_cnt_len)
+    }
+
+    /// Returns the PM1b Control Register Block
+    pub fn pm1b_cnt_blk(&self) -> Option<GenericAddressStructure> {
+        let f = self.fields;
+        Self::register_block(f.x_pm1b_cnt_blk, f.pm1b_cnt_blk, f.pm1_cnt_len)
+    }
+
+    /// Returns the PM2 Control Register Block
+    pub fn pm2_cnt_blk(&self) -> Option<GenericAddressStructure> {
+        let f = self.fields;
+        Self::register_block(f.x_pm2_cnt_blk, f.pm2_cnt_blk, f.pm2_cnt_len)
+    }
+
+    /// Returns the Power Management Timer Control Register Block
+    pub fn pm_tmr_blk(&self) -> Option<GenericAddressStructure> {
+        let f = self.fields;
+        Self::register_block(f.x_pm_tmr_blk, f.pm_tmr_blk, f.pm_tmr_len)
+    }
+
+    /// Returns the General-Purpose Event 0 Register Block
+    pub fn gpe0_blk(&self) -> Option<GenericAddressStructure> {
+        let f = self.fields;
+        Self::register_block(f.x_gpe0_blk, f.gpe0_blk, f.gpe0_blk_len)
+    }
+
+    /// Returns the General-Purpose Event 1 Register Block
+    pub fn gpe1_blk(&self) -> Option<GenericAddressStructure> {
+        let f = self.fields;
+        Self::register_block(f.x_gpe1_blk, f.gpe1_blk, f.gpe1_blk_len)
+    }
+
+    /// Returns the offset where GPE1 based events start
+    pub fn gpe1_base(&self) -> u8 {
+        self.fields.gpe1_base
+    }
+
+    /// Returns the RTC CMOS RAM index of the century, or 0 if not supported
+    pub fn century(&self) -> u8 {
+        self.fields.century
+    }
+
+    /// Returns the reset register and the value to write into it, if the platform supports
+    /// resetting through it
+    pub fn reset_reg(&self) -> Option<(GenericAddressStructure, u8)> {
+        let reset_reg = self.fields.reset_reg;
+
+        if !self.flags().contains(FixedFeatureFlags::RESET_REG_SUP) || !reset_reg.is_present() {
+            return None;
+        }
+
+        Some((reset_reg, self.fields.reset_value))
+    }
+
+    /// Returns the sleep control register of hardware-reduced ACPI platforms
+    pub fn sleep_control_reg(&self) -> Option<GenericAddressStructure> {
+        let sleep_control_reg = self.fields.sleep_control_reg;
+        sleep_control_reg.is_present().then_some(sleep_control_reg)
+    }
+
+    /// Returns the sleep status register of hardware-reduced ACPI platforms
+    pub fn sleep_status_reg(&self) -> Option<GenericAddressStructure> {
+        let sleep_status_reg = self.fields.sleep_status_reg;
+        sleep_status_reg.is_present().then_some(sleep_status_reg)
+    }
+
+    /// Returns the IA-PC Boot Architecture Flags. These are only defined starting with revision 2.
+    pub fn iapc_boot_arch(&self) -> IaPcBootArchFlags {
+        self.fields.iapc_boot_arch
+    }
+
+    /// Returns the fixed feature flags
+    pub fn flags(&self) -> FixedFeatureFlags {
+        self.fields.flags
+    }
+
+    /// Returns whether the platform implements the hardware-reduced ACPI model
+    pub fn is_hw_reduced(&self) -> bool {
+        self.flags().contains(FixedFeatureFlags::HW_REDUCED_ACPI)
+    }
+
+    /// Returns the identifier of the hypervisor vendor, if any
+    pub fn hypervisor_vendor_identity(&self) -> u64 {
+        self.fields.hypervisor_vendor_identity
+    }
+}
+
+impl AcpiTable for FADT {
+    const SIGNATURE: &'static [u8; 4] = FADT_SIGNATURE;
+
+    fn from_bytes(bytes: &[u8]) -> Result<Self, ParseError> {
+        FADT::from_bytes(bytes)
+    }
+}
+
+impl fmt::Debug for FADT {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("FADT")
+            .field("Revision", &self.revision())
+            .field("FACS", &self.facs_addr())
+            .field("DSDT", &self.dsdt_addr())
+            .field("SCI Interrupt", &self.sci_int())
+            .field("SMI Command", &self.smi_cmd())
+            .field("PM1a Event Block", &self.pm1a_evt_blk())
+            .field("PM1b Event Block", &self.pm1b_evt_blk())
+            .field("PM1a Control Block", &self.pm1a_cnt_blk())
+            .field("PM1b Control Block", &self.pm1b_cnt_blk())
+            .field("PM Timer Block", &self.pm_tmr_blk())
+            .field("Reset Register", &self.reset_reg())
+            .field("IA-PC Boot Architecture", &self.iapc_boot_arch())
+            .field("Flags", &self.flags())
+            .finish()
+    }
+}
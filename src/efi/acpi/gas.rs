@@ -0,0 +1,472 @@
+//! Module that holds the Generic Address Structure, used by ACPI tables to describe the position
+//! of registers, and the accessors used to read and write these registers
+use crate::cpu::{inb, inl, inw, outb, outl, outw};
+use crate::efi::acpi::FromBytes;
+use crate::pci::{self, PciAddress};
+use core::fmt;
+
+/// The Generic Address Structure (GAS) provides the platform with a robust means to describe
+/// register locations, in system memory, system I/O, PCI configuration space and other address
+/// spaces.
+#[derive(Default, Clone, Copy)]
+#[repr(C, packed)]
+pub struct GenericAddressStructure {
+    /// The address space where the data structure or register exists
+    pub address_space_id: u8,
+    /// The size in bits of the given register. When addressing a data structure, this field must
+    /// be zero.
+    pub register_bit_width: u8,
+    /// The bit offset of the given register at the given address. When addressing a data
+    /// structure, this field must be zero.
+    pub register_bit_offset: u8,
+    /// Specifies the access size. Unless otherwise defined by the address space id:
+    /// 0 Undefined (legacy reasons), 1 Byte access, 2 Word access, 3 Dword access, 4 QWord access
+    pub access_size: u8,
+    /// The 64-bit address of the data structure or register in the given address space
+    pub address: u64,
+}
+
+unsafe impl FromBytes for GenericAddressStructure {}
+
+impl GenericAddressStructure {
+    /// Builds the structure that describes a `length` bytes long block of I/O ports, starting at
+    /// `port`. This is how pre-ACPI 2.0 tables describe their register blocks.
+    pub fn from_io_block(port: u32, length: u8) -> Self {
+        Self {
+            address_space_id: address_space::SYSTEM_IO,
+            register_bit_width: length.saturating_mul(8),
+            register_bit_offset: 0,
+            access_size: 0,
+            address: port as u64,
+        }
+    }
+
+    /// Returns whether the structure describes an actual register, which is not the case when
+    /// the address is zero
+    pub fn is_present(&self) -> bool {
+        self.address != 0
+    }
+
+    /// Decodes the structure into a `GenericAddress` that can be read from and written to
+    pub fn decode(&self) -> Result<GenericAddress, GasError> {
+        GenericAddress::new(*self)
+    }
+}
+
+impl fmt::Debug for GenericAddressStructure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let address = self.address;
+
+        f.debug_struct("GenericAddressStructure")
+            .field("Address Space Id", &self.address_space_id)
+            .field("Register Bit Width", &self.register_bit_width)
+            .field("Register Bit Offset", &self.register_bit_offset)
+            .field("Access Size", &self.access_size)
+            .field("Address", &format_args!("{:#x}", address))
+            .finish()
+    }
+}
+
+/// Address space ids a Generic Address Structure can refer to
+pub mod address_space {
+    pub const SYSTEM_MEMORY: u8 = 0x00;
+    pub const SYSTEM_IO: u8 = 0x01;
+    pub const PCI_CONFIG: u8 = 0x02;
+    pub const EMBEDDED_CONTROLLER: u8 = 0x03;
+    pub const SMBUS: u8 = 0x04;
+    pub const SYSTEM_CMOS: u8 = 0x05;
+    pub const PCI_BAR_TARGET: u8 = 0x06;
+    pub const IPMI: u8 = 0x07;
+    pub const GENERAL_PURPOSE_IO: u8 = 0x08;
+    pub const GENERIC_SERIAL_BUS: u8 = 0x09;
+    pub const PLATFORM_COMM_CHANNEL: u8 = 0x0A;
+    pub const FUNCTIONAL_FIXED_HARDWARE: u8 = 0x7F;
+}
+
+/// The address spaces we know how to access
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AddressSpace {
+    SystemMemory,
+    SystemIo,
+    /// PCI configuration space of a function on segment group 0, bus 0
+    PciConfig,
+    /// Any other address space, which we cannot access
+    Other(u8),
+}
+
+impl From<u8> for AddressSpace {
+    fn from(value: u8) -> Self {
+        match value {
+            address_space::SYSTEM_MEMORY => Self::SystemMemory,
+            address_space::SYSTEM_IO => Self::SystemIo,
+            address_space::PCI_CONFIG => Self::PciConfig,
+            _ => Self::Other(value),
+        }
+    }
+}
+
+/// Reasons for which a Generic Address Structure cannot be used to access a register
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GasError {
+    /// The register lives in an address space we cannot access
+    UnsupportedAddressSpace(u8),
+    /// The access size is not defined, or not allowed in the register's address space
+    InvalidAccessSize(u8),
+    /// The register has no width, or goes past the 64 bits we can represent
+    InvalidBitWidth(u8),
+    /// The address does not fit in the register's address space
+    InvalidAddress(u64),
+}
+
+/// A decoded Generic Address Structure, describing a register that we know how to read and write
+#[derive(Debug, Clone, Copy)]
+pub struct GenericAddress {
+    // Address space of the register
+    space: AddressSpace,
+    // Size in bits of the register
+    bit_width: u8,
+    // Bit offset of the register from `address`
+    bit_offset: u8,
+    // Size in bits of each access we do to read or write the register
+    access_bits: u8,
+    // Address of the register in `space`
+    address: u64,
+}
+
+// Returns a mask with the lower `bits` bits set
+fn low_mask(bits: u32) -> u64 {
+    if bits >= u64::BITS {
+        u64::MAX
+    } else {
+        (1 << bits) - 1
+    }
+}
+
+// Splits the `len` bytes at `offset` in a PCI configuration space into naturally aligned
+// accesses, and calls `access` with the offset and size of each, and the bit at which it starts.
+// Accessing only the bytes of the unit matters for writes: writing back the rest of a dword
+// would clear the status bits in it that are cleared by writing 1 to them.
+fn pci_accesses(offset: u32, len: u32, mut access: impl FnMut(u8, u32, u32)) {
+    let mut done = 0;
+    while done < len {
+        let at = offset + done;
+        let size = [4, 2, 1]
+            .into_iter()
+            .find(|size| at.is_multiple_of(*size) && done + size <= len)
+            .unwrap_or(1);
+        access(at as u8, size, done * 8);
+        done += size;
+    }
+}
+
+impl GenericAddress {
+    /// Validates and decodes `gas`
+    pub fn new(gas: GenericAddressStructure) -> Result<Self, GasError> {
+        let space = AddressSpace::from(gas.address_space_id);
+        let address = gas.address;
+
+        if let AddressSpace::Other(id) = space {
+            return Err(GasError::UnsupportedAddressSpace(id));
+        }
+
+        let access_bits = match gas.access_size {
+            // Legacy structures do not define the access size, so we use the smallest access that
+            // covers the entire register
+            0 => {
+                let span = gas.register_bit_offset as u32 + gas.register_bit_width as u32;
+                if span == 0 {
+                    return Err(GasError::InvalidBitWidth(0));
+                }
+                span.next_power_of_two().clamp(8, 64) as u8
+            }
+            size @ 1..=4 => 8 << (size - 1),
+            size => return Err(GasError::InvalidAccessSize(size)),
+        };
+
+        // Some firmware leaves the width out, describing the register by its access size only
+        let bit_width = match gas.register_bit_width {
+            0 => access_bits,
+            width => width,
+        };
+
+        if gas.register_bit_offset as u32 + bit_width as u32 > u64::BITS {
+            return Err(GasError::InvalidBitWidth(bit_width));
+        }
+
+        // The number of bytes, from `address`, covered by the register
+        let span = (gas.register_bit_offset as u64 + bit_width as u64).div_ceil(8);
+
+        match space {
+            AddressSpace::SystemIo => {
+                if access_bits > 32 {
+                    return Err(GasError::InvalidAccessSize(gas.access_size));
+                }
+                if address.checked_add(span).is_none_or(|end| end > u16::MAX as u64 + 1) {
+                    return Err(GasError::InvalidAddress(address));
+                }
+            }
+            // The legacy configuration mechanism only reaches the first 256 bytes
+            AddressSpace::PciConfig if (address & 0xFFFF) + span > 0x100 => {
+                return Err(GasError::InvalidAddress(address));
+            }
+            _ => {}
+        }
+
+        Ok(Self {
+            space,
+            bit_width,
+            bit_offset: gas.register_bit_offset,
+            access_bits,
+            address,
+        })
+    }
+
+    pub fn space(&self) -> AddressSpace {
+        self.space
+    }
+    pub fn address(&self) -> u64 {
+        self.address
+    }
+    pub fn bit_width(&self) -> u8 {
+        self.bit_width
+    }
+    pub fn bit_offset(&self) -> u8 {
+        self.bit_offset
+    }
+    pub fn access_bits(&self) -> u8 {
+        self.access_bits
+    }
+
+    // Returns the indices of the first and last access units covered by the register
+    fn units(&self) -> core::ops::RangeInclusive<u32> {
+        let unit = self.access_bits as u32;
+        let first = self.bit_offset as u32 / unit;
+        let last = (self.bit_offset as u32 + self.bit_width as u32 - 1) / unit;
+        first..=last
+    }
+
+    /// Reads the register, returning its value shifted down to bit 0
+    ///
+    /// # Safety
+    ///
+    /// Memory registers must be mapped. Reading a register can have side effects on the device
+    /// behind it, which the caller must own.
+    pub unsafe fn read(&self) -> u64 {
+        let unit = self.access_bits as u32;
+        let mut raw = 0u64;
+
+        for idx in self.units() {
+            raw |= self.read_unit(idx * unit / 8) << (idx * unit);
+        }
+
+        (raw >> self.bit_offset) & low_mask(self.bit_width as u32)
+    }
+
+    /// Writes `value` into the register. Bits of the accessed units that are outside of the
+    /// register are preserved.
+    ///
+    /// # Safety
+    ///
+    /// Memory registers must be mapped. Writing a register can have any side effect on the
+    /// device behind it, which the caller must own.
+    pub unsafe fn write(&self, value: u64) {
+        let unit = self.access_bits as u32;
+        let reg_mask = low_mask(self.bit_width as u32) << self.bit_offset;
+        let bits = (value << self.bit_offset) & reg_mask;
+
+        for idx in self.units() {
+            let shift = idx * unit;
+            let unit_mask = (reg_mask >> shift) & low_mask(unit);
+            let unit_bits = (bits >> shift) & low_mask(unit);
+
+            let unit_value = if unit_mask == low_mask(unit) {
+                unit_bits
+            } else {
+                // The register only covers part of this unit
+                (self.read_unit(shift / 8) & !unit_mask) | unit_bits
+            };
+
+            self.write_unit(shift / 8, unit_value);
+        }
+    }
+
+    // Splits the address of a PCI configuration register into the function and the offset
+    fn pci_location(&self, byte_offset: u32) -> (PciAddress, u32) {
+        let device = (self.address >> 32) as u8;
+        let function = (self.address >> 16) as u8;
+        let offset = (self.address & 0xFFFF) as u32 + byte_offset;
+        (PciAddress::new(0, 0, device, function), offset)
+    }
+
+    // Reads a single access unit, `byte_offset` bytes after the register's address
+    unsafe fn read_unit(&self, byte_offset: u32) -> u64 {
+        match self.space {
+            AddressSpace::SystemMemory => {
+                let addr = self.address as usize + byte_offset as usize;
+                match self.access_bits {
+                    8 => core::ptr::read_volatile(addr as *const u8) as u64,
+                    16 => core::ptr::read_volatile(addr as *const u16) as u64,
+                    32 => core::ptr::read_volatile(addr as *const u32) as u64,
+                    _ => core::ptr::read_volatile(addr as *const u64),
+                }
+            }
+            AddressSpace::SystemIo => {
+                let port = self.address as u16 + byte_offset as u16;
+                match self.access_bits {
+                    8 => inb(port) as u64,
+                    16 => inw(port) as u64,
+                    _ => inl(port) as u64,
+                }
+            }
+            AddressSpace::PciConfig => {
+                let (function, offset) = self.pci_location(byte_offset);
+                let mut value = 0;
+                pci_accesses(offset, self.access_bits as u32 / 8, |offset, size, shift| {
+                    let part = match size {
+                        1 => pci::legacy::read_u8(function, offset) as u64,
+                        2 => pci::legacy::read_u16(function, offset) as u64,
+                        _ => pci::legacy::read_u32(function, offset) as u64,
+                    };
+                    value |= part << shift;
+                });
+                value
+            }
+            AddressSpace::Other(_) => unreachable!(),
+        }
+    }
+
+    // Writes a single access unit, `byte_offset` bytes after the register's address
+    unsafe fn write_unit(&self, byte_offset: u32, value: u64) {
+        match self.space {
+            AddressSpace::SystemMemory => {
+                let addr = self.address as usize + byte_offset as usize;
+                match self.access_bits {
+                    8 => core::ptr::write_volatile(addr as *mut u8, value as u8),
+                    16 => core::ptr::write_volatile(addr as *mut u16, value as u16),
+                    32 => core::ptr::write_volatile(addr as *mut u32, value as u32),
+                    _ => core::ptr::write_volatile(addr as *mut u64, value),
+                }
+            }
+            AddressSpace::SystemIo => {
+                let port = self.address as u16 + byte_offset as u16;
+                match self.access_bits {
+                    8 => outb(port, value as u8),
+                    16 => outw(port, value as u16),
+                    _ => outl(port, value as u32),
+                }
+            }
+            AddressSpace::PciConfig => {
+                let (function, offset) = self.pci_location(byte_offset);
+                pci_accesses(offset, self.access_bits as u32 / 8, |offset, size, shift| {
+                    let part = value >> shift;
+                    match size {
+                        1 => pci::legacy::write_u8(function, offset, part as u8),
+                        2 => pci::legacy::write_u16(function, offset, part as u16),
+                        _ => pci::legacy::write_u32(function, offset, part as u32),
+                    }
+                });
+            }
+            AddressSpace::Other(_) => unreachable!(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::gas;
+    use std::sync::atomic::{AtomicU64, Ordering};
+
+    #[test]
+    fn generic_address_checks() {
+        let io = address_space::SYSTEM_IO;
+        let pci = address_space::PCI_CONFIG;
+        let memory = address_space::SYSTEM_MEMORY;
+
+        // Access sizes beyond QWord, and QWord port I/O, are not allowed
+        assert_eq!(
+            GenericAddress::new(gas(memory, 32, 0, 5, 0x1000)).err(),
+            Some(GasError::InvalidAccessSize(5))
+        );
+        assert_eq!(
+            GenericAddress::new(gas(io, 64, 0, 4, 0x600)).err(),
+            Some(GasError::InvalidAccessSize(4))
+        );
+        let reg = GenericAddress::new(gas(memory, 64, 0, 4, 0x1000)).unwrap();
+        assert_eq!(reg.access_bits(), 64);
+
+        // Without an access size, the smallest access covering the register is used
+        let reg = GenericAddress::new(gas(io, 16, 4, 0, 0x600)).unwrap();
+        assert_eq!((reg.access_bits(), reg.bit_width()), (32, 16));
+        assert_eq!(
+            GenericAddress::new(gas(io, 0, 0, 0, 0x600)).err(),
+            Some(GasError::InvalidBitWidth(0))
+        );
+        assert_eq!(
+            GenericAddress::new(gas(memory, 64, 8, 4, 0x1000)).err(),
+            Some(GasError::InvalidBitWidth(64))
+        );
+
+        // Port I/O stops at 0xFFFF, including for addresses that would wrap around
+        assert!(GenericAddress::new(gas(io, 16, 0, 2, 0xFFFE)).is_ok());
+        for address in [0xFFFF, 0x1_0000, u64::MAX] {
+            assert_eq!(
+                GenericAddress::new(gas(io, 16, 0, 2, address)).err(),
+                Some(GasError::InvalidAddress(address))
+            );
+        }
+
+        // PCI registers must be within the 256 bytes the legacy mechanism reaches. The device and
+        // function above the offset do not count.
+        let function = 0x1F << 32 | 3 << 16;
+        assert!(GenericAddress::new(gas(pci, 32, 0, 3, function | 0xFC)).is_ok());
+        for offset in [0xFD, 0x100] {
+            assert_eq!(
+                GenericAddress::new(gas(pci, 32, 0, 3, function | offset)).err(),
+                Some(GasError::InvalidAddress(function | offset))
+            );
+        }
+        assert!(GenericAddress::new(gas(pci, 8, 0, 1, function | 0xFF)).is_ok());
+
+        assert_eq!(
+            GenericAddress::new(gas(address_space::SMBUS, 8, 0, 1, 0)).err(),
+            Some(GasError::UnsupportedAddressSpace(address_space::SMBUS))
+        );
+    }
+
+    #[test]
+    fn generic_address_masking() {
+        let memory = AtomicU64::new(0xFEDC_BA98_7654_3210);
+        let address = memory.as_ptr() as u64;
+
+        // A register of 12 bits at bit 4, accessed a byte at a time, covers the low nibble of the
+        // first byte and all of the second
+        let reg =
+            GenericAddress::new(gas(address_space::SYSTEM_MEMORY, 12, 4, 1, address)).unwrap();
+        assert_eq!(unsafe { reg.read() }, 0x321);
+        unsafe { reg.write(0xABC) };
+        assert_eq!(memory.load(Ordering::SeqCst), 0xFEDC_BA98_7654_ABC0);
+        // Bits of the value beyond the register are dropped
+        unsafe { reg.write(0xF_F00F) };
+        assert_eq!(memory.load(Ordering::SeqCst), 0xFEDC_BA98_7654_00F0);
+        assert_eq!(unsafe { reg.read() }, 0x00F);
+
+        // A register of 16 bits straddling two dwords
+        memory.store(0xFEDC_BA98_7654_3210, Ordering::SeqCst);
+        let reg =
+            GenericAddress::new(gas(address_space::SYSTEM_MEMORY, 16, 24, 3, address)).unwrap();
+        assert_eq!(unsafe { reg.read() }, 0x9876);
+        unsafe { reg.write(0x1234) };
+        assert_eq!(memory.load(Ordering::SeqCst), 0xFEDC_BA12_3454_3210);
+
+        // Registers that fill their access units
+        let reg =
+            GenericAddress::new(gas(address_space::SYSTEM_MEMORY, 32, 32, 3, address)).unwrap();
+        unsafe { reg.write(u64::MAX) };
+        assert_eq!(memory.load(Ordering::SeqCst), 0xFFFF_FFFF_3454_3210);
+        let reg =
+            GenericAddress::new(gas(address_space::SYSTEM_MEMORY, 64, 0, 4, address)).unwrap();
+        unsafe { reg.write(0x0123_4567_89AB_CDEF) };
+        assert_eq!(unsafe { reg.read() }, 0x0123_4567_89AB_CDEF);
+    }
+}
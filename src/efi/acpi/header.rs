@@ -1,6 +1,9 @@
+use crate::efi::acpi::parse::{checksum, read, FromBytes, ParseError};
+use core::mem::size_of;
+
 /// All system description tables begin with the structure below, `DescriptionHeader`
 /// The `signature` field determines the content of the system description table.
-#[derive(Debug)]
+#[derive(Debug, Default, Clone, Copy)]
 #[repr(C, packed)]
 pub struct DescriptionHeader {
     /// The ASCII string representation of the table identifier. Notice that if OSPM finds a
@@ -32,13 +35,57 @@ pub struct DescriptionHeader {
     creator_revision: u32,
 }
 
+unsafe impl FromBytes for DescriptionHeader {}
+
 impl DescriptionHeader {
-    pub fn from_addr(addr: usize) -> Self {
-        // Check if the pointer is not NULL ?
+    /// Reads the header from the start of `bytes`
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, ParseError> {
+        read(bytes, 0)
+    }
+
+    /// Reads the header of the table held by `bytes`, checking that it has the `signature` we
+    /// expect. Returns the header along with the bytes of the table, as many as its length says.
+    pub fn parse_table<'a>(
+        bytes: &'a [u8],
+        signature: &[u8; 4],
+    ) -> Result<(Self, &'a [u8]), ParseError> {
+        let header = Self::from_bytes(bytes)?;
+        if &header.signature != signature {
+            return Err(ParseError::InvalidSignature);
+        }
+
+        let length = header.length as usize;
+        if length < size_of::<DescriptionHeader>() {
+            return Err(ParseError::InvalidLength(length));
+        }
+        let table = bytes.get(..length).ok_or(ParseError::Truncated {
+            needed: length,
+            available: bytes.len(),
+        })?;
+
+        Ok((header, table))
+    }
+
+    pub fn revision(&self) -> u8 {
+        self.revision
+    }
 
-        // Read the header from the address
-        let header = unsafe { core::ptr::read_unaligned(addr as *const DescriptionHeader) };
+    /// Returns the OEM ID
+    pub fn oem_id(&self) -> [u8; 6] {
+        self.oemid
+    }
+
+    /// Returns the OEM Table ID, which helps telling apart tables with the same signature, like
+    /// the SSDTs
+    pub fn oem_table_id(&self) -> [u8; 8] {
+        self.oem_table_id
+    }
 
-        header
+    /// Verifies that all the `length` bytes of `table`, the table this header was read from, sum
+    /// to zero
+    pub fn is_checksum_valid(&self, table: &[u8]) -> bool {
+        table
+            .get(..self.length as usize)
+            .is_some_and(|table| checksum(table) == 0)
     }
 }
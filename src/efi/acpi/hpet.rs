@@ -0,0 +1,143 @@
+use crate::efi::acpi::{
+    read, AcpiTable, DescriptionHeader, FromBytes, GenericAddressStructure, ParseError,
+};
+use core::fmt;
+use core::mem::size_of;
+
+/// High Precision Event Timer Description Table.
+/// It replaces the obsolete Event Timer Description Table, and describes a single event timer
+/// block: where its registers are, how many comparators it has, and how often a periodic timer
+/// can be made to fire. Systems with several blocks have one table for each.
+pub struct HPET {
+    header: DescriptionHeader,
+    fields: HpetFields,
+}
+
+// The fields that follow the header
+#[derive(Clone, Copy)]
+#[repr(C, packed)]
+struct HpetFields {
+    // Hardware ID of the event timer block, which is a copy of the low 32 bits of its General
+    // Capabilities and ID Register
+    event_timer_block_id: u32,
+    // The address of the registers of the block. Only system memory is allowed.
+    base_addr: GenericAddressStructure,
+    // The sequence number of the block, starting at 0
+    hpet_number: u8,
+    // The minimum number of main counter ticks between two interrupts of a comparator in periodic
+    // mode, without losing interrupts
+    min_tick: u16,
+    // The page protection of the registers in the low nibble, and OEM attributes in the high one
+    page_protection: u8,
+}
+
+unsafe impl FromBytes for HpetFields {}
+
+/// The protection the registers of the event timer block are given, such that an OS can map them
+/// on their own pages
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PageProtection {
+    /// The registers may share a page with other registers
+    None,
+    /// No other register is in the 4 KiB page holding the block
+    Protected4K,
+    /// No other register is in the 64 KiB page holding the block
+    Protected64K,
+    Reserved(u8),
+}
+
+// The signature found in the first 4 bytes from the HPET table
+const HPET_SIGNATURE: &[u8; 4] = b"HPET";
+
+impl HPET {
+    /// Reads the HPET from the bytes of the entire table
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, ParseError> {
+        let (header, table) = DescriptionHeader::parse_table(bytes, HPET_SIGNATURE)?;
+        let fields = read(table, size_of::<DescriptionHeader>())?;
+
+        Ok(HPET { header, fields })
+    }
+
+    /// Returns the hardware revision of the event timer block
+    pub fn hardware_rev_id(&self) -> u8 {
+        self.fields.event_timer_block_id as u8
+    }
+
+    /// Returns the number of comparators, which is one more than the index of the last one
+    pub fn comparator_count(&self) -> u8 {
+        (self.fields.event_timer_block_id >> 8 & 0x1F) as u8 + 1
+    }
+
+    /// Returns whether the main counter is 64 bits wide, instead of 32
+    pub fn counter_64bit(&self) -> bool {
+        self.fields.event_timer_block_id & 1 << 13 != 0
+    }
+
+    /// Returns whether the block can replace the legacy 8254 PIT and RTC interrupts
+    pub fn legacy_replacement_capable(&self) -> bool {
+        self.fields.event_timer_block_id & 1 << 15 != 0
+    }
+
+    /// Returns the PCI vendor ID of the event timer block
+    pub fn pci_vendor_id(&self) -> u16 {
+        (self.fields.event_timer_block_id >> 16) as u16
+    }
+
+    /// Returns the address of the registers of the block
+    pub fn base_addr(&self) -> GenericAddressStructure {
+        self.fields.base_addr
+    }
+
+    /// Returns the sequence number of the block
+    pub fn hpet_number(&self) -> u8 {
+        self.fields.hpet_number
+    }
+
+    /// Returns the minimum number of main counter ticks between two interrupts of a periodic
+    /// comparator
+    pub fn min_tick(&self) -> u16 {
+        self.fields.min_tick
+    }
+
+    pub fn page_protection(&self) -> PageProtection {
+        match self.fields.page_protection & 0xF {
+            0 => PageProtection::None,
+            1 => PageProtection::Protected4K,
+            2 => PageProtection::Protected64K,
+            protection => PageProtection::Reserved(protection),
+        }
+    }
+
+    /// Returns the OEM attributes, held in the high nibble of the page protection field
+    pub fn oem_attributes(&self) -> u8 {
+        self.fields.page_protection >> 4
+    }
+}
+
+impl fmt::Debug for HPET {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("HPET")
+            .field("Revision", &self.header.revision())
+            .field("Hardware Revision", &self.hardware_rev_id())
+            .field("Comparators", &self.comparator_count())
+            .field("64-bit Counter", &self.counter_64bit())
+            .field("Legacy Replacement", &self.legacy_replacement_capable())
+            .field(
+                "PCI Vendor ID",
+                &format_args!("{:#x}", self.pci_vendor_id()),
+            )
+            .field("Base Address", &self.base_addr())
+            .field("HPET Number", &self.hpet_number())
+            .field("Minimum Tick", &self.min_tick())
+            .field("Page Protection", &self.page_protection())
+            .finish()
+    }
+}
+
+impl AcpiTable for HPET {
+    const SIGNATURE: &'static [u8; 4] = HPET_SIGNATURE;
+
+    fn from_bytes(bytes: &[u8]) -> Result<Self, ParseError> {
+        HPET::from_bytes(bytes)
+    }
+}
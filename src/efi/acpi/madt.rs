@@ -3,16 +3,34 @@ pub mod proc_local;
 pub mod io_apic;
 pub mod int_src_ovr;
 pub mod local_apic_nmi;
+pub mod lapic_addr_ovr;
+pub mod nmi_src;
+pub mod io_sapic;
+pub mod local_sapic;
+pub mod platform_int_src;
+pub mod proc_local_x2apic;
+pub mod local_x2apic_nmi;
+pub mod mp_wakeup;
 
-use crate::efi::acpi::DescriptionHeader;
+use crate::efi::acpi::{read, AcpiTable, DescriptionHeader, FromBytes, ParseError};
 use crate::print;
 use bitflags::bitflags;
+use core::fmt;
 use core::mem::size_of;
 use int_ctrl::{IntCtrl, IntCtrlHeader, MpsIntiFlags};
+use io_apic::InOutApic;
+use int_src_ovr::IntSrcOverride;
+use local_apic_nmi::LocalApicNmi;
+use mp_wakeup::MpWakeup;
+use nmi_src::NmiSrc;
+use proc_local::LocalApicFlags;
 
-/// This value comes from the fact that Rust can implement the Default trait for an array of type
-/// [T; N] as long as N <= 32
-pub const MAX_INT_CTRLS: usize = 32;
+/// The maximum number of interrupt controller structures kept from the MADT. Large systems have
+/// one structure per processor, so this must be well above the number of processors we support.
+pub const MAX_INT_CTRLS: usize = 512;
+
+// We make a constant that is able to initialize an array larger than 32 elements
+const INIT_INT_CTRL: Option<IntCtrl> = None;
 
 /// Multiple APIC Description Table.
 /// This is the ACPI way to describe all interrupts from the entire system in an uniform interrupt
@@ -21,13 +39,13 @@ pub const MAX_INT_CTRLS: usize = 32;
 /// - Intel Advanced Programmable Interrupt Controller (APIC)
 /// - The Streamlined version of the controller above (SAPIC)
 /// - The Generic Interrupt Controller(GIC) for ARM systems.
+///
 /// If a platform supports multiple models, an OS will install support for only one of the models;
 /// it will not mix models.
 /// ACPI represents all interrupts as "flat" values known as global system interrupts.
 /// Therefore to support APICs, SAPICs or GICs on an ACPI-enabled system, each used interrupt
 /// input must be mapped to the global system interrupt value used by ACPI.
 /// All addresses in the MADT are processor-relative physical addresses.
-#[repr(C, packed)]
 pub struct MADT {
     header: DescriptionHeader,
     // Local Interrupt Controller Address
@@ -40,12 +58,14 @@ pub struct MADT {
     // machine. The first byte of each structure decalres the type of that structure and the second
     // byte declared the length of that structure.
     int_ctrls: [Option<IntCtrl>; MAX_INT_CTRLS],
+    // Number of populated entries in `int_ctrls`
+    nint_ctrls: usize,
 }
 
 bitflags! {
     #[repr(transparent)]
     #[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
-    struct MultipleApicFlags: u32 {
+    pub struct MultipleApicFlags: u32 {
         /// A one indicates that the system also has a PC-AT-compatible dual-8259
         /// setup. The 8259 vectors must be disabled (that is, masked) when
         /// enabling the ACPI APIC operation.
@@ -53,50 +73,219 @@ bitflags! {
     }
 }
 
+unsafe impl FromBytes for MultipleApicFlags {}
+
+/// A processor that is either usable right away or can be brought online later, as described by a
+/// Processor Local APIC or a Processor Local x2APIC structure
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Processor {
+    /// The ACPI processor UID, matching the `_UID` of the processor device in the namespace
+    pub uid: u32,
+    /// The ID of the processor's local APIC
+    pub apic_id: u32,
+    /// Whether the processor is ready for use. Processors that are not can be enabled at runtime.
+    pub enabled: bool,
+}
+
+// The signature found in the first 4 bytes from the MADT table
+const MADT_SIGNATURE: &[u8; 4] = b"APIC";
+
 impl MADT {
-    pub fn from_header(addr: usize, header: DescriptionHeader) -> Option<Self> {
-        // Compute the address after the header of the MADT
-        let after_header_addr = addr + size_of::<DescriptionHeader>();
+    /// Reads the MADT from the bytes of the entire table, keeping up to `MAX_INT_CTRLS` interrupt
+    /// controller structures. Structures that are shorter than their header, or that go past the
+    /// end of the table, are rejected.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, ParseError> {
+        let (header, table) = DescriptionHeader::parse_table(bytes, MADT_SIGNATURE)?;
 
         // Read the Local Interrupt controller and the flags
-        let (lic_addr, flags): (u32, MultipleApicFlags) = unsafe {
-            (
-                core::ptr::read_unaligned(after_header_addr as *const u32),
-                core::ptr::read_unaligned(
-                    (after_header_addr + size_of::<u32>()) as *const MultipleApicFlags,
-                ),
-            )
-        };
+        let after_header = size_of::<DescriptionHeader>();
+        let lic_addr: u32 = read(table, after_header)?;
+        let flags: MultipleApicFlags = read(table, after_header + size_of::<u32>())?;
 
-        // Get the length of the entire structure
-        let madt_end_addr = addr + header.length as usize;
+        let mut madt = MADT {
+            header,
+            lic_addr,
+            flags,
+            int_ctrls: [INIT_INT_CTRL; MAX_INT_CTRLS],
+            nint_ctrls: 0,
+        };
 
-        // Compute the address for the next Interrupt Controller
-        let mut next_int_ctrl_addr = after_header_addr + 2 * size_of::<u32>();
+        // Compute the offset of the first Interrupt Controller
+        let mut offset = after_header + 2 * size_of::<u32>();
 
         // As long as we still have data to read, we read it
-        while next_int_ctrl_addr < madt_end_addr {
+        while offset < table.len() {
             // Read the Metadata for the controller
-            let int_ctrl_meta =
-                unsafe { core::ptr::read_unaligned(next_int_ctrl_addr as *const IntCtrlHeader) };
+            let int_ctrl_meta: IntCtrlHeader = read(table, offset)?;
+
+            // A length shorter than the metadata would have us read the same structure forever
+            let length = int_ctrl_meta.length as usize;
+            if length < size_of::<IntCtrlHeader>() {
+                return Err(ParseError::InvalidLength(length));
+            }
+            let entry = table.get(offset..offset + length).ok_or(ParseError::Truncated {
+                needed: offset + length,
+                available: table.len(),
+            })?;
 
-            let int_ctrl = IntCtrl::from_type(next_int_ctrl_addr, int_ctrl_meta.ctrl_type);
+            if madt.nint_ctrls == MAX_INT_CTRLS {
+                print!("The MADT holds more than {} entries, ignoring the rest\n", MAX_INT_CTRLS);
+                break;
+            }
 
-            // Print it for debugging
-            print!("{:#?}\n", int_ctrl);
+            madt.int_ctrls[madt.nint_ctrls] = Some(IntCtrl::from_bytes(entry)?);
+            madt.nint_ctrls += 1;
 
-            // Update the address to read the next Interrupt Controller
-            next_int_ctrl_addr += int_ctrl_meta.length as usize;
+            // Update the offset to read the next Interrupt Controller
+            offset += length;
         }
 
-        let madt = MADT {
-            header,
-            lic_addr,
-            flags,
-            int_ctrls: Default::default(),
-        };
+        Ok(madt)
+    }
+
+    /// Returns all the interrupt controller structures, in the order they appear in the table
+    pub fn int_ctrls(&self) -> impl Iterator<Item = &IntCtrl> {
+        self.int_ctrls[..self.nint_ctrls].iter().flatten()
+    }
+
+    pub fn flags(&self) -> MultipleApicFlags {
+        self.flags
+    }
+
+    /// Returns whether the system also has dual 8259 PICs, which must be masked before using the
+    /// APICs
+    pub fn pcat_compat(&self) -> bool {
+        self.flags.contains(MultipleApicFlags::PCAT_COMPAT)
+    }
+
+    /// Returns the physical address of the local APICs. A Local APIC Address Override structure,
+    /// if there is one, takes precedence over the 32-bit address of the table.
+    pub fn local_apic_addr(&self) -> u64 {
+        self.int_ctrls()
+            .find_map(|int_ctrl| match int_ctrl {
+                IntCtrl::LocalApicAddrOverride(ovr) => Some(ovr.local_apic_addr()),
+                _ => None,
+            })
+            .unwrap_or(self.lic_addr as u64)
+    }
 
-        Some(madt)
+    /// Returns the processors that are enabled, or that can be brought online at runtime, from
+    /// both the local APIC and the local x2APIC structures. Processors that are neither are
+    /// unusable and left out.
+    pub fn processors(&self) -> impl Iterator<Item = Processor> + '_ {
+        self.int_ctrls().filter_map(|int_ctrl| {
+            let (uid, apic_id, flags) = match int_ctrl {
+                IntCtrl::ProcLocalApic(lapic) => (
+                    lapic.acpi_processor_uid() as u32,
+                    lapic.apic_id() as u32,
+                    lapic.flags(),
+                ),
+                IntCtrl::ProcLocalX2Apic(x2apic) => (
+                    x2apic.acpi_processor_uid(),
+                    x2apic.x2apic_id(),
+                    x2apic.flags(),
+                ),
+                _ => return None,
+            };
+
+            if !flags.intersects(LocalApicFlags::ENABLED | LocalApicFlags::ONLINE_CAPABLE) {
+                return None;
+            }
+
+            Some(Processor {
+                uid,
+                apic_id,
+                enabled: flags.contains(LocalApicFlags::ENABLED),
+            })
+        })
+    }
+
+    /// Returns the I/O APICs, along with the first global system interrupt they handle
+    pub fn io_apics(&self) -> impl Iterator<Item = &InOutApic> {
+        self.int_ctrls().filter_map(|int_ctrl| match int_ctrl {
+            IntCtrl::InOutApic(io_apic) => Some(io_apic),
+            _ => None,
+        })
+    }
+
+    /// Returns the interrupt source overrides, which describe the ISA interrupts that are not
+    /// identity-mapped to global system interrupts
+    pub fn int_src_overrides(&self) -> impl Iterator<Item = &IntSrcOverride> {
+        self.int_ctrls().filter_map(|int_ctrl| match int_ctrl {
+            IntCtrl::IntSrcOverride(ovr) => Some(ovr),
+            _ => None,
+        })
+    }
+
+    /// Returns the global system interrupt the ISA interrupt `irq` is connected to, along with
+    /// its polarity and trigger mode. Interrupts without an override are identity-mapped and
+    /// conform to the ISA bus.
+    pub fn isa_irq_to_gsi(&self, irq: u8) -> (u32, MpsIntiFlags) {
+        self.int_src_overrides()
+            .find(|ovr| ovr.bus() == 0 && ovr.source() == irq)
+            .map_or((irq as u32, MpsIntiFlags::empty()), |ovr| {
+                (ovr.global_sys_int(), ovr.flags())
+            })
+    }
+
+    /// Returns the local APIC interrupt inputs NMI is connected to
+    pub fn local_apic_nmis(&self) -> impl Iterator<Item = &LocalApicNmi> {
+        self.int_ctrls().filter_map(|int_ctrl| match int_ctrl {
+            IntCtrl::LocalApicNmi(nmi) => Some(nmi),
+            _ => None,
+        })
+    }
+
+    /// Returns the local APIC interrupt input NMI is connected to for the processor with the ACPI
+    /// UID `uid`, along with its polarity and trigger mode. Both the local APIC and the local
+    /// x2APIC NMI structures are looked at.
+    pub fn nmi_lint(&self, uid: u32) -> Option<(u8, MpsIntiFlags)> {
+        self.int_ctrls().find_map(|int_ctrl| match int_ctrl {
+            IntCtrl::LocalApicNmi(nmi) if nmi.applies_to(uid) => {
+                Some((nmi.local_apic_lintn(), nmi.flags()))
+            }
+            IntCtrl::LocalX2ApicNmi(nmi) if nmi.applies_to(uid) => {
+                Some((nmi.local_x2apic_lintn(), nmi.flags()))
+            }
+            _ => None,
+        })
+    }
+
+    /// Returns the I/O APIC interrupt inputs that are non-maskable, and therefore not available
+    /// to devices
+    pub fn nmi_sources(&self) -> impl Iterator<Item = &NmiSrc> {
+        self.int_ctrls().filter_map(|int_ctrl| match int_ctrl {
+            IntCtrl::NmiSrc(nmi_src) => Some(nmi_src),
+            _ => None,
+        })
+    }
+
+    /// Returns the mailbox through which application processors are woken up, on platforms that
+    /// use one instead of INIT-SIPI-SIPI
+    pub fn mp_wakeup(&self) -> Option<&MpWakeup> {
+        self.int_ctrls().find_map(|int_ctrl| match int_ctrl {
+            IntCtrl::MpWakeup(mp_wakeup) => Some(mp_wakeup),
+            _ => None,
+        })
+    }
+}
+
+impl fmt::Debug for MADT {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("MADT")
+            .field("Revision", &self.header.revision())
+            .field("Local APIC Address", &self.local_apic_addr())
+            .field("Flags", &self.flags)
+            .field("Interrupt Controllers", &self.nint_ctrls)
+            .finish()
     }
 }
 
+
+impl AcpiTable for MADT {
+    const SIGNATURE: &'static [u8; 4] = MADT_SIGNATURE;
+
+    fn from_bytes(bytes: &[u8]) -> Result<Self, ParseError> {
+        MADT::from_bytes(bytes)
+    }
+}
@@ -3,11 +3,21 @@ use crate::efi::acpi::madt::{
     proc_local::ProcLocalApic,
     io_apic::InOutApic,
     int_src_ovr::IntSrcOverride,
+    nmi_src::NmiSrc,
     local_apic_nmi::LocalApicNmi,
+    lapic_addr_ovr::LocalApicAddrOverride,
+    io_sapic::InOutSapic,
+    local_sapic::LocalSapic,
+    platform_int_src::PlatformIntSrc,
+    proc_local_x2apic::ProcLocalX2Apic,
+    local_x2apic_nmi::LocalX2ApicNmi,
+    mp_wakeup::MpWakeup,
 };
+use crate::efi::acpi::{read, FromBytes, ParseError};
 use bitflags::bitflags;
 
 /// Represents common fields for any Interrupt controller that we will refer to as a header.
+#[derive(Clone, Copy)]
 #[repr(C, packed)]
 pub(crate) struct IntCtrlHeader {
     // Type of the structures
@@ -16,37 +26,125 @@ pub(crate) struct IntCtrlHeader {
     pub length: u8,
 }
 
+unsafe impl FromBytes for IntCtrlHeader {}
+
+/// An interrupt controller structure we do not parse, but whose type we know. The structure
+/// stays in the bytes the MADT was parsed from, such that it can be read from `addr` if needed.
+#[derive(Debug, Clone, Copy)]
+pub struct RawIntCtrl {
+    /// Address of the structure in the bytes the MADT was parsed from, starting with its type.
+    /// This is the address of the mapping the table was read through, not necessarily the
+    /// physical address of the table.
+    pub addr: usize,
+    /// Length of the structure in bytes
+    pub length: u8,
+}
+
+/// The Generic Interrupt Controller structures, used by ARM systems
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GicKind {
+    CpuInterface,
+    Distributor,
+    MsiFrame,
+    Redistributor,
+    InterruptTranslationService,
+}
+
+/// The interrupt controller structures used by LoongArch systems
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PicKind {
+    Core,
+    LegacyIo,
+    HyperTransport,
+    ExtendedIo,
+    Msi,
+    BridgeIo,
+    LowPinCount,
+}
+
 /// Represents supported interrupt controller types
-#[derive(Debug)]
-pub(crate) enum IntCtrl {
+#[derive(Debug, Clone, Copy)]
+pub enum IntCtrl {
     ProcLocalApic(ProcLocalApic),
     InOutApic(InOutApic),
     IntSrcOverride(IntSrcOverride),
-    NmiSrc,
+    NmiSrc(NmiSrc),
     LocalApicNmi(LocalApicNmi),
+    LocalApicAddrOverride(LocalApicAddrOverride),
+    InOutSapic(InOutSapic),
+    LocalSapic(LocalSapic),
+    PlatformIntSrc(PlatformIntSrc),
+    ProcLocalX2Apic(ProcLocalX2Apic),
+    LocalX2ApicNmi(LocalX2ApicNmi),
+    Gic(GicKind, RawIntCtrl),
+    MpWakeup(MpWakeup),
+    Pic(PicKind, RawIntCtrl),
     Unknown(u8),
 }
 
 impl IntCtrl {
-    pub fn from_type(addr: usize, ctrl_type: u8) -> Self {
-        match ctrl_type {
+    /// Parses an interrupt controller structure from `bytes`, which hold the entire structure as
+    /// given by its length. Structures too short for their type are rejected.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, ParseError> {
+        let header: IntCtrlHeader = read(bytes, 0)?;
+        let raw = || RawIntCtrl {
+            addr: bytes.as_ptr() as usize,
+            length: header.length,
+        };
+
+        let int_ctrl = match header.ctrl_type {
             int_ctrl_type::PROCESSOR_LOCAL_APIC => {
-                Self::ProcLocalApic(ProcLocalApic::from_addr(addr))
+                Self::ProcLocalApic(ProcLocalApic::from_bytes(bytes)?)
             }
             int_ctrl_type::IN_OUT_APIC => {
-                Self::InOutApic(InOutApic::from_addr(addr))
+                Self::InOutApic(InOutApic::from_bytes(bytes)?)
             }
             int_ctrl_type::INTERRUPT_SOURCE_OVERRIDE => {
-                Self::IntSrcOverride(IntSrcOverride::from_addr(addr))
+                Self::IntSrcOverride(IntSrcOverride::from_bytes(bytes)?)
             }
             int_ctrl_type::NON_MASKABLE_INTERRUPT => {
-                Self::NmiSrc
+                Self::NmiSrc(NmiSrc::from_bytes(bytes)?)
             }
             int_ctrl_type::LOCAL_APIC_NMI => {
-                Self::LocalApicNmi(LocalApicNmi::from_addr(addr))
+                Self::LocalApicNmi(LocalApicNmi::from_bytes(bytes)?)
             }
-            _ => Self::Unknown(ctrl_type),
-        }
+            int_ctrl_type::LOCAL_APIC_ADDRESS_OVERRIDE => {
+                Self::LocalApicAddrOverride(LocalApicAddrOverride::from_bytes(bytes)?)
+            }
+            int_ctrl_type::IN_OUT_SAPIC => {
+                Self::InOutSapic(InOutSapic::from_bytes(bytes)?)
+            }
+            int_ctrl_type::LOCAL_SAPIC => {
+                Self::LocalSapic(LocalSapic::from_bytes(bytes)?)
+            }
+            int_ctrl_type::PLATFORM_INTERRUPT_SOURCES => {
+                Self::PlatformIntSrc(PlatformIntSrc::from_bytes(bytes)?)
+            }
+            int_ctrl_type::PROCESSOR_LOCAL_X2APIC => {
+                Self::ProcLocalX2Apic(ProcLocalX2Apic::from_bytes(bytes)?)
+            }
+            int_ctrl_type::LOCAL_X2APIC_NMI => {
+                Self::LocalX2ApicNmi(LocalX2ApicNmi::from_bytes(bytes)?)
+            }
+            int_ctrl_type::GICC => Self::Gic(GicKind::CpuInterface, raw()),
+            int_ctrl_type::GICD => Self::Gic(GicKind::Distributor, raw()),
+            int_ctrl_type::GIC_MSI_FRAME => Self::Gic(GicKind::MsiFrame, raw()),
+            int_ctrl_type::GICR => Self::Gic(GicKind::Redistributor, raw()),
+            int_ctrl_type::GIC_ITS => Self::Gic(GicKind::InterruptTranslationService, raw()),
+            int_ctrl_type::MULTIPROCESSOR_WAKEUP => {
+                Self::MpWakeup(MpWakeup::from_bytes(bytes)?)
+            }
+            int_ctrl_type::CORE_PIC => Self::Pic(PicKind::Core, raw()),
+            int_ctrl_type::LIO_PIC => Self::Pic(PicKind::LegacyIo, raw()),
+            int_ctrl_type::HT_PIC => Self::Pic(PicKind::HyperTransport, raw()),
+            int_ctrl_type::EIO_PIC => Self::Pic(PicKind::ExtendedIo, raw()),
+            int_ctrl_type::MSI_PIC => Self::Pic(PicKind::Msi, raw()),
+            int_ctrl_type::BIO_PIC => Self::Pic(PicKind::BridgeIo, raw()),
+            int_ctrl_type::LPC_PIC => Self::Pic(PicKind::LowPinCount, raw()),
+            ctrl_type => Self::Unknown(ctrl_type),
+        };
+
+        Ok(int_ctrl)
     }
 }
 
@@ -56,6 +154,25 @@ mod int_ctrl_type {
     pub const INTERRUPT_SOURCE_OVERRIDE: u8 = 2;
     pub const NON_MASKABLE_INTERRUPT: u8 = 3;
     pub const LOCAL_APIC_NMI: u8 = 4;
+    pub const LOCAL_APIC_ADDRESS_OVERRIDE: u8 = 5;
+    pub const IN_OUT_SAPIC: u8 = 6;
+    pub const LOCAL_SAPIC: u8 = 7;
+    pub const PLATFORM_INTERRUPT_SOURCES: u8 = 8;
+    pub const PROCESSOR_LOCAL_X2APIC: u8 = 9;
+    pub const LOCAL_X2APIC_NMI: u8 = 0xA;
+    pub const GICC: u8 = 0xB;
+    pub const GICD: u8 = 0xC;
+    pub const GIC_MSI_FRAME: u8 = 0xD;
+    pub const GICR: u8 = 0xE;
+    pub const GIC_ITS: u8 = 0xF;
+    pub const MULTIPROCESSOR_WAKEUP: u8 = 0x10;
+    pub const CORE_PIC: u8 = 0x11;
+    pub const LIO_PIC: u8 = 0x12;
+    pub const HT_PIC: u8 = 0x13;
+    pub const EIO_PIC: u8 = 0x14;
+    pub const MSI_PIC: u8 = 0x15;
+    pub const BIO_PIC: u8 = 0x16;
+    pub const LPC_PIC: u8 = 0x17;
 }
 
 bitflags! {
@@ -73,3 +190,39 @@ bitflags! {
         const LEVEL_TRIGGERED = Self::EDGE_TRIGGERED.bits() | Self::TRIGGER_RESERVED.bits();
     }
 }
+
+/// Polarity of an interrupt, as described by `MpsIntiFlags`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Polarity {
+    /// The polarity conforms to the specifications of the bus, which is active high for ISA
+    ConformsToBus,
+    ActiveHigh,
+    ActiveLow,
+}
+
+/// Trigger mode of an interrupt, as described by `MpsIntiFlags`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TriggerMode {
+    /// The trigger mode conforms to the specifications of the bus, which is edge for ISA
+    ConformsToBus,
+    Edge,
+    Level,
+}
+
+impl MpsIntiFlags {
+    pub fn polarity(&self) -> Polarity {
+        match self.bits() & Self::ACTIVE_LOW.bits() {
+            0b01 => Polarity::ActiveHigh,
+            0b11 => Polarity::ActiveLow,
+            _ => Polarity::ConformsToBus,
+        }
+    }
+
+    pub fn trigger_mode(&self) -> TriggerMode {
+        match self.bits() & Self::LEVEL_TRIGGERED.bits() {
+            0b0100 => TriggerMode::Edge,
+            0b1100 => TriggerMode::Level,
+            _ => TriggerMode::ConformsToBus,
+        }
+    }
+}
@@ -1,6 +1,7 @@
 //! Module that parses the Input/Output APIC Interrupt Controller Structure
 use core::fmt;
 use crate::efi::acpi::madt::{IntCtrlHeader, MpsIntiFlags};
+use crate::efi::acpi::{read, FromBytes, ParseError};
 
 /// Describes an Interrupt Source Override structure from the MADT ACPI table.
 ///
@@ -11,8 +12,9 @@ use crate::efi::acpi::madt::{IntCtrlHeader, MpsIntiFlags};
 /// For example, if your machine has the ISA Programmable Interrupt Timer (PIT) connected to ISA 
 /// IRQ 0, but in APIC mode, it is connected to I/O APIC interrupt input 2, then you would need an
 /// Interrupt Source Override where the source entry is ‘0’ and the Global System Interrupt is ‘2.’
+#[derive(Clone, Copy)]
 #[repr(C, packed)]
-pub(crate) struct IntSrcOverride {
+pub struct IntSrcOverride {
     header: IntCtrlHeader,
     // Constant, meaning ISA
     bus: u8,
@@ -25,11 +27,25 @@ pub(crate) struct IntSrcOverride {
     flags: MpsIntiFlags,
 }
 
+unsafe impl FromBytes for IntSrcOverride {}
+
 impl IntSrcOverride {
-    /// Reads and parses a `InOutApic` from a Physical Address
-    pub fn from_addr(addr: usize) -> Self {
-        let int_src_ovr = unsafe { core::ptr::read_unaligned(addr as *const IntSrcOverride) };
-        int_src_ovr
+    /// Reads and parses a `IntSrcOverride` from the bytes of the structure
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, ParseError> {
+        read(bytes, 0)
+    }
+
+    pub fn bus(&self) -> u8 {
+        self.bus
+    }
+    pub fn source(&self) -> u8 {
+        self.source
+    }
+    pub fn global_sys_int(&self) -> u32 {
+        self.global_sys_int
+    }
+    pub fn flags(&self) -> MpsIntiFlags {
+        self.flags
     }
 }
 
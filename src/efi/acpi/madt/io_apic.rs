@@ -1,14 +1,16 @@
 //! Module that parses the Input/Output APIC Interrupt Controller Structure
 use core::fmt;
 use crate::efi::acpi::madt::IntCtrlHeader;
+use crate::efi::acpi::{read, FromBytes, ParseError};
 
 /// In an APIC implementation, there are one or more I/O APICs. Each I/O APIC has a series of
 /// interrupt inputs, referred to as INTIn, where the value of n is from 0 to the number of the
 /// last interrupt input on the I/O APIC. The I/O APIC structure declares which global system
 /// interrupts are uniquely associated with the I/O APIC interrupt inputs. There is one I/O APIC
 /// structure for each I/O APIC in the system
+#[derive(Clone, Copy)]
 #[repr(C, packed)]
-pub(crate) struct InOutApic {
+pub struct InOutApic {
     header: IntCtrlHeader,
     // I/O APIC Id
     io_apic_id: u8,
@@ -23,11 +25,22 @@ pub(crate) struct InOutApic {
     global_system_int_base: u32,
 }
 
+unsafe impl FromBytes for InOutApic {}
+
 impl InOutApic {
-    /// Reads and parses a `InOutApic` from a Physical Address
-    pub fn from_addr(addr: usize) -> Self {
-        let io_apic = unsafe { core::ptr::read_unaligned(addr as *const InOutApic) };
-        io_apic
+    /// Reads and parses a `InOutApic` from the bytes of the structure
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, ParseError> {
+        read(bytes, 0)
+    }
+
+    pub fn io_apic_id(&self) -> u8 {
+        self.io_apic_id
+    }
+    pub fn io_apic_addr(&self) -> u32 {
+        self.io_apic_addr
+    }
+    pub fn global_system_int_base(&self) -> u32 {
+        self.global_system_int_base
     }
 }
 
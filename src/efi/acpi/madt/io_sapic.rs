@@ -0,0 +1,56 @@
+//! Module that parses the I/O SAPIC Structure
+use core::fmt;
+use crate::efi::acpi::madt::IntCtrlHeader;
+use crate::efi::acpi::{read, FromBytes, ParseError};
+
+/// The I/O SAPIC structure is very similar to the I/O APIC structure. If both I/O APIC and I/O
+/// SAPIC structures exist for a specific APIC ID, the information in the I/O SAPIC structure must
+/// be used.
+#[derive(Clone, Copy)]
+#[repr(C, packed)]
+pub struct InOutSapic {
+    header: IntCtrlHeader,
+    // I/O SAPIC Id
+    io_apic_id: u8,
+    // Reserved, must be 0
+    reserved: u8,
+    // The global system interrupt number where this I/O SAPIC’s interrupt inputs start
+    global_system_int_base: u32,
+    // The 64-bit physical address to access this I/O SAPIC. Each I/O SAPIC resides at a unique
+    // address.
+    io_sapic_addr: u64,
+}
+
+unsafe impl FromBytes for InOutSapic {}
+
+impl InOutSapic {
+    /// Reads and parses a `InOutSapic` from the bytes of the structure
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, ParseError> {
+        read(bytes, 0)
+    }
+
+    pub fn io_apic_id(&self) -> u8 {
+        self.io_apic_id
+    }
+    pub fn global_system_int_base(&self) -> u32 {
+        self.global_system_int_base
+    }
+    pub fn io_sapic_addr(&self) -> u64 {
+        self.io_sapic_addr
+    }
+}
+
+impl fmt::Debug for InOutSapic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let global_system_int_base = self.global_system_int_base;
+        let io_sapic_addr = self.io_sapic_addr;
+
+        f.debug_struct("InOutSapic")
+            .field("Type", &self.header.ctrl_type)
+            .field("Length", &self.header.length)
+            .field("I/O APIC Id", &self.io_apic_id)
+            .field("Global System Interrupt Base", &global_system_int_base)
+            .field("I/O SAPIC Address", &io_sapic_addr)
+            .finish()
+    }
+}
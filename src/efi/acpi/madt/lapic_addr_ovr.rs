@@ -0,0 +1,43 @@
+//! Module that parses the Local APIC Address Override Structure
+use core::fmt;
+use crate::efi::acpi::madt::IntCtrlHeader;
+use crate::efi::acpi::{read, FromBytes, ParseError};
+
+/// This optional structure supports 64-bit systems by providing an override of the physical
+/// address of the local APIC in the MADT’s table header, which is defined as a 32-bit field.
+/// If defined, OSPM must use the address specified in this structure for all local APICs, rather
+/// than the address contained in the MADT’s table header. Only one such structure may be defined.
+#[derive(Clone, Copy)]
+#[repr(C, packed)]
+pub struct LocalApicAddrOverride {
+    header: IntCtrlHeader,
+    // Reserved, must be 0
+    reserved: u16,
+    // Physical address of the local APIC
+    local_apic_addr: u64,
+}
+
+unsafe impl FromBytes for LocalApicAddrOverride {}
+
+impl LocalApicAddrOverride {
+    /// Reads and parses a `LocalApicAddrOverride` from the bytes of the structure
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, ParseError> {
+        read(bytes, 0)
+    }
+
+    pub fn local_apic_addr(&self) -> u64 {
+        self.local_apic_addr
+    }
+}
+
+impl fmt::Debug for LocalApicAddrOverride {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let local_apic_addr = self.local_apic_addr;
+
+        f.debug_struct("LocalApicAddrOverride")
+            .field("Type", &self.header.ctrl_type)
+            .field("Length", &self.header.length)
+            .field("Local APIC Address", &local_apic_addr)
+            .finish()
+    }
+}
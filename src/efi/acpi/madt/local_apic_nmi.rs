@@ -1,6 +1,7 @@
 //! Module that parses the Local ACPI Non-Maskable Interrupt structure
 use core::fmt;
 use crate::efi::acpi::madt::{IntCtrlHeader, MpsIntiFlags};
+use crate::efi::acpi::{read, FromBytes, ParseError};
 
 /// This structure describes the Local APIC interrupt input (LINTn) that NMI is connected to for
 /// each of the processors in the system where such a connection exists. This information is needed
@@ -8,8 +9,9 @@ use crate::efi::acpi::madt::{IntCtrlHeader, MpsIntiFlags};
 /// separate Local APIC NMI structure. For example, if the platform has 4 processors with ID 0-3
 /// and NMI is connected LINT1 for processor 3 and 2, two Local APIC NMI entries would be needed in
 /// the MADT.
+#[derive(Clone, Copy)]
 #[repr(C, packed)]
-pub(crate) struct LocalApicNmi {
+pub struct LocalApicNmi {
     header: IntCtrlHeader,
     // Value corresponding to the _UID listed in the processor’s device object, or the Processor ID
     // corresponding to the ID listed in the processor object. A value of 0xFF signifies that this
@@ -21,11 +23,26 @@ pub(crate) struct LocalApicNmi {
     local_apic_lintn: u8,
 }
 
+unsafe impl FromBytes for LocalApicNmi {}
+
 impl LocalApicNmi {
-    /// Reads and parses a `InOutApic` from a Physical Address
-    pub fn from_addr(addr: usize) -> Self {
-        let local_apic_nmi = unsafe { core::ptr::read_unaligned(addr as *const LocalApicNmi) };
-        local_apic_nmi 
+    /// Reads and parses a `LocalApicNmi` from the bytes of the structure
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, ParseError> {
+        read(bytes, 0)
+    }
+
+    pub fn acpi_proc_uid(&self) -> u8 {
+        self.acpi_proc_uid
+    }
+    pub fn flags(&self) -> MpsIntiFlags {
+        self.flags
+    }
+    pub fn local_apic_lintn(&self) -> u8 {
+        self.local_apic_lintn
+    }
+    /// Returns whether the structure applies to the processor with the ACPI UID `uid`
+    pub fn applies_to(&self, uid: u32) -> bool {
+        self.acpi_proc_uid == 0xFF || self.acpi_proc_uid as u32 == uid
     }
 }
 
@@ -0,0 +1,67 @@
+//! Module that parses the Processor Local SAPIC Structure
+use core::fmt;
+use crate::efi::acpi::madt::{proc_local::LocalApicFlags, IntCtrlHeader};
+use crate::efi::acpi::{read, FromBytes, ParseError};
+
+/// Describes a processor and its local SAPIC. The structure ends with an ACPI Processor UID
+/// string of variable length, which is not kept: processors are matched through the numeric
+/// `acpi_proc_uid_value` instead.
+#[derive(Clone, Copy)]
+#[repr(C, packed)]
+pub struct LocalSapic {
+    header: IntCtrlHeader,
+    // The processor ID listed in the processor object
+    acpi_processor_id: u8,
+    // The processor’s local SAPIC ID
+    local_sapic_id: u8,
+    // The processor’s local SAPIC EID
+    local_sapic_eid: u8,
+    // Reserved, must be 0
+    reserved: [u8; 3],
+    // Local SAPIC flags, with the same meaning as the Local APIC flags
+    flags: LocalApicFlags,
+    // Matches the `_UID` of the processor device in the namespace
+    acpi_proc_uid_value: u32,
+}
+
+unsafe impl FromBytes for LocalSapic {}
+
+impl LocalSapic {
+    /// Reads and parses a `LocalSapic` from the bytes of the structure
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, ParseError> {
+        read(bytes, 0)
+    }
+
+    pub fn acpi_processor_id(&self) -> u8 {
+        self.acpi_processor_id
+    }
+    pub fn local_sapic_id(&self) -> u8 {
+        self.local_sapic_id
+    }
+    pub fn local_sapic_eid(&self) -> u8 {
+        self.local_sapic_eid
+    }
+    pub fn flags(&self) -> LocalApicFlags {
+        self.flags
+    }
+    pub fn acpi_proc_uid_value(&self) -> u32 {
+        self.acpi_proc_uid_value
+    }
+}
+
+impl fmt::Debug for LocalSapic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let flags = self.flags;
+        let acpi_proc_uid_value = self.acpi_proc_uid_value;
+
+        f.debug_struct("LocalSapic")
+            .field("Type", &self.header.ctrl_type)
+            .field("Length", &self.header.length)
+            .field("ACPI Processor Id", &self.acpi_processor_id)
+            .field("Local SAPIC Id", &self.local_sapic_id)
+            .field("Local SAPIC EID", &self.local_sapic_eid)
+            .field("Flags", &flags)
+            .field("ACPI Processor UID Value", &acpi_proc_uid_value)
+            .finish()
+    }
+}
@@ -0,0 +1,60 @@
+//! Module that parses the Local x2APIC Non-Maskable Interrupt structure
+use core::fmt;
+use crate::efi::acpi::madt::{IntCtrlHeader, MpsIntiFlags};
+use crate::efi::acpi::{read, FromBytes, ParseError};
+
+/// The Local x2APIC NMI structure is very similar to the Local APIC NMI structure, but identifies
+/// processors through their 32-bit ACPI Processor UID.
+#[derive(Clone, Copy)]
+#[repr(C, packed)]
+pub struct LocalX2ApicNmi {
+    header: IntCtrlHeader,
+    // MPS INTI flags.
+    flags: MpsIntiFlags,
+    // Value corresponding to the _UID listed in the processor’s device object. A value of
+    // 0xFFFFFFFF signifies that this applies to all processors in the machine.
+    acpi_proc_uid: u32,
+    // Local x2APIC Interrupt input LINTn to which NMI is connected.
+    local_x2apic_lintn: u8,
+    // Reserved, must be 0
+    reserved: [u8; 3],
+}
+
+unsafe impl FromBytes for LocalX2ApicNmi {}
+
+impl LocalX2ApicNmi {
+    /// Reads and parses a `LocalX2ApicNmi` from the bytes of the structure
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, ParseError> {
+        read(bytes, 0)
+    }
+
+    pub fn flags(&self) -> MpsIntiFlags {
+        self.flags
+    }
+    pub fn acpi_proc_uid(&self) -> u32 {
+        self.acpi_proc_uid
+    }
+    pub fn local_x2apic_lintn(&self) -> u8 {
+        self.local_x2apic_lintn
+    }
+    /// Returns whether the structure applies to the processor with the ACPI UID `uid`
+    pub fn applies_to(&self, uid: u32) -> bool {
+        let acpi_proc_uid = self.acpi_proc_uid;
+        acpi_proc_uid == u32::MAX || acpi_proc_uid == uid
+    }
+}
+
+impl fmt::Debug for LocalX2ApicNmi {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let flags = self.flags;
+        let acpi_proc_uid = self.acpi_proc_uid;
+
+        f.debug_struct("LocalX2ApicNmi")
+            .field("Type", &self.header.ctrl_type)
+            .field("Length", &self.header.length)
+            .field("Flags", &flags)
+            .field("ACPI Proc UID", &acpi_proc_uid)
+            .field("Local x2APIC LINT Number", &self.local_x2apic_lintn)
+            .finish()
+    }
+}
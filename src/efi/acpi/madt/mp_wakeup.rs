@@ -0,0 +1,49 @@
+//! Module that parses the Multiprocessor Wakeup Structure
+use core::fmt;
+use crate::efi::acpi::madt::IntCtrlHeader;
+use crate::efi::acpi::{read, FromBytes, ParseError};
+
+/// On platforms where processors cannot be started with INIT-SIPI-SIPI, like confidential
+/// computing guests, the firmware parks the application processors on a shared mailbox. The OS
+/// wakes them up by writing a command to that mailbox.
+#[derive(Clone, Copy)]
+#[repr(C, packed)]
+pub struct MpWakeup {
+    header: IntCtrlHeader,
+    // Version of the mailbox. 0 for ACPI 6.4.
+    mailbox_version: u16,
+    // Reserved, must be 0
+    reserved: u32,
+    // Physical address of the mailbox. It must be in ACPI NVS memory and 4K aligned.
+    mailbox_addr: u64,
+}
+
+unsafe impl FromBytes for MpWakeup {}
+
+impl MpWakeup {
+    /// Reads and parses a `MpWakeup` from the bytes of the structure
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, ParseError> {
+        read(bytes, 0)
+    }
+
+    pub fn mailbox_version(&self) -> u16 {
+        self.mailbox_version
+    }
+    pub fn mailbox_addr(&self) -> u64 {
+        self.mailbox_addr
+    }
+}
+
+impl fmt::Debug for MpWakeup {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mailbox_version = self.mailbox_version;
+        let mailbox_addr = self.mailbox_addr;
+
+        f.debug_struct("MpWakeup")
+            .field("Type", &self.header.ctrl_type)
+            .field("Length", &self.header.length)
+            .field("Mailbox Version", &mailbox_version)
+            .field("Mailbox Address", &mailbox_addr)
+            .finish()
+    }
+}
@@ -0,0 +1,47 @@
+//! Module that parses the Non-Maskable Interrupt Source Structure
+use core::fmt;
+use crate::efi::acpi::madt::{IntCtrlHeader, MpsIntiFlags};
+use crate::efi::acpi::{read, FromBytes, ParseError};
+
+/// This structure allows a platform designer to specify which I/O (S)APIC interrupt inputs should
+/// be enabled as non-maskable. Any source that is non-maskable will not be available for use by
+/// devices.
+#[derive(Clone, Copy)]
+#[repr(C, packed)]
+pub struct NmiSrc {
+    header: IntCtrlHeader,
+    // MPS INTI flags.
+    flags: MpsIntiFlags,
+    // The Global System Interrupt that this NMI will signal.
+    global_sys_int: u32,
+}
+
+unsafe impl FromBytes for NmiSrc {}
+
+impl NmiSrc {
+    /// Reads and parses a `NmiSrc` from the bytes of the structure
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, ParseError> {
+        read(bytes, 0)
+    }
+
+    pub fn flags(&self) -> MpsIntiFlags {
+        self.flags
+    }
+    pub fn global_sys_int(&self) -> u32 {
+        self.global_sys_int
+    }
+}
+
+impl fmt::Debug for NmiSrc {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let flags = self.flags;
+        let global_sys_int = self.global_sys_int;
+
+        f.debug_struct("NmiSrc")
+            .field("Type", &self.header.ctrl_type)
+            .field("Length", &self.header.length)
+            .field("Flags", &flags)
+            .field("Global System Interrupt", &global_sys_int)
+            .finish()
+    }
+}
@@ -0,0 +1,79 @@
+//! Module that parses the Platform Interrupt Source Structure
+use core::fmt;
+use crate::efi::acpi::madt::{IntCtrlHeader, MpsIntiFlags};
+use crate::efi::acpi::{read, FromBytes, ParseError};
+
+/// Communicates which I/O SAPIC interrupt inputs are connected to the platform interrupt sources,
+/// like Platform Management Interrupts (PMI), INIT messages and Corrected Platform Error
+/// Interrupts (CPEI).
+#[derive(Clone, Copy)]
+#[repr(C, packed)]
+pub struct PlatformIntSrc {
+    header: IntCtrlHeader,
+    // MPS INTI flags.
+    flags: MpsIntiFlags,
+    // 1 for PMI, 2 for INIT and 3 for Corrected Platform Error Interrupt
+    interrupt_type: u8,
+    // Processor ID of the destination
+    processor_id: u8,
+    // Processor EID of the destination
+    processor_eid: u8,
+    // Value that OSPM must use to program the vector field of the I/O SAPIC redirection entry
+    // for PMI entries
+    io_sapic_vector: u8,
+    // The Global System Interrupt that this platform interrupt will signal
+    global_sys_int: u32,
+    // Platform Interrupt Source flags
+    platform_int_src_flags: u32,
+}
+
+unsafe impl FromBytes for PlatformIntSrc {}
+
+impl PlatformIntSrc {
+    /// Reads and parses a `PlatformIntSrc` from the bytes of the structure
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, ParseError> {
+        read(bytes, 0)
+    }
+
+    pub fn flags(&self) -> MpsIntiFlags {
+        self.flags
+    }
+    pub fn interrupt_type(&self) -> u8 {
+        self.interrupt_type
+    }
+    pub fn processor_id(&self) -> u8 {
+        self.processor_id
+    }
+    pub fn processor_eid(&self) -> u8 {
+        self.processor_eid
+    }
+    pub fn io_sapic_vector(&self) -> u8 {
+        self.io_sapic_vector
+    }
+    pub fn global_sys_int(&self) -> u32 {
+        self.global_sys_int
+    }
+    pub fn platform_int_src_flags(&self) -> u32 {
+        self.platform_int_src_flags
+    }
+}
+
+impl fmt::Debug for PlatformIntSrc {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let flags = self.flags;
+        let global_sys_int = self.global_sys_int;
+        let platform_int_src_flags = self.platform_int_src_flags;
+
+        f.debug_struct("PlatformIntSrc")
+            .field("Type", &self.header.ctrl_type)
+            .field("Length", &self.header.length)
+            .field("Flags", &flags)
+            .field("Interrupt Type", &self.interrupt_type)
+            .field("Processor Id", &self.processor_id)
+            .field("Processor EID", &self.processor_eid)
+            .field("I/O SAPIC Vector", &self.io_sapic_vector)
+            .field("Global System Interrupt", &global_sys_int)
+            .field("Platform Interrupt Source Flags", &platform_int_src_flags)
+            .finish()
+    }
+}
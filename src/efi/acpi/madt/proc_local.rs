@@ -1,21 +1,35 @@
 use bitflags::bitflags;
 use core::fmt;
 use crate::efi::acpi::madt::IntCtrlHeader;
+use crate::efi::acpi::{read, FromBytes, ParseError};
 
+/// Describes a processor and its local APIC. There is one such structure for each processor, or
+/// one Processor Local x2APIC structure for processors whose APIC ID does not fit in 8 bits.
+#[derive(Clone, Copy)]
 #[repr(C, packed)]
-pub(crate) struct ProcLocalApic {
+pub struct ProcLocalApic {
     header: IntCtrlHeader,
     acpi_processor_uid: u8,
     apic_id: u8,
     flags: LocalApicFlags,
 }
 
+unsafe impl FromBytes for ProcLocalApic {}
+
 impl ProcLocalApic {
-    /// Reads and parses a `ProcLocalApic` from a Physical Address
-    pub fn from_addr(addr: usize) -> Self {
-        let proc_local_apic = unsafe { core::ptr::read_unaligned(addr as *const ProcLocalApic) };
+    /// Reads and parses a `ProcLocalApic` from the bytes of the structure
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, ParseError> {
+        read(bytes, 0)
+    }
 
-        proc_local_apic
+    pub fn acpi_processor_uid(&self) -> u8 {
+        self.acpi_processor_uid
+    }
+    pub fn apic_id(&self) -> u8 {
+        self.apic_id
+    }
+    pub fn flags(&self) -> LocalApicFlags {
+        self.flags
     }
 }
 
@@ -35,7 +49,7 @@ impl fmt::Debug for ProcLocalApic {
 bitflags! {
     #[repr(transparent)]
     #[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
-    struct LocalApicFlags: u32 {
+    pub struct LocalApicFlags: u32 {
         /// If this bit is set the processor is ready for use.
         /// If this bit is clear and the Online Capable bit is set, system hardware
         /// supports enabling this processor during OS runtime.
@@ -0,0 +1,55 @@
+//! Module that parses the Processor Local x2APIC Structure
+use core::fmt;
+use crate::efi::acpi::madt::{proc_local::LocalApicFlags, IntCtrlHeader};
+use crate::efi::acpi::{read, FromBytes, ParseError};
+
+/// Describes a processor and its local x2APIC. Processors whose APIC ID is 255 or above can only
+/// be described by this structure, as the Processor Local APIC structure holds an 8-bit ID.
+#[derive(Clone, Copy)]
+#[repr(C, packed)]
+pub struct ProcLocalX2Apic {
+    header: IntCtrlHeader,
+    // Reserved, must be 0
+    reserved: u16,
+    // The processor’s local x2APIC ID
+    x2apic_id: u32,
+    // Same as the Local APIC flags
+    flags: LocalApicFlags,
+    // Matches the `_UID` of the processor device in the namespace
+    acpi_processor_uid: u32,
+}
+
+unsafe impl FromBytes for ProcLocalX2Apic {}
+
+impl ProcLocalX2Apic {
+    /// Reads and parses a `ProcLocalX2Apic` from the bytes of the structure
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, ParseError> {
+        read(bytes, 0)
+    }
+
+    pub fn x2apic_id(&self) -> u32 {
+        self.x2apic_id
+    }
+    pub fn flags(&self) -> LocalApicFlags {
+        self.flags
+    }
+    pub fn acpi_processor_uid(&self) -> u32 {
+        self.acpi_processor_uid
+    }
+}
+
+impl fmt::Debug for ProcLocalX2Apic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let x2apic_id = self.x2apic_id;
+        let flags = self.flags;
+        let acpi_processor_uid = self.acpi_processor_uid;
+
+        f.debug_struct("ProcLocalX2Apic")
+            .field("Type", &self.header.ctrl_type)
+            .field("Length", &self.header.length)
+            .field("x2APIC Id", &x2apic_id)
+            .field("Flags", &flags)
+            .field("ACPI Processor Uid", &acpi_processor_uid)
+            .finish()
+    }
+}
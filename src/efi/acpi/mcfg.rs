@@ -0,0 +1,130 @@
+use crate::efi::acpi::{read, AcpiTable, DescriptionHeader, FromBytes, ParseError};
+use core::fmt;
+use core::mem::size_of;
+
+/// The maximum number of configuration space ranges kept from the MCFG. Most systems have a single
+/// PCI segment group, and therefore a single range.
+pub const MAX_MCFG_ENTRIES: usize = 16;
+
+/// PCI Express Memory Mapped Configuration Space Base Address Description Table.
+/// It gives, for each PCI segment group, the physical address at which the configuration space of
+/// its buses is memory mapped, following the Enhanced Configuration Access Mechanism (ECAM). This
+/// is the only way to reach the extended configuration space, past the first 256 bytes of each
+/// function, and the segment groups other than 0.
+pub struct MCFG {
+    header: DescriptionHeader,
+    // The configuration space ranges, one for each segment group and range of buses
+    entries: [Option<McfgEntry>; MAX_MCFG_ENTRIES],
+    // Number of populated entries in `entries`
+    nentries: usize,
+}
+
+/// A range of buses of a PCI segment group whose configuration space is memory mapped
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(C, packed)]
+pub struct McfgEntry {
+    // Base address of the memory mapped configuration space. This is where bus 0 would be mapped,
+    // even if the range starts at a later bus.
+    base_addr: u64,
+    // The PCI segment group the buses belong to
+    segment: u16,
+    // First bus decoded by the host bridge
+    start_bus: u8,
+    // Last bus decoded by the host bridge
+    end_bus: u8,
+    // Reserved field
+    reserved: u32,
+}
+
+unsafe impl FromBytes for McfgEntry {}
+
+impl McfgEntry {
+    /// Returns the physical address at which bus 0 of the segment group would be mapped
+    pub fn base_addr(&self) -> u64 {
+        self.base_addr
+    }
+    pub fn segment(&self) -> u16 {
+        self.segment
+    }
+    pub fn start_bus(&self) -> u8 {
+        self.start_bus
+    }
+    pub fn end_bus(&self) -> u8 {
+        self.end_bus
+    }
+
+    /// Returns whether the bus `bus` of the segment group `segment` is in this range
+    pub fn contains(&self, segment: u16, bus: u8) -> bool {
+        self.segment() == segment && (self.start_bus..=self.end_bus).contains(&bus)
+    }
+}
+
+// The signature found in the first 4 bytes from the MCFG table
+const MCFG_SIGNATURE: &[u8; 4] = b"MCFG";
+
+// The number of reserved bytes between the header and the first entry
+const MCFG_RESERVED: usize = 8;
+
+impl MCFG {
+    /// Reads the MCFG from the bytes of the entire table. The table must only hold whole entries
+    /// after its reserved field, and no more than `MAX_MCFG_ENTRIES` of them: a range left out
+    /// would make the configuration space of its buses unreachable.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, ParseError> {
+        let (header, table) = DescriptionHeader::parse_table(bytes, MCFG_SIGNATURE)?;
+
+        let first_entry = size_of::<DescriptionHeader>() + MCFG_RESERVED;
+        let entries = table.get(first_entry..).ok_or(ParseError::Truncated {
+            needed: first_entry,
+            available: table.len(),
+        })?;
+        if entries.len() % size_of::<McfgEntry>() != 0 {
+            return Err(ParseError::InvalidLength(table.len()));
+        }
+
+        let mut mcfg = MCFG {
+            header,
+            entries: [None; MAX_MCFG_ENTRIES],
+            nentries: 0,
+        };
+
+        for offset in (0..entries.len()).step_by(size_of::<McfgEntry>()) {
+            let slot = mcfg
+                .entries
+                .get_mut(mcfg.nentries)
+                .ok_or(ParseError::TooManyEntries {
+                    limit: MAX_MCFG_ENTRIES,
+                })?;
+            *slot = Some(read(entries, offset)?);
+            mcfg.nentries += 1;
+        }
+
+        Ok(mcfg)
+    }
+
+    /// Returns all the configuration space ranges, in the order they appear in the table
+    pub fn entries(&self) -> impl Iterator<Item = &McfgEntry> {
+        self.entries[..self.nentries].iter().flatten()
+    }
+
+    /// Returns the range holding the bus `bus` of the segment group `segment`
+    pub fn find(&self, segment: u16, bus: u8) -> Option<&McfgEntry> {
+        self.entries().find(|entry| entry.contains(segment, bus))
+    }
+}
+
+impl fmt::Debug for MCFG {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("MCFG")
+            .field("Revision", &self.header.revision())
+            .field("Entries", &&self.entries[..self.nentries])
+            .finish()
+    }
+}
+
+impl AcpiTable for MCFG {
+    const SIGNATURE: &'static [u8; 4] = MCFG_SIGNATURE;
+
+    fn from_bytes(bytes: &[u8]) -> Result<Self, ParseError> {
+        MCFG::from_bytes(bytes)
+    }
+}
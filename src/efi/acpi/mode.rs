@@ -0,0 +1,230 @@
+//! Module that moves the platform between legacy mode, where the firmware owns the ACPI hardware
+//! registers through SMIs, and ACPI mode, where the OS owns them and receives SCIs
+#[cfg(not(test))]
+use crate::cpu::outb;
+use crate::efi::acpi::{GasError, FADT};
+use crate::pm_timer::PmTimer;
+
+/// Reasons for which the platform could not be moved into or out of ACPI mode
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AcpiModeError {
+    /// The platform is hardware-reduced ACPI, which has no legacy mode, nor the registers used to
+    /// switch out of it
+    HardwareReduced,
+    /// The FADT does not describe the PM1a control block, which holds SCI_EN
+    NoControlBlock,
+    /// The FADT gives no SMI command port, or no value to write into it for this transition
+    NoSmiCommand,
+    /// The PM1 control register could not be accessed
+    InvalidRegister(GasError),
+    /// SCI_EN did not reach the requested state in time
+    Timeout,
+}
+
+impl From<GasError> for AcpiModeError {
+    fn from(value: GasError) -> Self {
+        Self::InvalidRegister(value)
+    }
+}
+
+/// The SCI_EN bit of the PM1 control registers, set while the platform is in ACPI mode
+const SCI_EN: u64 = 1 << 0;
+
+/// How long the firmware is given to hand over the ACPI hardware registers, or to take them back
+const MODE_SWITCH_TIMEOUT_NS: u64 = 3_000_000_000;
+
+/// Number of polls of SCI_EN when there is no PM timer to time them with
+const MODE_SWITCH_SPINS: u32 = 10_000_000;
+
+/// Returns whether the platform is in ACPI mode, as told by the SCI_EN bit. Hardware-reduced
+/// platforms are always in ACPI mode.
+pub fn is_acpi_mode(fadt: &FADT) -> Result<bool, AcpiModeError> {
+    if fadt.is_hw_reduced() {
+        return Ok(true);
+    }
+
+    let pm1a_cnt = fadt.pm1a_cnt_blk().ok_or(AcpiModeError::NoControlBlock)?;
+    let reg = pm1a_cnt.decode()?;
+    Ok(unsafe { reg.read() } & SCI_EN != 0)
+}
+
+/// Moves the platform into ACPI mode, by writing ACPI_ENABLE to the SMI command port and waiting
+/// for SCI_EN to be set. This does nothing if the platform is already in ACPI mode.
+pub fn enable_acpi_mode(fadt: &FADT) -> Result<(), AcpiModeError> {
+    switch_mode(fadt, true)
+}
+
+/// Hands the ACPI hardware registers back to the firmware, by writing ACPI_DISABLE to the SMI
+/// command port and waiting for SCI_EN to be cleared. This does nothing if the platform is
+/// already in legacy mode.
+pub fn disable_acpi_mode(fadt: &FADT) -> Result<(), AcpiModeError> {
+    switch_mode(fadt, false)
+}
+
+// Writes the command that sets SCI_EN to `enabled`, then waits for the firmware to do so
+fn switch_mode(fadt: &FADT, enabled: bool) -> Result<(), AcpiModeError> {
+    if fadt.is_hw_reduced() {
+        return Err(AcpiModeError::HardwareReduced);
+    }
+    if is_acpi_mode(fadt)? == enabled {
+        return Ok(());
+    }
+
+    // Platforms without an SMI command port, or without a command for this transition, cannot
+    // leave the mode they are in
+    let command = match enabled {
+        true => fadt.acpi_enable(),
+        false => fadt.acpi_disable(),
+    };
+    let smi_cmd = fadt.smi_cmd().ok_or(AcpiModeError::NoSmiCommand)?;
+    if command == 0 {
+        return Err(AcpiModeError::NoSmiCommand);
+    }
+
+    write_smi_cmd(smi_cmd as u16, command);
+
+    let mut done = || is_acpi_mode(fadt).is_ok_and(|acpi_mode| acpi_mode == enabled);
+    let switched = match PmTimer::new(fadt) {
+        Ok(timer) => timer.wait_until(MODE_SWITCH_TIMEOUT_NS, &mut done),
+        Err(_) => (0..MODE_SWITCH_SPINS).any(|_| {
+            core::hint::spin_loop();
+            done()
+        }),
+    };
+
+    match switched {
+        true => Ok(()),
+        false => Err(AcpiModeError::Timeout),
+    }
+}
+
+#[cfg(test)]
+std::thread_local! {
+    // The commands written to the SMI command port by this thread, along with the port
+    static SMI_CMD_WRITES: core::cell::RefCell<std::vec::Vec<(u16, u8)>> =
+        const { core::cell::RefCell::new(std::vec::Vec::new()) };
+}
+
+// Writes `command` to the SMI command port `smi_cmd`. There is no firmware behind the port under
+// test, so the write is only kept track of.
+fn write_smi_cmd(smi_cmd: u16, command: u8) {
+    #[cfg(test)]
+    SMI_CMD_WRITES.with(|writes| writes.borrow_mut().push((smi_cmd, command)));
+
+    #[cfg(not(test))]
+    unsafe {
+        outb(smi_cmd, command)
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::efi::acpi::{address_space, FixedFeatureFlags};
+    use crate::test_support::{fix_checksum, gas, gas_bytes, Machine, Q35};
+    use std::sync::atomic::{AtomicU16, Ordering};
+
+    // The SMI command port of q35, and the commands moving it into and out of ACPI mode
+    const SMI_CMD: u32 = 0xB2;
+    const COMMANDS: (u8, u8) = (0xF1, 0xF0);
+
+    // Returns the q35 FADT with the flags `flags`, the SMI command port `smi_cmd` taking the
+    // ACPI_ENABLE and ACPI_DISABLE `commands`, and the PM1a control block `pm1a_cnt` in system
+    // memory. It has no PM timer, such that the switches are timed by spinning.
+    fn fadt(
+        flags: FixedFeatureFlags,
+        smi_cmd: u32,
+        commands: (u8, u8),
+        pm1a_cnt: Option<&AtomicU16>,
+    ) -> FADT {
+        let blk = pm1a_cnt.map_or(Default::default(), |pm1a_cnt| {
+            gas(
+                address_space::SYSTEM_MEMORY,
+                16,
+                0,
+                2,
+                pm1a_cnt.as_ptr() as u64,
+            )
+        });
+        let mut facp = Q35::table("facp").to_vec();
+        facp[48..52].copy_from_slice(&smi_cmd.to_le_bytes());
+        (facp[52], facp[53]) = commands;
+        facp[64..68].fill(0);
+        facp[76..80].fill(0);
+        facp[112..116].copy_from_slice(&flags.bits().to_le_bytes());
+        facp[172..184].copy_from_slice(&gas_bytes(&blk));
+        facp[208..220].fill(0);
+        fix_checksum(&mut facp, 9);
+        FADT::from_bytes(&facp).unwrap()
+    }
+
+    // Returns the commands written to the SMI command port since the last call
+    fn smi_cmd_writes() -> Vec<(u16, u8)> {
+        SMI_CMD_WRITES.with(|writes| writes.take())
+    }
+
+    #[test]
+    fn mode_switches() {
+        let pm1a_cnt = AtomicU16::new(0);
+        let flags = FixedFeatureFlags::empty();
+        let q35 = fadt(flags, SMI_CMD, COMMANDS, Some(&pm1a_cnt));
+
+        // Only SCI_EN tells the mode, and switching to the current one does not go through the
+        // firmware
+        pm1a_cnt.store(0x0002, Ordering::SeqCst);
+        assert_eq!(is_acpi_mode(&q35), Ok(false));
+        assert_eq!(disable_acpi_mode(&q35), Ok(()));
+        pm1a_cnt.store(0x0003, Ordering::SeqCst);
+        assert_eq!(is_acpi_mode(&q35), Ok(true));
+        assert_eq!(enable_acpi_mode(&q35), Ok(()));
+        assert_eq!(smi_cmd_writes(), []);
+
+        // Without an SMI command port, or a command for the transition, the mode cannot change
+        let no_disable = fadt(flags, SMI_CMD, (0xF1, 0), Some(&pm1a_cnt));
+        assert_eq!(
+            disable_acpi_mode(&no_disable),
+            Err(AcpiModeError::NoSmiCommand)
+        );
+        pm1a_cnt.store(0, Ordering::SeqCst);
+        let no_enable = fadt(flags, SMI_CMD, (0, 0xF0), Some(&pm1a_cnt));
+        assert_eq!(
+            enable_acpi_mode(&no_enable),
+            Err(AcpiModeError::NoSmiCommand)
+        );
+        let no_smi_cmd = fadt(flags, 0, COMMANDS, Some(&pm1a_cnt));
+        assert_eq!(
+            enable_acpi_mode(&no_smi_cmd),
+            Err(AcpiModeError::NoSmiCommand)
+        );
+        assert_eq!(smi_cmd_writes(), []);
+
+        // The command is written once, and the switch fails when SCI_EN never follows
+        assert_eq!(enable_acpi_mode(&q35), Err(AcpiModeError::Timeout));
+        assert_eq!(smi_cmd_writes(), [(0xB2, 0xF1)]);
+        assert_eq!(pm1a_cnt.load(Ordering::SeqCst), 0);
+
+        // Hardware-reduced platforms are always in ACPI mode, and have nothing to switch
+        let hw_reduced = fadt(FixedFeatureFlags::HW_REDUCED_ACPI, SMI_CMD, COMMANDS, None);
+        assert_eq!(is_acpi_mode(&hw_reduced), Ok(true));
+        assert_eq!(
+            enable_acpi_mode(&hw_reduced),
+            Err(AcpiModeError::HardwareReduced)
+        );
+        assert_eq!(
+            disable_acpi_mode(&hw_reduced),
+            Err(AcpiModeError::HardwareReduced)
+        );
+
+        // Other platforms cannot tell their mode without a PM1a control block
+        let no_pm1a_cnt = fadt(flags, SMI_CMD, COMMANDS, None);
+        assert_eq!(
+            is_acpi_mode(&no_pm1a_cnt),
+            Err(AcpiModeError::NoControlBlock)
+        );
+        assert_eq!(
+            enable_acpi_mode(&no_pm1a_cnt),
+            Err(AcpiModeError::NoControlBlock)
+        );
+        assert_eq!(smi_cmd_writes(), []);
+    }
+}
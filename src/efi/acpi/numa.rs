@@ -0,0 +1,145 @@
+//! Module that combines the SRAT and the SLIT into the NUMA topology of the system, and uses it to
+//! tell which proximity domain each region of the UEFI memory map belongs to
+use crate::efi::acpi::{
+    AcpiTables, MemoryAffinity, MemoryAffinityFlags, ProcessorAffinity, LOCAL_DISTANCE, SLIT, SRAT,
+};
+use crate::efi::malloc::EfiMemoryDescriptor;
+
+/// The maximum number of proximity domains we keep track of
+pub const MAX_NUMA_DOMAINS: usize = 64;
+
+/// The distance between two different proximity domains when there is no SLIT, which is the
+/// distance most systems report
+pub const REMOTE_DISTANCE: u8 = 20;
+
+/// The processors and memory ranges of each proximity domain, along with the distances between
+/// the domains
+pub struct NumaTopology {
+    srat: SRAT,
+    slit: Option<SLIT>,
+    // The distinct proximity domains, in the order they are first found in the SRAT
+    domains: [u32; MAX_NUMA_DOMAINS],
+    // Number of populated entries in `domains`
+    ndomains: usize,
+}
+
+/// A part of a UEFI memory map region that lies in a single proximity domain, or in none
+#[derive(Debug, Clone, Copy)]
+pub struct NumaRegion {
+    /// The memory map region this part comes from
+    pub descriptor: EfiMemoryDescriptor,
+    pub start: u64,
+    pub end: u64,
+    /// The proximity domain, or `None` if the SRAT does not describe this memory
+    pub proximity_domain: Option<u32>,
+    /// The flags of the SRAT memory range, which are empty if there is none
+    pub flags: MemoryAffinityFlags,
+}
+
+impl NumaTopology {
+    /// Builds the topology from the SRAT and the SLIT found in `tables`. Returns `None` if there
+    /// is no SRAT, in which case the system has a single proximity domain. Without a SLIT, all
+    /// domains are `REMOTE_DISTANCE` away from each other.
+    pub fn from_acpi(tables: &AcpiTables) -> Option<Self> {
+        let srat = tables.find::<SRAT>()?;
+        let slit = tables.find::<SLIT>();
+
+        let processor_domains = srat.processors().map(|cpu| cpu.proximity_domain);
+        let memory_domains = srat.memory_ranges().map(|mem| mem.proximity_domain);
+        let mut domains = [0; MAX_NUMA_DOMAINS];
+        let mut ndomains = 0;
+        for domain in processor_domains.chain(memory_domains) {
+            if !domains[..ndomains].contains(&domain) && ndomains < MAX_NUMA_DOMAINS {
+                domains[ndomains] = domain;
+                ndomains += 1;
+            }
+        }
+
+        Some(Self {
+            srat,
+            slit,
+            domains,
+            ndomains,
+        })
+    }
+
+    /// Returns the distinct proximity domains that hold processors or memory
+    pub fn domains(&self) -> &[u32] {
+        &self.domains[..self.ndomains]
+    }
+
+    /// Returns the enabled processors, along with their proximity domain
+    pub fn processors(&self) -> impl Iterator<Item = &ProcessorAffinity> {
+        self.srat.processors()
+    }
+
+    /// Returns the enabled memory ranges, along with their proximity domain
+    pub fn memory_ranges(&self) -> impl Iterator<Item = &MemoryAffinity> {
+        self.srat.memory_ranges()
+    }
+
+    /// Returns the proximity domain of the processor whose local APIC or x2APIC ID is `apic_id`
+    pub fn domain_of_apic(&self, apic_id: u32) -> Option<u32> {
+        self.processors()
+            .find(|cpu| cpu.apic_id == apic_id)
+            .map(|cpu| cpu.proximity_domain)
+    }
+
+    /// Returns the proximity domain of the memory at the physical address `addr`
+    pub fn domain_of_addr(&self, addr: u64) -> Option<u32> {
+        self.memory_ranges()
+            .find(|mem| mem.contains(addr))
+            .map(|mem| mem.proximity_domain)
+    }
+
+    /// Returns the relative distance from the proximity domain `from` to the domain `to`, where
+    /// `LOCAL_DISTANCE` is the distance of a domain to itself
+    pub fn distance(&self, from: u32, to: u32) -> u8 {
+        if let Some(distance) = self.slit.as_ref().and_then(|slit| slit.distance(from, to)) {
+            return distance;
+        }
+
+        if from == to {
+            LOCAL_DISTANCE
+        } else {
+            REMOTE_DISTANCE
+        }
+    }
+
+    /// Calls `f` on each part of the memory map regions `descriptors` that lies in a single
+    /// proximity domain. Regions that span several SRAT memory ranges are split, and the parts
+    /// that are in no range have no proximity domain.
+    pub fn for_each_region<'a>(
+        &self,
+        descriptors: impl Iterator<Item = &'a EfiMemoryDescriptor>,
+        mut f: impl FnMut(&NumaRegion),
+    ) {
+        for descriptor in descriptors {
+            let mut start = descriptor.phys_start();
+            let end = descriptor.phys_end();
+
+            while start < end {
+                let range = self.memory_ranges().find(|mem| mem.contains(start));
+                let part_end = match range {
+                    Some(range) => range.end().min(end),
+                    // Stop at the next range that starts within the region, if there is one
+                    None => self
+                        .memory_ranges()
+                        .map(|mem| mem.base)
+                        .filter(|&base| base > start)
+                        .fold(end, u64::min),
+                };
+
+                f(&NumaRegion {
+                    descriptor: *descriptor,
+                    start,
+                    end: part_end,
+                    proximity_domain: range.map(|mem| mem.proximity_domain),
+                    flags: range.map_or(MemoryAffinityFlags::empty(), |mem| mem.flags),
+                });
+
+                start = part_end;
+            }
+        }
+    }
+}
@@ -0,0 +1,72 @@
+//! Module that holds the helpers used to parse ACPI structures out of bounded byte slices. Nothing
+//! in here touches physical memory, such that malformed tables are reported instead of causing
+//! reads past their end.
+use core::mem::size_of;
+
+/// Reasons for which an ACPI structure could not be parsed
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ParseError {
+    /// The structure needs `needed` bytes, but only `available` bytes are left
+    Truncated { needed: usize, available: usize },
+    /// The length field of a structure is smaller than its fixed part, or larger than what we
+    /// accept for a table
+    InvalidLength(usize),
+    /// The structure does not have the expected signature
+    InvalidSignature,
+    /// The structure is at the null address
+    NullAddress,
+    /// A field that must be accessed atomically is at the given address, which is not aligned on
+    /// its size
+    Misaligned(usize),
+    /// The table holds more entries of a kind than the `limit` we have room for
+    TooManyEntries { limit: usize },
+}
+
+/// Implemented by the structures that can be read from any sequence of bytes, which is the case
+/// of `repr(C, packed)` structures only made of integers, byte arrays and `repr(transparent)`
+/// bitflags.
+///
+/// # Safety
+///
+/// Every bit pattern of `size_of::<Self>()` bytes must be a valid value of the type.
+pub unsafe trait FromBytes: Copy {}
+
+unsafe impl FromBytes for u8 {}
+unsafe impl FromBytes for u16 {}
+unsafe impl FromBytes for u32 {}
+unsafe impl FromBytes for u64 {}
+
+/// Reads a `T` from `bytes`, `offset` bytes from its start
+pub fn read<T: FromBytes>(bytes: &[u8], offset: usize) -> Result<T, ParseError> {
+    let data = bytes
+        .get(offset..)
+        .and_then(|data| data.get(..size_of::<T>()))
+        .ok_or(ParseError::Truncated {
+            needed: offset + size_of::<T>(),
+            available: bytes.len(),
+        })?;
+
+    // SAFETY: `data` holds `size_of::<T>()` bytes, which make a valid `T` by `FromBytes`
+    Ok(unsafe { core::ptr::read_unaligned(data.as_ptr() as *const T) })
+}
+
+/// Reads a `T` from the start of `bytes`, the fields that are past the end of `bytes` being left
+/// to their default value. This is how structures that grew over revisions are read.
+pub fn read_prefix<T: FromBytes + Default>(bytes: &[u8]) -> T {
+    let mut value = T::default();
+    let len = bytes.len().min(size_of::<T>());
+
+    // SAFETY: at most `size_of::<T>()` bytes are written over `value`, and any bytes make a valid
+    // `T` by `FromBytes`
+    unsafe {
+        core::ptr::copy_nonoverlapping(bytes.as_ptr(), &mut value as *mut T as *mut u8, len);
+    }
+
+    value
+}
+
+/// Computes the 8-bit sum of `bytes`. ACPI structures are valid if this sum, including their
+/// checksum field, is zero.
+pub fn checksum(bytes: &[u8]) -> u8 {
+    bytes.iter().fold(0u8, |sum, byte| sum.wrapping_add(*byte))
+}
@@ -0,0 +1,226 @@
+//! Module that turns the physical addresses found in ACPI structures into byte slices. This is the
+//! only place where ACPI code reads physical memory directly: the firmware identity maps it, and
+//! the memory holding the tables is never reused.
+use crate::efi::acpi::{read, DescriptionHeader, ParseError, RSDP, RSDP_V1_LENGTH};
+use core::mem::{align_of, size_of};
+use core::sync::atomic::AtomicU32;
+#[cfg(any(test, fuzzing))]
+use core::sync::atomic::{AtomicBool, AtomicPtr, AtomicUsize, Ordering};
+
+/// The largest table we accept, such that a corrupted length does not make us read through all of
+/// memory. The largest tables, the DSDTs, are usually well under a megabyte.
+pub const MAX_TABLE_LENGTH: usize = 16 << 20;
+
+/// The largest RSDP we accept. Revision 2 structures are 36 bytes long.
+const MAX_RSDP_LENGTH: usize = 4096;
+
+/// Offset of the revision field in the RSDP
+const RSDP_REVISION_OFFSET: usize = 15;
+
+/// Returns the `length` bytes of physical memory starting at `addr`
+///
+/// # Safety
+///
+/// The memory must be mapped and must not be written to while the slice is in use.
+pub unsafe fn phys_slice(addr: usize, length: usize) -> Result<&'static [u8], ParseError> {
+    if addr == 0 {
+        return Err(ParseError::NullAddress);
+    }
+
+    map_phys(addr, length)
+}
+
+/// Returns the 32-bit word of physical memory at `addr`, for the fields the firmware updates
+/// behind our back, like the global lock. The word must be aligned on 4 bytes.
+///
+/// # Safety
+///
+/// The memory must be mapped, and the firmware must only access the word atomically.
+pub unsafe fn phys_atomic_u32(addr: usize) -> Result<&'static AtomicU32, ParseError> {
+    if addr == 0 {
+        return Err(ParseError::NullAddress);
+    }
+    if !addr.is_multiple_of(align_of::<AtomicU32>()) {
+        return Err(ParseError::Misaligned(addr));
+    }
+
+    map_phys_atomic(addr)
+}
+
+// Returns the `length` bytes of physical memory at `addr`, which the firmware identity maps
+#[cfg(not(any(test, fuzzing)))]
+unsafe fn map_phys(addr: usize, length: usize) -> Result<&'static [u8], ParseError> {
+    Ok(core::slice::from_raw_parts(addr as *const u8, length))
+}
+
+// Returns the word of physical memory at `addr`, which the firmware identity maps
+#[cfg(not(any(test, fuzzing)))]
+unsafe fn map_phys_atomic(addr: usize) -> Result<&'static AtomicU32, ParseError> {
+    // SAFETY: the word is mapped and aligned, and everyone accesses it atomically
+    Ok(AtomicU32::from_ptr(addr as *mut u32))
+}
+
+/// A range of the physical memory seen by the unit tests and the fuzz targets: `bytes` are found
+/// at the physical address `addr`
+#[cfg(any(test, fuzzing))]
+pub type PhysRegion<'a> = (usize, &'a [u8]);
+
+/// A range of the physical memory seen by the unit tests that is shared with the firmware, and
+/// only accessed atomically: `words` are found at the physical address `addr`
+#[cfg(any(test, fuzzing))]
+pub type PhysWords<'a> = (usize, &'a [AtomicU32]);
+
+// The regions installed by `with_phys_memory`, as a pointer to the first one and their number
+#[cfg(any(test, fuzzing))]
+static REGIONS: AtomicPtr<PhysRegion<'static>> = AtomicPtr::new(core::ptr::null_mut());
+#[cfg(any(test, fuzzing))]
+static NREGIONS: AtomicUsize = AtomicUsize::new(0);
+
+// The shared regions installed by `with_shared_phys_memory`, likewise
+#[cfg(any(test, fuzzing))]
+static WORDS: AtomicPtr<PhysWords<'static>> = AtomicPtr::new(core::ptr::null_mut());
+#[cfg(any(test, fuzzing))]
+static NWORDS: AtomicUsize = AtomicUsize::new(0);
+
+// Held while regions are installed, such that tests running in parallel take turns
+#[cfg(any(test, fuzzing))]
+static REGIONS_LOCK: AtomicBool = AtomicBool::new(false);
+
+/// Runs `f` with `regions` standing in for physical memory, as there is none to read the tables
+/// from on the host. Reads that are not entirely within one of the regions fail as truncated.
+///
+/// The slices `f` gets out of `phys_slice` must not outlive this call, even though they are
+/// `'static`.
+#[cfg(any(test, fuzzing))]
+pub fn with_phys_memory<R>(regions: &[PhysRegion], f: impl FnOnce() -> R) -> R {
+    with_shared_phys_memory(regions, &[], f)
+}
+
+/// Runs `f` like `with_phys_memory`, with `words` also standing in for the physical memory shared
+/// with the firmware, which `phys_atomic_u32` reads from. The words are never read as bytes, so
+/// the regions should hold a copy of them if `phys_slice` must find them too.
+#[cfg(any(test, fuzzing))]
+pub fn with_shared_phys_memory<R>(
+    regions: &[PhysRegion],
+    words: &[PhysWords],
+    f: impl FnOnce() -> R,
+) -> R {
+    // Uninstalls the regions and lets the next caller in, even when `f` panics
+    struct Installed;
+
+    impl Drop for Installed {
+        fn drop(&mut self) {
+            NWORDS.store(0, Ordering::SeqCst);
+            WORDS.store(core::ptr::null_mut(), Ordering::SeqCst);
+            NREGIONS.store(0, Ordering::SeqCst);
+            REGIONS.store(core::ptr::null_mut(), Ordering::SeqCst);
+            REGIONS_LOCK.store(false, Ordering::Release);
+        }
+    }
+
+    while REGIONS_LOCK
+        .compare_exchange_weak(false, true, Ordering::Acquire, Ordering::Relaxed)
+        .is_err()
+    {
+        core::hint::spin_loop();
+    }
+    let _installed = Installed;
+
+    REGIONS.store(
+        regions.as_ptr() as *mut PhysRegion<'static>,
+        Ordering::SeqCst,
+    );
+    NREGIONS.store(regions.len(), Ordering::SeqCst);
+    WORDS.store(words.as_ptr() as *mut PhysWords<'static>, Ordering::SeqCst);
+    NWORDS.store(words.len(), Ordering::SeqCst);
+    f()
+}
+
+// Returns the `length` bytes at `addr` out of the regions installed by `with_phys_memory`
+#[cfg(any(test, fuzzing))]
+unsafe fn map_phys(addr: usize, length: usize) -> Result<&'static [u8], ParseError> {
+    let regions = REGIONS.load(Ordering::SeqCst);
+    let regions: &[PhysRegion<'static>] = match regions.is_null() {
+        true => &[],
+        // SAFETY: the regions stay installed, and alive, for as long as `with_phys_memory` runs
+        false => unsafe { core::slice::from_raw_parts(regions, NREGIONS.load(Ordering::SeqCst)) },
+    };
+
+    let mut available = 0;
+    for (start, bytes) in regions {
+        let Some(tail) = addr
+            .checked_sub(*start)
+            .and_then(|offset| bytes.get(offset..))
+        else {
+            continue;
+        };
+        match tail.get(..length) {
+            Some(slice) => return Ok(slice),
+            None => available = available.max(tail.len()),
+        }
+    }
+
+    Err(ParseError::Truncated {
+        needed: length,
+        available,
+    })
+}
+
+// Returns the word at `addr` out of the shared regions installed by `with_shared_phys_memory`
+#[cfg(any(test, fuzzing))]
+unsafe fn map_phys_atomic(addr: usize) -> Result<&'static AtomicU32, ParseError> {
+    let words = WORDS.load(Ordering::SeqCst);
+    let words: &[PhysWords<'static>] = match words.is_null() {
+        true => &[],
+        // SAFETY: the words stay installed, and alive, for as long as `with_shared_phys_memory`
+        // runs
+        false => unsafe { core::slice::from_raw_parts(words, NWORDS.load(Ordering::SeqCst)) },
+    };
+
+    let word = words.iter().find_map(|(start, words)| {
+        let offset = addr.checked_sub(*start)?;
+        match offset.is_multiple_of(size_of::<AtomicU32>()) {
+            true => words.get(offset / size_of::<AtomicU32>()),
+            false => None,
+        }
+    });
+    word.ok_or(ParseError::Truncated {
+        needed: size_of::<AtomicU32>(),
+        available: 0,
+    })
+}
+
+/// Returns the bytes of the system description table found at `addr`, as many as its header
+/// says. Tables whose length is smaller than their header or above `MAX_TABLE_LENGTH` are
+/// rejected.
+pub fn table_bytes(addr: usize) -> Result<&'static [u8], ParseError> {
+    // SAFETY: `addr` comes from the RSDP or from a root table, which point to the firmware's
+    // tables. These are mapped, and reclaimed only once we are done with them.
+    let header_bytes = unsafe { phys_slice(addr, size_of::<DescriptionHeader>())? };
+    let header = DescriptionHeader::from_bytes(header_bytes)?;
+
+    let length = header.length as usize;
+    if length < size_of::<DescriptionHeader>() || length > MAX_TABLE_LENGTH {
+        return Err(ParseError::InvalidLength(length));
+    }
+
+    unsafe { phys_slice(addr, length) }
+}
+
+/// Returns the bytes of the RSDP found at `addr`: the first 20 bytes for ACPI 1.0 structures, and
+/// as many bytes as its length says for revision 2 and above
+pub fn rsdp_bytes(addr: usize) -> Result<&'static [u8], ParseError> {
+    // SAFETY: `addr` comes from the EFI configuration table, which points to the firmware's RSDP
+    let v1 = unsafe { phys_slice(addr, RSDP_V1_LENGTH)? };
+    if v1[RSDP_REVISION_OFFSET] < 2 {
+        return Ok(v1);
+    }
+
+    let fixed = unsafe { phys_slice(addr, size_of::<RSDP>())? };
+    let length = read::<u32>(fixed, RSDP_V1_LENGTH)? as usize;
+    if length < size_of::<RSDP>() || length > MAX_RSDP_LENGTH {
+        return Err(ParseError::InvalidLength(length));
+    }
+
+    unsafe { phys_slice(addr, length) }
+}
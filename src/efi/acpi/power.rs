@@ -0,0 +1,361 @@
+//! Module that resets and powers off the platform, using the registers advertised by the FADT and
+//! the sleep types from the `\_S5` object of the DSDT.
+use crate::cpu::{self, inb, outb};
+use crate::efi::acpi::aml::{AmlError, AmlValue, Interpreter};
+use crate::efi::acpi::{table_bytes, AcpiTables, DescriptionHeader, GasError, GenericAddress, FADT};
+use crate::pm_timer::PmTimer;
+use crate::print;
+use core::convert::Infallible;
+use core::mem::size_of;
+
+/// Reasons for which the platform could not be powered off
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PowerError {
+    /// The ACPI tables could not be found
+    NoAcpiTables,
+    /// There is no FADT amongst the ACPI tables
+    NoFadt,
+    /// Neither the DSDT nor the SSDTs define the `\_S5` sleep state
+    NoS5,
+    /// The FADT does not describe the registers used to enter a sleep state
+    NoSleepRegister,
+    /// One of the registers could not be accessed
+    InvalidRegister(GasError),
+    /// The sleep state was requested, but the platform is still running
+    TransitionFailed,
+}
+
+impl From<GasError> for PowerError {
+    fn from(value: GasError) -> Self {
+        Self::InvalidRegister(value)
+    }
+}
+
+/// I/O port of the 8042 keyboard controller's status and command register
+const I8042_COMMAND: u16 = 0x64;
+/// Command that makes the 8042 pulse the CPU reset line
+const I8042_PULSE_RESET: u8 = 0xFE;
+
+/// Bit position of the SLP_TYPx field in the PM1 control registers
+const SLP_TYP_SHIFT: u64 = 10;
+/// Mask of the SLP_TYPx field in the PM1 control registers
+const SLP_TYP_MASK: u64 = 0b111 << SLP_TYP_SHIFT;
+/// Setting this bit in the PM1 control registers makes the platform enter the sleep state
+const SLP_EN: u64 = 1 << 13;
+/// Bit position of the SLP_TYPx field in the hardware-reduced ACPI sleep control register
+const HW_REDUCED_SLP_TYP_SHIFT: u64 = 2;
+/// Setting this bit in the hardware-reduced sleep control register enters the sleep state
+const HW_REDUCED_SLP_EN: u64 = 1 << 5;
+
+/// How long a reset or sleep request is given to take effect, in nanoseconds
+const TRANSITION_TIMEOUT_NS: u64 = 500_000_000;
+/// Number of spins standing in for `TRANSITION_TIMEOUT_NS` when there is no PM timer
+const TRANSITION_TIMEOUT_SPINS: u64 = 100_000_000;
+
+// Gives a reset or sleep request the time to take effect. The wait is timed with the PM timer,
+// and only falls back to spinning for a fixed number of iterations on platforms without one.
+fn wait_for_reset(tables: Option<&AcpiTables>) {
+    match tables.and_then(|tables| PmTimer::from_acpi(tables).ok()) {
+        Some(timer) => timer.wait_ns(TRANSITION_TIMEOUT_NS),
+        None => {
+            for _ in 0..TRANSITION_TIMEOUT_SPINS {
+                core::hint::spin_loop();
+            }
+        }
+    }
+}
+
+/// Resets the platform. The reset register from the FADT is tried first, then the 8042 keyboard
+/// controller and, as a last resort, we triple fault the processor.
+pub fn reboot() -> ! {
+    let tables = AcpiTables::get();
+    if let Some(fadt) = tables.and_then(|tables| tables.find::<FADT>()) {
+        if let Some((reset_reg, reset_value)) = fadt.reset_reg() {
+            match reset_reg.decode() {
+                Ok(reg) => {
+                    unsafe { reg.write(reset_value as u64) };
+                    wait_for_reset(tables);
+                }
+                Err(err) => {
+                    print!("Cannot use the FADT reset register: {:?}\n", err);
+                }
+            }
+        }
+    }
+
+    unsafe {
+        // Wait for the 8042's input buffer to be empty, before sending it a command
+        for _ in 0..0x10000 {
+            if inb(I8042_COMMAND) & 0b10 == 0 {
+                break;
+            }
+        }
+        outb(I8042_COMMAND, I8042_PULSE_RESET);
+    }
+    wait_for_reset(tables);
+
+    unsafe { cpu::triple_fault() }
+}
+
+/// Powers off the platform by entering the S5 soft-off sleep state, after running the `\_PTS`
+/// method of the firmware. This only returns if the transition could not be started, or did not
+/// take effect.
+pub fn poweroff() -> Result<Infallible, PowerError> {
+    let tables = AcpiTables::get().ok_or(PowerError::NoAcpiTables)?;
+    let fadt = tables.find::<FADT>().ok_or(PowerError::NoFadt)?;
+    let (slp_typa, slp_typb) = find_s5(tables, &fadt).ok_or(PowerError::NoS5)?;
+
+    prepare_to_sleep(5);
+
+    if fadt.is_hw_reduced() {
+        let sleep_control = fadt.sleep_control_reg().ok_or(PowerError::NoSleepRegister)?;
+        let value = (slp_typa as u64) << HW_REDUCED_SLP_TYP_SHIFT | HW_REDUCED_SLP_EN;
+        unsafe { sleep_control.decode()?.write(value) };
+    } else {
+        let pm1a_cnt = fadt.pm1a_cnt_blk().ok_or(PowerError::NoSleepRegister)?;
+        // The PM1b control block is optional, and both blocks must be written for the
+        // transition to happen
+        let pm1b_cnt = fadt.pm1b_cnt_blk().map(|blk| blk.decode()).transpose()?;
+        enter_sleep_state(pm1a_cnt.decode()?, pm1b_cnt, slp_typa, slp_typb);
+    }
+
+    wait_for_reset(Some(tables));
+
+    Err(PowerError::TransitionFailed)
+}
+
+// Runs the `\_PTS` (Prepare To Sleep) method with the sleep state we are about to enter. The
+// method is optional, and a failure is reported but does not prevent the transition.
+fn prepare_to_sleep(state: u64) {
+    let Some(mut interpreter) = Interpreter::lock() else {
+        return;
+    };
+    match interpreter.evaluate("\\_PTS", &[AmlValue::Integer(state)]) {
+        Ok(_) | Err(AmlError::NameNotFound) => {}
+        Err(err) => print!("\\_PTS({}) failed: {:?}\n", state, err),
+    }
+}
+
+// Enters the sleep state of sleep types `slp_typa` and `slp_typb` through the PM1a control
+// register `pm1a_cnt` and, if there is one, the PM1b control register `pm1b_cnt`
+fn enter_sleep_state(
+    pm1a_cnt: GenericAddress,
+    pm1b_cnt: Option<GenericAddress>,
+    slp_typa: u8,
+    slp_typb: u8,
+) {
+    // The sleep types are programmed in both blocks before either SLP_EN bit is set, so that the
+    // chipset never sees a sleep request with a stale sleep type
+    write_slp_typ(pm1a_cnt, slp_typa);
+    if let Some(pm1b_cnt) = pm1b_cnt {
+        write_slp_typ(pm1b_cnt, slp_typb);
+    }
+    set_slp_en(pm1a_cnt);
+    if let Some(pm1b_cnt) = pm1b_cnt {
+        set_slp_en(pm1b_cnt);
+    }
+}
+
+// Writes the sleep type `slp_typ` to the PM1 control register `pm1_cnt`, with SLP_EN clear
+fn write_slp_typ(pm1_cnt: GenericAddress, slp_typ: u8) {
+    let value = unsafe { pm1_cnt.read() } & !(SLP_TYP_MASK | SLP_EN);
+    write_pm1_cnt(pm1_cnt, value | (slp_typ as u64) << SLP_TYP_SHIFT);
+}
+
+// Sets SLP_EN in the PM1 control register `pm1_cnt`, entering the sleep state it was given
+fn set_slp_en(pm1_cnt: GenericAddress) {
+    let value = unsafe { pm1_cnt.read() };
+    write_pm1_cnt(pm1_cnt, value | SLP_EN);
+}
+
+#[cfg(test)]
+std::thread_local! {
+    // The values written to PM1 control registers by this thread, along with their addresses
+    static PM1_CNT_WRITES: core::cell::RefCell<std::vec::Vec<(u64, u64)>> =
+        const { core::cell::RefCell::new(std::vec::Vec::new()) };
+}
+
+// Writes `value` to the PM1 control register `pm1_cnt`, keeping track of the write under test
+fn write_pm1_cnt(pm1_cnt: GenericAddress, value: u64) {
+    #[cfg(test)]
+    PM1_CNT_WRITES.with(|writes| writes.borrow_mut().push((pm1_cnt.address(), value)));
+
+    unsafe { pm1_cnt.write(value) };
+}
+
+/// Looks for the `\_S5` package in the DSDT and the SSDTs, returning its SLP_TYPa and SLP_TYPb
+/// values. The package is evaluated from the namespace if it was loaded, and otherwise searched
+/// for in the bytes of the definition blocks.
+pub fn find_s5(tables: &AcpiTables, fadt: &FADT) -> Option<(u8, u8)> {
+    if let Some(s5) = evaluate_s5() {
+        return Some(s5);
+    }
+
+    let dsdt = fadt.dsdt_addr().map(|addr| addr as usize);
+    let ssdts = tables.find_all(b"SSDT").map(|table| table.addr());
+
+    dsdt.into_iter().chain(ssdts).find_map(|addr| {
+        let table = table_bytes(addr).ok()?;
+        parse_s5(&table[size_of::<DescriptionHeader>()..])
+    })
+}
+
+// Evaluates the `\_S5` package from the namespace
+fn evaluate_s5() -> Option<(u8, u8)> {
+    let mut interpreter = Interpreter::lock()?;
+    let s5 = interpreter.evaluate("\\_S5", &[]).ok()?;
+    let (slp_typa, slp_typb) = match interpreter.package(s5)? {
+        [slp_typa, slp_typb, ..] => (*slp_typa, *slp_typb),
+        _ => return None,
+    };
+
+    let slp_typa = interpreter.integer(slp_typa).ok()?;
+    let slp_typb = interpreter.integer(slp_typb).ok()?;
+    Some((slp_typa as u8, slp_typb as u8))
+}
+
+/// AML opcode that defines a named object
+const NAME_OP: u8 = 0x08;
+/// AML opcode that defines a package
+const PACKAGE_OP: u8 = 0x12;
+/// AML prefix of a name path that starts from the root of the namespace
+const ROOT_CHAR: u8 = b'\\';
+
+// Looks for a `Name (_S5, Package () { SLP_TYPa, SLP_TYPb, ... })` definition in the `aml` bytes
+// of a definition block. This is a plain byte search, so it does not need to interpret the AML.
+fn parse_s5(aml: &[u8]) -> Option<(u8, u8)> {
+    let mut search_start = 0;
+
+    while let Some(pos) = aml[search_start..].windows(4).position(|window| window == b"_S5_") {
+        let name_idx = search_start + pos;
+        search_start = name_idx + 4;
+
+        // The name must be the one of a `Name` definition, possibly as an absolute path
+        let is_definition = match name_idx {
+            0 => false,
+            1 => aml[0] == NAME_OP,
+            _ => {
+                aml[name_idx - 1] == NAME_OP
+                    || (aml[name_idx - 1] == ROOT_CHAR && aml[name_idx - 2] == NAME_OP)
+            }
+        };
+
+        if !is_definition || aml.get(search_start) != Some(&PACKAGE_OP) {
+            continue;
+        }
+
+        // Skip the PackageOp and the PkgLength, whose lead byte tells how many bytes follow it
+        let pkg_length_idx = search_start + 1;
+        let lead = *aml.get(pkg_length_idx)?;
+        let num_elements_idx = pkg_length_idx + 1 + (lead >> 6) as usize;
+        let mut idx = num_elements_idx + 1;
+
+        let (slp_typa, size) = parse_small_integer(aml.get(idx..)?)?;
+        idx += size;
+        let (slp_typb, _) = parse_small_integer(aml.get(idx..)?)?;
+
+        return Some((slp_typa as u8, slp_typb as u8));
+    }
+
+    None
+}
+
+// Parses an AML integer constant from the start of `aml`, returning its value and its size
+fn parse_small_integer(aml: &[u8]) -> Option<(u64, usize)> {
+    match *aml.first()? {
+        // ZeroOp
+        0x00 => Some((0, 1)),
+        // OneOp
+        0x01 => Some((1, 1)),
+        // BytePrefix
+        0x0A => Some((*aml.get(1)? as u64, 2)),
+        // WordPrefix
+        0x0B => Some((u16::from_le_bytes(aml.get(1..3)?.try_into().ok()?) as u64, 3)),
+        // DWordPrefix
+        0x0C => Some((u32::from_le_bytes(aml.get(1..5)?.try_into().ok()?) as u64, 5)),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::efi::acpi::address_space;
+    use crate::test_support::{gas, Machine, Q35};
+    use std::sync::atomic::{AtomicU16, Ordering};
+
+    // `Name (_S5, Package (0x04) { 0x07, 0x05, Zero, Zero })`
+    const S5_BYTES: &[u8] = b"\x08_S5_\x12\x08\x04\x0A\x07\x0A\x05\x00\x00";
+
+    #[test]
+    fn s5_byte_search() {
+        // BytePrefix constants, and ZeroOp and OneOp ones
+        assert_eq!(parse_s5(S5_BYTES), Some((7, 5)));
+        assert_eq!(
+            parse_s5(b"\x08_S5_\x12\x06\x04\x01\x00\x00\x00"),
+            Some((1, 0))
+        );
+
+        // A definition inside a scope, after a reference to the name that is not one, and as an
+        // absolute path
+        let scope = [b"\x10\x17\\\x00\x70_S5_\x60".as_slice(), S5_BYTES].concat();
+        assert_eq!(parse_s5(&scope), Some((7, 5)));
+        assert_eq!(
+            parse_s5(b"\x08\\_S5_\x12\x06\x04\x00\x01\x00\x00"),
+            Some((0, 1))
+        );
+
+        // A package cut short, in its PkgLength, in its element count or in its elements
+        for len in 5..S5_BYTES.len() - 2 {
+            assert_eq!(parse_s5(&S5_BYTES[..len]), None, "{len} bytes");
+        }
+        assert_eq!(parse_s5(&S5_BYTES[..S5_BYTES.len() - 2]), Some((7, 5)));
+
+        // Elements that are not integer constants, and names that are not `_S5_`
+        assert_eq!(parse_s5(b"\x08_S5_\x12\x07\x04\x0D\x00\x00\x00\x00"), None);
+        assert_eq!(parse_s5(b"\x08_S4_\x12\x06\x04\x00\x00\x00\x00"), None);
+
+        // QEMU's DSDT, with the sleep types of both blocks at 0
+        let dsdt = Q35::table("dsdt");
+        assert_eq!(
+            parse_s5(&dsdt[size_of::<DescriptionHeader>()..]),
+            Some((0, 0))
+        );
+    }
+
+    #[test]
+    fn sleep_register_writes() {
+        // The PM1a and PM1b control registers, in system memory, with SCI_EN set and SLP_EN
+        // left over from a previous write
+        let memory = address_space::SYSTEM_MEMORY;
+        let pm1a = AtomicU16::new(1 | SLP_EN as u16);
+        let pm1b = AtomicU16::new(1);
+        let pm1a_addr = pm1a.as_ptr() as u64;
+        let pm1b_addr = pm1b.as_ptr() as u64;
+        let pm1a_cnt = gas(memory, 16, 0, 2, pm1a_addr).decode().unwrap();
+        let pm1b_cnt = gas(memory, 16, 0, 2, pm1b_addr).decode().unwrap();
+
+        // Both sleep types are written, with SLP_EN clear, before either SLP_EN bit is set
+        PM1_CNT_WRITES.with(|writes| writes.borrow_mut().clear());
+        enter_sleep_state(pm1a_cnt, Some(pm1b_cnt), 7, 5);
+        let writes = PM1_CNT_WRITES.with(|writes| writes.take());
+        let (typa, typb) = (7 << SLP_TYP_SHIFT | 1, 5 << SLP_TYP_SHIFT | 1);
+        assert_eq!(
+            writes,
+            [
+                (pm1a_addr, typa),
+                (pm1b_addr, typb),
+                (pm1a_addr, typa | SLP_EN),
+                (pm1b_addr, typb | SLP_EN)
+            ]
+        );
+        assert_eq!(pm1a.load(Ordering::SeqCst) as u64, typa | SLP_EN);
+        assert_eq!(pm1b.load(Ordering::SeqCst) as u64, typb | SLP_EN);
+
+        // Without a PM1b control block, only the PM1a one is written
+        pm1a.store(1, Ordering::SeqCst);
+        enter_sleep_state(pm1a_cnt, None, 0, 0);
+        let writes = PM1_CNT_WRITES.with(|writes| writes.take());
+        assert_eq!(writes, [(pm1a_addr, 1), (pm1a_addr, 1 | SLP_EN)]);
+    }
+}
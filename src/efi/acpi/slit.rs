@@ -0,0 +1,108 @@
+use crate::efi::acpi::{read, AcpiTable, DescriptionHeader, ParseError};
+use core::fmt;
+use core::mem::size_of;
+
+/// The maximum number of system localities kept from the SLIT. Systems with more localities have
+/// their extra distances dropped.
+pub const MAX_LOCALITIES: usize = 64;
+
+/// The distance between a locality and itself
+pub const LOCAL_DISTANCE: u8 = 10;
+
+/// The distance value telling that a locality cannot be reached from another one
+pub const UNREACHABLE_DISTANCE: u8 = 0xFF;
+
+/// System Locality Information Table.
+/// It gives the relative distance between each pair of system localities, which are the proximity
+/// domains of the SRAT. Distances are relative to `LOCAL_DISTANCE`, such that a distance of 20
+/// means that accesses take twice as long as local ones.
+pub struct SLIT {
+    header: DescriptionHeader,
+    // The number of localities in the table, which may be above `MAX_LOCALITIES`
+    localities: u64,
+    // The distance matrix, for the first `MAX_LOCALITIES` localities
+    distances: [[u8; MAX_LOCALITIES]; MAX_LOCALITIES],
+}
+
+// The signature found in the first 4 bytes from the SLIT table
+const SLIT_SIGNATURE: &[u8; 4] = b"SLIT";
+
+impl SLIT {
+    /// Reads the SLIT from the bytes of the entire table, which must hold the full distance
+    /// matrix
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, ParseError> {
+        let (header, table) = DescriptionHeader::parse_table(bytes, SLIT_SIGNATURE)?;
+
+        let localities: u64 = read(table, size_of::<DescriptionHeader>())?;
+        let matrix = &table[size_of::<DescriptionHeader>() + size_of::<u64>()..];
+        let entries = localities
+            .checked_mul(localities)
+            .filter(|&n| n <= matrix.len() as u64);
+        if entries.is_none() {
+            return Err(ParseError::InvalidLength(table.len()));
+        }
+
+        let mut slit = SLIT {
+            header,
+            localities,
+            distances: [[UNREACHABLE_DISTANCE; MAX_LOCALITIES]; MAX_LOCALITIES],
+        };
+
+        let kept = (localities as usize).min(MAX_LOCALITIES);
+        for (from, row) in slit.distances.iter_mut().enumerate().take(kept) {
+            let start = from * localities as usize;
+            row[..kept].copy_from_slice(&matrix[start..start + kept]);
+        }
+
+        Ok(slit)
+    }
+
+    /// Returns the number of localities in the table. Only the first `MAX_LOCALITIES` have their
+    /// distances kept.
+    pub fn localities(&self) -> u64 {
+        self.localities
+    }
+
+    /// Returns the relative distance from the locality `from` to the locality `to`, or `None` if
+    /// either of them is not in the table
+    pub fn distance(&self, from: u32, to: u32) -> Option<u8> {
+        let kept = (self.localities as usize).min(MAX_LOCALITIES);
+        if from as usize >= kept || to as usize >= kept {
+            return None;
+        }
+
+        Some(self.distances[from as usize][to as usize])
+    }
+}
+
+impl fmt::Debug for SLIT {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let kept = (self.localities as usize).min(MAX_LOCALITIES);
+
+        f.debug_struct("SLIT")
+            .field("Revision", &self.header.revision())
+            .field("Localities", &self.localities)
+            .field("Distances", &Distances(&self.distances[..kept]))
+            .finish()
+    }
+}
+
+// Prints the rows of the distance matrix, without the columns that were not filled
+struct Distances<'a>(&'a [[u8; MAX_LOCALITIES]]);
+
+impl fmt::Debug for Distances<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let kept = self.0.len();
+        f.debug_list()
+            .entries(self.0.iter().map(|row| &row[..kept]))
+            .finish()
+    }
+}
+
+impl AcpiTable for SLIT {
+    const SIGNATURE: &'static [u8; 4] = SLIT_SIGNATURE;
+
+    fn from_bytes(bytes: &[u8]) -> Result<Self, ParseError> {
+        SLIT::from_bytes(bytes)
+    }
+}
@@ -0,0 +1,227 @@
+use crate::efi::acpi::{
+    read_prefix, AcpiTable, DescriptionHeader, FromBytes, GenericAddressStructure, ParseError,
+};
+use crate::pci::PciAddress;
+use bitflags::bitflags;
+use core::fmt;
+use core::mem::size_of;
+
+/// Serial port interface types, shared by the SPCR and the serial ports of the DBG2
+pub mod serial_interface {
+    /// Fully 16550 compatible
+    pub const NS16550: u16 = 0x0000;
+    /// 16550 subset compatible, with the parameters defined in the DBGP table
+    pub const NS16550_SUBSET: u16 = 0x0001;
+    pub const MAX311XE_SPI: u16 = 0x0002;
+    pub const ARM_PL011: u16 = 0x0003;
+    pub const MSM8X60: u16 = 0x0004;
+    pub const NVIDIA_16550: u16 = 0x0005;
+    pub const TI_OMAP: u16 = 0x0006;
+    pub const APM88XXXX: u16 = 0x0008;
+    pub const MSM8974: u16 = 0x0009;
+    pub const SAM5250: u16 = 0x000A;
+    pub const INTEL_USIF: u16 = 0x000B;
+    pub const IMX6: u16 = 0x000C;
+    pub const ARM_SBSA_32BIT: u16 = 0x000D;
+    pub const ARM_SBSA: u16 = 0x000E;
+    pub const ARM_DCC: u16 = 0x000F;
+    pub const BCM2835: u16 = 0x0010;
+    pub const SDM845_1_8432MHZ: u16 = 0x0011;
+    /// 16550 compatible, with the register width and stride given by the Generic Address
+    /// Structure
+    pub const NS16550_GAS: u16 = 0x0012;
+    pub const SDM845_7_372MHZ: u16 = 0x0013;
+    pub const INTEL_LPSS: u16 = 0x0014;
+    pub const RISCV_SBI: u16 = 0x0015;
+
+    /// Returns whether the interface is driven like a 16550 UART
+    pub fn is_16550_compatible(interface: u16) -> bool {
+        matches!(interface, NS16550 | NS16550_SUBSET | NS16550_GAS)
+    }
+}
+
+bitflags! {
+    #[repr(transparent)]
+    #[derive(Default, Clone, Copy, Debug, PartialEq, Eq, Hash)]
+    pub struct SpcrFlowControl: u8 {
+        /// DCD must be asserted for the port to transmit
+        const DCD = 1 << 0;
+        /// RTS/CTS hardware flow control
+        const RTS_CTS = 1 << 1;
+        /// XON/XOFF software flow control
+        const XON_XOFF = 1 << 2;
+    }
+}
+
+/// Serial Port Console Redirection Table.
+/// It describes the serial port the firmware redirects its console to, and which the OS should
+/// use as its own console: the port's interface, where its registers are, and how it is
+/// configured.
+pub struct SPCR {
+    header: DescriptionHeader,
+    fields: SpcrFields,
+}
+
+// The fields that follow the header. Fields added by later revisions are zero in earlier ones.
+#[derive(Default, Clone, Copy)]
+#[repr(C, packed)]
+struct SpcrFields {
+    // The type of the serial port, which is the same as the `serial_interface` types in revisions
+    // 2 and above, and a subset of them before
+    interface_type: u8,
+    reserved: [u8; 3],
+    // The address of the registers
+    base_addr: GenericAddressStructure,
+    // The kinds of interrupts the port supports, as a mask of 8259, I/O APIC, I/O SAPIC, GIC and
+    // PLIC/APLIC
+    interrupt_type: u8,
+    // The 8259 IRQ of the port
+    irq: u8,
+    // The global system interrupt of the port
+    global_system_interrupt: u32,
+    // The baud rate the firmware uses, or 0 if the port is used as it is
+    configured_baud_rate: u8,
+    // 0 for no parity, the only value allowed
+    parity: u8,
+    // 1 for a single stop bit, the only value allowed
+    stop_bits: u8,
+    flow_control: SpcrFlowControl,
+    // The terminal protocol the firmware uses, like VT100 or ANSI
+    terminal_type: u8,
+    language: u8,
+    // The PCI function of the port, with 0xFFFF device and vendor IDs for non-PCI ports
+    pci_device_id: u16,
+    pci_vendor_id: u16,
+    pci_bus: u8,
+    pci_device: u8,
+    pci_function: u8,
+    pci_flags: u32,
+    pci_segment: u8,
+    // The frequency of the UART clock in Hz, or 0 if it is not known. Revision 3 and above.
+    uart_clock_freq: u32,
+    // The baud rate, overriding `configured_baud_rate` when not 0. Revision 4 and above.
+    precise_baud_rate: u32,
+    // The ACPI namespace path of the port. Revision 4 and above.
+    namespace_string_length: u16,
+    namespace_string_offset: u16,
+}
+
+unsafe impl FromBytes for SpcrFields {}
+
+// The signature found in the first 4 bytes from the SPCR table
+const SPCR_SIGNATURE: &[u8; 4] = b"SPCR";
+
+impl SPCR {
+    /// Reads the SPCR from the bytes of the entire table. Only the fields that fit in the length
+    /// reported by its header are read, the others being left as zero.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, ParseError> {
+        let (header, table) = DescriptionHeader::parse_table(bytes, SPCR_SIGNATURE)?;
+        let fields = read_prefix(&table[size_of::<DescriptionHeader>()..]);
+
+        Ok(SPCR { header, fields })
+    }
+
+    /// Returns the interface type of the port, as one of the `serial_interface` types
+    pub fn interface_type(&self) -> u16 {
+        self.fields.interface_type as u16
+    }
+
+    /// Returns the address of the registers of the port
+    pub fn base_addr(&self) -> GenericAddressStructure {
+        self.fields.base_addr
+    }
+
+    /// Returns the 8259 IRQ of the port, if it supports 8259 interrupts
+    pub fn irq(&self) -> Option<u8> {
+        (self.fields.interrupt_type & 1 != 0).then_some(self.fields.irq)
+    }
+
+    /// Returns the global system interrupt of the port, if it supports any other interrupt
+    /// controller than the 8259
+    pub fn global_system_interrupt(&self) -> Option<u32> {
+        (self.fields.interrupt_type & !1 != 0).then_some(self.fields.global_system_interrupt)
+    }
+
+    /// Returns the baud rate the port is configured with, or `None` if the port must be used as
+    /// it is
+    pub fn baud_rate(&self) -> Option<u32> {
+        let precise_baud_rate = self.fields.precise_baud_rate;
+        if precise_baud_rate != 0 {
+            return Some(precise_baud_rate);
+        }
+
+        match self.fields.configured_baud_rate {
+            3 => Some(9600),
+            4 => Some(19200),
+            6 => Some(57600),
+            7 => Some(115200),
+            _ => None,
+        }
+    }
+
+    /// Returns the parity, which is 0 for no parity
+    pub fn parity(&self) -> u8 {
+        self.fields.parity
+    }
+
+    /// Returns the number of stop bits
+    pub fn stop_bits(&self) -> u8 {
+        self.fields.stop_bits
+    }
+
+    pub fn flow_control(&self) -> SpcrFlowControl {
+        self.fields.flow_control
+    }
+
+    pub fn terminal_type(&self) -> u8 {
+        self.fields.terminal_type
+    }
+
+    /// Returns the frequency of the UART clock in Hz, if it is known
+    pub fn uart_clock_freq(&self) -> Option<u32> {
+        let uart_clock_freq = self.fields.uart_clock_freq;
+        (uart_clock_freq != 0).then_some(uart_clock_freq)
+    }
+
+    /// Returns the PCI function of the port, if it is a PCI device
+    pub fn pci_function(&self) -> Option<PciAddress> {
+        let fields = self.fields;
+        if fields.pci_device_id == 0xFFFF && fields.pci_vendor_id == 0xFFFF {
+            return None;
+        }
+
+        Some(PciAddress::new(
+            fields.pci_segment as u16,
+            fields.pci_bus,
+            fields.pci_device,
+            fields.pci_function,
+        ))
+    }
+}
+
+impl fmt::Debug for SPCR {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SPCR")
+            .field("Revision", &self.header.revision())
+            .field("Interface Type", &self.interface_type())
+            .field("Base Address", &self.base_addr())
+            .field("IRQ", &self.irq())
+            .field("GSI", &self.global_system_interrupt())
+            .field("Baud Rate", &self.baud_rate())
+            .field("Parity", &self.parity())
+            .field("Stop Bits", &self.stop_bits())
+            .field("Flow Control", &self.flow_control())
+            .field("Terminal Type", &self.terminal_type())
+            .field("UART Clock", &self.uart_clock_freq())
+            .field("PCI Function", &self.pci_function())
+            .finish()
+    }
+}
+
+impl AcpiTable for SPCR {
+    const SIGNATURE: &'static [u8; 4] = SPCR_SIGNATURE;
+
+    fn from_bytes(bytes: &[u8]) -> Result<Self, ParseError> {
+        SPCR::from_bytes(bytes)
+    }
+}
@@ -0,0 +1,296 @@
+use crate::efi::acpi::{read, AcpiTable, DescriptionHeader, FromBytes, ParseError};
+use crate::print;
+use bitflags::bitflags;
+use core::fmt;
+use core::mem::size_of;
+
+/// The maximum number of processor affinity structures kept from the SRAT, which is one per
+/// processor, like the local APIC structures of the MADT
+pub const MAX_SRAT_PROCESSORS: usize = 512;
+
+/// The maximum number of memory affinity structures kept from the SRAT
+pub const MAX_SRAT_MEMORY_RANGES: usize = 64;
+
+// We make constants that are able to initialize arrays larger than 32 elements
+const INIT_PROCESSOR: Option<ProcessorAffinity> = None;
+const INIT_MEMORY_RANGE: Option<MemoryAffinity> = None;
+
+/// System Resource Affinity Table.
+/// It associates processors and memory ranges with proximity domains, which are the NUMA nodes of
+/// the system: processors access the memory of their own proximity domain faster than the memory
+/// of the others. Structures that are not enabled are left out, as the specification asks.
+pub struct SRAT {
+    header: DescriptionHeader,
+    // The processors, from both the local APIC and the local x2APIC affinity structures
+    processors: [Option<ProcessorAffinity>; MAX_SRAT_PROCESSORS],
+    // Number of populated entries in `processors`
+    nprocessors: usize,
+    // The memory ranges
+    memory_ranges: [Option<MemoryAffinity>; MAX_SRAT_MEMORY_RANGES],
+    // Number of populated entries in `memory_ranges`
+    nmemory_ranges: usize,
+}
+
+/// The proximity domain of a processor
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ProcessorAffinity {
+    pub proximity_domain: u32,
+    /// The local APIC ID, or the x2APIC ID if `x2apic` is set
+    pub apic_id: u32,
+    pub x2apic: bool,
+    /// Processors in the same clock domain have synchronized TSCs
+    pub clock_domain: u32,
+}
+
+/// The proximity domain of a range of memory
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MemoryAffinity {
+    pub proximity_domain: u32,
+    pub base: u64,
+    pub length: u64,
+    pub flags: MemoryAffinityFlags,
+}
+
+impl MemoryAffinity {
+    /// Returns the address of the first byte after the range
+    pub fn end(&self) -> u64 {
+        self.base.saturating_add(self.length)
+    }
+
+    /// Returns whether `addr` is in the range
+    pub fn contains(&self, addr: u64) -> bool {
+        (self.base..self.end()).contains(&addr)
+    }
+}
+
+bitflags! {
+    #[repr(transparent)]
+    #[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+    pub struct MemoryAffinityFlags: u32 {
+        /// The structure is used. Disabled structures are ignored.
+        const ENABLED = 1 << 0;
+        /// The memory can be added or removed at runtime
+        const HOT_PLUGGABLE = 1 << 1;
+        /// The memory is persistent
+        const NON_VOLATILE = 1 << 2;
+    }
+}
+
+unsafe impl FromBytes for MemoryAffinityFlags {}
+
+bitflags! {
+    #[repr(transparent)]
+    #[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+    struct ProcessorAffinityFlags: u32 {
+        /// The structure is used. Disabled structures are ignored.
+        const ENABLED = 1 << 0;
+    }
+}
+
+unsafe impl FromBytes for ProcessorAffinityFlags {}
+
+// Processor Local APIC/SAPIC Affinity Structure
+#[derive(Clone, Copy)]
+#[repr(C, packed)]
+struct LocalApicAffinity {
+    entry_type: u8,
+    length: u8,
+    // Bits 0 to 7 of the proximity domain
+    proximity_domain_lo: u8,
+    apic_id: u8,
+    flags: ProcessorAffinityFlags,
+    local_sapic_eid: u8,
+    // Bits 8 to 31 of the proximity domain, starting with revision 2 of the table
+    proximity_domain_hi: [u8; 3],
+    clock_domain: u32,
+}
+
+unsafe impl FromBytes for LocalApicAffinity {}
+
+// Memory Affinity Structure
+#[derive(Clone, Copy)]
+#[repr(C, packed)]
+struct RawMemoryAffinity {
+    entry_type: u8,
+    length: u8,
+    proximity_domain: u32,
+    reserved0: u16,
+    base: u64,
+    length_bytes: u64,
+    reserved1: u32,
+    flags: MemoryAffinityFlags,
+    reserved2: u64,
+}
+
+unsafe impl FromBytes for RawMemoryAffinity {}
+
+// Processor Local x2APIC Affinity Structure
+#[derive(Clone, Copy)]
+#[repr(C, packed)]
+struct LocalX2ApicAffinity {
+    entry_type: u8,
+    length: u8,
+    reserved0: u16,
+    proximity_domain: u32,
+    x2apic_id: u32,
+    flags: ProcessorAffinityFlags,
+    clock_domain: u32,
+    reserved1: u32,
+}
+
+unsafe impl FromBytes for LocalX2ApicAffinity {}
+
+mod srat_entry_type {
+    pub const LOCAL_APIC_AFFINITY: u8 = 0;
+    pub const MEMORY_AFFINITY: u8 = 1;
+    pub const LOCAL_X2APIC_AFFINITY: u8 = 2;
+}
+
+// The signature found in the first 4 bytes from the SRAT table
+const SRAT_SIGNATURE: &[u8; 4] = b"SRAT";
+
+// The number of reserved bytes between the header and the first structure
+const SRAT_RESERVED: usize = 12;
+
+impl SRAT {
+    /// Reads the SRAT from the bytes of the entire table, keeping up to `MAX_SRAT_PROCESSORS`
+    /// processors and `MAX_SRAT_MEMORY_RANGES` memory ranges. Structures we do not know, like
+    /// the ones of ARM systems, are skipped. Structures that are shorter than their header, or
+    /// that go past the end of the table, are rejected.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, ParseError> {
+        let (header, table) = DescriptionHeader::parse_table(bytes, SRAT_SIGNATURE)?;
+
+        let mut srat = SRAT {
+            header,
+            processors: [INIT_PROCESSOR; MAX_SRAT_PROCESSORS],
+            nprocessors: 0,
+            memory_ranges: [INIT_MEMORY_RANGE; MAX_SRAT_MEMORY_RANGES],
+            nmemory_ranges: 0,
+        };
+
+        let mut offset = size_of::<DescriptionHeader>() + SRAT_RESERVED;
+        while offset < table.len() {
+            let entry_type: u8 = read(table, offset)?;
+            let length = read::<u8>(table, offset + 1)? as usize;
+            if length < 2 {
+                return Err(ParseError::InvalidLength(length));
+            }
+            let entry = table
+                .get(offset..offset + length)
+                .ok_or(ParseError::Truncated {
+                    needed: offset + length,
+                    available: table.len(),
+                })?;
+
+            match entry_type {
+                srat_entry_type::LOCAL_APIC_AFFINITY => {
+                    let affinity: LocalApicAffinity = read(entry, 0)?;
+                    let mut proximity_domain = affinity.proximity_domain_lo as u32;
+                    if srat.header.revision() >= 2 {
+                        let [b1, b2, b3] = affinity.proximity_domain_hi;
+                        proximity_domain |= u32::from_le_bytes([0, b1, b2, b3]);
+                    }
+
+                    let flags = affinity.flags;
+                    if flags.contains(ProcessorAffinityFlags::ENABLED) {
+                        srat.push_processor(ProcessorAffinity {
+                            proximity_domain,
+                            apic_id: affinity.apic_id as u32,
+                            x2apic: false,
+                            clock_domain: affinity.clock_domain,
+                        });
+                    }
+                }
+                srat_entry_type::MEMORY_AFFINITY => {
+                    let affinity: RawMemoryAffinity = read(entry, 0)?;
+                    let flags = affinity.flags;
+                    if flags.contains(MemoryAffinityFlags::ENABLED) {
+                        srat.push_memory_range(MemoryAffinity {
+                            proximity_domain: affinity.proximity_domain,
+                            base: affinity.base,
+                            length: affinity.length_bytes,
+                            flags,
+                        });
+                    }
+                }
+                srat_entry_type::LOCAL_X2APIC_AFFINITY => {
+                    let affinity: LocalX2ApicAffinity = read(entry, 0)?;
+                    let flags = affinity.flags;
+                    if flags.contains(ProcessorAffinityFlags::ENABLED) {
+                        srat.push_processor(ProcessorAffinity {
+                            proximity_domain: affinity.proximity_domain,
+                            apic_id: affinity.x2apic_id,
+                            x2apic: true,
+                            clock_domain: affinity.clock_domain,
+                        });
+                    }
+                }
+                _ => {}
+            }
+
+            offset += length;
+        }
+
+        Ok(srat)
+    }
+
+    // Keeps `processor`, if there is room left for it
+    fn push_processor(&mut self, processor: ProcessorAffinity) {
+        match self.processors.get_mut(self.nprocessors) {
+            Some(slot) => {
+                *slot = Some(processor);
+                self.nprocessors += 1;
+            }
+            None => {
+                print!(
+                    "The SRAT holds more than {} processors, ignoring the rest\n",
+                    MAX_SRAT_PROCESSORS
+                );
+            }
+        }
+    }
+
+    // Keeps `memory_range`, if there is room left for it
+    fn push_memory_range(&mut self, memory_range: MemoryAffinity) {
+        match self.memory_ranges.get_mut(self.nmemory_ranges) {
+            Some(slot) => {
+                *slot = Some(memory_range);
+                self.nmemory_ranges += 1;
+            }
+            None => {
+                print!(
+                    "The SRAT holds more than {} memory ranges, ignoring the rest\n",
+                    MAX_SRAT_MEMORY_RANGES
+                );
+            }
+        }
+    }
+
+    /// Returns the enabled processors, in the order they appear in the table
+    pub fn processors(&self) -> impl Iterator<Item = &ProcessorAffinity> {
+        self.processors[..self.nprocessors].iter().flatten()
+    }
+
+    /// Returns the enabled memory ranges, in the order they appear in the table
+    pub fn memory_ranges(&self) -> impl Iterator<Item = &MemoryAffinity> {
+        self.memory_ranges[..self.nmemory_ranges].iter().flatten()
+    }
+}
+
+impl fmt::Debug for SRAT {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SRAT")
+            .field("Revision", &self.header.revision())
+            .field("Processors", &self.nprocessors)
+            .field("Memory Ranges", &self.nmemory_ranges)
+            .finish()
+    }
+}
+
+impl AcpiTable for SRAT {
+    const SIGNATURE: &'static [u8; 4] = SRAT_SIGNATURE;
+
+    fn from_bytes(bytes: &[u8]) -> Result<Self, ParseError> {
+        SRAT::from_bytes(bytes)
+    }
+}
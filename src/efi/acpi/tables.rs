@@ -0,0 +1,286 @@
+//! Module that holds a registry of all the ACPI tables reachable from the RSDP, such that callers
+//! can look tables up without walking the root table again.
+use crate::efi::acpi::{
+    find_rsdp, phys_slice, read_rsdp, read_table, DescriptionHeader, ParseError, RSDP, RSDT, XSDT,
+};
+use crate::print;
+use core::cell::UnsafeCell;
+use core::mem::MaybeUninit;
+use core::sync::atomic::{AtomicU8, Ordering};
+
+/// The maximum number of tables the registry can hold
+pub const MAX_ACPI_TABLES: usize = 64;
+
+/// Implemented by every table which has a typed parser, such that it can be looked up in the
+/// `AcpiTables` registry with `find`.
+pub trait AcpiTable: Sized {
+    /// The signature identifying the table
+    const SIGNATURE: &'static [u8; 4];
+
+    /// Parses the table from `bytes`, which hold the entire table, starting with its header
+    fn from_bytes(bytes: &[u8]) -> Result<Self, ParseError>;
+}
+
+/// Information about a single table found in the registry, regardless of whether we know how to
+/// parse it or not.
+#[derive(Debug, Clone, Copy)]
+pub struct TableInfo {
+    // Signature of the table
+    signature: [u8; 4],
+    // Physical address of the table
+    addr: usize,
+    // Length of the entire table, including the header
+    length: u32,
+    // Revision of the table
+    revision: u8,
+}
+
+impl TableInfo {
+    // Builds the information about the table that starts at `addr` from its `header`
+    fn new(addr: usize, header: &DescriptionHeader) -> Self {
+        Self {
+            signature: header.signature,
+            addr,
+            length: header.length,
+            revision: header.revision(),
+        }
+    }
+
+    pub fn signature(&self) -> [u8; 4] {
+        self.signature
+    }
+    /// Returns the signature as a string, or "????" if it is not valid UTF-8
+    pub fn signature_str(&self) -> &str {
+        core::str::from_utf8(&self.signature).unwrap_or("????")
+    }
+    pub fn addr(&self) -> usize {
+        self.addr
+    }
+    pub fn length(&self) -> u32 {
+        self.length
+    }
+    pub fn revision(&self) -> u8 {
+        self.revision
+    }
+    /// Returns the bytes of the entire table, starting with its header
+    pub fn bytes(&self) -> &'static [u8] {
+        // SAFETY: the table was found at `addr` with this length when the registry was built
+        unsafe { phys_slice(self.addr, self.length as usize) }.unwrap_or(&[])
+    }
+    /// Reads the entire header of the table
+    pub fn header(&self) -> DescriptionHeader {
+        DescriptionHeader::from_bytes(self.bytes()).unwrap_or_default()
+    }
+}
+
+// The registry `AcpiTables::get` returns, written once by `AcpiTables::install`. We keep it
+// around as the EFI configuration table is no longer reachable after exiting boot services.
+struct RegistryCell(UnsafeCell<MaybeUninit<AcpiTables>>);
+
+// SAFETY: the registry is only written by the single caller that moves `REGISTRY_STATE` out of
+// `REGISTRY_EMPTY`, and only read once the state is `REGISTRY_INSTALLED`
+unsafe impl Sync for RegistryCell {}
+
+static REGISTRY: RegistryCell = RegistryCell(UnsafeCell::new(MaybeUninit::uninit()));
+static REGISTRY_STATE: AtomicU8 = AtomicU8::new(REGISTRY_EMPTY);
+
+const REGISTRY_EMPTY: u8 = 0;
+const REGISTRY_INSTALLING: u8 = 1;
+const REGISTRY_INSTALLED: u8 = 2;
+
+// We make a constant that is able to initialize an array larger than 32 elements
+const INIT_TABLE_INFO: Option<TableInfo> = None;
+
+/// Registry of all the ACPI tables referred to by the root table, built once from the RSDP. The
+/// XSDT is used as the root table whenever present, otherwise the RSDT is.
+pub struct AcpiTables {
+    // Address of the RSDP the registry was built from
+    rsdp_addr: usize,
+    // The RSDP itself
+    rsdp: RSDP,
+    // Information about every table referred to by the root table
+    tables: [Option<TableInfo>; MAX_ACPI_TABLES],
+    // Number of populated entries in `tables`
+    ntables: usize,
+}
+
+impl AcpiTables {
+    /// Reads the RSDP from `rsdp_addr` and records every table it leads to. Tables that fail
+    /// their checksum are left out if the `ChecksumPolicy` says to reject them. Returns `None` if
+    /// the RSDP itself is rejected.
+    pub fn new(rsdp_addr: usize) -> Option<Self> {
+        let rsdp = read_rsdp(rsdp_addr)?;
+
+        let mut acpi_tables = Self {
+            rsdp_addr,
+            rsdp,
+            tables: [INIT_TABLE_INFO; MAX_ACPI_TABLES],
+            ntables: 0,
+        };
+
+        // An ACPI-compatible OS must use the XSDT if present, which is the case for revision 2
+        // and above, when its address is not null. We only fall back to the RSDT if the XSDT
+        // could not be used.
+        if !acpi_tables.walk_xsdt() {
+            acpi_tables.walk_rsdt();
+        }
+
+        Some(acpi_tables)
+    }
+
+    /// Reads the RSDP from the EFI configuration table, preferring the ACPI 2.0 one over the ACPI
+    /// 1.0 one, and builds the registry from it
+    pub fn from_config_table() -> Option<Self> {
+        Self::new(find_rsdp()?)
+    }
+
+    /// Keeps this registry as the one `get` returns, and returns it. Only the first registry
+    /// installed is kept: later calls return it and drop theirs.
+    pub fn install(self) -> &'static Self {
+        if REGISTRY_STATE
+            .compare_exchange(
+                REGISTRY_EMPTY,
+                REGISTRY_INSTALLING,
+                Ordering::Acquire,
+                Ordering::Acquire,
+            )
+            .is_ok()
+        {
+            // SAFETY: we are the only caller to have moved the state out of `REGISTRY_EMPTY`
+            unsafe { (*REGISTRY.0.get()).write(self) };
+            REGISTRY_STATE.store(REGISTRY_INSTALLED, Ordering::Release);
+        }
+
+        // Another caller may still be writing its registry
+        loop {
+            if let Some(tables) = Self::get() {
+                return tables;
+            }
+            core::hint::spin_loop();
+        }
+    }
+
+    /// Returns the registry `efi_main` installed while the EFI configuration table was reachable.
+    /// This is how code running after exiting boot services gets to the ACPI tables, without
+    /// walking the root table again.
+    pub fn get() -> Option<&'static Self> {
+        if REGISTRY_STATE.load(Ordering::Acquire) != REGISTRY_INSTALLED {
+            return None;
+        }
+
+        // SAFETY: the registry was written before the state became `REGISTRY_INSTALLED`, and is
+        // never written again
+        Some(unsafe { (*REGISTRY.0.get()).assume_init_ref() })
+    }
+
+    // Records the tables referred to by the XSDT. Returns whether the XSDT could be used.
+    fn walk_xsdt(&mut self) -> bool {
+        let xsdt_addr = self.rsdp.xsdt_addr() as usize;
+        if xsdt_addr == 0 {
+            return false;
+        }
+
+        let Some((table, _)) = read_table(xsdt_addr) else {
+            return false;
+        };
+        let xsdt = match XSDT::from_bytes(table) {
+            Ok(xsdt) => xsdt,
+            Err(err) => {
+                print!("Invalid XSDT at {:#x}: {:?}\n", xsdt_addr, err);
+                return false;
+            }
+        };
+
+        for table_addr in xsdt.entries.into_iter() {
+            self.record(table_addr as usize);
+        }
+
+        true
+    }
+
+    // Records the tables referred to by the RSDT
+    fn walk_rsdt(&mut self) {
+        let rsdt_addr = self.rsdp.rsdt_addr() as usize;
+        if rsdt_addr == 0 {
+            return;
+        }
+
+        let Some((table, _)) = read_table(rsdt_addr) else {
+            return;
+        };
+
+        match RSDT::from_bytes(table) {
+            Ok(rsdt) => {
+                for table_addr in rsdt.entries.into_iter() {
+                    self.record(table_addr as usize);
+                }
+            }
+            Err(err) => {
+                print!("Invalid RSDT at {:#x}: {:?}\n", rsdt_addr, err);
+            }
+        }
+    }
+
+    // Adds the table found at `addr` to the registry, if its checksum is accepted and it was not
+    // already recorded
+    fn record(&mut self, addr: usize) {
+        if addr == 0 || self.iter().any(|table| table.addr == addr) {
+            return;
+        }
+
+        let Some((_, header)) = read_table(addr) else {
+            return;
+        };
+
+        let info = TableInfo::new(addr, &header);
+
+        let Some(slot) = self.tables.get_mut(self.ntables) else {
+            print!("Too many ACPI tables, dropping {:?} at {:#x}\n", info.signature_str(), addr);
+            return;
+        };
+
+        *slot = Some(info);
+        self.ntables += 1;
+    }
+
+    /// Returns the address of the RSDP the registry was built from
+    pub fn rsdp_addr(&self) -> usize {
+        self.rsdp_addr
+    }
+
+    /// Returns the RSDP the registry was built from
+    pub fn rsdp(&self) -> &RSDP {
+        &self.rsdp
+    }
+
+    /// Returns an iterator over all the tables in the registry, including the ones we do not have
+    /// a parser for
+    pub fn iter(&self) -> impl Iterator<Item = &TableInfo> {
+        self.tables[..self.ntables].iter().filter_map(|table| table.as_ref())
+    }
+
+    /// Returns an iterator over all the tables with the given `signature`. Some tables, like the
+    /// SSDT, can appear multiple times.
+    pub fn find_all(&self, signature: &[u8; 4]) -> impl Iterator<Item = &TableInfo> {
+        let signature = *signature;
+        self.iter().filter(move |table| table.signature == signature)
+    }
+
+    /// Returns the first table with the given `signature`
+    pub fn find_raw(&self, signature: &[u8; 4]) -> Option<&TableInfo> {
+        self.find_all(signature).next()
+    }
+
+    /// Looks up the table `T` and parses it. Tables that fail to parse are reported.
+    pub fn find<T: AcpiTable>(&self) -> Option<T> {
+        let table = self.find_raw(T::SIGNATURE)?;
+        match T::from_bytes(table.bytes()) {
+            Ok(parsed) => Some(parsed),
+            Err(err) => {
+                let signature = table.signature_str();
+                print!("Invalid {:?} table at {:#x}: {:?}\n", signature, table.addr, err);
+                None
+            }
+        }
+    }
+}
@@ -0,0 +1,1538 @@
+//! Tests of the table parsers and of the table registry, over the tables of the QEMU machines
+//! found in `tests/data/acpi`, over damaged copies of them, and over DMARs, SRATs and SLITs built
+//! here. The drivers of the hardware the tables describe are tested in their own modules.
+use crate::efi::acpi::aml::{
+    pnp_id, AmlError, AmlValue, DeviceId, DeviceInfo, DeviceStatus, Interpreter, PciRoute,
+    PrtEntry, Resource,
+};
+use crate::efi::acpi::madt::int_ctrl::{Polarity, TriggerMode};
+use crate::efi::acpi::{
+    address_space, debug_port_type, phys_slice, read_rsdp, rsdp_bytes, serial_interface,
+    set_checksum_policy, table_bytes, usb_subtype, with_phys_memory, AcpiTables, ChecksumPolicy,
+    DebugDevice, DebugTransport, FixedFeatureFlags, GenericAddressStructure, IaPcBootArchFlags,
+    MemoryAffinityFlags, NumaTopology, ParseError, SpcrFlowControl, DBG2, DMAR, FACS, FADT, HPET,
+    MADT, MAX_DBG2_ADDRESSES, MAX_DBG2_DEVICES, MAX_DEVICE_SCOPES, MAX_DRHD_UNITS,
+    MAX_MCFG_ENTRIES, MAX_RMRR_REGIONS, MAX_TABLE_LENGTH, MCFG, RSDP, RSDT, SLIT, SPCR, SRAT, XSDT,
+};
+use crate::efi::malloc::{EfiMemoryAttributes, EfiMemoryDescriptor, EfiMemoryType};
+use crate::pci::PciAddress;
+use crate::test_support::{
+    dbg2, dbg2_device, dmar, drhd, fix_checksum, gas, header, mcfg, spcr, spcr_fields, with_entry,
+    with_length, Firecracker, Machine, Pc, Q35,
+};
+use std::sync::atomic::{AtomicU64, Ordering};
+
+// Runs `f` with the interpreter, emptied of the namespace other tests loaded. The namespace
+// refers to the tables, so it is emptied again before they go away.
+fn with_interpreter<R>(f: impl FnOnce(&mut Interpreter) -> R) -> R {
+    let mut interpreter = loop {
+        if let Some(interpreter) = Interpreter::lock() {
+            break interpreter;
+        }
+        std::thread::yield_now();
+    };
+
+    interpreter.reset();
+    let result = f(&mut interpreter);
+    interpreter.reset();
+    result
+}
+
+// Returns the devices of the namespace, in the order they are defined
+fn devices(interpreter: &mut Interpreter) -> Vec<DeviceInfo> {
+    let mut devices = Vec::new();
+    interpreter.for_each_device(|_, info| devices.push(*info));
+    devices
+}
+
+// Returns the integers of the package `value`
+fn integers(interpreter: &Interpreter, value: AmlValue) -> Vec<u64> {
+    interpreter
+        .package(value)
+        .unwrap()
+        .iter()
+        .map(|element| match element {
+            AmlValue::Integer(value) => *value,
+            element => panic!("{element:?} is not an integer"),
+        })
+        .collect()
+}
+
+// Returns a definition block with the header of `table`, the signature `signature` and the code
+// `aml`
+fn definition_block(table: &[u8], signature: &[u8; 4], aml: &[u8]) -> Vec<u8> {
+    let mut block = table[..36].to_vec();
+    block[..4].copy_from_slice(signature);
+    block.extend_from_slice(aml);
+    let length = block.len() as u32;
+    with_length(&block, length)
+}
+
+// Encodes the opcode `op`, followed by the PkgLength of `body` and `body` itself, for bodies
+// shorter than 4 KiB
+fn aml_package(op: &[u8], body: &[u8]) -> Vec<u8> {
+    let mut package = op.to_vec();
+    match body.len() + 1 {
+        len @ 0..=0x3F => package.push(len as u8),
+        len => package.extend_from_slice(&[0x40 | (len + 1) as u8 & 0xF, ((len + 1) >> 4) as u8]),
+    }
+    package.extend_from_slice(body);
+    package
+}
+
+// Encodes `Scope (path) { body }`
+fn aml_scope(path: &[u8], body: &[u8]) -> Vec<u8> {
+    aml_package(b"\x10", &[path, body].concat())
+}
+
+// Encodes `Device (name) { body }`
+fn aml_device(name: &[u8; 4], body: &[u8]) -> Vec<u8> {
+    aml_package(b"\x5B\x82", &[name.as_slice(), body].concat())
+}
+
+// Returns the signatures of the tables in the registry
+fn signatures(tables: &AcpiTables) -> Vec<&str> {
+    tables.iter().map(|table| table.signature_str()).collect()
+}
+
+#[test]
+fn q35_rsdp() {
+    let bytes = Q35::table("rsdp");
+    let rsdp = RSDP::from_bytes(bytes).unwrap();
+
+    assert_eq!(rsdp.revision(), 2);
+    assert!(rsdp.is_checksum_valid(bytes));
+    assert_eq!(rsdp.rsdt_addr() as usize, Q35::addr("rsdt"));
+    assert_eq!(rsdp.xsdt_addr() as usize, Q35::addr("xsdt"));
+}
+
+#[test]
+fn pc_rsdp() {
+    let bytes = Pc::table("rsdp");
+    let rsdp = RSDP::from_bytes(bytes).unwrap();
+
+    assert_eq!(rsdp.revision(), 0);
+    assert!(rsdp.is_checksum_valid(bytes));
+    assert_eq!(rsdp.rsdt_addr() as usize, Pc::addr("rsdt"));
+    // Revision 0 structures have no XSDT, whatever follows them
+    assert_eq!(rsdp.xsdt_addr(), 0);
+}
+
+#[test]
+fn root_tables() {
+    let xsdt = XSDT::from_bytes(Q35::table("xsdt")).unwrap();
+    let rsdt = RSDT::from_bytes(Q35::table("rsdt")).unwrap();
+    let xsdt_entries: Vec<_> = xsdt.entries.into_iter().map(|addr| addr as usize).collect();
+    let rsdt_entries: Vec<_> = rsdt.entries.into_iter().map(|addr| addr as usize).collect();
+
+    let expected: Vec<_> = ["facp", "apic", "hpet", "mcfg", "waet"]
+        .into_iter()
+        .map(Q35::addr)
+        .collect();
+    assert_eq!(xsdt_entries, expected);
+    assert_eq!(rsdt_entries, expected);
+}
+
+#[test]
+fn q35_fadt() {
+    let fadt = FADT::from_bytes(Q35::table("facp")).unwrap();
+
+    assert_eq!(fadt.revision(), 3);
+    assert_eq!(fadt.facs_addr(), Some(Q35::addr("facs") as u64));
+    assert_eq!(fadt.dsdt_addr(), Some(Q35::addr("dsdt") as u64));
+    assert_eq!(fadt.sci_int(), 9);
+    assert_eq!(fadt.smi_cmd(), Some(0xB2));
+    assert_eq!((fadt.acpi_enable(), fadt.acpi_disable()), (0x02, 0x03));
+    assert_eq!(fadt.century(), 0x32);
+    assert!(!fadt.is_hw_reduced());
+    assert!(fadt.flags().contains(FixedFeatureFlags::RESET_REG_SUP));
+    assert!(!fadt.flags().contains(FixedFeatureFlags::TMR_VAL_EXT));
+    assert_eq!(fadt.iapc_boot_arch(), IaPcBootArchFlags::I8042);
+
+    // The extended blocks are used
+    let pm1a_cnt = fadt.pm1a_cnt_blk().unwrap();
+    let (address, width) = (pm1a_cnt.address, pm1a_cnt.register_bit_width);
+    assert_eq!(pm1a_cnt.address_space_id, address_space::SYSTEM_IO);
+    assert_eq!((address, width), (0x604, 16));
+    let gpe0 = fadt.gpe0_blk().unwrap();
+    let (address, width) = (gpe0.address, gpe0.register_bit_width);
+    assert_eq!((address, width), (0x620, 128));
+    assert!(fadt.pm1b_evt_blk().is_none());
+
+    let (reset_reg, reset_value) = fadt.reset_reg().unwrap();
+    let address = reset_reg.address;
+    assert_eq!((address, reset_value), (0xCF9, 0x0F));
+}
+
+#[test]
+fn pc_fadt() {
+    let fadt = FADT::from_bytes(Pc::table("facp")).unwrap();
+
+    // Revision 1 tables only have the legacy blocks, which are turned into I/O blocks
+    assert_eq!(fadt.revision(), 1);
+    assert_eq!(fadt.dsdt_addr(), Some(Pc::addr("dsdt") as u64));
+    assert_eq!(fadt.smi_cmd(), Some(0xB2));
+    assert_eq!((fadt.acpi_enable(), fadt.acpi_disable()), (0xF1, 0xF0));
+    let pm_tmr = fadt.pm_tmr_blk().unwrap();
+    let (address, width) = (pm_tmr.address, pm_tmr.register_bit_width);
+    assert_eq!(pm_tmr.address_space_id, address_space::SYSTEM_IO);
+    assert_eq!((address, width), (0x608, 32));
+    assert_eq!(fadt.pm1_evt_len(), 4);
+    let gpe0 = fadt.gpe0_blk().unwrap();
+    let (address, width) = (gpe0.address, gpe0.register_bit_width);
+    assert_eq!((address, width), (0xAFE0, 32));
+
+    // The fields past the end of the table read as zero
+    assert!(fadt.reset_reg().is_none());
+    assert!(fadt.sleep_control_reg().is_none());
+    assert_eq!(fadt.hypervisor_vendor_identity(), 0);
+}
+
+#[test]
+fn q35_madt() {
+    let madt = MADT::from_bytes(Q35::table("apic")).unwrap();
+
+    assert!(madt.pcat_compat());
+    assert_eq!(madt.local_apic_addr(), 0xFEE0_0000);
+
+    let processors: Vec<_> = madt
+        .processors()
+        .map(|cpu| (cpu.uid, cpu.apic_id))
+        .collect();
+    assert_eq!(processors, [(0, 0), (1, 1)]);
+    assert!(madt.processors().all(|cpu| cpu.enabled));
+
+    let io_apics: Vec<_> = madt.io_apics().collect();
+    assert_eq!(io_apics.len(), 1);
+    assert_eq!(io_apics[0].io_apic_addr(), 0xFEC0_0000);
+    assert_eq!(io_apics[0].global_system_int_base(), 0);
+
+    // The PIT is moved to GSI 2, the keyboard is identity-mapped and conforms to the ISA bus
+    assert_eq!(madt.isa_irq_to_gsi(0).0, 2);
+    let (gsi, flags) = madt.isa_irq_to_gsi(1);
+    assert_eq!(gsi, 1);
+    assert_eq!(flags.trigger_mode(), TriggerMode::ConformsToBus);
+    let (gsi, flags) = madt.isa_irq_to_gsi(9);
+    assert_eq!(gsi, 9);
+    assert_eq!(flags.polarity(), Polarity::ActiveHigh);
+    assert_eq!(flags.trigger_mode(), TriggerMode::Level);
+
+    // The NMI structure applies to every processor
+    assert_eq!(madt.nmi_lint(0).map(|(lint, _)| lint), Some(1));
+    assert_eq!(madt.nmi_lint(1).map(|(lint, _)| lint), Some(1));
+}
+
+#[test]
+fn pc_madt() {
+    let madt = MADT::from_bytes(Pc::table("apic")).unwrap();
+
+    assert_eq!(madt.processors().count(), 1);
+    assert_eq!(madt.int_src_overrides().count(), 5);
+    assert_eq!(madt.local_apic_nmis().count(), 1);
+}
+
+#[test]
+fn madt_entries() {
+    let entries: [&[u8]; 8] = [
+        // Processor Local APIC, UID 0, APIC ID 0, enabled
+        b"\x00\x08\x00\x00\x01\x00\x00\x00",
+        // Local APIC Address Override
+        b"\x05\x0C\x00\x00\x00\x00\xE0\xFE\x01\x00\x00\x00",
+        // Processor Local x2APICs: enabled, online capable, and neither
+        b"\x09\x10\x00\x00\x00\x01\x00\x00\x01\x00\x00\x00\x00\x01\x00\x00",
+        b"\x09\x10\x00\x00\x01\x01\x00\x00\x02\x00\x00\x00\x01\x01\x00\x00",
+        b"\x09\x10\x00\x00\x02\x01\x00\x00\x00\x00\x00\x00\x02\x01\x00\x00",
+        // Local x2APIC NMI on LINT0 of UID 0x100, active high and level triggered
+        b"\x0A\x0C\x0D\x00\x00\x01\x00\x00\x00\x00\x00\x00",
+        // Local APIC NMI on LINT1 of every processor
+        b"\x04\x06\xFF\x00\x00\x01",
+        // Multiprocessor Wakeup, with its mailbox
+        b"\x10\x10\x00\x00\x00\x00\x00\x00\x00\x00\xB9\x7F\x00\x00\x00\x00",
+    ];
+    let apic = Q35::table("apic");
+    let body = [&apic[36..44], &entries.concat()].concat();
+    let madt = MADT::from_bytes(&definition_block(apic, b"APIC", &body)).unwrap();
+    assert_eq!(madt.int_ctrls().count(), 8);
+
+    // The override takes precedence over the address in the table
+    assert_eq!(madt.local_apic_addr(), 0x1_FEE0_0000);
+
+    // Processors come from both kinds of structures, and unusable ones are left out
+    let processors: Vec<_> = madt
+        .processors()
+        .map(|cpu| (cpu.uid, cpu.apic_id, cpu.enabled))
+        .collect();
+    assert_eq!(
+        processors,
+        [(0, 0, true), (0x100, 0x100, true), (0x101, 0x101, false)]
+    );
+
+    // The first structure that applies to a processor wins
+    let (lint, flags) = madt.nmi_lint(0x100).unwrap();
+    assert_eq!(lint, 0);
+    assert_eq!(flags.polarity(), Polarity::ActiveHigh);
+    assert_eq!(flags.trigger_mode(), TriggerMode::Level);
+    assert_eq!(madt.nmi_lint(0).map(|(lint, _)| lint), Some(1));
+    assert_eq!(madt.nmi_lint(0x101).map(|(lint, _)| lint), Some(1));
+
+    let mp_wakeup = madt.mp_wakeup().unwrap();
+    assert_eq!(mp_wakeup.mailbox_version(), 0);
+    assert_eq!(mp_wakeup.mailbox_addr(), 0x7FB9_0000);
+
+    // Without an override, the address in the table is used
+    let body = [&apic[36..44], entries[0]].concat();
+    let madt = MADT::from_bytes(&definition_block(apic, b"APIC", &body)).unwrap();
+    assert_eq!(madt.local_apic_addr(), 0xFEE0_0000);
+    assert!(madt.mp_wakeup().is_none());
+}
+
+#[test]
+fn other_tables() {
+    let hpet = HPET::from_bytes(Q35::table("hpet")).unwrap();
+    let base = hpet.base_addr().address;
+    assert_eq!(base, 0xFED0_0000);
+    assert_eq!(hpet.pci_vendor_id(), 0x8086);
+
+    let mcfg = MCFG::from_bytes(Q35::table("mcfg")).unwrap();
+    let entry = mcfg.find(0, 0).unwrap();
+    assert_eq!(entry.base_addr(), 0xB000_0000);
+    assert_eq!((entry.start_bus(), entry.end_bus()), (0, 0xFF));
+
+    let facs = FACS::from_bytes(Q35::table("facs"), Q35::addr("facs")).unwrap();
+    assert_eq!(facs.length(), 64);
+}
+
+#[test]
+fn firecracker_tables() {
+    // A hardware-reduced platform, whose FADT only points to the DSDT through its 64-bit field
+    let fadt = FADT::from_bytes(Firecracker::table("facp")).unwrap();
+    assert_eq!(fadt.revision(), 6);
+    assert!(fadt.is_hw_reduced());
+    let buttons = FixedFeatureFlags::PWR_BUTTON | FixedFeatureFlags::SLP_BUTTON;
+    assert_eq!(fadt.flags(), FixedFeatureFlags::HW_REDUCED_ACPI | buttons);
+    assert_eq!(fadt.iapc_boot_arch(), IaPcBootArchFlags::VGA_NOT_PRESENT);
+    assert_eq!(
+        fadt.hypervisor_vendor_identity().to_le_bytes(),
+        *b"FIRECKVM"
+    );
+    assert_eq!(fadt.facs_addr(), None);
+    assert_eq!(fadt.dsdt_addr(), Some(0x9_FD30));
+    assert_eq!(fadt.smi_cmd(), None);
+
+    // There are no fixed hardware registers, not even the sleep registers of hardware-reduced
+    // platforms
+    assert!(fadt.pm1a_evt_blk().is_none());
+    assert!(fadt.pm1a_cnt_blk().is_none());
+    assert!(fadt.pm_tmr_blk().is_none());
+    assert!(fadt.reset_reg().is_none());
+    assert!(fadt.sleep_control_reg().is_none());
+    assert!(fadt.sleep_status_reg().is_none());
+
+    // A single processor, and an I/O APIC without any ISA interrupt override
+    let madt = MADT::from_bytes(Firecracker::table("apic")).unwrap();
+    assert!(!madt.pcat_compat());
+    assert_eq!(madt.local_apic_addr(), 0xFEE0_0000);
+    let processors: Vec<_> = madt
+        .processors()
+        .map(|cpu| (cpu.uid, cpu.apic_id, cpu.enabled))
+        .collect();
+    assert_eq!(processors, [(0, 0, true)]);
+    let io_apics: Vec<_> = madt.io_apics().collect();
+    assert_eq!(io_apics.len(), 1);
+    assert_eq!(io_apics[0].io_apic_addr(), 0xFEC0_0000);
+    assert_eq!(madt.int_src_overrides().count(), 0);
+    assert_eq!(madt.isa_irq_to_gsi(4).0, 4);
+
+    // The ECAM window of bus 0, which the DSDT also reserves in the `_CRS` of its root bridge
+    let mcfg = MCFG::from_bytes(Firecracker::table("mcfg")).unwrap();
+    let entries: Vec<_> = mcfg
+        .entries()
+        .map(|entry| {
+            (
+                entry.base_addr(),
+                entry.segment(),
+                entry.start_bus(),
+                entry.end_bus(),
+            )
+        })
+        .collect();
+    assert_eq!(entries, [(0xEEC0_0000, 0, 0, 0)]);
+}
+
+#[test]
+fn mcfg_limits() {
+    // Ranges are kept up to the limit, and a table holding more is refused rather than losing
+    // the configuration space of some buses
+    let entries: Vec<_> = (0..=MAX_MCFG_ENTRIES as u16)
+        .map(|segment| (0xB000_0000 + ((segment as u64) << 28), segment, 0, 0xFF))
+        .collect();
+    let table = MCFG::from_bytes(&mcfg(&entries[..MAX_MCFG_ENTRIES])).unwrap();
+    assert_eq!(table.entries().count(), MAX_MCFG_ENTRIES);
+    let last = table.find(MAX_MCFG_ENTRIES as u16 - 1, 0x80).unwrap();
+    assert_eq!(
+        last.base_addr(),
+        0xB000_0000 + ((MAX_MCFG_ENTRIES as u64 - 1) << 28)
+    );
+    assert_eq!(
+        MCFG::from_bytes(&mcfg(&entries)).err(),
+        Some(ParseError::TooManyEntries {
+            limit: MAX_MCFG_ENTRIES
+        })
+    );
+
+    // Entries must be whole
+    let mut table = mcfg(&entries[..1]);
+    table.truncate(table.len() - 1);
+    let length = table.len() as u32;
+    assert_eq!(
+        MCFG::from_bytes(&with_length(&table, length)).err(),
+        Some(ParseError::InvalidLength(length as usize))
+    );
+}
+
+#[test]
+fn q35_registry() {
+    let memory = Q35::memory(&[], &[]);
+    with_phys_memory(&memory, || {
+        let tables = AcpiTables::new(Q35::addr("rsdp")).unwrap();
+
+        assert_eq!(
+            signatures(&tables),
+            ["FACP", "APIC", "HPET", "MCFG", "WAET"]
+        );
+        let fadt = tables.find::<FADT>().unwrap();
+        assert_eq!(fadt.revision(), 3);
+        let madt = tables.find::<MADT>().unwrap();
+        assert_eq!(madt.processors().count(), 2);
+        let apic = tables.find_raw(b"APIC").unwrap();
+        assert_eq!(apic.addr(), Q35::addr("apic"));
+        assert_eq!(apic.bytes(), Q35::table("apic"));
+
+        let facs = FACS::from_fadt(&fadt).unwrap();
+        assert_eq!(facs.addr(), Q35::addr("facs"));
+    });
+}
+
+#[test]
+fn pc_registry() {
+    let memory = Pc::memory(&[], &[]);
+    with_phys_memory(&memory, || {
+        let tables = AcpiTables::new(Pc::addr("rsdp")).unwrap();
+
+        assert_eq!(signatures(&tables), ["FACP", "APIC", "HPET", "WAET"]);
+        assert_eq!(tables.find::<FADT>().unwrap().revision(), 1);
+        assert!(tables.find::<MCFG>().is_none());
+    });
+}
+
+#[test]
+fn registry_falls_back_to_rsdt() {
+    // Without a usable XSDT, the RSDT is walked instead
+    let memory = Q35::memory(&[], &["xsdt"]);
+    with_phys_memory(&memory, || {
+        let tables = AcpiTables::new(Q35::addr("rsdp")).unwrap();
+        assert_eq!(
+            signatures(&tables),
+            ["FACP", "APIC", "HPET", "MCFG", "WAET"]
+        );
+    });
+
+    let xsdt = with_length(Q35::table("xsdt"), 36 + 8 * 5 + 4);
+    let memory = Q35::memory(&[("xsdt", &xsdt)], &[]);
+    with_phys_memory(&memory, || {
+        let tables = AcpiTables::new(Q35::addr("rsdp")).unwrap();
+        assert_eq!(signatures(&tables).len(), 5);
+    });
+}
+
+#[test]
+fn registry_skips_damaged_tables() {
+    // The MADT is cut short in memory, and the HPET is missing
+    let apic = Q35::table("apic");
+    let memory = Q35::memory(&[("apic", &apic[..apic.len() - 1])], &["hpet"]);
+    with_phys_memory(&memory, || {
+        let tables = AcpiTables::new(Q35::addr("rsdp")).unwrap();
+        assert_eq!(signatures(&tables), ["FACP", "MCFG", "WAET"]);
+    });
+
+    // A table whose checksum is wrong is only dropped when the policy says so
+    let mut mcfg = Q35::table("mcfg").to_vec();
+    mcfg[9] = mcfg[9].wrapping_add(1);
+    let memory = Q35::memory(&[("mcfg", &mcfg)], &[]);
+    with_phys_memory(&memory, || {
+        let tables = AcpiTables::new(Q35::addr("rsdp")).unwrap();
+        assert!(tables.find_raw(b"MCFG").is_some());
+
+        set_checksum_policy(ChecksumPolicy::Reject);
+        let tables = AcpiTables::new(Q35::addr("rsdp"));
+        set_checksum_policy(ChecksumPolicy::Warn);
+        assert!(tables.unwrap().find_raw(b"MCFG").is_none());
+    });
+}
+
+#[test]
+fn truncated_tables() {
+    for name in ["xsdt", "rsdt", "facp", "apic"] {
+        let table = Q35::table(name);
+        let truncated = &table[..table.len() - 1];
+        let err = match name {
+            "xsdt" => XSDT::from_bytes(truncated).err(),
+            "rsdt" => RSDT::from_bytes(truncated).err(),
+            "facp" => FADT::from_bytes(truncated).err(),
+            _ => MADT::from_bytes(truncated).err(),
+        };
+
+        let needed = table.len();
+        let available = needed - 1;
+        assert_eq!(err, Some(ParseError::Truncated { needed, available }));
+    }
+
+    // Even the header does not fit
+    assert_eq!(
+        MADT::from_bytes(&Q35::table("apic")[..20]).err(),
+        Some(ParseError::Truncated {
+            needed: 36,
+            available: 20
+        })
+    );
+
+    // The last interrupt controller structure, which is 6 bytes long, goes past the end of the
+    // table
+    let apic = Q35::table("apic");
+    let short = with_length(apic, apic.len() as u32 - 2);
+    assert_eq!(
+        MADT::from_bytes(&short).err(),
+        Some(ParseError::Truncated {
+            needed: apic.len(),
+            available: apic.len() - 2
+        })
+    );
+
+    let rsdp = Q35::table("rsdp");
+    assert_eq!(
+        RSDP::from_bytes(&rsdp[..35]).err(),
+        Some(ParseError::Truncated {
+            needed: 36,
+            available: 35
+        })
+    );
+    assert_eq!(
+        RSDP::from_bytes(&Pc::table("rsdp")[..19]).err(),
+        Some(ParseError::Truncated {
+            needed: 20,
+            available: 19
+        })
+    );
+}
+
+#[test]
+fn overlong_tables() {
+    // A length past the end of the bytes is as good as a truncated table
+    let facp = Q35::table("facp");
+    let long = with_length(facp, facp.len() as u32 + 16);
+    assert_eq!(
+        FADT::from_bytes(&long).err(),
+        Some(ParseError::Truncated {
+            needed: facp.len() + 16,
+            available: facp.len()
+        })
+    );
+
+    // Bytes past the length of the table are not part of it
+    let mut padded = Q35::table("apic").to_vec();
+    padded.extend_from_slice(&[0xFF; 16]);
+    assert_eq!(MADT::from_bytes(&padded).unwrap().int_ctrls().count(), 9);
+    let mut padded = Q35::table("xsdt").to_vec();
+    padded.extend_from_slice(&[0xFF; 8]);
+    assert_eq!(
+        XSDT::from_bytes(&padded)
+            .unwrap()
+            .entries
+            .into_iter()
+            .count(),
+        5
+    );
+
+    // Lengths that do not even cover the header
+    for name in ["xsdt", "facp", "apic"] {
+        let short = with_length(Q35::table(name), 35);
+        let err = match name {
+            "xsdt" => XSDT::from_bytes(&short).err(),
+            "facp" => FADT::from_bytes(&short).err(),
+            _ => MADT::from_bytes(&short).err(),
+        };
+        assert_eq!(err, Some(ParseError::InvalidLength(35)));
+    }
+
+    // Root tables must hold whole entries
+    let xsdt = Q35::table("xsdt");
+    let odd = with_length(&[xsdt, &[0; 4]].concat(), xsdt.len() as u32 + 4);
+    assert_eq!(
+        XSDT::from_bytes(&odd).err(),
+        Some(ParseError::InvalidLength(xsdt.len() + 4))
+    );
+
+    // An interrupt controller structure shorter than its own header
+    let mut apic = Q35::table("apic").to_vec();
+    apic[44 + 1] = 1;
+    assert_eq!(
+        MADT::from_bytes(&apic).err(),
+        Some(ParseError::InvalidLength(1))
+    );
+
+    // The length of revision 2 RSDPs must cover the whole structure, and fit in the bytes
+    for length in [20u32, 64] {
+        let mut rsdp = Q35::table("rsdp").to_vec();
+        rsdp[20..24].copy_from_slice(&length.to_le_bytes());
+        assert_eq!(
+            RSDP::from_bytes(&rsdp).err(),
+            Some(ParseError::InvalidLength(length as usize))
+        );
+    }
+}
+
+#[test]
+fn invalid_signatures() {
+    assert_eq!(
+        FADT::from_bytes(Q35::table("apic")).err(),
+        Some(ParseError::InvalidSignature)
+    );
+    assert_eq!(
+        MADT::from_bytes(Q35::table("facp")).err(),
+        Some(ParseError::InvalidSignature)
+    );
+    assert_eq!(
+        XSDT::from_bytes(Q35::table("rsdt")).err(),
+        Some(ParseError::InvalidSignature)
+    );
+    assert_eq!(
+        FACS::from_bytes(Q35::table("facp"), Q35::addr("facp")).err(),
+        Some(ParseError::InvalidSignature)
+    );
+
+    let mut rsdp = Q35::table("rsdp").to_vec();
+    rsdp[0] = b'r';
+    assert_eq!(
+        RSDP::from_bytes(&rsdp).err(),
+        Some(ParseError::InvalidSignature)
+    );
+}
+
+#[test]
+fn physical_memory_errors() {
+    let facp = Q35::table("facp");
+    let huge = with_length(facp, MAX_TABLE_LENGTH as u32 + 1);
+    let memory = Q35::memory(&[("facp", &huge)], &[]);
+
+    with_phys_memory(&memory, || {
+        assert_eq!(table_bytes(0).err(), Some(ParseError::NullAddress));
+        assert_eq!(rsdp_bytes(0).err(), Some(ParseError::NullAddress));
+        assert!(read_rsdp(0).is_none());
+        assert!(AcpiTables::new(0).is_none());
+
+        // The length of the FADT is above what we accept
+        assert_eq!(
+            table_bytes(Q35::addr("facp")).err(),
+            Some(ParseError::InvalidLength(MAX_TABLE_LENGTH + 1))
+        );
+
+        // Reads that go past the end of what is in memory
+        let apic = Q35::addr("apic");
+        let apic_len = Q35::table("apic").len();
+        assert_eq!(
+            unsafe { phys_slice(apic + 8, apic_len) }.err(),
+            Some(ParseError::Truncated {
+                needed: apic_len,
+                available: apic_len - 8
+            })
+        );
+        assert_eq!(
+            unsafe { phys_slice(0x1000, 4) }.err(),
+            Some(ParseError::Truncated {
+                needed: 4,
+                available: 0
+            })
+        );
+    });
+
+    // A FADT without a FACS
+    let mut facp = Q35::table("facp").to_vec();
+    facp[36..40].fill(0);
+    fix_checksum(&mut facp, 9);
+    let fadt = FADT::from_bytes(&facp).unwrap();
+    assert_eq!(FACS::from_fadt(&fadt).err(), Some(ParseError::NullAddress));
+}
+
+// Returns an RMRR covering the page at `base` for the device 00:1d.0
+fn rmrr(base: u64) -> Vec<u8> {
+    let mut rmrr = vec![1, 0, 32, 0, 0, 0, 0, 0];
+    rmrr.extend_from_slice(&base.to_le_bytes());
+    rmrr.extend_from_slice(&(base + 0xFFF).to_le_bytes());
+    rmrr.extend_from_slice(&[1, 8, 0, 0, 0, 0, 0x1D, 0]);
+    rmrr
+}
+
+#[test]
+fn dmar_limits() {
+    // As many structures as we have room for are kept
+    let units: Vec<Vec<u8>> = (0..MAX_DRHD_UNITS as u64)
+        .map(|unit| drhd(0xFED9_0000 + unit * 0x1000, MAX_DEVICE_SCOPES))
+        .collect();
+    let regions: Vec<Vec<u8>> = (0..MAX_RMRR_REGIONS as u64)
+        .map(|region| rmrr(0x7C00_0000 + region * 0x1000))
+        .collect();
+    let full = DMAR::from_bytes(&dmar(&[units.clone(), regions.clone()].concat())).unwrap();
+    assert_eq!(full.host_address_width(), 39);
+    assert_eq!(full.drhds().count(), MAX_DRHD_UNITS);
+    assert!(full
+        .drhds()
+        .all(|drhd| drhd.scopes().count() == MAX_DEVICE_SCOPES));
+    assert_eq!(full.rmrrs().count(), MAX_RMRR_REGIONS);
+
+    // One more unit, reserved region or device scope makes the whole table unusable, rather
+    // than leaving devices out of the translation
+    let too_many_units = [units.clone(), vec![drhd(0xFEDF_0000, 1)]].concat();
+    assert_eq!(
+        DMAR::from_bytes(&dmar(&too_many_units)).err(),
+        Some(ParseError::TooManyEntries {
+            limit: MAX_DRHD_UNITS
+        })
+    );
+    let too_many_regions = [regions, vec![rmrr(0x7D00_0000)]].concat();
+    assert_eq!(
+        DMAR::from_bytes(&dmar(&too_many_regions)).err(),
+        Some(ParseError::TooManyEntries {
+            limit: MAX_RMRR_REGIONS
+        })
+    );
+    let too_many_scopes = [drhd(0xFED9_0000, MAX_DEVICE_SCOPES + 1)];
+    assert_eq!(
+        DMAR::from_bytes(&dmar(&too_many_scopes)).err(),
+        Some(ParseError::TooManyEntries {
+            limit: MAX_DEVICE_SCOPES
+        })
+    );
+}
+
+// Returns an SRAT of revision `revision` made of `structures`, laid out as QEMU's `-numa` ones
+fn srat(revision: u8, structures: &[Vec<u8>]) -> Vec<u8> {
+    let mut table = header(b"SRAT", revision);
+    // The reserved field, which must be 1 for backward compatibility
+    table.extend_from_slice(&[1, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0]);
+    table.extend(structures.concat());
+    let length = table.len() as u32;
+    with_length(&table, length)
+}
+
+// Returns a Processor Local APIC Affinity structure putting the local APIC `apic_id` in the
+// proximity domain `domain`
+fn apic_affinity(apic_id: u8, domain: u32, enabled: bool) -> Vec<u8> {
+    let domain = domain.to_le_bytes();
+    let mut affinity = vec![0, 16, domain[0], apic_id];
+    affinity.extend_from_slice(&(enabled as u32).to_le_bytes());
+    affinity.push(0);
+    affinity.extend_from_slice(&domain[1..]);
+    affinity.extend_from_slice(&[0; 4]);
+    affinity
+}
+
+// Returns a Processor Local x2APIC Affinity structure putting the x2APIC `x2apic_id` in the
+// proximity domain `domain`
+fn x2apic_affinity(x2apic_id: u32, domain: u32, enabled: bool) -> Vec<u8> {
+    let mut affinity = vec![2, 24, 0, 0];
+    affinity.extend_from_slice(&domain.to_le_bytes());
+    affinity.extend_from_slice(&x2apic_id.to_le_bytes());
+    affinity.extend_from_slice(&(enabled as u32).to_le_bytes());
+    affinity.extend_from_slice(&[0; 8]);
+    affinity
+}
+
+// Returns a Memory Affinity structure putting `length` bytes at `base` in the proximity domain
+// `domain`
+fn memory_affinity(domain: u32, base: u64, length: u64, flags: MemoryAffinityFlags) -> Vec<u8> {
+    let mut affinity = vec![1, 40];
+    affinity.extend_from_slice(&domain.to_le_bytes());
+    affinity.extend_from_slice(&[0; 2]);
+    affinity.extend_from_slice(&base.to_le_bytes());
+    affinity.extend_from_slice(&length.to_le_bytes());
+    affinity.extend_from_slice(&[0; 4]);
+    affinity.extend_from_slice(&flags.bits().to_le_bytes());
+    affinity.extend_from_slice(&[0; 8]);
+    affinity
+}
+
+// Returns a SLIT with the distance matrix `distances`
+fn slit(distances: &[&[u8]]) -> Vec<u8> {
+    let mut table = header(b"SLIT", 1);
+    table.extend_from_slice(&(distances.len() as u64).to_le_bytes());
+    table.extend(distances.concat());
+    let length = table.len() as u32;
+    with_length(&table, length)
+}
+
+#[test]
+fn numa_topology() {
+    const SRAT_ADDR: usize = 0x7FB8_5000;
+    const SLIT_ADDR: usize = 0x7FB8_6000;
+    let enabled = MemoryAffinityFlags::ENABLED;
+    let hot_pluggable = enabled | MemoryAffinityFlags::HOT_PLUGGABLE;
+
+    // Two nodes, as with `-numa node,nodeid=0 -numa node,nodeid=1`: the processors and the low
+    // memory in domain 0, and the rest in domain 1. The disabled structures are left out.
+    let srat_table = srat(
+        3,
+        &[
+            apic_affinity(0, 0, true),
+            apic_affinity(1, 0, true),
+            apic_affinity(2, 0, false),
+            x2apic_affinity(0x100, 1, true),
+            x2apic_affinity(0x101, 1, false),
+            memory_affinity(0, 0, 0xA_0000, enabled),
+            memory_affinity(0, 0x10_0000, 0x7F0_0000, enabled),
+            memory_affinity(1, 0x800_0000, 0x800_0000, enabled),
+            memory_affinity(1, 0x1000_0000, 0x1000_0000, MemoryAffinityFlags::empty()),
+            memory_affinity(1, 0x1_0000_0000, 0x1_0000_0000, hot_pluggable),
+        ],
+    );
+    let slit_table = slit(&[&[10, 21], &[21, 10]]);
+
+    let table = SRAT::from_bytes(&srat_table).unwrap();
+    let processors: Vec<_> = table
+        .processors()
+        .map(|cpu| (cpu.apic_id, cpu.x2apic, cpu.proximity_domain))
+        .collect();
+    assert_eq!(processors, [(0, false, 0), (1, false, 0), (0x100, true, 1)]);
+    assert_eq!(table.memory_ranges().count(), 4);
+
+    let table = SLIT::from_bytes(&slit_table).unwrap();
+    assert_eq!(table.localities(), 2);
+    assert_eq!(
+        (table.distance(0, 0), table.distance(0, 1)),
+        (Some(10), Some(21))
+    );
+    assert_eq!(table.distance(2, 0), None);
+    // The matrix must hold every distance
+    let short = with_length(
+        &slit_table[..slit_table.len() - 1],
+        slit_table.len() as u32 - 1,
+    );
+    assert_eq!(
+        SLIT::from_bytes(&short).err(),
+        Some(ParseError::InvalidLength(short.len()))
+    );
+
+    // The upper bits of the proximity domain of local APICs only count from revision 2
+    let wide = apic_affinity(3, 0x0102, true);
+    let domain = |revision| {
+        let table = SRAT::from_bytes(&srat(revision, std::slice::from_ref(&wide))).unwrap();
+        let processor = *table.processors().next().unwrap();
+        processor.proximity_domain
+    };
+    assert_eq!((domain(1), domain(2)), (2, 0x0102));
+
+    let xsdt = with_entry(&with_entry(Q35::table("xsdt"), SRAT_ADDR), SLIT_ADDR);
+    let mut memory = Q35::memory(&[("xsdt", &xsdt)], &[]);
+    memory.extend([
+        (SRAT_ADDR, srat_table.as_slice()),
+        (SLIT_ADDR, slit_table.as_slice()),
+    ]);
+    with_phys_memory(&memory, || {
+        let tables = AcpiTables::new(Q35::addr("rsdp")).unwrap();
+        let numa = NumaTopology::from_acpi(&tables).unwrap();
+
+        assert_eq!(numa.domains(), [0, 1]);
+        assert_eq!(numa.domain_of_apic(1), Some(0));
+        assert_eq!(numa.domain_of_apic(0x100), Some(1));
+        assert_eq!(numa.domain_of_apic(2), None);
+        assert_eq!(numa.domain_of_addr(0x900_0000), Some(1));
+        assert_eq!(numa.domain_of_addr(0x1800_0000), None);
+        assert_eq!((numa.distance(1, 1), numa.distance(1, 0)), (10, 21));
+
+        // The memory map regions are split where the domain changes, including into the holes
+        // the SRAT does not describe
+        let region = |start: u64, end: u64| {
+            let pages = (end - start) / 0x1000;
+            let attr = EfiMemoryAttributes::WB;
+            EfiMemoryDescriptor::new(EfiMemoryType::ConventionalMemory, start, pages, attr)
+        };
+        let descriptors = [
+            region(0, 0xA_0000),
+            region(0xA_0000, 0x10_1000),
+            region(0x7F0_0000, 0x810_0000),
+            region(0xFF0_0000, 0x1010_0000),
+            region(0x1_0000_0000, 0x1_0000_1000),
+        ];
+        let mut parts = Vec::new();
+        numa.for_each_region(descriptors.iter(), |part| {
+            parts.push((
+                part.descriptor.phys_start(),
+                part.start,
+                part.end,
+                part.proximity_domain,
+            ));
+        });
+        assert_eq!(
+            parts,
+            [
+                (0, 0, 0xA_0000, Some(0)),
+                (0xA_0000, 0xA_0000, 0x10_0000, None),
+                (0xA_0000, 0x10_0000, 0x10_1000, Some(0)),
+                (0x7F0_0000, 0x7F0_0000, 0x800_0000, Some(0)),
+                (0x7F0_0000, 0x800_0000, 0x810_0000, Some(1)),
+                (0xFF0_0000, 0xFF0_0000, 0x1000_0000, Some(1)),
+                (0xFF0_0000, 0x1000_0000, 0x1010_0000, None),
+                (0x1_0000_0000, 0x1_0000_0000, 0x1_0000_1000, Some(1)),
+            ]
+        );
+
+        let mut flags = Vec::new();
+        numa.for_each_region(descriptors[4..].iter(), |part| flags.push(part.flags));
+        assert_eq!(flags, [hot_pluggable]);
+    });
+}
+
+// Where the OVMF RAM disk SSDT is placed, after the q35 tables
+const SSDT_ADDR: usize = 0x7FB8_4000;
+
+// The SSDT OVMF installs for its RAM disks, which defines an NVDIMM root device
+const RAMDISK_SSDT: &[u8] = include_bytes!(concat!(
+    env!("CARGO_MANIFEST_DIR"),
+    "/tests/data/acpi/ovmf/ramdisk-ssdt.dat"
+));
+
+#[test]
+fn q35_namespace() {
+    let memory = Q35::memory(&[], &[]);
+    with_phys_memory(&memory, || {
+        let tables = AcpiTables::new(Q35::addr("rsdp")).unwrap();
+        with_interpreter(|interpreter| {
+            interpreter.load_tables(&tables).unwrap();
+
+            let devices = devices(interpreter);
+            let paths: Vec<String> = devices
+                .iter()
+                .map(|device| interpreter.namespace().path(device.node).to_string())
+                .collect();
+            assert_eq!(
+                paths,
+                [
+                    "\\_SB_.PCI0",
+                    "\\_SB_.PCI0.LPC_",
+                    "\\_SB_.PCI0.LPC_.KBD_",
+                    "\\_SB_.PCI0.LPC_.MOU_",
+                    "\\_SB_.PCI0.LPC_.COM1",
+                    "\\_SB_.PCI0.LPC_.RTC_",
+                    "\\_SB_.PCI0.HPET",
+                    "\\_SB_.GSIE",
+                    "\\_SB_.GSIF",
+                    "\\_SB_.GSIG",
+                    "\\_SB_.GSIH",
+                    "\\_SB_.CPUS",
+                    "\\_SB_.CPUS.C000",
+                    "\\_SB_.CPUS.C001",
+                ]
+            );
+
+            let root = devices[0];
+            assert_eq!(root.hid.unwrap().to_string(), pnp_id::PCIE_ROOT_BRIDGE);
+            assert!(root.matches(pnp_id::PCI_ROOT_BRIDGE));
+            assert_eq!(root.uid, Some(DeviceId::Integer(0)));
+            assert_eq!(devices[1].adr, Some(0x001F_0000));
+            assert!(devices[11].matches("PNP0A05"));
+            assert_eq!(devices[13].uid, Some(DeviceId::Integer(1)));
+
+            // The serial port, as the firmware set it up
+            let com1 = devices[4];
+            assert!(com1.matches(pnp_id::COM_PORT) && com1.is_present());
+            let resources: Vec<Resource> = interpreter
+                .current_resources(com1.node)
+                .unwrap()
+                .map(Result::unwrap)
+                .collect();
+            assert!(matches!(
+                resources[..],
+                [
+                    Resource::Io {
+                        min: 0x3F8,
+                        max: 0x3F8,
+                        length: 8,
+                        ..
+                    },
+                    Resource::Irq { irqs: 0x10, .. }
+                ]
+            ));
+
+            // The root bridge decodes all the buses, and the PCI hole below 4 GiB
+            let resources: Vec<Resource> = interpreter
+                .current_resources(root.node)
+                .unwrap()
+                .map(Result::unwrap)
+                .collect();
+            assert_eq!(resources.len(), 5);
+            assert!(matches!(
+                resources[0],
+                Resource::AddressSpace(space) if space.min == 0 && space.max == 0xFF
+            ));
+            assert!(matches!(
+                resources[4],
+                Resource::AddressSpace(space) if space.min == 0xC000_0000 && space.length == 0x3EC0_0000
+            ));
+
+            // In APIC mode, the pins are routed to the GSI links
+            interpreter
+                .evaluate("\\_PIC", &[AmlValue::Integer(1)])
+                .unwrap();
+            let mut prt = [PrtEntry::default(); 32];
+            assert_eq!(interpreter.pci_routing_table(root.node, &mut prt), Ok(16));
+            let gsif = interpreter.lookup("\\_SB.GSIF").unwrap();
+            assert_eq!(prt[0].device(), 1);
+            assert_eq!(
+                prt[0].route,
+                PciRoute::Link {
+                    link: gsif,
+                    index: 0
+                }
+            );
+            let resources: Vec<Resource> = interpreter
+                .current_resources(gsif)
+                .unwrap()
+                .map(Result::unwrap)
+                .collect();
+            assert!(matches!(
+                resources[..],
+                [Resource::ExtendedIrq {
+                    count: 1,
+                    interrupts: [0x15, ..],
+                    ..
+                }]
+            ));
+
+            let s5 = interpreter.evaluate("\\_S5", &[]).unwrap();
+            assert_eq!(integers(interpreter, s5), [0, 0, 0, 0]);
+        });
+    });
+}
+
+#[test]
+fn pc_namespace() {
+    let memory = Pc::memory(&[], &[]);
+    with_phys_memory(&memory, || {
+        let tables = AcpiTables::new(Pc::addr("rsdp")).unwrap();
+        with_interpreter(|interpreter| {
+            interpreter.load_tables(&tables).unwrap();
+
+            let devices = devices(interpreter);
+            assert_eq!(devices.len(), 13);
+            let root = devices[0];
+            assert_eq!(root.hid.unwrap().to_string(), pnp_id::PCI_ROOT_BRIDGE);
+            assert_eq!(root.cids, [None; 4]);
+            assert_eq!(devices[1].adr, Some(0x0001_0000));
+
+            // The links are enabled, but not shown in the UI
+            let lnka = interpreter.lookup("\\_SB.LNKA").unwrap();
+            let lnka = devices.iter().find(|device| device.node == lnka).unwrap();
+            assert_eq!(
+                lnka.status,
+                DeviceStatus::PRESENT | DeviceStatus::ENABLED | DeviceStatus::FUNCTIONING
+            );
+
+            let mut prt = [PrtEntry::default(); 32];
+            assert_eq!(interpreter.pci_routing_table(root.node, &mut prt), Ok(16));
+            let lnkb = interpreter.lookup("\\_SB.LNKB").unwrap();
+            assert_eq!(
+                prt[0].route,
+                PciRoute::Link {
+                    link: lnkb,
+                    index: 0
+                }
+            );
+            assert_eq!((prt[15].device(), prt[15].pin), (4, 3));
+
+            let s3 = interpreter.evaluate("\\_S3", &[]).unwrap();
+            assert_eq!(integers(interpreter, s3), [1, 1, 0, 0]);
+            let s4 = interpreter.evaluate("\\_S4", &[]).unwrap();
+            assert_eq!(integers(interpreter, s4), [2, 2, 0, 0]);
+        });
+    });
+}
+
+#[test]
+fn ssdt_namespace() {
+    let xsdt = with_entry(Q35::table("xsdt"), SSDT_ADDR);
+    let mut memory = Q35::memory(&[("xsdt", &xsdt)], &[]);
+    memory.push((SSDT_ADDR, RAMDISK_SSDT));
+    with_phys_memory(&memory, || {
+        let tables = AcpiTables::new(Q35::addr("rsdp")).unwrap();
+        with_interpreter(|interpreter| {
+            interpreter.load_tables(&tables).unwrap();
+
+            let nvdr = interpreter.lookup("\\_SB.NVDR").unwrap();
+            let info = interpreter.device_info(nvdr).unwrap();
+            assert!(info.matches("ACPI0012") && info.is_present());
+            assert!(interpreter.lookup("\\_SB.PCI0.LPC.COM1").is_some());
+        });
+    });
+
+    // The SSDTs are loaded even if the DSDT cannot be
+    let mut memory = Q35::memory(&[("xsdt", &xsdt)], &["dsdt"]);
+    memory.push((SSDT_ADDR, RAMDISK_SSDT));
+    with_phys_memory(&memory, || {
+        let tables = AcpiTables::new(Q35::addr("rsdp")).unwrap();
+        with_interpreter(|interpreter| {
+            assert!(interpreter.load_tables(&tables).is_err());
+            assert!(interpreter.lookup("\\_SB.NVDR").is_some());
+            assert!(interpreter.lookup("\\_SB.PCI0").is_none());
+        });
+    });
+}
+
+#[test]
+fn firecracker_namespace() {
+    let memory = Q35::memory(&[("dsdt", Firecracker::table("dsdt"))], &[]);
+    with_phys_memory(&memory, || {
+        let tables = AcpiTables::new(Q35::addr("rsdp")).unwrap();
+        with_interpreter(|interpreter| {
+            interpreter.load_tables(&tables).unwrap();
+
+            // A root bridge with a function for each of the 32 slots of bus 0
+            let devices = devices(interpreter);
+            assert_eq!(devices.len(), 38);
+            let root = devices[3];
+            assert_eq!(
+                interpreter.namespace().path(root.node).to_string(),
+                "\\_SB_.PC00"
+            );
+            assert_eq!(root.hid.unwrap().to_string(), pnp_id::PCIE_ROOT_BRIDGE);
+            assert!(root.matches(pnp_id::PCI_ROOT_BRIDGE));
+            let slots: Vec<u64> = devices[4..36]
+                .iter()
+                .map(|slot| slot.adr.unwrap())
+                .collect();
+            assert_eq!(slots, (0..32).map(|slot| slot << 16).collect::<Vec<_>>());
+
+            // The `_STA` methods of the clock and of the keyboard controller
+            let ps2 = interpreter.lookup("\\_SB.PS2_").unwrap();
+            assert!(interpreter.namespace().child(ps2, *b"_STA").is_some());
+            let info = interpreter.device_info(ps2).unwrap();
+            assert!(info.matches("PNP0303"));
+            assert_eq!(info.status, DeviceStatus::from_bits_retain(0xF));
+            let vclk = interpreter.lookup("\\_SB.VCLK").unwrap();
+            assert!(interpreter.device_info(vclk).unwrap().is_present());
+
+            // The bus range, the ECAM window and the apertures of the root bridge
+            let resources: Vec<Resource> = interpreter
+                .current_resources(root.node)
+                .unwrap()
+                .collect::<Result<_, _>>()
+                .unwrap();
+            assert_eq!(resources.len(), 7);
+            assert!(matches!(
+                resources[0],
+                Resource::AddressSpace(space) if space.min == 0 && space.max == 0
+            ));
+            assert!(matches!(
+                resources[2],
+                Resource::FixedMemory32 {
+                    base: 0xEEC0_0000,
+                    length: 0x10_0000,
+                    ..
+                }
+            ));
+            assert!(matches!(
+                resources[4],
+                Resource::AddressSpace(space) if space.min == 0x40_0000_0000 && space.length == 0x40_0000_0000
+            ));
+
+            // The serial port
+            let com1 = interpreter.lookup("\\_SB.COM1").unwrap();
+            let resources: Vec<Resource> = interpreter
+                .current_resources(com1)
+                .unwrap()
+                .collect::<Result<_, _>>()
+                .unwrap();
+            assert!(matches!(
+                resources[..],
+                [
+                    Resource::ExtendedIrq {
+                        count: 1,
+                        interrupts: [4, ..],
+                        ..
+                    },
+                    Resource::Io {
+                        min: 0x3F8,
+                        length: 8,
+                        ..
+                    }
+                ]
+            ));
+
+            // Every slot has its pin A routed to a GSI, without interrupt links
+            let mut prt = [PrtEntry::default(); 40];
+            assert_eq!(interpreter.pci_routing_table(root.node, &mut prt), Ok(32));
+            for (slot, entry) in prt[..32].iter().enumerate() {
+                assert_eq!(entry.device(), slot as u8);
+                assert_eq!((entry.pin, entry.route), (0, PciRoute::Gsi(0)));
+            }
+        });
+    });
+}
+
+#[test]
+fn failing_terms_are_skipped() {
+    let dsdt = Q35::table("dsdt");
+    let mut aml = Vec::new();
+    // A scope that does not exist, whose package is skipped as a whole
+    aml.extend(aml_scope(b"\\\x2E_SB_NOPE", b"\x08XXXX\x01"));
+    // A name whose value refers to an object that does not exist
+    aml.extend_from_slice(b"\x08BAD1\\\x2E_SB_NOPE");
+    // An opcode that does not exist, after which the rest of the scope cannot be parsed
+    aml.extend(aml_scope(b"\\_SB_", b"\x08GOOD\x01\x02\x08LOST\x01"));
+    aml.extend_from_slice(&dsdt[36..]);
+    let dsdt = definition_block(dsdt, b"DSDT", &aml);
+
+    let memory = Q35::memory(&[("dsdt", &dsdt)], &[]);
+    with_phys_memory(&memory, || {
+        let tables = AcpiTables::new(Q35::addr("rsdp")).unwrap();
+        with_interpreter(|interpreter| {
+            interpreter.load_tables(&tables).unwrap();
+
+            for missing in ["\\_SB.NOPE.XXXX", "\\BAD1", "\\_SB.LOST"] {
+                assert!(interpreter.lookup(missing).is_none(), "{missing}");
+            }
+            assert!(interpreter.lookup("\\_SB.GOOD").is_some());
+            assert_eq!(devices(interpreter).len(), 14);
+        });
+    });
+
+    // Within a control method, the first failing term fails the whole method
+    let aml = [
+        b"\x14\x0CFAIL\x00\x70BAD2\x60\xA4\x01".as_slice(),
+        &Q35::table("dsdt")[36..],
+    ]
+    .concat();
+    let dsdt = definition_block(Q35::table("dsdt"), b"DSDT", &aml);
+    let memory = Q35::memory(&[("dsdt", &dsdt)], &[]);
+    with_phys_memory(&memory, || {
+        let tables = AcpiTables::new(Q35::addr("rsdp")).unwrap();
+        with_interpreter(|interpreter| {
+            interpreter.load_tables(&tables).unwrap();
+            assert_eq!(
+                interpreter.evaluate("\\FAIL", &[]).err(),
+                Some(AmlError::NameNotFound)
+            );
+        });
+    });
+}
+
+#[test]
+fn region_addresses() {
+    // OperationRegion (CFGx, PCI_Config, 0, 0x100) and Field (CFGx, DWordAcc) { VIDx, 32 }
+    let config = |region: &[u8; 4], field: &[u8; 4]| {
+        let mut aml = [b"\x5B\x80".as_slice(), region, b"\x02\x00\x0B\x00\x01"].concat();
+        aml.extend(aml_package(
+            b"\x5B\x81",
+            &[region.as_slice(), b"\x03", field, b"\x20"].concat(),
+        ));
+        aml
+    };
+
+    let mut aml = Vec::new();
+    // A function on bus 1 of a second root bridge
+    let pci1 = [
+        b"\x08_HID\x0C\x41\xD0\x0A\x08\x08_BBN\x01".as_slice(),
+        &aml_device(
+            b"DEV0",
+            &[
+                b"\x08_ADR\x0C\x00\x00\x01\x00".as_slice(),
+                &config(b"CFG0", b"VID0"),
+            ]
+            .concat(),
+        ),
+    ]
+    .concat();
+    aml.extend(aml_scope(b"\\_SB_", &aml_device(b"PCI1", &pci1)));
+    // A function behind a bridge of the first root bridge
+    let bridge = [
+        b"\x08_ADR\x0C\x00\x00\x1E\x00".as_slice(),
+        &aml_device(
+            b"DEV1",
+            &[b"\x08_ADR\x00".as_slice(), &config(b"CFG1", b"VID1")].concat(),
+        ),
+    ]
+    .concat();
+    aml.extend(aml_scope(b"\\\x2E_SB_PCI0", &aml_device(b"BRG0", &bridge)));
+    // A region outside of any root bridge
+    aml.extend(config(b"CFG2", b"VID2"));
+    // OperationRegion (HIGH, SystemMemory, 0xFFFFFFFFFFFFFFF0, 0x20), whose end overflows, and
+    // Field (HIGH, QWordAcc) { Offset (0x18), HIGF, 64 }
+    aml.extend_from_slice(b"\x5B\x80HIGH\x00\x0E\xF0\xFF\xFF\xFF\xFF\xFF\xFF\xFF\x0A\x20");
+    aml.extend(aml_package(
+        b"\x5B\x81",
+        b"HIGH\x04\x00\x40\x0CHIGF\x40\x04",
+    ));
+
+    let ssdt = definition_block(Q35::table("dsdt"), b"SSDT", &aml);
+    let xsdt = with_entry(Q35::table("xsdt"), SSDT_ADDR);
+    // Revision 2 of the DSDT makes integers 64 bits wide, so that the address is not truncated
+    let mut dsdt = Q35::table("dsdt").to_vec();
+    dsdt[8] = 2;
+    fix_checksum(&mut dsdt, 9);
+    let mut memory = Q35::memory(&[("xsdt", &xsdt), ("dsdt", &dsdt)], &[]);
+    memory.push((SSDT_ADDR, &ssdt));
+    with_phys_memory(&memory, || {
+        let tables = AcpiTables::new(Q35::addr("rsdp")).unwrap();
+        with_interpreter(|interpreter| {
+            interpreter.load_tables(&tables).unwrap();
+
+            // Only bus 0 of segment 0 is reachable through a Generic Address Structure
+            let unsupported = AmlError::UnsupportedRegion(address_space::PCI_CONFIG);
+            for field in [
+                "\\_SB.PCI1.DEV0.VID0",
+                "\\_SB.PCI0.BRG0.DEV1.VID1",
+                "\\VID2",
+            ] {
+                assert_eq!(
+                    interpreter.evaluate(field, &[]).err(),
+                    Some(unsupported),
+                    "{field}"
+                );
+            }
+
+            assert_eq!(
+                interpreter.evaluate("\\HIGF", &[]).err(),
+                Some(AmlError::InvalidArgument)
+            );
+        });
+    });
+}
+
+#[test]
+fn aml_timer() {
+    // The HPET registers, with a 64-bit main counter ticking every 10 ns
+    let registers: Vec<AtomicU64> = (0..0x80).map(|_| AtomicU64::new(0)).collect();
+    registers[0].store(10_000_000 << 32 | 1 << 13, Ordering::SeqCst);
+    let main_counter = &registers[0xF0 / 8];
+    let config = &registers[0x10 / 8];
+
+    // Without a PM timer, the HPET is used
+    let mut facp = Q35::table("facp").to_vec();
+    facp[76..80].fill(0);
+    facp[208..220].fill(0);
+    fix_checksum(&mut facp, 9);
+    let mut hpet = Q35::table("hpet").to_vec();
+    hpet[44..52].copy_from_slice(&(registers.as_ptr() as u64).to_le_bytes());
+    fix_checksum(&mut hpet, 9);
+
+    // Method (TIME) { Return (Timer) }
+    let ssdt = definition_block(Q35::table("dsdt"), b"SSDT", b"\x14\x09TIME\x00\xA4\x5B\x33");
+    let xsdt = with_entry(Q35::table("xsdt"), SSDT_ADDR);
+    let mut memory = Q35::memory(&[("facp", &facp), ("hpet", &hpet), ("xsdt", &xsdt)], &[]);
+    memory.push((SSDT_ADDR, &ssdt));
+    with_phys_memory(&memory, || {
+        let tables = AcpiTables::new(Q35::addr("rsdp")).unwrap();
+        with_interpreter(|interpreter| {
+            interpreter.load_tables(&tables).unwrap();
+
+            // The timer counts from its first read, which enables the HPET
+            main_counter.store(1234, Ordering::SeqCst);
+            let time = |interpreter: &mut Interpreter| interpreter.evaluate("\\TIME", &[]).unwrap();
+            assert_eq!(time(interpreter), AmlValue::Integer(0));
+            assert_eq!(config.load(Ordering::SeqCst) & 1, 1);
+
+            // 1 ms later
+            main_counter.store(1234 + 100_000, Ordering::SeqCst);
+            assert_eq!(time(interpreter), AmlValue::Integer(10_000));
+            main_counter.store(1234 + 100_010, Ordering::SeqCst);
+            assert_eq!(time(interpreter), AmlValue::Integer(10_001));
+        });
+    });
+
+    // Without an HPET either, there is no timer to read
+    let mut memory = Q35::memory(&[("facp", &facp), ("xsdt", &xsdt)], &["hpet"]);
+    memory.push((SSDT_ADDR, &ssdt));
+    with_phys_memory(&memory, || {
+        let tables = AcpiTables::new(Q35::addr("rsdp")).unwrap();
+        with_interpreter(|interpreter| {
+            interpreter.load_tables(&tables).unwrap();
+            assert_eq!(
+                interpreter.evaluate("\\TIME", &[]).err(),
+                Some(AmlError::NoTimer)
+            );
+        });
+    });
+}
+
+#[test]
+fn spcr_table() {
+    const SPCR_ADDR: usize = 0x7FB8_7000;
+    let com1 = gas(address_space::SYSTEM_IO, 8, 0, 1, 0x3F8);
+
+    // A revision 2 table for COM1
+    let mut fields = spcr_fields(serial_interface::NS16550 as u8, com1);
+    fields.extend_from_slice(&[0; 4]);
+    let table = SPCR::from_bytes(&spcr(2, &fields)).unwrap();
+    assert_eq!(table.interface_type(), serial_interface::NS16550);
+    assert_eq!({ table.base_addr().address }, 0x3F8);
+    assert_eq!(table.irq(), Some(4));
+    assert_eq!(table.global_system_interrupt(), Some(4));
+    assert_eq!(table.baud_rate(), Some(115200));
+    assert_eq!(table.stop_bits(), 1);
+    assert_eq!(table.flow_control(), SpcrFlowControl::RTS_CTS);
+    assert_eq!(table.uart_clock_freq(), None);
+    assert_eq!(table.pci_function(), None);
+
+    // A revision 4 table for an MMIO UART of a PCI function, with a precise baud rate and a UART
+    // clock
+    let uart = gas(address_space::SYSTEM_MEMORY, 32, 0, 3, 0xFE03_2000);
+    let mut fields = spcr_fields(serial_interface::NS16550_GAS as u8, uart);
+    fields[16] = 0b10;
+    fields[28..35].copy_from_slice(&[0x28, 0xA3, 0x86, 0x80, 0, 0x1E, 0]);
+    fields.extend_from_slice(&48_000_000u32.to_le_bytes());
+    fields.extend_from_slice(&1_500_000u32.to_le_bytes());
+    fields.extend_from_slice(&[0; 4]);
+    let table = SPCR::from_bytes(&spcr(4, &fields)).unwrap();
+    assert_eq!(table.irq(), None);
+    assert_eq!(table.global_system_interrupt(), Some(4));
+    assert_eq!(table.baud_rate(), Some(1_500_000));
+    assert_eq!(table.uart_clock_freq(), Some(48_000_000));
+    assert_eq!(table.pci_function(), Some(PciAddress::new(0, 0, 0x1E, 0)));
+
+    // The fields past the length of a short table read as zero
+    let short = SPCR::from_bytes(&spcr(1, &spcr_fields(0, com1)[..16])).unwrap();
+    assert_eq!(short.irq(), None);
+    assert_eq!(short.baud_rate(), None);
+    let mut bad = spcr(2, &spcr_fields(0, com1));
+    bad[0] = b'X';
+    assert!(SPCR::from_bytes(&bad).is_err());
+
+    // The table is found through the XSDT
+    let table = spcr(2, &fields[..44]);
+    let xsdt = with_entry(Q35::table("xsdt"), SPCR_ADDR);
+    let mut memory = Q35::memory(&[("xsdt", &xsdt)], &[]);
+    memory.push((SPCR_ADDR, &table));
+    with_phys_memory(&memory, || {
+        let tables = AcpiTables::new(Q35::addr("rsdp")).unwrap();
+        let found = tables.find::<SPCR>().unwrap();
+        assert_eq!(found.interface_type(), serial_interface::NS16550_GAS);
+        assert_eq!(found.uart_clock_freq(), Some(48_000_000));
+        assert_eq!(found.baud_rate(), Some(115200));
+    });
+}
+
+#[test]
+fn dbg2_devices() {
+    let gas = |address_space_id, access_size, address| GenericAddressStructure {
+        address_space_id,
+        register_bit_width: 8u8 << (access_size - 1),
+        register_bit_offset: 0,
+        access_size,
+        address,
+    };
+    let com2 = gas(address_space::SYSTEM_IO, 1, 0x2F8);
+    let xhci_regs = gas(address_space::SYSTEM_MEMORY, 3, 0xFEB0_0000);
+    let xhci_dbc = gas(address_space::SYSTEM_MEMORY, 3, 0xFEB1_0000);
+    let pl011 = gas(address_space::SYSTEM_MEMORY, 3, 0x0900_0000);
+
+    let serial = debug_port_type::SERIAL;
+    let devices = [
+        dbg2_device(
+            serial,
+            serial_interface::ARM_PL011,
+            &[(pl011, 0x1000)],
+            b".\0",
+            false,
+        ),
+        dbg2_device(
+            serial,
+            serial_interface::NS16550,
+            &[(com2, 8)],
+            b"\\_SB.COM2\0",
+            true,
+        ),
+        dbg2_device(
+            debug_port_type::USB,
+            usb_subtype::XHCI,
+            &[(xhci_regs, 0x1_0000), (xhci_dbc, 0x1000)],
+            b"\\_SB.PCI0.XHCI\0",
+            false,
+        ),
+        dbg2_device(debug_port_type::NET, 0x8086, &[], b".\0", false),
+    ];
+    let table = DBG2::from_bytes(&dbg2(&devices)).unwrap();
+
+    let transports: Vec<_> = table.devices().map(DebugDevice::transport).collect();
+    assert_eq!(
+        transports,
+        [
+            DebugTransport::Serial(serial_interface::ARM_PL011),
+            DebugTransport::Serial(serial_interface::NS16550),
+            DebugTransport::Xhci,
+            DebugTransport::Net(0x8086),
+        ]
+    );
+    let paths: Vec<_> = table.devices().map(DebugDevice::namespace_path).collect();
+    assert_eq!(
+        paths,
+        [None, Some("\\_SB.COM2"), Some("\\_SB.PCI0.XHCI"), None]
+    );
+
+    // The register blocks are read from the offsets the device gives, wherever they are
+    let addresses = |device: &DebugDevice| -> Vec<(u8, u64, u32)> {
+        let addresses = device.addresses();
+        addresses
+            .map(|(gas, size)| (gas.access_size, gas.address, *size))
+            .collect()
+    };
+    let xhci = table.find(DebugTransport::Xhci).next().unwrap();
+    assert_eq!(
+        addresses(xhci),
+        [(3, 0xFEB0_0000, 0x1_0000), (3, 0xFEB1_0000, 0x1000)]
+    );
+    let serial_ports: Vec<_> = table.serial_ports().collect();
+    assert_eq!(serial_ports.len(), 1);
+    assert_eq!(addresses(serial_ports[0]), [(1, 0x2F8, 8)]);
+    assert!(addresses(table.devices().last().unwrap()).is_empty());
+
+    // Only the first register blocks and devices are kept
+    let many_addresses = [(xhci_regs, 0x1000); MAX_DBG2_ADDRESSES + 1];
+    let device = dbg2_device(
+        debug_port_type::USB,
+        usb_subtype::EHCI,
+        &many_addresses,
+        b".\0",
+        false,
+    );
+    let table = DBG2::from_bytes(&dbg2(&[device])).unwrap();
+    assert_eq!(
+        table.devices().next().unwrap().addresses().count(),
+        MAX_DBG2_ADDRESSES
+    );
+    let many_devices = vec![devices[1].clone(); MAX_DBG2_DEVICES + 1];
+    let table = DBG2::from_bytes(&dbg2(&many_devices)).unwrap();
+    assert_eq!(table.devices().count(), MAX_DBG2_DEVICES);
+
+    // Devices going past the end of the table, or with parts going past their end
+    let truncated = &devices[2][..devices[2].len() - 1];
+    assert_eq!(
+        DBG2::from_bytes(&dbg2(&[truncated.to_vec()])).err(),
+        Some(ParseError::Truncated {
+            needed: 44 + devices[2].len(),
+            available: 44 + truncated.len()
+        })
+    );
+    let mut short = devices[1].clone();
+    short[1..3].copy_from_slice(&21u16.to_le_bytes());
+    assert_eq!(
+        DBG2::from_bytes(&dbg2(&[short])).err(),
+        Some(ParseError::InvalidLength(21))
+    );
+    let mut long_namespace = devices[1].clone();
+    long_namespace[4..6].copy_from_slice(&100u16.to_le_bytes());
+    assert!(matches!(
+        DBG2::from_bytes(&dbg2(&[long_namespace])).err(),
+        Some(ParseError::Truncated { .. })
+    ));
+    let mut far_sizes = devices[2].clone();
+    far_sizes[20..22].copy_from_slice(&0x100u16.to_le_bytes());
+    assert!(DBG2::from_bytes(&dbg2(&[far_sizes])).is_err());
+}
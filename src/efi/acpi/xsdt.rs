@@ -1,28 +1,26 @@
-use crate::efi::acpi::DescriptionHeader;
+use crate::efi::acpi::{read, DescriptionHeader, ParseError};
 use core::mem::size_of;
 
 /// The XSDT provides identical functionality to the RSDT but accommodates physical addresses of
 /// DESCRIPTION HEADERs that are larger than 32 bits. Notice that both the XSDT and the RSDT can be
 /// pointed to by the RSDP structure. An ACPI-compatible OS must use the XSDT if present.
-#[repr(C, packed)]
-pub struct XSDT {
+pub struct XSDT<'a> {
     // Header for the Table
     header: DescriptionHeader,
-    // Custom structure which holds the next address in memory after the above header and how many
-    // entries does the XSDT contain
-    pub entries: Entries,
+    // Custom structure which holds the bytes of the entries that follow the above header
+    pub entries: Entries<'a>,
 }
 
-/// The entries that are stored in the XSDT table, specified here by their address and their
-/// cardinal.
-pub struct Entries {
-    addr: u64,
-    nentries: usize,
+/// The entries that are stored in the XSDT table, kept as the bytes that follow the header, as
+/// they are not aligned.
+#[derive(Clone, Copy)]
+pub struct Entries<'a> {
+    bytes: &'a [u8],
 }
 
-impl IntoIterator for Entries {
+impl<'a> IntoIterator for Entries<'a> {
     type Item = u64;
-    type IntoIter = EntriesIterator;
+    type IntoIter = EntriesIterator<'a>;
 
     fn into_iter(self) -> Self::IntoIter {
         EntriesIterator {
@@ -33,25 +31,17 @@ impl IntoIterator for Entries {
 }
 
 /// An Iterator struct used to iterate over the records in Entries
-pub struct EntriesIterator {
-    entries: Entries,
+pub struct EntriesIterator<'a> {
+    entries: Entries<'a>,
     idx: usize,
 }
 
-impl Iterator for EntriesIterator {
+impl<'a> Iterator for EntriesIterator<'a> {
     type Item = u64;
 
     fn next(&mut self) -> Option<Self::Item> {
         // If there isn't enough data to read one more element, return None
-        if self.idx >= self.entries.nentries {
-            return None;
-        }
-
-        // Compute the current element's address
-        let elem_addr = self.entries.addr as usize + size_of::<Self::Item>() * self.idx;
-
-        // Read the element
-        let table_addr = unsafe { core::ptr::read_unaligned(elem_addr as *const u64) };
+        let table_addr = read::<u64>(self.entries.bytes, size_of::<Self::Item>() * self.idx).ok()?;
 
         // Go to the next element for the next iteration
         self.idx += 1;
@@ -64,30 +54,24 @@ impl Iterator for EntriesIterator {
 // The signature found in the first 4 bytes from the XSDT table
 const XSDT_SIGNATURE: &[u8; 4] = b"XSDT";
 
-impl XSDT {
-    /// Read the Extended System Description Table from `addr`, whith the specified length.
-    /// We also have to pass the original Physical Address from where the Header was read from,
-    /// as we have to compute the start address for the entries that follow
-    pub fn from_header(addr: usize, header: DescriptionHeader) -> Option<XSDT> {
-        // If the header's signature is no the right signature, we return `None`
-        if &header.signature != XSDT_SIGNATURE {
-            return None;
-        }
+impl<'a> XSDT<'a> {
+    /// Read the Extended System Description Table from the bytes of the entire table. The table
+    /// must only hold whole 64-bit entries after its header.
+    pub fn from_bytes(bytes: &'a [u8]) -> Result<Self, ParseError> {
+        let (header, table) = DescriptionHeader::parse_table(bytes, XSDT_SIGNATURE)?;
 
-        // Compute the number of entries followin the XSDT Table Header
-        let nentries = (header.length as usize - size_of::<DescriptionHeader>()) / size_of::<u64>();
+        let entries = &table[size_of::<DescriptionHeader>()..];
+        if entries.len() % size_of::<u64>() != 0 {
+            return Err(ParseError::InvalidLength(table.len()));
+        }
 
-        // Since the data for the entries in the XSDT table is not aligned, we cannot read it
-        // properly, using a `core::slice::from_raw_parts` call, so we will only save the address
-        // of the list and the number of entries
-        let xsdt = XSDT {
+        Ok(XSDT {
             header,
-            entries: Entries {
-                addr: (addr + size_of::<DescriptionHeader>()) as u64,
-                nentries: nentries,
-            },
-        };
+            entries: Entries { bytes: entries },
+        })
+    }
 
-        Some(xsdt)
+    pub fn header(&self) -> &DescriptionHeader {
+        &self.header
     }
 }
@@ -1,13 +1,23 @@
 //! Module that handles all of the EFI Boot Services table functions
-use crate::{
-    efi::{status, EfiTableHeader, EFI_SYSTEM_TABLE},
-    EfiHandle, EfiStatus,
-};
+use crate::efi::malloc::EfiMemoryType;
+use crate::efi::{status, EfiGuid, EfiHandle, EfiStatus, EfiTableHeader, EFI_SYSTEM_TABLE};
 use core::sync::atomic::Ordering;
 
 /// Signature for the `EfiBootServicesTable` structure
 pub const EFI_BOOT_SERVICES_SIGNATURE: u64 = 0x5652_4553_544f_4f42;
 
+/// How `allocate_pages` picks the address of the pages it allocates
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u32)]
+pub enum EfiAllocateType {
+    /// Any free pages will do
+    AnyPages,
+    /// The pages must end below the address given on input
+    MaxAddress,
+    /// The pages must start at the address given on input
+    Address,
+}
+
 /// Represents the EFI Boot Service Table, which contains a table header and pointers to all of the
 /// boot services as described in the Boot Service chapter from any UEFI Spec.
 /// The function pointers in this table are not valied after the OS has taken control of the
@@ -25,7 +35,14 @@ pub struct EfiBootServicesTable {
     //
     // Memory Services, all from EFI 1.0+
     //
-    _allocate_pages: usize,
+    /// Allocates `pages` pages of the memory type `memory_type`, at an address chosen according
+    /// to `allocate_type`. The address of the first page is returned in `memory`.
+    pub allocate_pages: extern "efiapi" fn(
+        allocate_type: EfiAllocateType,
+        memory_type: EfiMemoryType,
+        pages: usize,
+        memory: &mut u64,
+    ) -> EfiStatus,
     _free_pages: usize,
     // Returns the current boot services memory map and memory map key
     pub get_memory_map: fn(
@@ -89,7 +106,12 @@ pub struct EfiBootServicesTable {
     //
     _protocols_per_handle: usize,
     _locate_handle_buffer: usize,
-    _locate_protocol: usize,
+    /// Returns the first protocol instance that matches the given protocol GUID
+    pub locate_protocol: extern "efiapi" fn(
+        protocol: *const EfiGuid,
+        registration: *const u8,
+        interface: *mut *mut u8,
+    ) -> EfiStatus,
     _install_multiple_protocol_interfaces: usize,
     _uninstall_multiple_protocol_interfaces: usize,
     //
@@ -0,0 +1,382 @@
+//! Module that handles the Graphics Output Protocol (GOP), through which the firmware lets us pick
+//! a video mode and gives us a linear framebuffer for it. The framebuffer stays usable after
+//! exiting boot services, while the protocol itself does not.
+use crate::bmp::{Bmp, Rgb};
+use crate::efi::acpi::{BgrtImageError, BGRT};
+use crate::efi::malloc::{allocate_pages, EfiMemoryType, EFI_PAGE_SIZE};
+use crate::efi::{status, EfiGuid, EfiStatus, EFI_SYSTEM_TABLE};
+use core::sync::atomic::Ordering;
+
+/// GUID for the Graphics Output Protocol
+pub const EFI_GRAPHICS_OUTPUT_PROTOCOL_GUID: EfiGuid = 0x6a5180d0_de7afb96_4a3823dc_9042a9de;
+
+/// The Graphics Output Protocol, as installed by the firmware on the console output device
+#[repr(C)]
+pub struct EfiGraphicsOutputProtocol {
+    // Returns information about the available video mode `mode_number`
+    query_mode: extern "efiapi" fn(
+        this: *const Self,
+        mode_number: u32,
+        size_of_info: *mut usize,
+        info: *mut *const EfiGraphicsOutputModeInformation,
+    ) -> EfiStatus,
+    // Sets the video device into the mode `mode_number` and clears the screen
+    set_mode: extern "efiapi" fn(this: *const Self, mode_number: u32) -> EfiStatus,
+    // Block transfers to and from the screen, which we do not need as we draw in the framebuffer
+    _blt: usize,
+    // The current mode of the device
+    mode: *const EfiGraphicsOutputProtocolMode,
+}
+
+/// Describes the current mode of the Graphics Output Protocol
+#[repr(C)]
+struct EfiGraphicsOutputProtocolMode {
+    // The number of modes the device supports, numbered from 0 to `max_mode` - 1
+    max_mode: u32,
+    // The current mode
+    mode: u32,
+    // Information about the current mode
+    info: *const EfiGraphicsOutputModeInformation,
+    // Size of the structure `info` points to
+    size_of_info: usize,
+    // Physical address of the linear framebuffer
+    frame_buffer_base: u64,
+    // Size of the linear framebuffer, in bytes
+    frame_buffer_size: usize,
+}
+
+/// Describes a video mode
+#[derive(Debug, Clone, Copy)]
+#[repr(C)]
+pub struct EfiGraphicsOutputModeInformation {
+    /// Version of the structure, which is 0
+    pub version: u32,
+    /// Number of pixels in a row of the screen
+    pub horizontal_resolution: u32,
+    /// Number of rows of the screen
+    pub vertical_resolution: u32,
+    /// Layout of the pixels, as a `pixel_format` value
+    pub pixel_format: u32,
+    /// The masks of the red, green, blue and reserved bits of a pixel, if `pixel_format` is
+    /// `pixel_format::BIT_MASK`
+    pub pixel_information: [u32; 4],
+    /// Number of pixels in a row of the framebuffer, which may be above the horizontal resolution
+    pub pixels_per_scan_line: u32,
+}
+
+/// Layouts of the pixels of the framebuffer
+pub mod pixel_format {
+    /// Red in byte 0, green in byte 1, blue in byte 2
+    pub const RGB_RESERVED_8BIT: u32 = 0;
+    /// Blue in byte 0, green in byte 1, red in byte 2
+    pub const BGR_RESERVED_8BIT: u32 = 1;
+    /// The components are given by masks
+    pub const BIT_MASK: u32 = 2;
+    /// There is no framebuffer, only block transfers
+    pub const BLT_ONLY: u32 = 3;
+}
+
+/// Handle to the Graphics Output Protocol of the console, which is only usable before exiting boot
+/// services
+pub struct GraphicsOutput {
+    protocol: *const EfiGraphicsOutputProtocol,
+}
+
+impl GraphicsOutput {
+    /// Looks up the Graphics Output Protocol. Returns `None` if boot services are gone, or if the
+    /// firmware does not provide one, like when running headless.
+    pub fn locate() -> Option<Self> {
+        let sys_table = EFI_SYSTEM_TABLE.load(Ordering::SeqCst);
+
+        if sys_table.is_null() {
+            return None;
+        }
+
+        let boot_services_table = unsafe { (*sys_table).boot_services };
+        let mut interface = core::ptr::null_mut();
+        let status = unsafe {
+            ((*boot_services_table).locate_protocol)(
+                &EFI_GRAPHICS_OUTPUT_PROTOCOL_GUID,
+                core::ptr::null(),
+                &mut interface,
+            )
+        };
+
+        (status == status::EFI_SUCCESS && !interface.is_null()).then_some(Self {
+            protocol: interface as *const EfiGraphicsOutputProtocol,
+        })
+    }
+
+    /// Returns the number of video modes the device supports
+    pub fn mode_count(&self) -> u32 {
+        unsafe { (*(*self.protocol).mode).max_mode }
+    }
+
+    /// Returns the current video mode
+    pub fn current_mode(&self) -> u32 {
+        unsafe { (*(*self.protocol).mode).mode }
+    }
+
+    /// Returns information about the video mode `mode_number`
+    pub fn query_mode(
+        &self,
+        mode_number: u32,
+    ) -> Result<EfiGraphicsOutputModeInformation, EfiStatus> {
+        let mut size_of_info = 0;
+        let mut info = core::ptr::null();
+        let status = unsafe {
+            ((*self.protocol).query_mode)(self.protocol, mode_number, &mut size_of_info, &mut info)
+        };
+
+        match status {
+            status::EFI_SUCCESS if !info.is_null() => Ok(unsafe { *info }),
+            status::EFI_SUCCESS => Err(status::EFI_DEVICE_ERROR),
+            _ => Err(status),
+        }
+    }
+
+    /// Switches to the video mode `mode_number`, which clears the screen, and returns the
+    /// framebuffer of the new mode
+    pub fn set_mode(&self, mode_number: u32) -> Result<Framebuffer, EfiStatus> {
+        let status = unsafe { ((*self.protocol).set_mode)(self.protocol, mode_number) };
+        if status != status::EFI_SUCCESS {
+            return Err(status);
+        }
+
+        self.framebuffer().ok_or(status::EFI_UNSUPPORTED)
+    }
+
+    /// Returns the framebuffer of the current mode, or `None` if the mode only supports block
+    /// transfers
+    pub fn framebuffer(&self) -> Option<Framebuffer> {
+        let mode = unsafe { &*(*self.protocol).mode };
+        if mode.info.is_null() {
+            return None;
+        }
+        let info = unsafe { *mode.info };
+
+        let format = match info.pixel_format {
+            pixel_format::RGB_RESERVED_8BIT => PixelFormat::Rgb,
+            pixel_format::BGR_RESERVED_8BIT => PixelFormat::Bgr,
+            pixel_format::BIT_MASK => {
+                let [red, green, blue, _] = info.pixel_information;
+                PixelFormat::BitMask { red, green, blue }
+            }
+            _ => return None,
+        };
+
+        Some(Framebuffer {
+            base: mode.frame_buffer_base as usize,
+            size: mode.frame_buffer_size,
+            width: info.horizontal_resolution,
+            height: info.vertical_resolution,
+            stride: info.pixels_per_scan_line,
+            format,
+        })
+    }
+}
+
+/// Layout of a 32 bits pixel of the framebuffer
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PixelFormat {
+    Rgb,
+    Bgr,
+    BitMask { red: u32, green: u32, blue: u32 },
+}
+
+impl PixelFormat {
+    // Encodes `color` as a pixel
+    fn encode(&self, color: Rgb) -> u32 {
+        let Rgb { red, green, blue } = color;
+        match *self {
+            Self::Rgb => u32::from_le_bytes([red, green, blue, 0]),
+            Self::Bgr => u32::from_le_bytes([blue, green, red, 0]),
+            Self::BitMask {
+                red: red_mask,
+                green: green_mask,
+                blue: blue_mask,
+            } => {
+                place_component(red, red_mask)
+                    | place_component(green, green_mask)
+                    | place_component(blue, blue_mask)
+            }
+        }
+    }
+}
+
+// Scales the 8 bits `component` to the width of `mask`, and moves it in place
+fn place_component(component: u8, mask: u32) -> u32 {
+    if mask == 0 {
+        return 0;
+    }
+
+    let shift = mask.trailing_zeros();
+    let bits = (mask >> shift).trailing_ones();
+    let value = if bits >= 8 {
+        (component as u32) << (bits - 8)
+    } else {
+        component as u32 >> (8 - bits)
+    };
+
+    (value << shift) & mask
+}
+
+/// A linear framebuffer of 32 bits pixels
+#[derive(Debug, Clone, Copy)]
+pub struct Framebuffer {
+    // Physical address of the framebuffer
+    base: usize,
+    // Size of the framebuffer, in bytes
+    size: usize,
+    width: u32,
+    height: u32,
+    // Number of pixels in a row of the framebuffer
+    stride: u32,
+    format: PixelFormat,
+}
+
+impl Framebuffer {
+    pub fn base(&self) -> usize {
+        self.base
+    }
+    pub fn size(&self) -> usize {
+        self.size
+    }
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+    pub fn format(&self) -> PixelFormat {
+        self.format
+    }
+
+    /// Sets the pixel at column `x` and row `y` to `color`. Pixels outside of the screen are
+    /// ignored.
+    pub fn put_pixel(&self, x: u32, y: u32, color: Rgb) {
+        if x >= self.width || y >= self.height {
+            return;
+        }
+
+        let offset = (y as usize * self.stride as usize + x as usize) * 4;
+        if offset + 4 > self.size {
+            return;
+        }
+
+        // SAFETY: the pixel is within the framebuffer the firmware gave us
+        unsafe {
+            core::ptr::write_volatile((self.base + offset) as *mut u32, self.format.encode(color));
+        }
+    }
+
+    /// Draws `image` with its top left corner at column `x` and row `y`. The parts of the image
+    /// that fall outside of the screen are clipped.
+    pub fn draw_bmp(&self, image: &Bmp, x: u32, y: u32) {
+        for (row_idx, row) in image.rows().enumerate() {
+            let Some(pixel_y) = y.checked_add(row_idx as u32).filter(|&py| py < self.height) else {
+                break;
+            };
+
+            for (col_idx, color) in row.enumerate() {
+                let Some(pixel_x) = x.checked_add(col_idx as u32).filter(|&px| px < self.width)
+                else {
+                    break;
+                };
+                self.put_pixel(pixel_x, pixel_y, color);
+            }
+        }
+    }
+}
+
+// Copies `bytes` into `LoaderData` pages, which the firmware leaves alone after exiting boot
+// services, and returns the copy
+fn copy_logo(bytes: &[u8]) -> Result<&'static [u8], BgrtImageError> {
+    let pages = bytes.len().div_ceil(EFI_PAGE_SIZE);
+    let addr = allocate_pages(EfiMemoryType::LoaderData, pages)
+        .map_err(BgrtImageError::OutOfMemory)?;
+
+    // SAFETY: the pages were just allocated to us, and the firmware identity maps memory
+    let buffer = unsafe { core::slice::from_raw_parts_mut(addr as *mut u8, bytes.len()) };
+    buffer.copy_from_slice(bytes);
+
+    Ok(buffer)
+}
+
+/// The boot logo the firmware drew, as described by the BGRT, kept around such that it can be
+/// drawn again after the screen is cleared, like after a mode change. The image is copied out of
+/// boot services memory into pages of our own, such that the logo can still be drawn after exiting
+/// boot services.
+#[derive(Debug, Clone, Copy)]
+pub struct BootLogo {
+    // The image, in the pages it was copied to
+    image: Bmp<'static>,
+    // Where the firmware drew the image
+    offset: (u32, u32),
+    // The resolution of the screen when the firmware drew the image
+    resolution: (u32, u32),
+}
+
+impl BootLogo {
+    /// Copies the image of `bgrt`, which the firmware drew on `framebuffer`. This must be done
+    /// before changing the video mode, as the offsets of the BGRT are relative to the resolution
+    /// at boot, and before exiting boot services, as boot services memory holds the image and the
+    /// copy is allocated from the boot services. The copy is sized from the BMP header.
+    pub fn capture(bgrt: &BGRT, framebuffer: &Framebuffer) -> Result<Self, BgrtImageError> {
+        let bytes = copy_logo(bgrt.image()?.bytes())?;
+
+        Ok(Self {
+            image: Bmp::from_bytes(bytes)?,
+            offset: bgrt.image_offset(),
+            resolution: (framebuffer.width(), framebuffer.height()),
+        })
+    }
+
+    pub fn image(&self) -> &Bmp<'_> {
+        &self.image
+    }
+
+    /// Returns where to draw the logo on a `width` by `height` screen. The firmware's offsets are
+    /// used at the boot resolution. At other resolutions, the center of the logo is kept at the
+    /// same relative position, and the logo is kept on the screen if it fits.
+    pub fn position(&self, width: u32, height: u32) -> (u32, u32) {
+        if (width, height) == self.resolution {
+            return self.offset;
+        }
+
+        let place = |offset: u32, size: u32, old: u32, new: u32| {
+            let center = (offset as u64 + size as u64 / 2) * new as u64 / old.max(1) as u64;
+            let start = center.saturating_sub(size as u64 / 2);
+            start.min(new.saturating_sub(size) as u64) as u32
+        };
+
+        (
+            place(self.offset.0, self.image.width(), self.resolution.0, width),
+            place(
+                self.offset.1,
+                self.image.height(),
+                self.resolution.1,
+                height,
+            ),
+        )
+    }
+
+    /// Draws the logo on `framebuffer`, at the position given by `position`
+    pub fn draw(&self, framebuffer: &Framebuffer) {
+        let (x, y) = self.position(framebuffer.width(), framebuffer.height());
+        framebuffer.draw_bmp(&self.image, x, y);
+    }
+}
+
+/// Switches `gop` to the video mode `mode_number` and draws `logo` again, such that the vendor
+/// logo stays on the screen through the mode change
+pub fn set_mode_keeping_logo(
+    gop: &GraphicsOutput,
+    mode_number: u32,
+    logo: &BootLogo,
+) -> Result<Framebuffer, EfiStatus> {
+    let framebuffer = gop.set_mode(mode_number)?;
+    logo.draw(&framebuffer);
+
+    Ok(framebuffer)
+}
@@ -4,15 +4,15 @@
 //! By UEFI's default memory design, EFI firmware owns the system's memory map during preboot.
 //! Key features:
 //! 1. During preboot, all components(includeing executing EFI images) must cooperate with the
-//! firmware by allocating and freeing memory from the system using the EFI's incorporated memory
-//! allocation functions.
+//!    firmware by allocating and freeing memory from the system using the EFI's incorporated
+//!    memory allocation functions.
 //! 2. During preboot, an executing EFI Image must only use the memory is has allocated.
 //! 3. Before an executing EFI image exits and returns control to the firmware, it must free
-//! all resources it has explicitly allocated. This includes all memory pages, pool allocations,
-//! open file handles, etc. Memory allocated by the firmware to load an image is freed by the
-//! firmware when the image is unloaded.
+//!    all resources it has explicitly allocated. This includes all memory pages, pool
+//!    allocations, open file handles, etc. Memory allocated by the firmware to load an image is
+//!    freed by the firmware when the image is unloaded.
 use crate::{
-    efi::{status, EFI_SYSTEM_TABLE}, print,
+    efi::{boot_services::EfiAllocateType, status, EfiStatus, EFI_SYSTEM_TABLE}, print,
 };
 use bitflags::bitflags;
 use core::sync::atomic::Ordering;
@@ -33,6 +33,12 @@ pub struct EfiMemoryManager {
 // We make a constant that is able to initialize a memory pool that is larger than 32 elements
 const INIT_MEMORY_POOL: Option<EfiMemoryDescriptor> = None;
 
+impl Default for EfiMemoryManager {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl EfiMemoryManager {
     pub fn new() -> Self {
         Self {
@@ -42,17 +48,17 @@ impl EfiMemoryManager {
 
     /// Returns the current boot services memory map and memory map key where
     /// - `memory_map_size` is a pointer to the size, in bytes, of the `memory_map` buffer.
-    /// On input, this is the size of the buffer allocated by the caller.
-    /// On output, it is the size of the buffer returned by the firmware if the buffer was large
-    /// enough, or the size of the buffer needed to contain the map if the buffer was too small.
+    ///   On input, this is the size of the buffer allocated by the caller.
+    ///   On output, it is the size of the buffer returned by the firmware if the buffer was large
+    ///   enough, or the size of the buffer needed to contain the map if the buffer was too small.
     /// - `memory_map` is a pointer to the buffer in which firmware places the current memory map.
-    /// The map is an array of `EfiMemoryDescriptor`s
+    ///   The map is an array of `EfiMemoryDescriptor`s
     /// - `map_key` is a pointer to the location in which firmware returns the key for the current
-    /// memory map.
+    ///   memory map.
     /// - `descriptor_size` is a pointer to the location in which firmware returns the size, in
-    /// bytes, of an individual `EfiMemoryDescriptor`.
+    ///   bytes, of an individual `EfiMemoryDescriptor`.
     /// - `descriptor_version` is a pointer to the location in which firmware returns the version
-    /// number associated with the `EfiMemoryDescriptor`.
+    ///   number associated with the `EfiMemoryDescriptor`.
     ///
     /// This function returns the map key obtained from a `get_memory_map` call
     pub fn get_memory_map(&mut self) -> usize {
@@ -126,25 +132,57 @@ impl EfiMemoryManager {
     pub fn free_mem_after_exit_bs(&self) -> u64 {
         // Initialize the total available memory
         let mut total_avlbl_mem = 0;
-        for maybe_entry in &self.memory_pool {
-            if let Some(entry) = maybe_entry {
-                match entry.mem_type {
-                    EfiMemoryType::BootServicesCode
-                    | EfiMemoryType::BootServicesData
-                    | EfiMemoryType::ConventionalMemory
-                    | EfiMemoryType::PersistentMemory
-                    | EfiMemoryType::LoaderCode
-                    | EfiMemoryType::LoaderData => {
-                        // Compute the total available memory
-                        total_avlbl_mem += entry.number_pages * EFI_PAGE_SIZE as u64;
-                    } 
-                    _ => {}
+        for entry in self.memory_pool.iter().flatten() {
+            match entry.mem_type {
+                EfiMemoryType::BootServicesCode
+                | EfiMemoryType::BootServicesData
+                | EfiMemoryType::ConventionalMemory
+                | EfiMemoryType::PersistentMemory
+                | EfiMemoryType::LoaderCode
+                | EfiMemoryType::LoaderData => {
+                    // Compute the total available memory
+                    total_avlbl_mem += entry.number_pages * EFI_PAGE_SIZE as u64;
                 }
+                _ => {}
             }
         }
 
         total_avlbl_mem
     }
+
+    /// Returns an iterator over all the descriptors obtained by the last `get_memory_map` call
+    pub fn descriptors(&self) -> impl Iterator<Item = &EfiMemoryDescriptor> {
+        self.memory_pool.iter().filter_map(|maybe_entry| maybe_entry.as_ref())
+    }
+}
+
+/// Allocates `pages` contiguous pages of the memory type `memory_type`, anywhere in memory, and
+/// returns the physical address of the first one. Pages allocated as `LoaderData` are still ours
+/// after exiting boot services.
+pub fn allocate_pages(memory_type: EfiMemoryType, pages: usize) -> Result<u64, EfiStatus> {
+    // Get a hold of the global EFI System Table
+    let sys_table = EFI_SYSTEM_TABLE.load(Ordering::SeqCst);
+
+    // Boot services are gone once the system table was cleared
+    if sys_table.is_null() {
+        return Err(status::EFI_NOT_READY);
+    }
+
+    let boot_services_table = unsafe { (*sys_table).boot_services };
+    let mut memory = 0;
+    let status = unsafe {
+        ((*boot_services_table).allocate_pages)(
+            EfiAllocateType::AnyPages,
+            memory_type,
+            pages,
+            &mut memory,
+        )
+    };
+
+    match status {
+        status::EFI_SUCCESS => Ok(memory),
+        status => Err(status),
+    }
 }
 
 
@@ -155,11 +193,11 @@ type EfiPhysicalAddress = u64;
 // Type that represents a UEFI Virtual Address
 type EfiVirtualAddress = u64;
 
-// Memory descriptor version number
-const _EFI_MEMORY_DESCRIPTOR_VERSION: u8 = 1;
+/// Memory descriptor version number
+pub const EFI_MEMORY_DESCRIPTOR_VERSION: u32 = 1;
 
 /// Structure that describes a single memory map entry from `EfiBootServicesTable` memory map
-#[derive(Debug)]
+#[derive(Debug, Default, Clone, Copy)]
 #[repr(C)]
 pub struct EfiMemoryDescriptor {
     // Type of the memory region
@@ -179,6 +217,53 @@ pub struct EfiMemoryDescriptor {
     attr_mask: EfiMemoryAttributes,
 }
 
+impl EfiMemoryDescriptor {
+    /// Type of the memory region
+    pub fn mem_type(&self) -> EfiMemoryType {
+        self.mem_type
+    }
+    /// Physical address of the first byte in the memory region
+    pub fn phys_start(&self) -> u64 {
+        self.phys_start
+    }
+    /// Virtual address of the first byte in the memory region
+    pub fn virt_start(&self) -> u64 {
+        self.virt_start
+    }
+    /// Number of 4KiB pages in the memory region
+    pub fn number_pages(&self) -> u64 {
+        self.number_pages
+    }
+    /// Physical address of the first byte after the memory region
+    pub fn phys_end(&self) -> u64 {
+        self.phys_start + self.number_pages * EFI_PAGE_SIZE as u64
+    }
+    /// Attributes of the memory region
+    pub fn attributes(&self) -> EfiMemoryAttributes {
+        self.attr_mask
+    }
+    /// Returns a descriptor of `number_pages` pages of type `mem_type` starting at `phys_start`
+    #[cfg(test)]
+    pub(crate) fn new(
+        mem_type: EfiMemoryType,
+        phys_start: u64,
+        number_pages: u64,
+        attr_mask: EfiMemoryAttributes,
+    ) -> Self {
+        Self {
+            mem_type,
+            phys_start,
+            virt_start: 0,
+            number_pages,
+            attr_mask,
+        }
+    }
+    /// Returns a copy of this descriptor, which maps the memory region at `virt_start`
+    pub fn with_virt_start(&self, virt_start: u64) -> Self {
+        Self { virt_start, ..*self }
+    }
+}
+
 impl From<u32> for EfiMemoryType {
     fn from(value: u32) -> Self {
         match value {
@@ -205,13 +290,14 @@ impl From<u32> for EfiMemoryType {
 /// Structure that describes the types of memory from the system, according to the UEFI Memory Map
 /// Each memory type has one purpose BEFORE exiting Boot Services and another one after exiting
 /// Boot Services
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
 #[repr(u32)]
 pub enum EfiMemoryType {
     /// Before exiting Boot Sevices
     /// Not usable.
     /// After exiting Boot Services
     /// Not usable.
+    #[default]
     ReservedMemoryType = 0,
     /// Before exiting Boot Sevices
     /// The code portions of a loaded UEFI application.
@@ -305,8 +391,8 @@ pub enum EfiMemoryType {
 }
 
 bitflags! {
-    #[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
-    struct EfiMemoryAttributes: u64 {
+    #[derive(Default, Clone, Copy, Debug, PartialEq, Eq, Hash)]
+    pub struct EfiMemoryAttributes: u64 {
         //
         // Memory cacheability attribues
         //
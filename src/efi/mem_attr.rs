@@ -0,0 +1,519 @@
+//! Module that parses the EFI Memory Attributes Table and the, now deprecated, EFI Properties
+//! Table, which firmware installs in the configuration table to describe how the memory of
+//! runtime services images is laid out.
+//!
+//! A runtime services image is reported in the UEFI memory map as a single `RuntimeServicesCode`
+//! region, even though it contains both code and data sections. The Memory Attributes Table splits
+//! such regions in sub-regions, each of them being either read-only (`RO`) or non-executable
+//! (`XP`), such that an OS can map runtime services with W^X permissions before calling
+//! `SetVirtualAddressMap()`.
+use crate::efi::{
+    find_config_table,
+    malloc::{EfiMemoryAttributes, EfiMemoryDescriptor, EfiMemoryManager, EfiMemoryType},
+};
+use bitflags::bitflags;
+use core::mem::size_of;
+
+/// GUID for the EFI Memory Attributes Table, as reported by the UEFI System Table
+pub const EFI_MEMORY_ATTRIBUTES_TABLE_GUID: u128 = 0x201246dc_b73820a2_469f26eb_dcfa911d;
+
+/// GUID for the EFI Properties Table, as reported by the UEFI System Table
+pub const EFI_PROPERTIES_TABLE_GUID: u128 = 0xe5250834_47b77990_4a044adc_880aaca3;
+
+/// The maximum number of runtime regions we keep track of
+pub const MAX_RUNTIME_REGIONS: usize = 128;
+
+/// Header of the EFI Memory Attributes Table. It is followed by `nentries` memory descriptors,
+/// each of them being `descriptor_size` bytes long.
+#[derive(Debug, Clone, Copy)]
+#[repr(C)]
+struct EfiMemoryAttributesTableHeader {
+    // Version of the table. Version 1 is the original one, while version 2 also defines the
+    // `flags` field
+    version: u32,
+    // Number of memory descriptors that follow the header
+    nentries: u32,
+    // Size in bytes of a single memory descriptor. This may be larger than the size of the
+    // `EfiMemoryDescriptor` structure
+    descriptor_size: u32,
+    // Flags for the table. This field was reserved in version 1
+    flags: MemoryAttributesTableFlags,
+}
+
+bitflags! {
+    #[repr(transparent)]
+    #[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+    pub struct MemoryAttributesTableFlags: u32 {
+        /// Runtime services code regions have been compiled with forward control flow guard
+        /// instructions (Intel CET's IBT or ARM's BTI)
+        const RT_FORWARD_CONTROL_FLOW_GUARD = 0x0000_0001;
+    }
+}
+
+/// The EFI Memory Attributes Table, which describes the permissions of the memory regions used
+/// by the runtime services images
+pub struct MemoryAttributesTable {
+    // Physical address of the table
+    addr: usize,
+    // Header of the table
+    header: EfiMemoryAttributesTableHeader,
+}
+
+impl MemoryAttributesTable {
+    /// Reads the Memory Attributes Table from `addr`. Returns `None` if the table reports a
+    /// version we do not know about, or descriptors smaller than our `EfiMemoryDescriptor`
+    pub fn from_addr(addr: usize) -> Option<Self> {
+        let header =
+            unsafe { core::ptr::read_unaligned(addr as *const EfiMemoryAttributesTableHeader) };
+
+        if !(1..=2).contains(&header.version)
+            || (header.descriptor_size as usize) < size_of::<EfiMemoryDescriptor>()
+        {
+            return None;
+        }
+
+        Some(Self { addr, header })
+    }
+
+    /// Looks up the Memory Attributes Table in the EFI configuration table and reads it
+    pub fn find() -> Option<Self> {
+        find_config_table(EFI_MEMORY_ATTRIBUTES_TABLE_GUID).and_then(Self::from_addr)
+    }
+
+    /// Returns the flags of the table
+    pub fn flags(&self) -> MemoryAttributesTableFlags {
+        self.header.flags
+    }
+
+    /// Returns an iterator over the memory descriptors that are stored in the table
+    pub fn entries(&self) -> impl Iterator<Item = EfiMemoryDescriptor> + '_ {
+        let entries_addr = self.addr + size_of::<EfiMemoryAttributesTableHeader>();
+        let descriptor_size = self.header.descriptor_size as usize;
+
+        (0..self.header.nentries as usize).map(move |idx| unsafe {
+            core::ptr::read_unaligned(
+                (entries_addr + idx * descriptor_size) as *const EfiMemoryDescriptor,
+            )
+        })
+    }
+}
+
+/// The EFI Properties Table. This table is deprecated in favor of the Memory Attributes Table,
+/// and should only be used when the latter is not present.
+#[derive(Debug, Clone, Copy)]
+#[repr(C)]
+pub struct EfiPropertiesTable {
+    // Version of the table
+    version: u32,
+    // Length of the entire table, in bytes
+    length: u32,
+    // Memory protection policy applied to runtime services images
+    memory_protection_attribute: PropertiesMemoryProtection,
+}
+
+bitflags! {
+    #[repr(transparent)]
+    #[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+    pub struct PropertiesMemoryProtection: u64 {
+        /// The code and data sections of runtime services images are placed in separate
+        /// `RuntimeServicesCode` and `RuntimeServicesData` regions, and the data sections are not
+        /// executable
+        const NON_EXECUTABLE_PE_DATA = 0x0000_0000_0000_0001;
+    }
+}
+
+impl EfiPropertiesTable {
+    /// Looks up the Properties Table in the EFI configuration table and reads it
+    pub fn find() -> Option<Self> {
+        let addr = find_config_table(EFI_PROPERTIES_TABLE_GUID)?;
+        Some(unsafe { core::ptr::read_unaligned(addr as *const EfiPropertiesTable) })
+    }
+
+    /// Returns the memory protection policy reported by the table
+    pub fn memory_protection(&self) -> PropertiesMemoryProtection {
+        self.memory_protection_attribute
+    }
+}
+
+bitflags! {
+    /// Page permissions a kernel should use when mapping a runtime region
+    #[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+    pub struct RegionPermissions: u8 {
+        const READ = 0b001;
+        const WRITE = 0b010;
+        const EXECUTE = 0b100;
+    }
+}
+
+impl RegionPermissions {
+    /// Derives the permissions from the protection attributes of a memory descriptor
+    fn from_attributes(attr: EfiMemoryAttributes) -> Self {
+        let mut perms = Self::all();
+
+        if attr.contains(EfiMemoryAttributes::RP) {
+            perms.remove(Self::READ);
+        }
+        if attr.contains(EfiMemoryAttributes::RO) {
+            perms.remove(Self::WRITE);
+        }
+        if attr.contains(EfiMemoryAttributes::XP) {
+            perms.remove(Self::EXECUTE);
+        }
+
+        perms
+    }
+
+    /// Returns the permissions a region of type `mem_type` must have, when no Memory Attributes
+    /// Table entry describes it. If `split_pe_data` is set, the firmware told us, through the
+    /// Properties Table, that runtime images have their code and data in separate regions.
+    fn default_for(mem_type: EfiMemoryType, split_pe_data: bool) -> Self {
+        match mem_type {
+            EfiMemoryType::RuntimeServicesCode if split_pe_data => Self::READ | Self::EXECUTE,
+            // Without any information, a runtime code region may also hold the image's data
+            EfiMemoryType::RuntimeServicesCode => Self::all(),
+            _ => Self::READ | Self::WRITE,
+        }
+    }
+
+    /// Returns whether a region with these permissions is both writable and executable
+    pub fn is_wx(&self) -> bool {
+        self.contains(Self::WRITE | Self::EXECUTE)
+    }
+}
+
+/// A memory region used by the runtime services, alongside the permissions it should be mapped
+/// with
+#[derive(Debug, Clone, Copy)]
+pub struct RuntimeRegion {
+    // Type of the memory region, as reported by the UEFI memory map
+    mem_type: EfiMemoryType,
+    // Physical address of the first byte in the region
+    phys_start: u64,
+    // Number of 4KiB pages in the region
+    number_pages: u64,
+    // Permissions the region should be mapped with
+    permissions: RegionPermissions,
+    // Whether the permissions come from the Memory Attributes Table, or were derived from the
+    // region's type
+    from_mat: bool,
+}
+
+impl RuntimeRegion {
+    pub fn mem_type(&self) -> EfiMemoryType {
+        self.mem_type
+    }
+    pub fn phys_start(&self) -> u64 {
+        self.phys_start
+    }
+    pub fn number_pages(&self) -> u64 {
+        self.number_pages
+    }
+    pub fn permissions(&self) -> RegionPermissions {
+        self.permissions
+    }
+    pub fn from_mat(&self) -> bool {
+        self.from_mat
+    }
+}
+
+/// All the runtime memory regions from the UEFI memory map, split according to the Memory
+/// Attributes Table, such that each region can be mapped with its own permissions.
+pub struct RuntimeRegions {
+    regions: [Option<RuntimeRegion>; MAX_RUNTIME_REGIONS],
+    nregions: usize,
+}
+
+// We make a constant that is able to initialize an array larger than 32 elements
+const INIT_RUNTIME_REGION: Option<RuntimeRegion> = None;
+
+impl RuntimeRegions {
+    /// Correlates the descriptors with the `RUNTIME` attribute from `mem_manager`'s memory map
+    /// with the Memory Attributes Table, and, if the latter is missing, with the Properties Table.
+    pub fn new(mem_manager: &EfiMemoryManager) -> Self {
+        Self::from_tables(
+            mem_manager.descriptors(),
+            MemoryAttributesTable::find(),
+            EfiPropertiesTable::find(),
+        )
+    }
+
+    // Splits the runtime descriptors of `descriptors` according to `mat`, or according to
+    // `properties` when there is no Memory Attributes Table
+    fn from_tables<'a>(
+        descriptors: impl Iterator<Item = &'a EfiMemoryDescriptor>,
+        mat: Option<MemoryAttributesTable>,
+        properties: Option<EfiPropertiesTable>,
+    ) -> Self {
+        // The Properties Table is only consulted if there is no Memory Attributes Table
+        let split_pe_data = mat.is_none()
+            && properties.is_some_and(|props| {
+                props
+                    .memory_protection()
+                    .contains(PropertiesMemoryProtection::NON_EXECUTABLE_PE_DATA)
+            });
+
+        let mut runtime_regions = Self {
+            regions: [INIT_RUNTIME_REGION; MAX_RUNTIME_REGIONS],
+            nregions: 0,
+        };
+
+        for desc in
+            descriptors.filter(|desc| desc.attributes().contains(EfiMemoryAttributes::RUNTIME))
+        {
+            let default_perms = RegionPermissions::default_for(desc.mem_type(), split_pe_data);
+            // Address up to which the descriptor has been described by regions
+            let mut cursor = desc.phys_start();
+
+            // The Memory Attributes Table entries are sorted in ascending order, so we can fill
+            // the gaps between them as we go
+            for entry in mat.iter().flat_map(|mat| mat.entries()) {
+                let start = entry.phys_start().max(cursor);
+                let end = entry.phys_end().min(desc.phys_end());
+
+                if start >= end {
+                    continue;
+                }
+
+                if start > cursor {
+                    runtime_regions.push(desc.mem_type(), cursor, start, default_perms, false);
+                }
+
+                let perms = RegionPermissions::from_attributes(entry.attributes());
+                runtime_regions.push(entry.mem_type(), start, end, perms, true);
+                cursor = end;
+            }
+
+            if cursor < desc.phys_end() {
+                runtime_regions.push(desc.mem_type(), cursor, desc.phys_end(), default_perms, false);
+            }
+        }
+
+        runtime_regions
+    }
+
+    // Records a new region spanning [`start`, `end`). Regions that do not fit are dropped, which
+    // we report, as it means the kernel will not map them.
+    fn push(
+        &mut self,
+        mem_type: EfiMemoryType,
+        start: u64,
+        end: u64,
+        permissions: RegionPermissions,
+        from_mat: bool,
+    ) {
+        let Some(slot) = self.regions.get_mut(self.nregions) else {
+            crate::print!("Too many runtime regions, dropping {:#x}-{:#x}\n", start, end);
+            return;
+        };
+
+        *slot = Some(RuntimeRegion {
+            mem_type,
+            phys_start: start,
+            number_pages: (end - start) / crate::efi::malloc::EFI_PAGE_SIZE as u64,
+            permissions,
+            from_mat,
+        });
+        self.nregions += 1;
+    }
+
+    /// Returns an iterator over all the runtime regions, in the order of the memory map
+    pub fn iter(&self) -> impl Iterator<Item = &RuntimeRegion> {
+        self.regions[..self.nregions].iter().filter_map(|region| region.as_ref())
+    }
+
+    /// Returns the permissions of the region containing the physical address `addr`
+    pub fn permissions_at(&self, addr: u64) -> Option<RegionPermissions> {
+        self.iter()
+            .find(|region| {
+                let end = region.phys_start
+                    + region.number_pages * crate::efi::malloc::EFI_PAGE_SIZE as u64;
+                (region.phys_start..end).contains(&addr)
+            })
+            .map(|region| region.permissions)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::efi::malloc::EFI_PAGE_SIZE;
+
+    const PAGE: u64 = EFI_PAGE_SIZE as u64;
+    // Where the runtime image we describe is loaded
+    const IMAGE: u64 = 0x7F00_0000;
+
+    // Returns a runtime descriptor of `pages` pages at `start`
+    fn runtime(mem_type: EfiMemoryType, start: u64, pages: u64) -> EfiMemoryDescriptor {
+        let attr = EfiMemoryAttributes::RUNTIME | EfiMemoryAttributes::WB;
+        EfiMemoryDescriptor::new(mem_type, start, pages, attr)
+    }
+
+    // Returns a Memory Attributes Table of version `version` holding `entries`, each of them
+    // taking `descriptor_size` bytes
+    fn mat_bytes(version: u32, descriptor_size: u32, entries: &[EfiMemoryDescriptor]) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        for field in [version, entries.len() as u32, descriptor_size, 0] {
+            bytes.extend_from_slice(&field.to_le_bytes());
+        }
+        for entry in entries {
+            let mut desc = vec![0; size_of::<EfiMemoryDescriptor>()];
+            desc[0..4].copy_from_slice(&(entry.mem_type() as u32).to_le_bytes());
+            desc[8..16].copy_from_slice(&entry.phys_start().to_le_bytes());
+            desc[24..32].copy_from_slice(&entry.number_pages().to_le_bytes());
+            desc[32..40].copy_from_slice(&entry.attributes().bits().to_le_bytes());
+            desc.resize(descriptor_size as usize, 0);
+            bytes.extend_from_slice(&desc);
+        }
+        bytes
+    }
+
+    fn properties(memory_protection: PropertiesMemoryProtection) -> EfiPropertiesTable {
+        EfiPropertiesTable {
+            version: 0x0001_0000,
+            length: size_of::<EfiPropertiesTable>() as u32,
+            memory_protection_attribute: memory_protection,
+        }
+    }
+
+    // Returns the type, start, pages, permissions and origin of every region
+    fn layout(regions: &RuntimeRegions) -> Vec<(EfiMemoryType, u64, u64, RegionPermissions, bool)> {
+        regions
+            .iter()
+            .map(|region| {
+                (
+                    region.mem_type(),
+                    region.phys_start(),
+                    region.number_pages(),
+                    region.permissions(),
+                    region.from_mat(),
+                )
+            })
+            .collect()
+    }
+
+    const CODE: EfiMemoryType = EfiMemoryType::RuntimeServicesCode;
+    const DATA: EfiMemoryType = EfiMemoryType::RuntimeServicesData;
+    const RW: RegionPermissions = RegionPermissions::READ.union(RegionPermissions::WRITE);
+    const RX: RegionPermissions = RegionPermissions::READ.union(RegionPermissions::EXECUTE);
+    const RWX: RegionPermissions = RegionPermissions::all();
+
+    #[test]
+    fn mat_splits_code_and_data() {
+        let descriptors = [
+            EfiMemoryDescriptor::new(
+                EfiMemoryType::ConventionalMemory,
+                0,
+                16,
+                EfiMemoryAttributes::WB,
+            ),
+            runtime(CODE, IMAGE, 4),
+            runtime(DATA, IMAGE + 4 * PAGE, 2),
+        ];
+        let entries = [
+            // The headers and the code of the image
+            EfiMemoryDescriptor::new(
+                CODE,
+                IMAGE,
+                2,
+                EfiMemoryAttributes::RUNTIME | EfiMemoryAttributes::RO,
+            ),
+            // Its data, leaving its last page undescribed
+            EfiMemoryDescriptor::new(
+                DATA,
+                IMAGE + 2 * PAGE,
+                1,
+                EfiMemoryAttributes::RUNTIME | EfiMemoryAttributes::XP,
+            ),
+            // An entry that goes past the end of the data descriptor is cut at its end
+            EfiMemoryDescriptor::new(
+                DATA,
+                IMAGE + 5 * PAGE,
+                3,
+                EfiMemoryAttributes::RUNTIME | EfiMemoryAttributes::XP,
+            ),
+            // An entry outside of every descriptor is ignored
+            EfiMemoryDescriptor::new(
+                CODE,
+                0x1000_0000,
+                1,
+                EfiMemoryAttributes::RUNTIME | EfiMemoryAttributes::RO,
+            ),
+        ];
+        // Firmware commonly uses descriptors larger than ours
+        let bytes = mat_bytes(1, 48, &entries);
+        let mat = MemoryAttributesTable::from_addr(bytes.as_ptr() as usize).unwrap();
+        assert_eq!(mat.entries().count(), 4);
+
+        // The Properties Table is not looked at when there is a Memory Attributes Table
+        let props = properties(PropertiesMemoryProtection::NON_EXECUTABLE_PE_DATA);
+        let regions = RuntimeRegions::from_tables(descriptors.iter(), Some(mat), Some(props));
+
+        assert_eq!(
+            layout(&regions),
+            [
+                (CODE, IMAGE, 2, RX, true),
+                (DATA, IMAGE + 2 * PAGE, 1, RW, true),
+                (CODE, IMAGE + 3 * PAGE, 1, RWX, false),
+                (DATA, IMAGE + 4 * PAGE, 1, RW, false),
+                (DATA, IMAGE + 5 * PAGE, 1, RW, true),
+            ]
+        );
+        assert_eq!(regions.permissions_at(IMAGE + PAGE + 8), Some(RX));
+        assert_eq!(regions.permissions_at(IMAGE + 3 * PAGE), Some(RWX));
+        assert!(regions.permissions_at(IMAGE + 3 * PAGE).unwrap().is_wx());
+        assert_eq!(regions.permissions_at(IMAGE + 6 * PAGE), None);
+        assert_eq!(regions.permissions_at(0x1000), None);
+    }
+
+    #[test]
+    fn properties_table_fallback() {
+        let descriptors = [runtime(CODE, IMAGE, 4), runtime(DATA, IMAGE + 4 * PAGE, 2)];
+
+        // The firmware keeps the data of its images out of their code regions
+        let props = properties(PropertiesMemoryProtection::NON_EXECUTABLE_PE_DATA);
+        let regions = RuntimeRegions::from_tables(descriptors.iter(), None, Some(props));
+        assert_eq!(
+            layout(&regions),
+            [
+                (CODE, IMAGE, 4, RX, false),
+                (DATA, IMAGE + 4 * PAGE, 2, RW, false),
+            ]
+        );
+
+        // A Properties Table without the policy tells us nothing
+        let props = properties(PropertiesMemoryProtection::empty());
+        let regions = RuntimeRegions::from_tables(descriptors.iter(), None, Some(props));
+        assert_eq!(regions.permissions_at(IMAGE), Some(RWX));
+    }
+
+    #[test]
+    fn missing_mat() {
+        let descriptors = [
+            runtime(CODE, IMAGE, 4),
+            runtime(DATA, IMAGE + 4 * PAGE, 2),
+            EfiMemoryDescriptor::new(
+                EfiMemoryType::BootServicesCode,
+                IMAGE + 6 * PAGE,
+                2,
+                EfiMemoryAttributes::WB,
+            ),
+        ];
+
+        // Without any table, code regions may also hold data
+        let regions = RuntimeRegions::from_tables(descriptors.iter(), None, None);
+        assert_eq!(
+            layout(&regions),
+            [
+                (CODE, IMAGE, 4, RWX, false),
+                (DATA, IMAGE + 4 * PAGE, 2, RW, false),
+            ]
+        );
+
+        // Tables we cannot read are as good as missing
+        let entry = runtime(CODE, IMAGE, 4);
+        for (version, descriptor_size) in [(0, 48), (3, 48), (1, 32)] {
+            let bytes = mat_bytes(version, descriptor_size, &[entry]);
+            assert!(MemoryAttributesTable::from_addr(bytes.as_ptr() as usize).is_none());
+        }
+    }
+}
@@ -0,0 +1,404 @@
+//! Module that handles all of the EFI Runtime Services table functions.
+//!
+//! Unlike the boot services, runtime services remain available after `exit_boot_services`.
+//! However, they are linked against the physical addresses the firmware loaded them at, so once
+//! the OS installs its own page tables, it has to tell the firmware the new virtual addresses of
+//! every `RUNTIME` memory region through `SetVirtualAddressMap()`.
+use crate::efi::{
+    malloc::{
+        EfiMemoryAttributes, EfiMemoryDescriptor, EfiMemoryManager,
+        EFI_MEMORY_DESCRIPTOR_VERSION, EFI_PAGE_SIZE,
+    },
+    status, EfiStatus, EfiSystemTable, EfiTableHeader, EFI_SYSTEM_TABLE,
+};
+use core::mem::size_of;
+use core::sync::atomic::{AtomicBool, AtomicPtr, Ordering};
+
+/// Signature for the `EfiRuntimeServicesTable` structure
+pub const EFI_RUNTIME_SERVICES_SIGNATURE: u64 = 0x5652_4553_544e_5552;
+
+/// The maximum number of `RUNTIME` descriptors we can pass to `SetVirtualAddressMap()`
+pub const MAX_RUNTIME_DESCRIPTORS: usize = 128;
+
+/// Pointer to the EFI System Table that stays valid after exiting boot services. Contrary to
+/// `EFI_SYSTEM_TABLE`, this is not cleared by `exit_boot_services`.
+pub static EFI_RUNTIME_SYSTEM_TABLE: AtomicPtr<EfiSystemTable> =
+    AtomicPtr::new(core::ptr::null_mut());
+
+/// Pointer to the EFI Runtime Services Table
+pub static EFI_RUNTIME_SERVICES: AtomicPtr<EfiRuntimeServicesTable> =
+    AtomicPtr::new(core::ptr::null_mut());
+
+// Whether `SetVirtualAddressMap()` was already called. The firmware only accepts it once.
+static VIRTUAL_MODE: AtomicBool = AtomicBool::new(false);
+
+/// Represents the EFI Runtime Services Table, which contains a table header and pointers to all
+/// of the runtime services as described in the Runtime Services chapter from any UEFI Spec.
+#[repr(C)]
+pub struct EfiRuntimeServicesTable {
+    /// Header for this table
+    pub hdr: EfiTableHeader,
+    //
+    // Time Services
+    //
+    // Returns the current time and date information, and the time-keeping capabilities of the
+    // hardware platform
+    get_time: extern "efiapi" fn(
+        time: *mut EfiTime,
+        capabilities: *mut EfiTimeCapabilities,
+    ) -> EfiStatus,
+    _set_time: usize,
+    _get_wakeup_time: usize,
+    _set_wakeup_time: usize,
+    //
+    // Virtual Memory Services
+    //
+    // Changes the runtime addressing mode of EFI firmware from physical to virtual
+    set_virtual_address_map: extern "efiapi" fn(
+        memory_map_size: usize,
+        descriptor_size: usize,
+        descriptor_version: u32,
+        virtual_map: *const EfiMemoryDescriptor,
+    ) -> EfiStatus,
+    _convert_pointer: usize,
+    //
+    // Variable Services
+    //
+    _get_variable: usize,
+    _get_next_variable_name: usize,
+    _set_variable: usize,
+    //
+    // Miscellaneous Services
+    //
+    _get_next_high_monotonic_count: usize,
+    // Resets the entire platform
+    reset_system: extern "efiapi" fn(
+        reset_type: EfiResetType,
+        reset_status: EfiStatus,
+        data_size: usize,
+        reset_data: *const u16,
+    ) -> !,
+    //
+    // UEFI 2.0 Capsule Services
+    //
+    _update_capsule: usize,
+    _query_capsule_capabilities: usize,
+    //
+    // Miscellaneous UEFI 2.0 Service
+    //
+    _query_variable_info: usize,
+}
+
+/// Represents the current time information, as returned by `get_time`
+#[derive(Debug, Default, Clone, Copy)]
+#[repr(C)]
+pub struct EfiTime {
+    /// 1900 - 9999
+    pub year: u16,
+    /// 1 - 12
+    pub month: u8,
+    /// 1 - 31
+    pub day: u8,
+    /// 0 - 23
+    pub hour: u8,
+    /// 0 - 59
+    pub minute: u8,
+    /// 0 - 59
+    pub second: u8,
+    pad1: u8,
+    /// 0 - 999,999,999
+    pub nanosecond: u32,
+    /// The time's offset in minutes from UTC, in the range -1440 to 1440, or 2047 if the time is
+    /// interpreted as local time
+    pub time_zone: i16,
+    /// A bitmask containing the daylight savings time information for the time
+    pub daylight: u8,
+    pad2: u8,
+}
+
+/// Provides the capabilities of the real time clock device, as exposed through the `EfiTime`
+#[derive(Debug, Default, Clone, Copy)]
+#[repr(C)]
+pub struct EfiTimeCapabilities {
+    /// Resolution of the real time clock device, in counts per second
+    pub resolution: u32,
+    /// Accuracy of the real time clock device, in parts per million (times 1,000,000)
+    pub accuracy: u32,
+    /// Whether setting the time clears the device's time below the resolution reporting level
+    pub sets_to_zero: bool,
+}
+
+/// The type of reset to perform through `reset_system`
+#[derive(Debug, Clone, Copy)]
+#[repr(u32)]
+pub enum EfiResetType {
+    /// Causes a system-wide reset, setting all circuitry within the system to its initial state
+    Cold = 0,
+    /// Causes a system-wide initialization, where processors are reset but the system's memory
+    /// may be preserved
+    Warm,
+    /// Causes the system to enter a power state equivalent to the ACPI G2/S5 or G3 states
+    Shutdown,
+    /// Causes a system-wide reset, whose exact type is described by the reset data
+    PlatformSpecific,
+}
+
+/// How `set_virtual_address_map` assigns virtual addresses to the runtime regions
+#[derive(Debug, Clone, Copy)]
+pub enum VirtualMapping {
+    /// Each runtime region is mapped at its physical address
+    Identity,
+    /// Each runtime region is mapped at its physical address plus the given offset, which must be
+    /// a multiple of the page size, e.g.: a higher-half base
+    Offset(u64),
+}
+
+impl VirtualMapping {
+    // Returns the virtual address of the physical address `phys`
+    fn virt_addr(&self, phys: u64) -> u64 {
+        match self {
+            Self::Identity => phys,
+            Self::Offset(offset) => phys.wrapping_add(*offset),
+        }
+    }
+}
+
+/// Stores the runtime services pointers from `system_table`, such that they stay available after
+/// exiting boot services. Fails with `EFI_INVALID_PARAMETER` if the table it points to does not
+/// have the runtime services signature.
+///
+/// # Safety
+///
+/// `system_table` must point to the EFI System Table the firmware handed to the image, whose
+/// runtime services pointer is valid. Both stay in use for as long as the runtime services are
+/// called.
+pub unsafe fn initialize_runtime_services(
+    system_table: *mut EfiSystemTable,
+) -> Result<(), EfiStatus> {
+    let runtime_services = unsafe { (*system_table).runtime_services };
+
+    // Make sure we were not handed something that is not a runtime services table
+    let signature = unsafe { (*runtime_services).hdr.signature() };
+    if signature != EFI_RUNTIME_SERVICES_SIGNATURE {
+        return Err(status::EFI_INVALID_PARAMETER);
+    }
+
+    EFI_RUNTIME_SYSTEM_TABLE.store(system_table, Ordering::SeqCst);
+    EFI_RUNTIME_SERVICES.store(runtime_services as *mut _, Ordering::SeqCst);
+
+    Ok(())
+}
+
+/// Returns the current time and date information, as given by the platform's real time clock
+pub fn get_time() -> Result<EfiTime, EfiStatus> {
+    let runtime_services = EFI_RUNTIME_SERVICES.load(Ordering::SeqCst);
+
+    if runtime_services.is_null() {
+        return Err(status::EFI_NOT_STARTED);
+    }
+
+    let mut time = EfiTime::default();
+    let status =
+        unsafe { ((*runtime_services).get_time)(&mut time, core::ptr::null_mut()) };
+
+    match status {
+        status::EFI_SUCCESS => Ok(time),
+        _ => Err(status),
+    }
+}
+
+/// Resets the entire platform, according to `reset_type`. If the runtime services are not
+/// available, we just hang.
+pub fn reset_system(reset_type: EfiResetType, reset_status: EfiStatus) -> ! {
+    let runtime_services = EFI_RUNTIME_SERVICES.load(Ordering::SeqCst);
+
+    if runtime_services.is_null() {
+        loop {
+            core::hint::spin_loop();
+        }
+    }
+
+    unsafe { ((*runtime_services).reset_system)(reset_type, reset_status, 0, core::ptr::null()) }
+}
+
+/// Assigns a virtual address, according to `mapping`, to every descriptor from the memory map of
+/// `mem_manager` that has the `RUNTIME` attribute, and switches the runtime services to virtual
+/// mode, using these addresses. The memory map must be the one that was used to exit boot
+/// services, as the firmware checks it against its own.
+///
+/// On success, the cached system table and runtime services table pointers are converted to
+/// their new virtual addresses. Calling any runtime service afterwards requires the current page
+/// tables to map the runtime regions at these addresses.
+pub fn set_virtual_address_map(
+    mem_manager: &EfiMemoryManager,
+    mapping: VirtualMapping,
+) -> Result<(), EfiStatus> {
+    if let VirtualMapping::Offset(offset) = mapping {
+        if offset % EFI_PAGE_SIZE as u64 != 0 {
+            return Err(status::EFI_INVALID_PARAMETER);
+        }
+    }
+
+    let runtime_services = EFI_RUNTIME_SERVICES.load(Ordering::SeqCst);
+
+    if runtime_services.is_null() {
+        return Err(status::EFI_NOT_STARTED);
+    }
+
+    // The firmware only allows switching to virtual mode once
+    if VIRTUAL_MODE.load(Ordering::SeqCst) {
+        return Err(status::EFI_UNSUPPORTED);
+    }
+
+    // Build the virtual map, which only needs to contain the runtime regions
+    let mut descriptors = [EfiMemoryDescriptor::default(); MAX_RUNTIME_DESCRIPTORS];
+    let nentries = build_virtual_map(mem_manager.descriptors(), mapping, &mut descriptors)?;
+
+    let status = unsafe {
+        ((*runtime_services).set_virtual_address_map)(
+            nentries * size_of::<EfiMemoryDescriptor>(),
+            size_of::<EfiMemoryDescriptor>(),
+            EFI_MEMORY_DESCRIPTOR_VERSION,
+            descriptors.as_ptr(),
+        )
+    };
+
+    if status != status::EFI_SUCCESS {
+        return Err(status);
+    }
+
+    VIRTUAL_MODE.store(true, Ordering::SeqCst);
+
+    // Now that the firmware runs in virtual mode, our cached pointers into runtime memory must be
+    // converted too. The system table and runtime services table live in runtime data regions.
+    let virtual_map = &descriptors[..nentries];
+    for table in [&EFI_RUNTIME_SYSTEM_TABLE, &EFI_SYSTEM_TABLE] {
+        convert_pointer(table, virtual_map);
+    }
+    convert_pointer(&EFI_RUNTIME_SERVICES, virtual_map);
+
+    Ok(())
+}
+
+// Fills `virtual_map` with the descriptors of `descriptors` that have the `RUNTIME` attribute,
+// each one with the virtual address `mapping` assigns to it, and returns how many there are
+fn build_virtual_map<'a>(
+    descriptors: impl Iterator<Item = &'a EfiMemoryDescriptor>,
+    mapping: VirtualMapping,
+    virtual_map: &mut [EfiMemoryDescriptor],
+) -> Result<usize, EfiStatus> {
+    let mut nentries = 0;
+
+    for desc in descriptors.filter(|desc| desc.attributes().contains(EfiMemoryAttributes::RUNTIME))
+    {
+        let slot = virtual_map
+            .get_mut(nentries)
+            .ok_or(status::EFI_OUT_OF_RESOURCES)?;
+        *slot = desc.with_virt_start(mapping.virt_addr(desc.phys_start()));
+        nentries += 1;
+    }
+
+    Ok(nentries)
+}
+
+// Converts the physical address held by `ptr` to the virtual address assigned to it by
+// `virtual_map`. Null pointers and pointers outside of any runtime region are left untouched.
+fn convert_pointer<T>(ptr: &AtomicPtr<T>, virtual_map: &[EfiMemoryDescriptor]) {
+    let phys = ptr.load(Ordering::SeqCst) as u64;
+
+    if phys == 0 {
+        return;
+    }
+
+    let maybe_desc = virtual_map
+        .iter()
+        .find(|desc| (desc.phys_start()..desc.phys_end()).contains(&phys));
+
+    if let Some(desc) = maybe_desc {
+        let virt = desc.virt_start() + (phys - desc.phys_start());
+        ptr.store(virt as *mut T, Ordering::SeqCst);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::efi::malloc::EfiMemoryType;
+
+    // A higher-half base, as a kernel would map the runtime regions at
+    const OFFSET: u64 = 0xFFFF_8000_0000_0000;
+
+    // Returns a memory map with runtime code and data regions around conventional memory
+    fn memory_map() -> [EfiMemoryDescriptor; 3] {
+        let runtime = EfiMemoryAttributes::RUNTIME;
+        [
+            EfiMemoryDescriptor::new(EfiMemoryType::RuntimeServicesCode, 0x7F00_0000, 4, runtime),
+            EfiMemoryDescriptor::new(
+                EfiMemoryType::ConventionalMemory,
+                0x7F00_4000,
+                0x7FC,
+                EfiMemoryAttributes::empty(),
+            ),
+            EfiMemoryDescriptor::new(EfiMemoryType::RuntimeServicesData, 0x7F80_0000, 2, runtime),
+        ]
+    }
+
+    #[test]
+    fn offset_mapping() {
+        let mut virtual_map = [EfiMemoryDescriptor::default(); MAX_RUNTIME_DESCRIPTORS];
+        let mapping = VirtualMapping::Offset(OFFSET);
+        let nentries = build_virtual_map(memory_map().iter(), mapping, &mut virtual_map).unwrap();
+
+        // Only the runtime regions are in the map, at their physical address plus the offset
+        let virtual_map = &virtual_map[..nentries];
+        let regions: Vec<(u64, u64, u64)> = virtual_map
+            .iter()
+            .map(|desc| (desc.phys_start(), desc.virt_start(), desc.number_pages()))
+            .collect();
+        assert_eq!(
+            regions,
+            [
+                (0x7F00_0000, 0xFFFF_8000_7F00_0000, 4),
+                (0x7F80_0000, 0xFFFF_8000_7F80_0000, 2)
+            ]
+        );
+
+        // Pointers into a runtime region move with it, other ones are left alone
+        let table = AtomicPtr::new(0x7F80_1FF8 as *mut u64);
+        convert_pointer(&table, virtual_map);
+        assert_eq!(table.load(Ordering::SeqCst) as u64, 0xFFFF_8000_7F80_1FF8);
+        let table = AtomicPtr::new(0x7F80_2000 as *mut u64);
+        convert_pointer(&table, virtual_map);
+        assert_eq!(table.load(Ordering::SeqCst) as u64, 0x7F80_2000);
+        let table = AtomicPtr::new(core::ptr::null_mut::<u64>());
+        convert_pointer(&table, virtual_map);
+        assert!(table.load(Ordering::SeqCst).is_null());
+    }
+
+    #[test]
+    fn mapping_limits() {
+        // The identity mapping keeps the physical addresses
+        let mut virtual_map = [EfiMemoryDescriptor::default(); MAX_RUNTIME_DESCRIPTORS];
+        let mapping = VirtualMapping::Identity;
+        assert_eq!(
+            build_virtual_map(memory_map().iter(), mapping, &mut virtual_map),
+            Ok(2)
+        );
+        assert_eq!(virtual_map[1].virt_start(), 0x7F80_0000);
+
+        // Runtime regions that do not fit in the map
+        let mapping = VirtualMapping::Offset(OFFSET);
+        assert_eq!(
+            build_virtual_map(memory_map().iter(), mapping, &mut virtual_map[..1]),
+            Err(status::EFI_OUT_OF_RESOURCES)
+        );
+
+        // Offsets must be page aligned, which is checked before the runtime services are
+        let mem_manager = EfiMemoryManager::new();
+        let mapping = VirtualMapping::Offset(OFFSET + 0x800);
+        assert_eq!(
+            set_virtual_address_map(&mem_manager, mapping),
+            Err(status::EFI_INVALID_PARAMETER)
+        );
+    }
+}
@@ -0,0 +1,487 @@
+//! Driver for the High Precision Event Timer, whose main counter is used as a reference clock and
+//! whose comparators raise interrupts after a given number of ticks
+use crate::efi::acpi::{AcpiTables, AddressSpace, HPET};
+use bitflags::bitflags;
+
+/// The largest counter period allowed by the specification, which is 100 nanoseconds
+pub const MAX_PERIOD_FS: u32 = 100_000_000;
+
+/// The number of femtoseconds in a second
+const FS_PER_SECOND: u64 = 1_000_000_000_000_000;
+
+/// The number of femtoseconds in a nanosecond
+const FS_PER_NS: u64 = 1_000_000;
+
+/// Offsets of the registers of the event timer block
+mod reg {
+    pub const CAPABILITIES: usize = 0x000;
+    pub const CONFIG: usize = 0x010;
+    pub const INTERRUPT_STATUS: usize = 0x020;
+    pub const MAIN_COUNTER: usize = 0x0F0;
+
+    // Each comparator has its own set of registers
+    pub const fn timer_config(timer: u8) -> usize {
+        0x100 + 0x20 * timer as usize
+    }
+    pub const fn timer_comparator(timer: u8) -> usize {
+        0x108 + 0x20 * timer as usize
+    }
+    pub const fn timer_fsb_route(timer: u8) -> usize {
+        0x110 + 0x20 * timer as usize
+    }
+}
+
+bitflags! {
+    /// The General Configuration Register
+    #[repr(transparent)]
+    #[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+    pub struct GeneralConfig: u64 {
+        /// Runs the main counter, and allows the comparators to raise interrupts
+        const ENABLE = 1 << 0;
+        /// Routes comparator 0 to IRQ 0 and comparator 1 to IRQ 8, in place of the PIT and RTC
+        const LEGACY_REPLACEMENT = 1 << 1;
+    }
+}
+
+bitflags! {
+    /// The low half of a comparator's Configuration and Capabilities Register. The high half
+    /// holds the I/O APIC inputs the comparator can be routed to.
+    #[repr(transparent)]
+    #[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+    pub struct TimerConfig: u64 {
+        /// The interrupt is level triggered instead of edge triggered
+        const LEVEL_TRIGGERED = 1 << 1;
+        const INTERRUPT_ENABLE = 1 << 2;
+        const PERIODIC = 1 << 3;
+        /// The comparator supports periodic mode (read-only)
+        const PERIODIC_CAPABLE = 1 << 4;
+        /// The comparator is 64 bits wide (read-only)
+        const SIZE_64BIT = 1 << 5;
+        /// The next write to the comparator of a periodic timer sets its value, instead of its
+        /// period
+        const VALUE_SET = 1 << 6;
+        /// Forces a 64-bit comparator to behave as a 32-bit one
+        const MODE_32BIT = 1 << 8;
+        /// Delivers the interrupt as a message to the FSB route, instead of to the I/O APIC
+        const FSB_ENABLE = 1 << 14;
+        /// The comparator supports FSB interrupt delivery (read-only)
+        const FSB_CAPABLE = 1 << 15;
+    }
+}
+
+// The I/O APIC input a comparator is routed to is a 5-bit field in its configuration
+const TIMER_ROUTE_SHIFT: u64 = 9;
+const TIMER_ROUTE_MASK: u64 = 0x1F << TIMER_ROUTE_SHIFT;
+
+/// Reasons for which the HPET cannot be used as asked
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HpetError {
+    /// There is no HPET table
+    NotFound,
+    /// The registers are not in system memory
+    UnsupportedAddressSpace(u8),
+    /// The registers are at the null address
+    NullAddress,
+    /// The counter period is null or larger than `MAX_PERIOD_FS`
+    InvalidPeriod(u32),
+    /// The block has no comparator with this index
+    InvalidTimer(u8),
+    /// The comparator cannot be routed to this I/O APIC input
+    InvalidRoute(u8),
+    /// The comparator cannot deliver its interrupt to the FSB
+    FsbNotSupported,
+    /// The comparator cannot run in periodic mode
+    PeriodicNotSupported,
+    /// The main counter is stopped, while the operation needs it to run
+    CounterStopped,
+}
+
+/// Where a comparator delivers its interrupt
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TimerRoute {
+    /// An input of the I/O APIC, which must be among the ones given by `TimerCaps::routes`. The
+    /// interrupt is edge triggered.
+    IoApic(u8),
+    /// A message written to `address`, as MSIs are
+    Fsb { address: u32, data: u32 },
+}
+
+/// What a comparator is able to do
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TimerCaps {
+    pub periodic: bool,
+    pub size_64bit: bool,
+    pub fsb: bool,
+    /// The mask of the I/O APIC inputs the comparator can be routed to
+    pub routes: u32,
+}
+
+/// An event timer block, with its main counter and its comparators
+#[derive(Debug, Clone, Copy)]
+pub struct Hpet {
+    // Address of the registers, which are identity mapped
+    base: usize,
+    // Period of the main counter, in femtoseconds
+    period_fs: u32,
+    // Number of comparators
+    ntimers: u8,
+    // Whether the main counter is 64 bits wide
+    counter_64bit: bool,
+    // The minimum number of ticks between two interrupts of a periodic comparator
+    min_tick: u16,
+}
+
+impl Hpet {
+    /// Sets up the event timer block described by `hpet`, reading its capabilities from its
+    /// registers. The main counter is left as it was.
+    pub fn new(hpet: &HPET) -> Result<Self, HpetError> {
+        let base_addr = hpet.base_addr();
+        if AddressSpace::from(base_addr.address_space_id) != AddressSpace::SystemMemory {
+            return Err(HpetError::UnsupportedAddressSpace(
+                base_addr.address_space_id,
+            ));
+        }
+        if base_addr.address == 0 {
+            return Err(HpetError::NullAddress);
+        }
+
+        let mut timer = Self {
+            base: base_addr.address as usize,
+            period_fs: 0,
+            ntimers: 0,
+            counter_64bit: false,
+            min_tick: hpet.min_tick(),
+        };
+
+        // SAFETY: the registers are in system memory, which the firmware identity maps
+        let caps = unsafe { timer.read(reg::CAPABILITIES) };
+        timer.period_fs = (caps >> 32) as u32;
+        timer.ntimers = (caps >> 8 & 0x1F) as u8 + 1;
+        timer.counter_64bit = caps & 1 << 13 != 0;
+
+        if timer.period_fs == 0 || timer.period_fs > MAX_PERIOD_FS {
+            return Err(HpetError::InvalidPeriod(timer.period_fs));
+        }
+
+        Ok(timer)
+    }
+
+    /// Sets up the first event timer block described by the ACPI tables
+    pub fn from_acpi(tables: &AcpiTables) -> Result<Self, HpetError> {
+        let hpet = tables.find::<HPET>().ok_or(HpetError::NotFound)?;
+        Self::new(&hpet)
+    }
+
+    unsafe fn read(&self, offset: usize) -> u64 {
+        core::ptr::read_volatile((self.base + offset) as *const u64)
+    }
+
+    unsafe fn write(&self, offset: usize, value: u64) {
+        core::ptr::write_volatile((self.base + offset) as *mut u64, value);
+    }
+
+    /// Returns the period of the main counter, in femtoseconds
+    pub fn period_fs(&self) -> u32 {
+        self.period_fs
+    }
+
+    /// Returns the frequency of the main counter, in Hz
+    pub fn frequency(&self) -> u64 {
+        FS_PER_SECOND / self.period_fs as u64
+    }
+
+    /// Returns the number of comparators
+    pub fn num_timers(&self) -> u8 {
+        self.ntimers
+    }
+
+    /// Returns whether the main counter is 64 bits wide. A 32-bit counter wraps around in about
+    /// 5 minutes at the usual 14.318 MHz.
+    pub fn counter_64bit(&self) -> bool {
+        self.counter_64bit
+    }
+
+    /// Returns the number of ticks in `ns` nanoseconds, rounded up
+    pub fn ns_to_ticks(&self, ns: u64) -> u64 {
+        let fs = ns.saturating_mul(FS_PER_NS);
+        fs.div_ceil(self.period_fs as u64)
+    }
+
+    /// Returns the number of nanoseconds in `ticks` ticks, rounded down
+    pub fn ticks_to_ns(&self, ticks: u64) -> u64 {
+        (ticks as u128 * self.period_fs as u128 / FS_PER_NS as u128) as u64
+    }
+
+    /// Starts the main counter, enabling the comparators to raise interrupts
+    pub fn enable(&self) {
+        unsafe {
+            let config = GeneralConfig::from_bits_retain(self.read(reg::CONFIG));
+            self.write(reg::CONFIG, (config | GeneralConfig::ENABLE).bits());
+        }
+    }
+
+    /// Stops the main counter, which also stops the comparators from raising interrupts
+    pub fn disable(&self) {
+        unsafe {
+            let config = GeneralConfig::from_bits_retain(self.read(reg::CONFIG));
+            self.write(reg::CONFIG, (config - GeneralConfig::ENABLE).bits());
+        }
+    }
+
+    /// Routes comparators 0 and 1 to the PIT and RTC interrupts when `enabled`, in which case
+    /// their own routes are ignored
+    pub fn set_legacy_replacement(&self, enabled: bool) {
+        unsafe {
+            let mut config = GeneralConfig::from_bits_retain(self.read(reg::CONFIG));
+            config.set(GeneralConfig::LEGACY_REPLACEMENT, enabled);
+            self.write(reg::CONFIG, config.bits());
+        }
+    }
+
+    /// Returns whether the main counter is running
+    pub fn is_enabled(&self) -> bool {
+        let config = GeneralConfig::from_bits_retain(unsafe { self.read(reg::CONFIG) });
+        config.contains(GeneralConfig::ENABLE)
+    }
+
+    /// Returns the value of the main counter
+    pub fn counter(&self) -> u64 {
+        unsafe { self.read(reg::MAIN_COUNTER) }
+    }
+
+    /// Sets the value of the main counter, which must be stopped
+    pub fn set_counter(&self, value: u64) {
+        unsafe { self.write(reg::MAIN_COUNTER, value) }
+    }
+
+    /// Spins until `ns` nanoseconds have elapsed on the main counter, which must be running
+    pub fn wait_ns(&self, ns: u64) {
+        let start = self.counter();
+        let ticks = self.ns_to_ticks(ns);
+        while self.elapsed_since(start) < ticks {
+            core::hint::spin_loop();
+        }
+    }
+
+    /// Returns the number of ticks between the main counter reads `start` and `end`, accounting
+    /// for a 32-bit counter wrapping around once
+    pub fn ticks_between(&self, start: u64, end: u64) -> u64 {
+        if self.counter_64bit {
+            end.wrapping_sub(start)
+        } else {
+            (end as u32).wrapping_sub(start as u32) as u64
+        }
+    }
+
+    /// Returns the number of ticks elapsed since the main counter read `start`, accounting for a
+    /// 32-bit counter wrapping around once
+    pub fn elapsed_since(&self, start: u64) -> u64 {
+        self.ticks_between(start, self.counter())
+    }
+
+    /// Returns the capabilities of the comparator `timer`
+    pub fn timer_caps(&self, timer: u8) -> Result<TimerCaps, HpetError> {
+        let config = self.timer_config(timer)?;
+        let flags = TimerConfig::from_bits_retain(config);
+
+        Ok(TimerCaps {
+            periodic: flags.contains(TimerConfig::PERIODIC_CAPABLE),
+            size_64bit: flags.contains(TimerConfig::SIZE_64BIT),
+            fsb: flags.contains(TimerConfig::FSB_CAPABLE),
+            routes: (config >> 32) as u32,
+        })
+    }
+
+    // Reads the configuration register of the comparator `timer`
+    fn timer_config(&self, timer: u8) -> Result<u64, HpetError> {
+        if timer >= self.ntimers {
+            return Err(HpetError::InvalidTimer(timer));
+        }
+
+        Ok(unsafe { self.read(reg::timer_config(timer)) })
+    }
+
+    // Builds the configuration of the comparator `timer` so that its interrupt is delivered to
+    // `route`, programming the FSB route register if needed. The interrupt is left disabled.
+    fn route_config(&self, timer: u8, route: TimerRoute) -> Result<TimerConfig, HpetError> {
+        let caps = self.timer_caps(timer)?;
+        let config = self.timer_config(timer)? & !TIMER_ROUTE_MASK;
+        let mut config = TimerConfig::from_bits_retain(config)
+            - TimerConfig::INTERRUPT_ENABLE
+            - TimerConfig::PERIODIC
+            - TimerConfig::LEVEL_TRIGGERED
+            - TimerConfig::FSB_ENABLE;
+
+        match route {
+            TimerRoute::IoApic(input) => {
+                if input >= 32 || caps.routes & 1 << input == 0 {
+                    return Err(HpetError::InvalidRoute(input));
+                }
+                config |= TimerConfig::from_bits_retain((input as u64) << TIMER_ROUTE_SHIFT);
+            }
+            TimerRoute::Fsb { address, data } => {
+                if !caps.fsb {
+                    return Err(HpetError::FsbNotSupported);
+                }
+                let fsb_route = (address as u64) << 32 | data as u64;
+                unsafe { self.write(reg::timer_fsb_route(timer), fsb_route) };
+                config |= TimerConfig::FSB_ENABLE;
+            }
+        }
+
+        Ok(config)
+    }
+
+    /// Makes the comparator `timer` raise a single interrupt on `route`, `ticks` ticks from now.
+    /// The main counter must be running.
+    pub fn one_shot(&self, timer: u8, route: TimerRoute, ticks: u64) -> Result<(), HpetError> {
+        let config = self.route_config(timer, route)?;
+
+        unsafe {
+            self.write(reg::timer_config(timer), config.bits());
+            self.write(
+                reg::timer_comparator(timer),
+                self.counter().wrapping_add(ticks),
+            );
+            self.write(
+                reg::timer_config(timer),
+                (config | TimerConfig::INTERRUPT_ENABLE).bits(),
+            );
+        }
+
+        Ok(())
+    }
+
+    /// Makes the comparator `timer` raise an interrupt on `route` every `period` ticks, starting
+    /// `period` ticks from now. Periods below the minimum tick of the HPET table are raised to
+    /// it. The main counter must be running, as setting the period waits for it to tick.
+    pub fn periodic(&self, timer: u8, route: TimerRoute, period: u64) -> Result<(), HpetError> {
+        if !self.timer_caps(timer)?.periodic {
+            return Err(HpetError::PeriodicNotSupported);
+        }
+        if !self.is_enabled() {
+            return Err(HpetError::CounterStopped);
+        }
+        let config = self.route_config(timer, route)?
+            | TimerConfig::PERIODIC
+            | TimerConfig::VALUE_SET
+            | TimerConfig::INTERRUPT_ENABLE;
+        let period = period.max(self.min_tick as u64);
+
+        unsafe {
+            self.write(reg::timer_config(timer), config.bits());
+            // With `VALUE_SET`, the first write sets the comparator and the second one the
+            // period. Some implementations need a tick between the two.
+            self.write(
+                reg::timer_comparator(timer),
+                self.counter().wrapping_add(period),
+            );
+            let start = self.counter();
+            while self.elapsed_since(start) == 0 {
+                core::hint::spin_loop();
+            }
+            self.write(reg::timer_comparator(timer), period);
+        }
+
+        Ok(())
+    }
+
+    /// Stops the comparator `timer` from raising interrupts
+    pub fn stop(&self, timer: u8) -> Result<(), HpetError> {
+        let config = TimerConfig::from_bits_retain(self.timer_config(timer)?)
+            - TimerConfig::INTERRUPT_ENABLE
+            - TimerConfig::PERIODIC;
+        unsafe { self.write(reg::timer_config(timer), config.bits()) };
+
+        Ok(())
+    }
+
+    /// Acknowledges the level triggered interrupt of the comparator `timer`. Edge triggered
+    /// interrupts need no acknowledgement.
+    pub fn acknowledge(&self, timer: u8) -> Result<(), HpetError> {
+        if timer >= self.ntimers {
+            return Err(HpetError::InvalidTimer(timer));
+        }
+
+        unsafe { self.write(reg::INTERRUPT_STATUS, 1 << timer) };
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::efi::acpi::address_space;
+    use crate::test_support::{fix_checksum, Machine, Q35};
+    use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+
+    // Returns the q35 HPET, with its registers at `address` in `space`
+    fn hpet_table(space: u8, address: u64) -> HPET {
+        let mut table = Q35::table("hpet").to_vec();
+        table[40] = space;
+        table[44..52].copy_from_slice(&address.to_le_bytes());
+        fix_checksum(&mut table, 9);
+        HPET::from_bytes(&table).unwrap()
+    }
+
+    #[test]
+    fn hpet_timers() {
+        // Three comparators and a 64-bit main counter ticking every 10 ns. Comparator 0 can be
+        // periodic and routed to I/O APIC input 2, comparator 1 cannot be periodic.
+        let registers: Vec<AtomicU64> = (0..0x80).map(|_| AtomicU64::new(0)).collect();
+        registers[0].store(10_000_000 << 32 | 1 << 13 | 2 << 8, Ordering::SeqCst);
+        registers[0x100 / 8].store(1 << (32 + 2) | 1 << 4, Ordering::SeqCst);
+        let config = &registers[0x10 / 8];
+        let status = &registers[0x20 / 8];
+        let main_counter = &registers[0xF0 / 8];
+
+        let hpet = Hpet::new(&hpet_table(
+            address_space::SYSTEM_MEMORY,
+            registers.as_ptr() as u64,
+        ));
+        let hpet = hpet.unwrap();
+        assert_eq!((hpet.num_timers(), hpet.frequency()), (3, 100_000_000));
+
+        assert_eq!(hpet.acknowledge(1), Ok(()));
+        assert_eq!(status.load(Ordering::SeqCst), 1 << 1);
+        assert_eq!(hpet.acknowledge(3), Err(HpetError::InvalidTimer(3)));
+
+        // Setting the period waits for the main counter to tick, so it must be running
+        let route = TimerRoute::IoApic(2);
+        assert_eq!(
+            hpet.periodic(0, route, 1000),
+            Err(HpetError::CounterStopped)
+        );
+        assert_eq!(
+            hpet.periodic(1, route, 1000),
+            Err(HpetError::PeriodicNotSupported)
+        );
+
+        hpet.enable();
+        assert_eq!(config.load(Ordering::SeqCst), 1);
+        let running = AtomicBool::new(true);
+        std::thread::scope(|scope| {
+            scope.spawn(|| {
+                while running.load(Ordering::SeqCst) {
+                    main_counter.fetch_add(1, Ordering::SeqCst);
+                }
+            });
+            assert_eq!(hpet.periodic(0, route, 1000), Ok(()));
+            running.store(false, Ordering::SeqCst);
+        });
+        assert_eq!(registers[0x108 / 8].load(Ordering::SeqCst), 1000);
+    }
+
+    #[test]
+    fn hpet_addresses() {
+        // The registers must be somewhere in system memory, which is checked before reading them
+        assert_eq!(
+            Hpet::new(&hpet_table(address_space::SYSTEM_MEMORY, 0)).err(),
+            Some(HpetError::NullAddress)
+        );
+        assert_eq!(
+            Hpet::new(&hpet_table(address_space::SYSTEM_IO, 0xFED0_0000)).err(),
+            Some(HpetError::UnsupportedAddressSpace(address_space::SYSTEM_IO))
+        );
+    }
+}
@@ -0,0 +1,15 @@
+//! pril is a UEFI application that takes the platform over from the firmware. This library holds
+//! everything but its entry point, such that the parsers can also be unit tested and fuzzed on the
+//! host, where the standard library is available.
+#![cfg_attr(not(test), no_std)]
+
+pub mod bmp;
+pub mod cpu;
+pub mod efi;
+pub mod hpet;
+pub mod pci;
+pub mod pm_timer;
+pub mod print;
+#[cfg(test)]
+mod test_support;
+pub mod vtd;
@@ -1,34 +1,343 @@
-#![feature(panic_info_message)]
-#![feature(strict_provenance)]
-#![no_std]
-#![no_main]
+#![cfg_attr(target_os = "uefi", no_std)]
+#![cfg_attr(target_os = "uefi", no_main)]
 
-pub mod efi;
+#[cfg(target_os = "uefi")]
 mod panic;
-pub mod print;
-pub(crate) mod cpu; 
 
-use crate::efi::{initialize_system_table, EfiHandle, EfiStatus, EfiSystemTable};
-use crate::efi::malloc::EfiMemoryManager;
-use cpu::msr_reg_addr;
+use pril::cpu::cpuid::{self, Cpu};
+use pril::cpu::{self, msr_reg_addr};
+use pril::efi::acpi::{
+    self, aml::Interpreter, AcpiModeError, AcpiTables, FixedEvent, FixedEventHandler,
+    NumaTopology, BGRT, FACS, FADT,
+};
+use pril::efi::gop::{BootLogo, GraphicsOutput};
+use pril::efi::{
+    exit_boot_services, initialize_system_table, status, EfiHandle, EfiStatus, EfiSystemTable,
+};
+use pril::efi::malloc::{EfiMemoryManager, EfiMemoryType};
+use pril::hpet::Hpet;
+use pril::pm_timer::PmTimer;
+use pril::print;
+use pril::vtd::{Vtd, VtdError};
+use pril::efi::runtime_services::{self, EfiResetType, VirtualMapping};
+
+// Host builds, which are there for the unit tests of the library, still need an entry point
+#[cfg(not(target_os = "uefi"))]
+fn main() {
+    eprintln!("pril is a UEFI application, see qemu.sh to run it");
+}
 
 #[no_mangle]
-extern "C" fn efi_main(_image_handle: EfiHandle, system_table: *mut EfiSystemTable) -> EfiStatus {
-    initialize_system_table(system_table);
+extern "C" fn efi_main(image_handle: EfiHandle, system_table: *mut EfiSystemTable) -> EfiStatus {
+    // SAFETY: this is the system table the firmware handed to us
+    if let Err(status) = unsafe { initialize_system_table(system_table) } {
+        return status;
+    }
+
+    // Nothing is printed to a serial port until we know which one is the console
+    print::SerialWriter::hold_output();
+    // Find the ACPI tables while the EFI configuration table is still reachable, and keep them
+    // for the code that runs after exiting boot services
+    let acpi_tables = AcpiTables::from_config_table().map(AcpiTables::install);
+    // Move the console to the serial port the firmware redirects its own console to, if any
+    print::SerialWriter::select_console(acpi_tables);
+    print!("Found ACPI tables: {}\n", acpi_tables.is_some());
+    report_cpu();
+    // Report the serial port the firmware sets aside for debugging, if any
+    if let Some(debug_port) = acpi_tables.and_then(print::SerialConfig::debug_port) {
+        print!("DBG2 debug port: {:x?}\n", debug_port.registers);
+    }
+
+    // Dump the tables before doing anything with them, such that they can be looked at even if
+    // something below goes wrong
+    #[cfg(feature = "acpidump")]
+    if let Some(tables) = &acpi_tables {
+        acpi::dump_tables(tables);
+    }
+
+    if let Some(tables) = &acpi_tables {
+        match acpi::aml::load_namespace(tables) {
+            Ok(count) => {
+                print!("Loaded {} objects into the ACPI namespace\n", count);
+                if let Some(mut interpreter) = Interpreter::lock() {
+                    list_devices(&mut interpreter);
+                }
+            }
+            Err(err) => {
+                print!("Cannot load the ACPI namespace: {:?}\n", err);
+            }
+        }
+    }
+
+    if let Some(tables) = &acpi_tables {
+        match Hpet::from_acpi(tables) {
+            Ok(hpet) => {
+                hpet.enable();
+                print!(
+                    "HPET runs at {} Hz with {} comparators\n",
+                    hpet.frequency(),
+                    hpet.num_timers()
+                );
+            }
+            Err(err) => {
+                print!("Cannot use the HPET: {:?}\n", err);
+            }
+        }
+    }
+
+    // Read the vendor logo while the GOP and the boot services memory holding it are still there,
+    // such that it can be drawn again after a mode change
+    let boot_logo = GraphicsOutput::locate()
+        .and_then(|gop| gop.framebuffer())
+        .zip(acpi_tables.and_then(|tables| tables.find::<BGRT>()))
+        .map(|(framebuffer, bgrt)| BootLogo::capture(&bgrt, &framebuffer));
+    match boot_logo {
+        Some(Ok(logo)) => {
+            print!("Boot logo is {}x{}\n", logo.image().width(), logo.image().height());
+        }
+        Some(Err(err)) => {
+            print!("Cannot use the boot logo: {:?}\n", err);
+        }
+        None => {}
+    }
 
     let mut mem_manager =  EfiMemoryManager::new();
     let map_key = mem_manager.get_memory_map();
 
+    // From this point on, only the runtime services are available
+    exit_boot_services(image_handle, map_key);
+
+    // We keep the firmware's identity mapping, such that runtime services can still be called
+    // using the current page tables
+    if let Err(status) =
+        runtime_services::set_virtual_address_map(&mem_manager, VirtualMapping::Identity)
+    {
+        print!("Cannot switch runtime services to virtual mode, status {:#x}\n", status);
+    }
+
+    // Check that the runtime services can still be called in virtual mode, for qemu.sh's
+    // --svam-reset runs
+    if cfg!(feature = "svam-reset") {
+        print!("Shutting down through the runtime services\n");
+        runtime_services::reset_system(EfiResetType::Shutdown, status::EFI_SUCCESS)
+    }
+
     let total_avlbl_mem = mem_manager.free_mem_after_exit_bs();
 
     print!("Total available memory {}!!!\n", total_avlbl_mem);
 
-    let cr0 = unsafe { cpu::cr0() };
-    let ia_efer = unsafe { cpu::rdmsr(msr_reg_addr::IA32_EFER) };
+    if let Some(numa) = acpi_tables.and_then(NumaTopology::from_acpi) {
+        list_numa_memory(&numa, &mem_manager);
+    }
 
+    // Block the DMA of every device, but for the memory the firmware reserved for some of them,
+    // before anything else gets to run
+    if let Some(tables) = &acpi_tables {
+        match Vtd::from_acpi(tables).and_then(|mut vtd| vtd.enable().map(|()| vtd)) {
+            Ok(vtd) => {
+                print!("DMA remapping enabled on {} units\n", vtd.units().count());
+            }
+            Err(VtdError::NotFound) => {}
+            Err(err) => {
+                print!("Cannot enable DMA remapping: {:?}\n", err);
+            }
+        }
+    }
+
+    let cr0 = unsafe { cpu::cr0() };
     print!("Cr0: {:#?}\n", cr0);
-    print!("ia_efer: {:#b}\n", ia_efer);
+    match cpu::read_msr(msr_reg_addr::IA32_EFER) {
+        Some(ia_efer) => {
+            print!("ia_efer: {:#b}\n", ia_efer);
+        }
+        None => {
+            print!("IA32_EFER is not supported\n");
+        }
+    }
+
+    match runtime_services::get_time() {
+        Ok(time) => {
+            print!("Time: {:?}\n", time);
+        }
+        Err(status) => {
+            print!("Cannot get time, status {:#x}\n", status);
+        }
+    }
+
+    // Report the hardware signature, which tells whether the hardware changed since last boot
+    if let Some(fadt) = acpi_tables.and_then(|tables| tables.find::<FADT>()) {
+        match FACS::from_fadt(&fadt) {
+            Ok(facs) => {
+                print!("FACS hardware signature: {:#010x}\n", facs.hardware_signature());
+            }
+            Err(err) => {
+                print!("Cannot read the FACS: {:?}\n", err);
+            }
+        }
+    }
+
+    // Take the ACPI hardware registers over from the firmware, such that the fixed events are
+    // ours to handle
+    if let Some(fadt) = acpi_tables.and_then(|tables| tables.find::<FADT>()) {
+        match acpi::enable_acpi_mode(&fadt) {
+            Ok(()) => {
+                print!("Platform is in ACPI mode\n");
+            }
+            Err(AcpiModeError::HardwareReduced) => {
+                print!("Platform is hardware-reduced ACPI, which has no legacy mode\n");
+            }
+            Err(err) => {
+                print!("Cannot enter ACPI mode: {:?}\n", err);
+            }
+        }
+    }
+
+    // Give the power button, which QEMU's `system_powerdown` presses, a few seconds to be
+    // pressed, before powering off on our own
+    if let Some(tables) = &acpi_tables {
+        wait_for_power_button(tables);
+    }
 
+    // Power off, such that automated runs end on their own, asking the firmware to do it when
+    // the ACPI way is not available
+    let Err(err) = acpi::poweroff();
+    print!("Cannot power off: {:?}\n", err);
+
+    runtime_services::reset_system(EfiResetType::Shutdown, status::EFI_SUCCESS)
+}
+
+// Prints what the processor is, what it supports, its caches and its topology, for hardware
+// qualification
+fn report_cpu() {
+    let signature = cpuid::signature(&Cpu);
+    print!(
+        "CPU: {} family {:#x} model {:#x} stepping {}\n",
+        cpuid::vendor_string(&Cpu).as_str(),
+        signature.family,
+        signature.model,
+        signature.stepping
+    );
+    if let Some(brand) = cpuid::brand_string(&Cpu) {
+        print!("    {}\n", brand.as_str());
+    }
+    if let Some(hypervisor) = cpuid::hypervisor(&Cpu) {
+        print!(
+            "    Running under {:?} ({})\n",
+            hypervisor.vendor(),
+            hypervisor.vendor_string.as_str()
+        );
+        if let Some(regs) = hypervisor.leaf(&Cpu, 1, 0) {
+            print!("    Hypervisor leaf 0x40000001: {:x?}\n", regs);
+        }
+    }
+
+    print!("    {:?}\n", cpuid::Features::read(&Cpu));
+    if let Some(xsave) = cpuid::xsave_info(&Cpu) {
+        print!("    {:?}\n", xsave);
+    }
+
+    for cache in cpuid::caches(&Cpu) {
+        print!(
+            "    L{} {:?} cache: {} KiB, {}-way, {}-byte lines, shared by {}\n",
+            cache.level,
+            cache.cache_type,
+            cache.size() / 1024,
+            cache.ways,
+            cache.line_size,
+            cache.shared_by
+        );
+    }
+    for level in cpuid::topology(&Cpu) {
+        print!(
+            "    {:?} level: {} logical processors, x2APIC ID shift {}\n",
+            level.level_type, level.logical_processors, level.shift
+        );
+    }
+}
+
+// Prints the present devices of the ACPI namespace, along with the resources they use
+fn list_devices(interpreter: &mut Interpreter) {
+    interpreter.for_each_device(|interpreter, info| {
+        if !info.is_present() {
+            return;
+        }
+
+        print!("{}:", interpreter.namespace().path(info.node));
+        if let Some(hid) = info.hid {
+            print!(" HID {}", hid);
+        }
+        for cid in info.cids.iter().flatten() {
+            print!(" CID {}", cid);
+        }
+        if let Some(uid) = info.uid {
+            print!(" UID {}", uid);
+        }
+        if let Some(adr) = info.adr {
+            print!(" ADR {:#x}", adr);
+        }
+        print!("\n");
+
+        if let Ok(resources) = interpreter.current_resources(info.node) {
+            for resource in resources {
+                print!("    {:x?}\n", resource);
+            }
+        }
+    });
+}
+
+// Prints the available memory of each proximity domain, along with the distances between domains
+fn list_numa_memory(numa: &NumaTopology, mem_manager: &EfiMemoryManager) {
+    for &domain in numa.domains() {
+        let mut available = 0;
+        let conventional = mem_manager
+            .descriptors()
+            .filter(|desc| desc.mem_type() == EfiMemoryType::ConventionalMemory);
+        numa.for_each_region(conventional, |region| {
+            if region.proximity_domain == Some(domain) {
+                available += region.end - region.start;
+            }
+        });
+
+        let cpus = numa.processors().filter(|cpu| cpu.proximity_domain == domain).count();
+        print!("NUMA domain {}: {} CPUs, {} bytes available, distances", domain, cpus, available);
+        for &other in numa.domains() {
+            print!(" {}", numa.distance(domain, other));
+        }
+        print!("\n");
+    }
+}
+
+// Polls the fixed events for a few seconds, timed with the PM timer, powering off if the power
+// button gets pressed
+fn wait_for_power_button(tables: &AcpiTables) {
+    let timer = match PmTimer::from_acpi(tables) {
+        Ok(timer) => timer,
+        Err(err) => {
+            print!("Cannot use the PM timer: {:?}\n", err);
+            return;
+        }
+    };
+    let mut events = match FixedEventHandler::from_acpi(tables) {
+        Ok(events) => events,
+        Err(err) => {
+            print!("Cannot handle the fixed events: {:?}\n", err);
+            return;
+        }
+    };
+
+    if let Err(err) = events.register(FixedEvent::POWER_BUTTON, on_power_button) {
+        print!("Cannot handle the power button: {:?}\n", err);
+        return;
+    }
+
+    print!("Waiting for the power button, with a {}-bit PM timer\n", timer.bits());
+    timer.wait_until(5_000_000_000, || !events.poll_events().is_empty());
+    events.unregister(FixedEvent::POWER_BUTTON);
+}
 
-    loop {}
+// Shuts down cleanly once the power button was pressed
+fn on_power_button(_event: FixedEvent) {
+    print!("Power button pressed, powering off\n");
+    let Err(err) = acpi::poweroff();
+    print!("Cannot power off: {:?}\n", err);
 }
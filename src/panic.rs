@@ -1,8 +1,12 @@
-use crate::print;
+use pril::print;
 use core::panic::PanicInfo;
 
 #[panic_handler]
 fn panic(info: &PanicInfo) -> ! {
+    // Panicking before the console was selected must not leave the output held
+    if !print::SerialWriter::is_initialized() {
+        print::SerialWriter::select_console(None);
+    }
     print!("!!! PANIC !!!\n");
     if let Some(location) = info.location() {
         print!(
@@ -13,10 +17,6 @@ fn panic(info: &PanicInfo) -> ! {
     } else {
         print!("Can't get panic location...\n");
     }
-    if let Some(message) = info.message() {
-        print!("{}\n", message);
-    } else {
-        print!("Can't get panic message...\n");
-    }
+    print!("{}\n", info.message());
     loop {}
 }
@@ -0,0 +1,107 @@
+//! Module that provides access to the PCI configuration space
+use crate::cpu::{inb, inl, inw, outb, outl, outw};
+
+pub mod ecam;
+
+/// I/O port used to select the configuration space register to access
+const CONFIG_ADDRESS: u16 = 0xCF8;
+/// I/O port through which the selected configuration space register is accessed
+const CONFIG_DATA: u16 = 0xCFC;
+
+/// Identifies a single PCI function
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct PciAddress {
+    pub segment: u16,
+    pub bus: u8,
+    pub device: u8,
+    pub function: u8,
+}
+
+impl PciAddress {
+    pub fn new(segment: u16, bus: u8, device: u8, function: u8) -> Self {
+        Self {
+            segment,
+            bus,
+            device,
+            function,
+        }
+    }
+}
+
+/// Access to the first 256 bytes of each function's configuration space, through the legacy I/O
+/// port mechanism. This only reaches PCI segment group 0.
+pub mod legacy {
+    use super::*;
+
+    // Selects the dword containing `offset` in the configuration space of `addr`
+    unsafe fn select(addr: PciAddress, offset: u8) {
+        let address = 1 << 31
+            | (addr.bus as u32) << 16
+            | (addr.device as u32 & 0x1F) << 11
+            | (addr.function as u32 & 0x7) << 8
+            | (offset as u32 & 0xFC);
+        outl(CONFIG_ADDRESS, address);
+    }
+
+    /// Reads the byte at `offset` in the configuration space of `addr`
+    ///
+    /// # Safety
+    ///
+    /// Reading a register can have side effects on the device, which the caller must own.
+    pub unsafe fn read_u8(addr: PciAddress, offset: u8) -> u8 {
+        select(addr, offset);
+        inb(CONFIG_DATA + (offset & 3) as u16)
+    }
+
+    /// Reads the word at the word aligned `offset` in the configuration space of `addr`
+    ///
+    /// # Safety
+    ///
+    /// Reading a register can have side effects on the device, which the caller must own.
+    pub unsafe fn read_u16(addr: PciAddress, offset: u8) -> u16 {
+        select(addr, offset);
+        inw(CONFIG_DATA + (offset & 2) as u16)
+    }
+
+    /// Reads the dword at the dword aligned `offset` in the configuration space of `addr`
+    ///
+    /// # Safety
+    ///
+    /// Reading a register can have side effects on the device, which the caller must own.
+    pub unsafe fn read_u32(addr: PciAddress, offset: u8) -> u32 {
+        select(addr, offset);
+        inl(CONFIG_DATA)
+    }
+
+    /// Writes the byte at `offset` in the configuration space of `addr`, leaving the other bytes
+    /// of its dword alone
+    ///
+    /// # Safety
+    ///
+    /// Writing a register can have any side effect on the device, which the caller must own.
+    pub unsafe fn write_u8(addr: PciAddress, offset: u8, value: u8) {
+        select(addr, offset);
+        outb(CONFIG_DATA + (offset & 3) as u16, value);
+    }
+
+    /// Writes the word at the word aligned `offset` in the configuration space of `addr`, leaving
+    /// the other word of its dword alone
+    ///
+    /// # Safety
+    ///
+    /// Writing a register can have any side effect on the device, which the caller must own.
+    pub unsafe fn write_u16(addr: PciAddress, offset: u8, value: u16) {
+        select(addr, offset);
+        outw(CONFIG_DATA + (offset & 2) as u16, value);
+    }
+
+    /// Writes the dword at the dword aligned `offset` in the configuration space of `addr`
+    ///
+    /// # Safety
+    ///
+    /// Writing a register can have any side effect on the device, which the caller must own.
+    pub unsafe fn write_u32(addr: PciAddress, offset: u8, value: u32) {
+        select(addr, offset);
+        outl(CONFIG_DATA, value);
+    }
+}
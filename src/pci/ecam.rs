@@ -0,0 +1,251 @@
+//! Access to the entire 4 KiB configuration space of each function, through the memory mapped
+//! Enhanced Configuration Access Mechanism (ECAM). The regions where the configuration space is
+//! mapped are described by the MCFG.
+use super::PciAddress;
+use crate::efi::acpi::{AcpiTables, MAX_MCFG_ENTRIES, MCFG};
+
+/// The size of the configuration space of each function, including the extended configuration
+/// space that starts at offset 0x100
+pub const CONFIG_SPACE_SIZE: u16 = 4096;
+
+/// Reasons for which a configuration space access could not be made
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EcamError {
+    /// No region maps the configuration space of the bus the function is on
+    NotMapped,
+    /// The offset is past the configuration space, or is not aligned on the access size
+    InvalidOffset(u16),
+    /// The device number is not below 32, or the function number is not below 8
+    InvalidFunction { device: u8, function: u8 },
+}
+
+/// The memory mapped configuration space of a range of buses of a PCI segment group
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct EcamRegion {
+    /// Physical address at which bus 0 would be mapped, even if the region starts at a later bus
+    pub base_addr: u64,
+    pub segment: u16,
+    pub start_bus: u8,
+    pub end_bus: u8,
+}
+
+/// Configuration space accessor for all the regions described by the MCFG
+pub struct Ecam {
+    regions: [Option<EcamRegion>; MAX_MCFG_ENTRIES],
+    // Number of populated entries in `regions`
+    nregions: usize,
+}
+
+impl Ecam {
+    /// Builds the accessor from the regions described by `mcfg`
+    pub fn from_mcfg(mcfg: &MCFG) -> Self {
+        let mut ecam = Self {
+            regions: [None; MAX_MCFG_ENTRIES],
+            nregions: 0,
+        };
+
+        for entry in mcfg.entries() {
+            ecam.regions[ecam.nregions] = Some(EcamRegion {
+                base_addr: entry.base_addr(),
+                segment: entry.segment(),
+                start_bus: entry.start_bus(),
+                end_bus: entry.end_bus(),
+            });
+            ecam.nregions += 1;
+        }
+
+        ecam
+    }
+
+    /// Builds the accessor from the MCFG found in `tables`. Returns `None` if there is no MCFG,
+    /// in which case only the legacy mechanism is available.
+    pub fn from_acpi(tables: &AcpiTables) -> Option<Self> {
+        tables.find::<MCFG>().map(|mcfg| Self::from_mcfg(&mcfg))
+    }
+
+    /// Returns all the regions, in the order they appear in the MCFG
+    pub fn regions(&self) -> impl Iterator<Item = &EcamRegion> {
+        self.regions[..self.nregions].iter().flatten()
+    }
+
+    /// Returns the physical address of the register at `offset` in the configuration space of
+    /// `addr`. The offset must be aligned on `size` bytes.
+    pub fn config_addr(
+        &self,
+        addr: PciAddress,
+        offset: u16,
+        size: u16,
+    ) -> Result<usize, EcamError> {
+        if addr.device >= 32 || addr.function >= 8 {
+            return Err(EcamError::InvalidFunction {
+                device: addr.device,
+                function: addr.function,
+            });
+        }
+        if offset >= CONFIG_SPACE_SIZE || !offset.is_multiple_of(size) {
+            return Err(EcamError::InvalidOffset(offset));
+        }
+
+        let region = self
+            .regions()
+            .find(|region| {
+                region.segment == addr.segment
+                    && (region.start_bus..=region.end_bus).contains(&addr.bus)
+            })
+            .ok_or(EcamError::NotMapped)?;
+
+        // Each bus takes 1 MiB, split in 32 devices of 8 functions of 4 KiB each
+        let function_offset =
+            (addr.bus as u64) << 20 | (addr.device as u64) << 15 | (addr.function as u64) << 12;
+
+        Ok((region.base_addr + function_offset + offset as u64) as usize)
+    }
+
+    /// Reads the byte at `offset` in the configuration space of `addr`
+    ///
+    /// # Safety
+    ///
+    /// The ECAM regions must be mapped, and reading a register can have side effects on the
+    /// device, which the caller must own.
+    pub unsafe fn read_u8(&self, addr: PciAddress, offset: u16) -> Result<u8, EcamError> {
+        let reg = self.config_addr(addr, offset, 1)?;
+        Ok(core::ptr::read_volatile(reg as *const u8))
+    }
+
+    /// Reads the word at the word aligned `offset` in the configuration space of `addr`
+    ///
+    /// # Safety
+    ///
+    /// The ECAM regions must be mapped, and reading a register can have side effects on the
+    /// device, which the caller must own.
+    pub unsafe fn read_u16(&self, addr: PciAddress, offset: u16) -> Result<u16, EcamError> {
+        let reg = self.config_addr(addr, offset, 2)?;
+        Ok(core::ptr::read_volatile(reg as *const u16))
+    }
+
+    /// Reads the dword at the dword aligned `offset` in the configuration space of `addr`
+    ///
+    /// # Safety
+    ///
+    /// The ECAM regions must be mapped, and reading a register can have side effects on the
+    /// device, which the caller must own.
+    pub unsafe fn read_u32(&self, addr: PciAddress, offset: u16) -> Result<u32, EcamError> {
+        let reg = self.config_addr(addr, offset, 4)?;
+        Ok(core::ptr::read_volatile(reg as *const u32))
+    }
+
+    /// Writes the byte at `offset` in the configuration space of `addr`
+    ///
+    /// # Safety
+    ///
+    /// The ECAM regions must be mapped, and writing a register can have any side effect on the
+    /// device, which the caller must own.
+    pub unsafe fn write_u8(
+        &self,
+        addr: PciAddress,
+        offset: u16,
+        value: u8,
+    ) -> Result<(), EcamError> {
+        let reg = self.config_addr(addr, offset, 1)?;
+        core::ptr::write_volatile(reg as *mut u8, value);
+        Ok(())
+    }
+
+    /// Writes the word at the word aligned `offset` in the configuration space of `addr`
+    ///
+    /// # Safety
+    ///
+    /// The ECAM regions must be mapped, and writing a register can have any side effect on the
+    /// device, which the caller must own.
+    pub unsafe fn write_u16(
+        &self,
+        addr: PciAddress,
+        offset: u16,
+        value: u16,
+    ) -> Result<(), EcamError> {
+        let reg = self.config_addr(addr, offset, 2)?;
+        core::ptr::write_volatile(reg as *mut u16, value);
+        Ok(())
+    }
+
+    /// Writes the dword at the dword aligned `offset` in the configuration space of `addr`
+    ///
+    /// # Safety
+    ///
+    /// The ECAM regions must be mapped, and writing a register can have any side effect on the
+    /// device, which the caller must own.
+    pub unsafe fn write_u32(
+        &self,
+        addr: PciAddress,
+        offset: u16,
+        value: u32,
+    ) -> Result<(), EcamError> {
+        let reg = self.config_addr(addr, offset, 4)?;
+        core::ptr::write_volatile(reg as *mut u32, value);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::efi::acpi::MCFG;
+    use crate::test_support::{mcfg, Machine, Q35};
+
+    #[test]
+    fn ecam_addresses() {
+        let table = MCFG::from_bytes(Q35::table("mcfg")).unwrap();
+        let ecam = Ecam::from_mcfg(&table);
+        let function = |bus, device, function| PciAddress::new(0, bus, device, function);
+
+        // Each bus takes 1 MiB, each device 32 KiB and each function 4 KiB
+        assert_eq!(ecam.config_addr(function(0, 0, 0), 0, 4), Ok(0xB000_0000));
+        assert_eq!(
+            ecam.config_addr(function(1, 0x1F, 7), 0xFFC, 4),
+            Ok(0xB000_0000 + (1 << 20) + (0x1F << 15) + (7 << 12) + 0xFFC)
+        );
+        assert_eq!(
+            ecam.config_addr(function(0xFF, 2, 3), 0x101, 1),
+            Ok(0xB000_0000 + (0xFF << 20) + (2 << 15) + (3 << 12) + 0x101)
+        );
+
+        // Offsets must be within the 4 KiB of the function, and aligned on the access size
+        for (offset, size) in [(0x1000, 1), (0xFFFF, 1), (0x102, 4), (0x3, 2)] {
+            assert_eq!(
+                ecam.config_addr(function(0, 0, 0), offset, size),
+                Err(EcamError::InvalidOffset(offset))
+            );
+        }
+        assert_eq!(
+            ecam.config_addr(PciAddress::new(1, 0, 0, 0), 0, 4),
+            Err(EcamError::NotMapped)
+        );
+
+        // There are 32 devices on each bus, and 8 functions in each device
+        for (device, function) in [(32, 0), (0xFF, 0), (0, 8), (0x1F, 0xFF)] {
+            assert_eq!(
+                ecam.config_addr(PciAddress::new(0, 0, device, function), 0, 4),
+                Err(EcamError::InvalidFunction { device, function })
+            );
+        }
+
+        // Regions that start at a later bus have their base given for bus 0 anyway
+        let table = MCFG::from_bytes(&mcfg(&[
+            (0xB000_0000, 0, 0, 0xFF),
+            (0xC000_0000, 1, 0x10, 0x1F),
+        ]))
+        .unwrap();
+        let ecam = Ecam::from_mcfg(&table);
+        assert_eq!(ecam.regions().count(), 2);
+        assert_eq!(
+            ecam.config_addr(PciAddress::new(1, 0x10, 1, 0), 0x10, 4),
+            Ok(0xC000_0000 + (0x10 << 20) + (1 << 15) + 0x10)
+        );
+        for bus in [0x0F, 0x20] {
+            assert_eq!(
+                ecam.config_addr(PciAddress::new(1, bus, 0, 0), 0, 4),
+                Err(EcamError::NotMapped)
+            );
+        }
+    }
+}
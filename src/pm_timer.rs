@@ -0,0 +1,169 @@
+//! Driver for the ACPI Power Management Timer, a free running counter of the chipset that is
+//! always present on platforms that are not hardware-reduced ACPI
+use crate::efi::acpi::{AcpiTables, FixedFeatureFlags, GasError, GenericAddress, FADT};
+
+/// The frequency of the PM timer, in Hz
+pub const PM_TIMER_FREQUENCY: u64 = 3_579_545;
+
+/// The number of nanoseconds in a second
+const NS_PER_SECOND: u64 = 1_000_000_000;
+
+/// Reasons for which the PM timer cannot be used
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PmTimerError {
+    /// There is no FADT, or it does not describe a PM timer
+    NotFound,
+    /// The timer register cannot be accessed
+    InvalidRegister(GasError),
+}
+
+impl From<GasError> for PmTimerError {
+    fn from(value: GasError) -> Self {
+        Self::InvalidRegister(value)
+    }
+}
+
+/// The PM timer, counting up at `PM_TIMER_FREQUENCY` and wrapping around every 4.7 seconds when
+/// 24 bits wide, or every 20 minutes when 32 bits wide
+#[derive(Debug, Clone, Copy)]
+pub struct PmTimer {
+    // The timer register, read as a whole
+    reg: GenericAddress,
+    // Mask of the bits the counter uses
+    mask: u32,
+}
+
+impl PmTimer {
+    /// Sets up the timer whose register is in the PM_TMR_BLK of `fadt`. The counter's width is
+    /// given by the TMR_VAL_EXT flag.
+    pub fn new(fadt: &FADT) -> Result<Self, PmTimerError> {
+        let blk = fadt.pm_tmr_blk().ok_or(PmTimerError::NotFound)?;
+        let reg = blk.decode()?;
+        let mask = match fadt.flags().contains(FixedFeatureFlags::TMR_VAL_EXT) {
+            true => u32::MAX,
+            false => (1 << 24) - 1,
+        };
+
+        Ok(Self { reg, mask })
+    }
+
+    /// Sets up the timer described by the FADT of the ACPI tables
+    pub fn from_acpi(tables: &AcpiTables) -> Result<Self, PmTimerError> {
+        let fadt = tables.find::<FADT>().ok_or(PmTimerError::NotFound)?;
+        Self::new(&fadt)
+    }
+
+    /// Returns the number of bits of the counter, which is either 24 or 32
+    pub fn bits(&self) -> u32 {
+        self.mask.count_ones()
+    }
+
+    /// Returns the value of the counter
+    pub fn counter(&self) -> u32 {
+        // SAFETY: the register was advertised by the FADT, and reading it has no side effect
+        unsafe { self.reg.read() as u32 & self.mask }
+    }
+
+    /// Returns the number of ticks between the counter reads `start` and `end`, accounting for
+    /// the counter wrapping around once
+    pub fn ticks_between(&self, start: u32, end: u32) -> u32 {
+        end.wrapping_sub(start) & self.mask
+    }
+
+    /// Returns the number of ticks elapsed since the counter read `start`. This is only right if
+    /// the counter did not wrap around more than once since.
+    pub fn elapsed_since(&self, start: u32) -> u32 {
+        self.ticks_between(start, self.counter())
+    }
+
+    /// Returns the number of ticks in `ns` nanoseconds, rounded up
+    pub fn ns_to_ticks(&self, ns: u64) -> u64 {
+        (ns as u128 * PM_TIMER_FREQUENCY as u128).div_ceil(NS_PER_SECOND as u128) as u64
+    }
+
+    /// Returns the number of nanoseconds in `ticks` ticks, rounded down
+    pub fn ticks_to_ns(&self, ticks: u64) -> u64 {
+        (ticks as u128 * NS_PER_SECOND as u128 / PM_TIMER_FREQUENCY as u128) as u64
+    }
+
+    /// Spins until `ns` nanoseconds have elapsed. Waits longer than the wrap around period of the
+    /// counter are fine, since the elapsed ticks are added up at each read.
+    pub fn wait_ns(&self, ns: u64) {
+        self.wait_until(ns, || false);
+    }
+
+    /// Spins until `ns` nanoseconds have elapsed or `done` returns true, which is called at each
+    /// read of the counter. Returns whether `done` returned true.
+    pub fn wait_until(&self, ns: u64, mut done: impl FnMut() -> bool) -> bool {
+        let ticks = self.ns_to_ticks(ns);
+        let mut elapsed = 0u64;
+        let mut last = self.counter();
+
+        while elapsed < ticks {
+            if done() {
+                return true;
+            }
+            core::hint::spin_loop();
+
+            let now = self.counter();
+            elapsed += self.ticks_between(last, now) as u64;
+            last = now;
+        }
+
+        false
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::efi::acpi::address_space;
+    use crate::test_support::{fadt_with_block, gas};
+    use std::sync::atomic::{AtomicU32, Ordering};
+
+    #[test]
+    fn pm_timer_wraparound() {
+        // The X_PM_TMR_BLK, in system memory
+        let counter = AtomicU32::new(0);
+        let blk = gas(
+            address_space::SYSTEM_MEMORY,
+            32,
+            0,
+            3,
+            counter.as_ptr() as u64,
+        );
+
+        // A 24-bit counter ignores the upper byte of the register, and wraps around at 2^24
+        let timer = PmTimer::new(&fadt_with_block(FixedFeatureFlags::empty(), 208, blk)).unwrap();
+        assert_eq!(timer.bits(), 24);
+        counter.store(0xAB12_3456, Ordering::SeqCst);
+        assert_eq!(timer.counter(), 0x12_3456);
+        assert_eq!(timer.ticks_between(0xFF_FFF0, 0x10), 0x20);
+        assert_eq!(timer.ticks_between(0x10, 0xFF_FFF0), 0xFF_FFE0);
+        assert_eq!(timer.ticks_between(0x12_3456, 0x12_3456), 0);
+        assert_eq!(timer.elapsed_since(0xFF_0000), 0x13_3456);
+
+        // A 32-bit counter wraps around at 2^32
+        let flags = FixedFeatureFlags::TMR_VAL_EXT;
+        let timer = PmTimer::new(&fadt_with_block(flags, 208, blk)).unwrap();
+        assert_eq!(timer.bits(), 32);
+        assert_eq!(timer.counter(), 0xAB12_3456);
+        assert_eq!(timer.ticks_between(0xFFFF_FFF0, 0x10), 0x20);
+        assert_eq!(timer.ticks_between(0xFF_FFF0, 0x10), 0xFF00_0020);
+        assert_eq!(timer.elapsed_since(0xAB00_0000), 0x12_3456);
+
+        // Waits longer than the wrap around period add up the ticks of each read: 5 s is 17.9 M
+        // ticks, or 5 reads of a 24-bit counter moving 4 M ticks between reads
+        let timer = PmTimer::new(&fadt_with_block(FixedFeatureFlags::empty(), 208, blk)).unwrap();
+        assert_eq!(timer.ns_to_ticks(5_000_000_000), 17_897_725);
+        let mut reads = 0;
+        let done = timer.wait_until(5_000_000_000, || {
+            reads += 1;
+            counter.fetch_add(0x40_0000, Ordering::SeqCst);
+            false
+        });
+        assert!(!done);
+        assert_eq!(reads, 5);
+        assert!(timer.wait_until(5_000_000_000, || true));
+    }
+}
@@ -1,6 +1,11 @@
 //! Module that holds the print macro
-use core::sync::atomic::{AtomicPtr, Ordering};
+use core::cell::UnsafeCell;
+use core::sync::atomic::{AtomicBool, AtomicU8, AtomicUsize, Ordering};
 use crate::cpu::{inb, outb};
+use crate::efi::acpi::{
+    serial_interface, AcpiTables, AddressSpace, DebugDevice, GenericAddressStructure,
+    SpcrFlowControl, DBG2, SPCR,
+};
 
 // Dummy writer we can implement `Write` trait on, so that we can support formatted strings
 pub struct ConsoleOutWriter;
@@ -13,11 +18,234 @@ impl core::fmt::Write for ConsoleOutWriter {
     }
 }
 
-/// Know address for the COM1 serial port(should be taken from the BDA)
+/// Know address for the COM1 serial port, used when neither the SPCR nor the BDA give us one
 pub const PORT_COM1: u16 = 0x3F8;
 
-/// Static pointer that hold the COM port pointer we want to write to
-pub static SERIAL_PORT: AtomicPtr<u16> = AtomicPtr::new(core::ptr::null_mut());
+/// The baud rate of the ports that are not described by the SPCR
+pub const DEFAULT_BAUD_RATE: u32 = 38400;
+
+/// The clock of PC compatible UARTs, which is 16 times their highest baud rate
+pub const DEFAULT_UART_CLOCK: u32 = 1_843_200;
+
+/// Physical address of the I/O ports of COM1 to COM4 in the BIOS Data Area
+const BDA_COM_PORTS: usize = 0x400;
+
+/// The I/O ports of COM1 to COM4 on PC compatible platforms
+const LEGACY_COM_PORTS: [u16; 4] = [PORT_COM1, 0x2F8, 0x3E8, 0x2E8];
+
+/// The number of times we poll the modem status for a flow control signal, before transmitting
+/// anyway. This keeps a missing cable from hanging the system.
+const FLOW_CONTROL_SPINS: usize = 1_000_000;
+
+/// Registers of a 16550 compatible UART, as offsets in register units
+mod uart_reg {
+    /// Transmit Holding Register, or Receive Buffer Register when read
+    pub const DATA: usize = 0;
+    pub const INTERRUPT_ENABLE: usize = 1;
+    /// FIFO Control Register
+    pub const FIFO_CONTROL: usize = 2;
+    pub const LINE_CONTROL: usize = 3;
+    pub const MODEM_CONTROL: usize = 4;
+    pub const LINE_STATUS: usize = 5;
+    pub const MODEM_STATUS: usize = 6;
+    /// Low byte of the baud rate divisor, when the DLAB bit of the Line Control Register is set
+    pub const DIVISOR_LOW: usize = 0;
+    /// High byte of the baud rate divisor, when the DLAB bit of the Line Control Register is set
+    pub const DIVISOR_HIGH: usize = 1;
+}
+
+// XON/XOFF software flow control characters
+const XON: u8 = 0x11;
+const XOFF: u8 = 0x13;
+
+/// Where the registers of a 16550 compatible UART are
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UartRegisters {
+    /// Consecutive I/O ports, starting at the given port
+    Io(u16),
+    /// Memory mapped registers, `width` bytes apart and accessed with `width` bytes wide
+    /// accesses
+    Mmio { addr: usize, width: u8 },
+}
+
+impl UartRegisters {
+    /// Decodes where the registers described by `base_addr` are. Returns `None` if they are not
+    /// in system memory nor in system I/O, if they are at the null address, or if they need
+    /// QWord accesses, which no 16550 compatible UART uses.
+    pub fn from_gas(base_addr: &GenericAddressStructure) -> Option<Self> {
+        if base_addr.address == 0 {
+            return None;
+        }
+
+        match AddressSpace::from(base_addr.address_space_id) {
+            AddressSpace::SystemIo => Some(Self::Io(u16::try_from(base_addr.address).ok()?)),
+            AddressSpace::SystemMemory => Some(Self::Mmio {
+                addr: base_addr.address as usize,
+                // The access size gives the register width, with the bit width as a fallback
+                width: match (base_addr.access_size, base_addr.register_bit_width) {
+                    (4, _) | (0, 64) => return None,
+                    (3, _) | (0, 32) => 4,
+                    (2, _) | (0, 16) => 2,
+                    _ => 1,
+                },
+            }),
+            _ => None,
+        }
+    }
+
+    unsafe fn read(&self, reg: usize) -> u8 {
+        match *self {
+            Self::Io(port) => inb(port + reg as u16),
+            Self::Mmio { addr, width: 4 } => {
+                core::ptr::read_volatile((addr + reg * 4) as *const u32) as u8
+            }
+            Self::Mmio { addr, width: 2 } => {
+                core::ptr::read_volatile((addr + reg * 2) as *const u16) as u8
+            }
+            Self::Mmio { addr, .. } => core::ptr::read_volatile((addr + reg) as *const u8),
+        }
+    }
+
+    unsafe fn write(&self, reg: usize, value: u8) {
+        match *self {
+            Self::Io(port) => outb(port + reg as u16, value),
+            Self::Mmio { addr, width: 4 } => {
+                core::ptr::write_volatile((addr + reg * 4) as *mut u32, value as u32)
+            }
+            Self::Mmio { addr, width: 2 } => {
+                core::ptr::write_volatile((addr + reg * 2) as *mut u16, value as u16)
+            }
+            Self::Mmio { addr, .. } => core::ptr::write_volatile((addr + reg) as *mut u8, value),
+        }
+    }
+}
+
+/// Parity of the characters sent over the serial port
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Parity {
+    None,
+    Odd,
+    Even,
+    Mark,
+    Space,
+}
+
+/// How a serial port is set up
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SerialConfig {
+    pub registers: UartRegisters,
+    /// The baud rate to program, or `None` to keep the one the firmware programmed
+    pub baud_rate: Option<u32>,
+    /// The frequency of the UART clock, in Hz
+    pub uart_clock: u32,
+    pub parity: Parity,
+    /// Either 1 or 2 stop bits
+    pub stop_bits: u8,
+    pub flow_control: SpcrFlowControl,
+}
+
+impl SerialConfig {
+    /// The configuration of a PC compatible serial port, at `DEFAULT_BAUD_RATE` and without flow
+    /// control
+    pub fn legacy(port: u16) -> Self {
+        Self {
+            registers: UartRegisters::Io(port),
+            baud_rate: Some(DEFAULT_BAUD_RATE),
+            uart_clock: DEFAULT_UART_CLOCK,
+            parity: Parity::None,
+            stop_bits: 1,
+            flow_control: SpcrFlowControl::empty(),
+        }
+    }
+
+    /// The configuration of the serial port described by the SPCR. Returns `None` if the port is
+    /// not a 16550 compatible one we can reach.
+    pub fn from_spcr(spcr: &SPCR) -> Option<Self> {
+        if !serial_interface::is_16550_compatible(spcr.interface_type()) {
+            return None;
+        }
+
+        let registers = UartRegisters::from_gas(&spcr.base_addr())?;
+
+        Some(Self {
+            registers,
+            baud_rate: spcr.baud_rate(),
+            uart_clock: spcr.uart_clock_freq().unwrap_or(DEFAULT_UART_CLOCK),
+            // Only no parity and a single stop bit are defined by the specification
+            parity: Parity::None,
+            stop_bits: if spcr.stop_bits() == 2 { 2 } else { 1 },
+            flow_control: spcr.flow_control(),
+        })
+    }
+
+    /// The configuration of the serial debug port `device` of the DBG2. Returns `None` if the
+    /// port is not a 16550 compatible one we can reach. The DBG2 does not give the line settings,
+    /// so the ones the firmware programmed are kept.
+    pub fn from_dbg2(device: &DebugDevice) -> Option<Self> {
+        if !device.is_16550_compatible() {
+            return None;
+        }
+
+        Some(Self {
+            registers: UartRegisters::from_gas(&device.base_addr()?)?,
+            baud_rate: None,
+            uart_clock: DEFAULT_UART_CLOCK,
+            parity: Parity::None,
+            stop_bits: 1,
+            flow_control: SpcrFlowControl::empty(),
+        })
+    }
+
+    /// The configuration of the first serial debug port of the DBG2 of `tables` we can drive.
+    /// This is the port a debugger or a log sink should use, which may differ from the console.
+    pub fn debug_port(tables: &AcpiTables) -> Option<Self> {
+        tables.find::<DBG2>()?.serial_ports().find_map(Self::from_dbg2)
+    }
+}
+
+/// Static that holds the base of the registers of the serial port we write to: the first I/O port,
+/// or the address of the memory mapped registers. It is 0 until a port is selected.
+pub static SERIAL_BASE: AtomicUsize = AtomicUsize::new(0);
+
+// The width of the memory mapped registers of the serial port, or 0 if it uses I/O ports
+static SERIAL_MMIO_WIDTH: AtomicU8 = AtomicU8::new(0);
+
+// The flow control the serial port uses, as `SpcrFlowControl` bits
+static SERIAL_FLOW_CONTROL: AtomicU8 = AtomicU8::new(0);
+
+/// Size of the buffer that holds the output printed before a serial port is selected
+const HELD_OUTPUT_SIZE: usize = 4096;
+
+// Output printed while `hold_output` is in effect, written to the serial port once one is
+// selected. Bytes past `HELD_OUTPUT_SIZE` are dropped.
+struct HeldOutput(UnsafeCell<[u8; HELD_OUTPUT_SIZE]>);
+
+// SAFETY: output is only held while the bootstrap processor runs alone, before the console is
+// selected, so the buffer is never accessed concurrently
+unsafe impl Sync for HeldOutput {}
+
+static HELD_OUTPUT: HeldOutput = HeldOutput(UnsafeCell::new([0; HELD_OUTPUT_SIZE]));
+// Number of bytes in `HELD_OUTPUT`
+static HELD_OUTPUT_LEN: AtomicUsize = AtomicUsize::new(0);
+// Whether output is held instead of selecting the default serial port
+static HOLD_OUTPUT: AtomicBool = AtomicBool::new(false);
+
+// Appends the text written to it to `HELD_OUTPUT`
+#[cfg(not(any(test, fuzzing)))]
+struct HeldOutputWriter;
+
+#[cfg(not(any(test, fuzzing)))]
+impl core::fmt::Write for HeldOutputWriter {
+    fn write_str(&mut self, s: &str) -> core::fmt::Result {
+        let len = HELD_OUTPUT_LEN.load(Ordering::SeqCst);
+        let count = s.len().min(HELD_OUTPUT_SIZE - len);
+        // SAFETY: see `HeldOutput`
+        let buffer = unsafe { &mut *HELD_OUTPUT.0.get() };
+        buffer[len..len + count].copy_from_slice(&s.as_bytes()[..count]);
+        HELD_OUTPUT_LEN.store(len + count, Ordering::SeqCst);
+        Ok(())
+    }
+}
 
 pub struct SerialWriter;
 
@@ -31,76 +259,218 @@ impl core::fmt::Write for SerialWriter {
 }
 
 impl SerialWriter {
-    pub fn init_serial(port: &mut u16) {
-        SERIAL_PORT
-            .compare_exchange(
-                core::ptr::null_mut(),
-                port,
-                Ordering::SeqCst,
-                Ordering::SeqCst,
-            )
-            .unwrap();
-
-        let port = SERIAL_PORT.load(Ordering::SeqCst);
-
-        if port.is_null() {
-            return;
+    /// Returns whether a serial port was selected to write to
+    pub fn is_initialized() -> bool {
+        SERIAL_BASE.load(Ordering::SeqCst) != 0
+    }
+
+    // Returns the registers of the selected serial port
+    fn registers() -> Option<UartRegisters> {
+        let base = SERIAL_BASE.load(Ordering::SeqCst);
+        if base == 0 {
+            return None;
         }
 
+        match SERIAL_MMIO_WIDTH.load(Ordering::SeqCst) {
+            0 => Some(UartRegisters::Io(base as u16)),
+            width => Some(UartRegisters::Mmio { addr: base, width }),
+        }
+    }
+
+    // Makes the serial port described by `config` the one we write to
+    fn select(config: &SerialConfig) {
+        let (base, width) = match config.registers {
+            UartRegisters::Io(port) => (port as usize, 0),
+            UartRegisters::Mmio { addr, width } => (addr, width),
+        };
+
+        SERIAL_MMIO_WIDTH.store(width, Ordering::SeqCst);
+        SERIAL_FLOW_CONTROL.store(config.flow_control.bits(), Ordering::SeqCst);
+        SERIAL_BASE.store(base, Ordering::SeqCst);
+    }
+
+    /// Sets up the serial port described by `config` and, if it passes its loopback test, makes
+    /// it the one we write to. Returns whether the port was selected.
+    pub fn init_serial(config: &SerialConfig) -> bool {
+        let regs = config.registers;
+
+        let line_control = 0b00000011 // 8 bits
+            | if config.stop_bits == 2 { 0b00000100 } else { 0 }
+            | match config.parity {
+                Parity::None => 0b00000000,
+                Parity::Odd => 0b00001000,
+                Parity::Even => 0b00011000,
+                Parity::Mark => 0b00101000,
+                Parity::Space => 0b00111000,
+            };
+
         unsafe {
-            outb(*port + 1, 0x00); // Disable all interrupts
-            outb(*port + 3, 0x80); // Enable DLAB (set baud rate divisor)
-            outb(*port + 0, 0x03); // Set divisor to 3 (lo byte) 38400 baud
-            outb(*port + 1, 0x00); //                  (hi byte)
-            outb(*port + 3, 0b00000011); // 8 bits, no parity, one stop bit
-            outb(*port + 2, 0b11000111); // Enable FIFO, clear them, with 14-byte threshold
-            outb(*port + 4, 0x0B); // IRQs enabled, RTS/DSR set
-            outb(*port + 4, 0x1E); // Set in loopback mode, test the serial chip
-            outb(*port + 0, 0xAE); // Test serial chip
-                                   // (send byte 0xAE and check if serial returns same byte)
+            regs.write(uart_reg::INTERRUPT_ENABLE, 0x00); // Disable all interrupts
+            if let Some(baud_rate) = config.baud_rate {
+                let divisor = (config.uart_clock / (16 * baud_rate.max(1))).clamp(1, 0xFFFF);
+                regs.write(uart_reg::LINE_CONTROL, 0x80); // Enable DLAB (set baud rate divisor)
+                regs.write(uart_reg::DIVISOR_LOW, divisor as u8);
+                regs.write(uart_reg::DIVISOR_HIGH, (divisor >> 8) as u8);
+            }
+            regs.write(uart_reg::LINE_CONTROL, line_control);
+            // Enable FIFO, clear them, with 14-byte threshold
+            regs.write(uart_reg::FIFO_CONTROL, 0b11000111);
+            regs.write(uart_reg::MODEM_CONTROL, 0x0B); // IRQs enabled, RTS/DSR set
+            regs.write(uart_reg::MODEM_CONTROL, 0x1E); // Set in loopback mode, test the serial chip
+            // Test serial chip (send byte 0xAE and check if serial returns same byte)
+            regs.write(uart_reg::DATA, 0xAE);
         };
 
         // Check if serial is faulty (i.e: not same byte as sent)
-        if (unsafe { inb(*port + 0) } != 0xAE) {
-            // We just keep this print here for debug purposes
-            crate::print!("Faulty");
-            return;
+        if unsafe { regs.read(uart_reg::DATA) } != 0xAE {
+            return false;
         }
 
         // If serial is not faulty set it in normal operation mode
         // (not-loopback with IRQs enabled and OUT#1 and OUT#2 bits enabled)
         unsafe {
-            outb(*port + 4, 0b00001111);
+            regs.write(uart_reg::MODEM_CONTROL, 0b00001111);
         }
+
+        Self::select(config);
+        true
     }
 
-    pub fn transmit(byte: u8) {
-        let port = SERIAL_PORT.load(Ordering::SeqCst);
+    /// Selects the first working legacy COM port listed in the BIOS Data Area, falling back to
+    /// COM1. COM1 is selected even if it fails its loopback test, as there is nothing else left
+    /// to try.
+    pub fn init_default() {
+        // SAFETY: the BIOS Data Area is in the first page of memory, which the firmware
+        // identity maps
+        let bda = core::array::from_fn(|idx| unsafe {
+            core::ptr::read_volatile((BDA_COM_PORTS + idx * 2) as *const u16)
+        });
 
-        if port.is_null() {
-            return;
+        for port in bda_com_ports(bda) {
+            if Self::init_serial(&SerialConfig::legacy(port)) {
+                return;
+            }
+        }
+
+        let com1 = SerialConfig::legacy(PORT_COM1);
+        if !Self::init_serial(&com1) {
+            Self::select(&com1);
+        }
+    }
+
+    /// Holds the output printed until `select_console` is called, instead of writing it to the
+    /// default serial port. This keeps the messages printed while looking for the SPCR from
+    /// going to a port other than the console. Only the first 4 KiB are kept.
+    pub fn hold_output() {
+        if !Self::is_initialized() {
+            HOLD_OUTPUT.store(true, Ordering::SeqCst);
+        }
+    }
+
+    /// Selects the serial port described by the SPCR of `tables`, falling back to the COM ports
+    /// of the BIOS Data Area and then to COM1. The output held since `hold_output` is written to
+    /// the selected port.
+    pub fn select_console(tables: Option<&AcpiTables>) {
+        let spcr_config = tables
+            .and_then(|tables| tables.find::<SPCR>())
+            .and_then(|spcr| SerialConfig::from_spcr(&spcr));
+
+        match spcr_config {
+            Some(config) if Self::init_serial(&config) => {}
+            _ => Self::init_default(),
+        }
+
+        if HOLD_OUTPUT.swap(false, Ordering::SeqCst) {
+            let len = HELD_OUTPUT_LEN.swap(0, Ordering::SeqCst);
+            // SAFETY: see `HeldOutput`
+            let held = unsafe { &(&*HELD_OUTPUT.0.get())[..len] };
+            for byte in held {
+                Self::transmit(*byte);
+            }
         }
+    }
+
+    pub fn transmit(byte: u8) {
+        let Some(regs) = Self::registers() else {
+            return;
+        };
+        let flow_control =
+            SpcrFlowControl::from_bits_retain(SERIAL_FLOW_CONTROL.load(Ordering::SeqCst));
 
         unsafe {
-            while (inb(*port + 5) & 0x20) == 0 {}
-            outb(*port, byte);
+            while (regs.read(uart_reg::LINE_STATUS) & 0x20) == 0 {}
+
+            // Wait for the other end to be ready, as long as the cable seems to be plugged in
+            if flow_control.intersects(SpcrFlowControl::RTS_CTS | SpcrFlowControl::DCD) {
+                let mut ready_mask = 0;
+                if flow_control.contains(SpcrFlowControl::RTS_CTS) {
+                    ready_mask |= 0x10; // Clear To Send
+                }
+                if flow_control.contains(SpcrFlowControl::DCD) {
+                    ready_mask |= 0x80; // Data Carrier Detect
+                }
+                for _ in 0..FLOW_CONTROL_SPINS {
+                    if regs.read(uart_reg::MODEM_STATUS) & ready_mask == ready_mask {
+                        break;
+                    }
+                }
+            }
+
+            // The other end asks us to pause by sending XOFF, until it sends XON
+            if flow_control.contains(SpcrFlowControl::XON_XOFF)
+                && regs.read(uart_reg::LINE_STATUS) & 0x01 != 0
+                && regs.read(uart_reg::DATA) == XOFF
+            {
+                for _ in 0..FLOW_CONTROL_SPINS {
+                    if regs.read(uart_reg::LINE_STATUS) & 0x01 != 0
+                        && regs.read(uart_reg::DATA) == XON
+                    {
+                        break;
+                    }
+                }
+            }
+
+            regs.write(uart_reg::DATA, byte);
+        }
+    }
+}
+
+// Returns the ports of the COM1 to COM4 entries `bda` of the BIOS Data Area that are legacy COM
+// ports. Without a compatibility support module, nothing fills the BIOS Data Area in, so anything
+// else found there is not taken for a port.
+fn bda_com_ports(bda: [u16; 4]) -> impl Iterator<Item = u16> {
+    bda.into_iter().filter(|port| LEGACY_COM_PORTS.contains(port))
+}
+
+/// Writes `args` to the serial port, selecting the default one if none was selected yet, unless
+/// the output is held until the console is selected. This is what `print!` expands to. Host builds
+/// have no serial port: the unit tests write to the standard output instead, and the fuzz targets
+/// discard the text.
+#[doc(hidden)]
+pub fn _print(args: core::fmt::Arguments) {
+    #[cfg(test)]
+    std::print!("{}", args);
+
+    #[cfg(all(fuzzing, not(test)))]
+    let _ = args;
+
+    #[cfg(not(any(test, fuzzing)))]
+    {
+        if HOLD_OUTPUT.load(Ordering::SeqCst) {
+            core::fmt::write(&mut HeldOutputWriter, args).unwrap();
+            return;
+        }
+        if !SerialWriter::is_initialized() {
+            SerialWriter::init_default();
         }
+        core::fmt::write(&mut SerialWriter, args).unwrap();
     }
 }
 
 #[macro_export]
 macro_rules! print {
     ( $($arg:tt)* ) => {
-        // Get the COM port pointer
-        let port = $crate::print::SERIAL_PORT.load(core::sync::atomic::Ordering::SeqCst);
-
-        // If the port is null, initialize the port
-        if port.is_null() {
-            let mut port_com1 = $crate::print::PORT_COM1;
-            $crate::print::SerialWriter::init_serial(&mut port_com1);
-        }
-        let mut serial_writer = $crate::print::SerialWriter;
-        core::fmt::write(&mut serial_writer, core::format_args!($($arg)*)).unwrap();
+        $crate::print::_print(core::format_args!($($arg)*))
     }
 }
 
@@ -111,3 +481,167 @@ macro_rules! print_uefi {
         core::fmt::write(&mut console_writer, core::format_args!($($arg)*)).unwrap();
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::efi::acpi::{
+        address_space, debug_port_type, serial_interface, with_phys_memory,
+        GenericAddressStructure, SpcrFlowControl, SPCR,
+    };
+    use crate::test_support::{
+        dbg2, dbg2_device, gas, spcr, spcr_fields, with_entry, Machine, Q35,
+    };
+
+    #[test]
+    fn spcr_console() {
+        let com1 = gas(address_space::SYSTEM_IO, 8, 0, 1, PORT_COM1 as u64);
+
+        // COM1, at the baud rate the SPCR gives
+        let mut fields = spcr_fields(serial_interface::NS16550 as u8, com1);
+        fields.extend_from_slice(&[0; 4]);
+        let table = SPCR::from_bytes(&spcr(2, &fields)).unwrap();
+        assert_eq!(
+            SerialConfig::from_spcr(&table),
+            Some(SerialConfig {
+                registers: UartRegisters::Io(PORT_COM1),
+                baud_rate: Some(115200),
+                uart_clock: DEFAULT_UART_CLOCK,
+                parity: Parity::None,
+                stop_bits: 1,
+                flow_control: SpcrFlowControl::RTS_CTS,
+            })
+        );
+
+        // An MMIO UART with a precise baud rate, a UART clock and 2 stop bits
+        let uart = gas(address_space::SYSTEM_MEMORY, 32, 0, 3, 0xFE03_2000);
+        let mut fields = spcr_fields(serial_interface::NS16550_GAS as u8, uart);
+        fields[24] = 2;
+        fields.extend_from_slice(&48_000_000u32.to_le_bytes());
+        fields.extend_from_slice(&1_500_000u32.to_le_bytes());
+        fields.extend_from_slice(&[0; 4]);
+        let config =
+            SerialConfig::from_spcr(&SPCR::from_bytes(&spcr(4, &fields)).unwrap()).unwrap();
+        assert_eq!(
+            config.registers,
+            UartRegisters::Mmio {
+                addr: 0xFE03_2000,
+                width: 4
+            }
+        );
+        assert_eq!(config.baud_rate, Some(1_500_000));
+        assert_eq!(config.uart_clock, 48_000_000);
+        assert_eq!(config.stop_bits, 2);
+
+        // The register width comes from the access size, or from the bit width when it is undefined
+        let widths = [
+            (0, 8, Some(1)),
+            (0, 16, Some(2)),
+            (2, 32, Some(2)),
+            (0, 64, None),
+            (4, 32, None),
+        ];
+        for (access_size, register_bit_width, width) in widths {
+            let uart = gas(
+                address_space::SYSTEM_MEMORY,
+                register_bit_width,
+                0,
+                access_size,
+                0xFE03_2000,
+            );
+            let registers = UartRegisters::from_gas(&uart);
+            assert_eq!(
+                registers.map(|registers| match registers {
+                    UartRegisters::Mmio { width, .. } => width,
+                    UartRegisters::Io(_) => 0,
+                }),
+                width,
+                "access size {} and bit width {}",
+                access_size,
+                register_bit_width
+            );
+        }
+
+        // Ports we cannot drive: not 16550 compatible, at the null address, or out of the I/O space
+        let unsupported = [
+            spcr_fields(serial_interface::ARM_PL011 as u8, uart),
+            spcr_fields(0, gas(address_space::SYSTEM_IO, 8, 0, 1, 0)),
+            spcr_fields(0, gas(address_space::SYSTEM_IO, 8, 0, 1, 0x1_03F8)),
+            spcr_fields(0, gas(address_space::PLATFORM_COMM_CHANNEL, 8, 0, 1, 0x3F8)),
+        ];
+        for fields in unsupported {
+            let table = SPCR::from_bytes(&spcr(2, &fields)).unwrap();
+            assert_eq!(SerialConfig::from_spcr(&table), None);
+        }
+
+        // A table too short to give a baud rate keeps the one the firmware programmed
+        let short = SPCR::from_bytes(&spcr(1, &spcr_fields(0, com1)[..16])).unwrap();
+        assert_eq!(
+            SerialConfig::from_spcr(&short).map(|config| config.baud_rate),
+            Some(None)
+        );
+    }
+
+    #[test]
+    fn dbg2_console() {
+        const DBG2_ADDR: usize = 0x7FB8_8000;
+        let serial = |subtype, addresses: &[(GenericAddressStructure, u32)]| {
+            dbg2_device(debug_port_type::SERIAL, subtype, addresses, b".\0", false)
+        };
+        let pl011 = gas(address_space::SYSTEM_MEMORY, 32, 0, 3, 0x0900_0000);
+        let high_io = gas(address_space::SYSTEM_IO, 8, 0, 1, 0x1_03F8);
+        let com2 = gas(address_space::SYSTEM_IO, 8, 0, 1, 0x2F8);
+        let devices = [
+            serial(serial_interface::ARM_PL011, &[(pl011, 0x1000)]),
+            serial(serial_interface::NS16550, &[]),
+            serial(serial_interface::NS16550, &[(high_io, 8)]),
+            serial(serial_interface::NS16550, &[(com2, 8)]),
+        ];
+
+        // Ports we cannot drive: not 16550 compatible, without registers, or out of the I/O space
+        let table = DBG2::from_bytes(&dbg2(&devices)).unwrap();
+        let configs: Vec<_> = table.devices().map(SerialConfig::from_dbg2).collect();
+        assert_eq!(configs[..3], [None, None, None]);
+        assert_eq!(
+            configs[3],
+            Some(SerialConfig {
+                registers: UartRegisters::Io(0x2F8),
+                baud_rate: None,
+                uart_clock: DEFAULT_UART_CLOCK,
+                parity: Parity::None,
+                stop_bits: 1,
+                flow_control: SpcrFlowControl::empty(),
+            })
+        );
+
+        // The debug port is the first serial port we can drive
+        let table = dbg2(&devices);
+        let xsdt = with_entry(Q35::table("xsdt"), DBG2_ADDR);
+        let mut memory = Q35::memory(&[("xsdt", &xsdt)], &[]);
+        memory.push((DBG2_ADDR, &table));
+        with_phys_memory(&memory, || {
+            let tables = AcpiTables::new(Q35::addr("rsdp")).unwrap();
+            let debug_port = SerialConfig::debug_port(&tables).unwrap();
+            assert_eq!(debug_port.registers, UartRegisters::Io(0x2F8));
+        });
+
+        // There is none without a DBG2
+        with_phys_memory(&Q35::memory(&[], &[]), || {
+            let tables = AcpiTables::new(Q35::addr("rsdp")).unwrap();
+            assert_eq!(SerialConfig::debug_port(&tables), None);
+        });
+    }
+
+    #[test]
+    fn bda_ports() {
+        // The ports of COM1 to COM4 are taken in the order of the BIOS Data Area, skipping the
+        // missing ones
+        let ports: Vec<u16> = bda_com_ports([0x2F8, 0, 0x3F8, 0x2E8]).collect();
+        assert_eq!(ports, [0x2F8, 0x3F8, 0x2E8]);
+
+        // Whatever is found there without a compatibility support module is not a port
+        let ports: Vec<u16> = bda_com_ports([0xFFFF, 0x1234, 0x3F9, 0x3E8]).collect();
+        assert_eq!(ports, [0x3E8]);
+        assert_eq!(bda_com_ports([0xAFAF; 4]).count(), 0);
+    }
+}
@@ -0,0 +1,286 @@
+//! Fixtures shared by the unit tests: the ACPI tables of the QEMU machines found in
+//! `tests/data/acpi`, and builders for the tables and structures the tests make up or damage
+use crate::efi::acpi::{checksum, FixedFeatureFlags, GenericAddressStructure, PhysRegion, FADT};
+
+// The tables of a machine, each one with the name `acpidump -b` gives it
+macro_rules! tables {
+    ($machine:literal: $($name:literal),*) => {
+        &[$((
+            $name,
+            include_bytes!(concat!(
+                env!("CARGO_MANIFEST_DIR"), "/tests/data/acpi/", $machine, "/", $name, ".dat"
+            )).as_slice(),
+        )),*]
+    };
+}
+
+/// QEMU's q35 machine with two processors, booted with OVMF
+pub(crate) struct Q35;
+
+/// QEMU's pc machine with one processor, booted with SeaBIOS, which only has an RSDT
+pub(crate) struct Pc;
+
+pub(crate) trait Machine {
+    const LAYOUT: &'static str;
+    const TABLES: &'static [(&'static str, &'static [u8])];
+
+    // Returns the table `name`
+    fn table(name: &str) -> &'static [u8] {
+        find_table(Self::TABLES, name)
+    }
+
+    // Returns the physical address of the table `name`
+    fn addr(name: &str) -> usize {
+        let file = format!("{name}.dat");
+        let line = Self::LAYOUT
+            .lines()
+            .find(|line| line.starts_with(&file))
+            .unwrap();
+        let addr = line.split_whitespace().nth(1).unwrap();
+        usize::from_str_radix(addr.trim_start_matches("0x"), 16).unwrap()
+    }
+
+    // Returns the physical memory of the machine, with `replaced` standing in for the tables of
+    // the same name, and without the tables named in `removed`
+    fn memory<'a>(replaced: &[(&str, &'a [u8])], removed: &[&str]) -> Vec<PhysRegion<'a>> {
+        Self::TABLES
+            .iter()
+            .filter(|(name, _)| !removed.contains(name))
+            .map(|(name, bytes)| {
+                let bytes = replaced
+                    .iter()
+                    .find(|(replaced, _)| replaced == name)
+                    .map_or(*bytes, |(_, bytes)| *bytes);
+                (Self::addr(name), bytes)
+            })
+            .collect()
+    }
+}
+
+impl Machine for Q35 {
+    const LAYOUT: &'static str = include_str!(concat!(
+        env!("CARGO_MANIFEST_DIR"),
+        "/tests/data/acpi/q35/layout.txt"
+    ));
+    const TABLES: &'static [(&'static str, &'static [u8])] = tables!(
+        "q35": "rsdp", "xsdt", "rsdt", "facp", "facs", "dsdt", "apic", "hpet", "mcfg", "waet"
+    );
+}
+
+impl Machine for Pc {
+    const LAYOUT: &'static str = include_str!(concat!(
+        env!("CARGO_MANIFEST_DIR"),
+        "/tests/data/acpi/pc/layout.txt"
+    ));
+    const TABLES: &'static [(&'static str, &'static [u8])] =
+        tables!("pc": "rsdp", "rsdt", "facp", "facs", "dsdt", "apic", "hpet", "waet");
+}
+
+/// A Firecracker guest, whose tables were read from `/sys/firmware/acpi/tables`. Unlike the QEMU
+/// ones, they are exactly what the VMM hands to the guest, but the RSDP and the addresses of the
+/// tables are not exposed there.
+pub(crate) struct Firecracker;
+
+impl Firecracker {
+    const TABLES: &'static [(&'static str, &'static [u8])] =
+        tables!("firecracker": "facp", "apic", "mcfg", "dsdt");
+
+    // Returns the table `name`
+    pub(crate) fn table(name: &str) -> &'static [u8] {
+        find_table(Self::TABLES, name)
+    }
+}
+
+// Returns the table `name` amongst `tables`
+fn find_table(tables: &[(&str, &'static [u8])], name: &str) -> &'static [u8] {
+    let (_, bytes) = tables.iter().find(|(table, _)| *table == name).unwrap();
+    bytes
+}
+
+// Returns a copy of `table` whose length field says `length`, with its checksum fixed
+pub(crate) fn with_length(table: &[u8], length: u32) -> Vec<u8> {
+    let mut table = table.to_vec();
+    table[4..8].copy_from_slice(&length.to_le_bytes());
+    fix_checksum(&mut table, 9);
+    table
+}
+
+// Sets the checksum byte at `offset` such that `bytes` sum to zero
+pub(crate) fn fix_checksum(bytes: &mut [u8], offset: usize) {
+    bytes[offset] = 0;
+    bytes[offset] = checksum(bytes).wrapping_neg();
+}
+
+// Returns the header of a table with the signature `signature` and the revision `revision`,
+// following the header of the q35 FADT
+pub(crate) fn header(signature: &[u8; 4], revision: u8) -> Vec<u8> {
+    let mut table = Q35::table("facp")[..36].to_vec();
+    table[..4].copy_from_slice(signature);
+    table[8] = revision;
+    table
+}
+
+// Returns the structure describing a `width` bits register at bit `offset` of `address` in
+// `space`, accessed `access_size` bytes at a time
+pub(crate) fn gas(
+    space: u8,
+    width: u8,
+    offset: u8,
+    access_size: u8,
+    address: u64,
+) -> GenericAddressStructure {
+    GenericAddressStructure {
+        address_space_id: space,
+        register_bit_width: width,
+        register_bit_offset: offset,
+        access_size,
+        address,
+    }
+}
+
+// Returns the bytes of the Generic Address Structure `gas`
+pub(crate) fn gas_bytes(gas: &GenericAddressStructure) -> Vec<u8> {
+    let mut bytes = vec![
+        gas.address_space_id,
+        gas.register_bit_width,
+        gas.register_bit_offset,
+        gas.access_size,
+    ];
+    bytes.extend_from_slice(&{ gas.address }.to_le_bytes());
+    bytes
+}
+
+// Returns an MCFG whose entries map, for each of `entries`, the buses of a segment group at a base
+// address, given as `(base_addr, segment, start_bus, end_bus)`
+pub(crate) fn mcfg(entries: &[(u64, u16, u8, u8)]) -> Vec<u8> {
+    let mut table = header(b"MCFG", 1);
+    table.extend_from_slice(&[0; 8]);
+    for &(base_addr, segment, start_bus, end_bus) in entries {
+        table.extend_from_slice(&base_addr.to_le_bytes());
+        table.extend_from_slice(&segment.to_le_bytes());
+        table.extend_from_slice(&[start_bus, end_bus, 0, 0, 0, 0]);
+    }
+    let length = table.len() as u32;
+    with_length(&table, length)
+}
+
+// Returns a DMAR made of `structures`, following the header of the q35 FADT
+pub(crate) fn dmar(structures: &[Vec<u8>]) -> Vec<u8> {
+    let mut table = Q35::table("facp")[..36].to_vec();
+    table[..4].copy_from_slice(b"DMAR");
+    table[8] = 1;
+    table.extend_from_slice(&[38, 0]);
+    table.extend_from_slice(&[0; 10]);
+    table.extend(structures.concat());
+    let length = table.len() as u32;
+    with_length(&table, length)
+}
+
+// Returns a DRHD at `register_base` whose scopes are `scopes` PCI endpoints on bus 0
+pub(crate) fn drhd(register_base: u64, scopes: usize) -> Vec<u8> {
+    let mut drhd = vec![0, 0, 0, 0, 0, 0, 0, 0];
+    drhd.extend_from_slice(&register_base.to_le_bytes());
+    for device in 0..scopes {
+        drhd.extend_from_slice(&[1, 8, 0, 0, 0, 0, device as u8, 0]);
+    }
+    let length = drhd.len() as u16;
+    drhd[2..4].copy_from_slice(&length.to_le_bytes());
+    drhd
+}
+
+// Returns the q35 FADT with the flags `flags`, and with its extended register block at `offset`
+// replaced by `gas`
+pub(crate) fn fadt_with_block(
+    flags: FixedFeatureFlags,
+    offset: usize,
+    gas: GenericAddressStructure,
+) -> FADT {
+    let mut facp = Q35::table("facp").to_vec();
+    facp[112..116].copy_from_slice(&flags.bits().to_le_bytes());
+    facp[offset..offset + 12].copy_from_slice(&gas_bytes(&gas));
+    fix_checksum(&mut facp, 9);
+    FADT::from_bytes(&facp).unwrap()
+}
+
+// Returns the fields of an SPCR following the header, for a port of type `interface` whose
+// registers are described by `base_addr`, with the 8259 IRQ 4 and the GSI 4
+pub(crate) fn spcr_fields(interface: u8, base_addr: GenericAddressStructure) -> Vec<u8> {
+    let mut fields = vec![interface, 0, 0, 0];
+    fields.extend(gas_bytes(&base_addr));
+    // 8259 and I/O APIC interrupts
+    fields.extend_from_slice(&[0b11, 4]);
+    fields.extend_from_slice(&4u32.to_le_bytes());
+    // 115200 baud, no parity, 1 stop bit, RTS/CTS, VT100, and not a PCI device
+    fields.extend_from_slice(&[7, 0, 1, 0b10, 0, 0, 0xFF, 0xFF, 0xFF, 0xFF]);
+    fields.extend_from_slice(&[0; 8]);
+    fields
+}
+
+// Returns an SPCR of revision `revision` made of `fields`
+pub(crate) fn spcr(revision: u8, fields: &[u8]) -> Vec<u8> {
+    let mut table = header(b"SPCR", revision);
+    table.extend_from_slice(fields);
+    let length = table.len() as u32;
+    with_length(&table, length)
+}
+
+// Returns a copy of `xsdt` with `addr` added to its entries
+pub(crate) fn with_entry(xsdt: &[u8], addr: usize) -> Vec<u8> {
+    let mut xsdt = xsdt.to_vec();
+    xsdt.extend_from_slice(&(addr as u64).to_le_bytes());
+    let length = xsdt.len() as u32;
+    with_length(&xsdt, length)
+}
+
+// Returns a Debug Device Information structure of the DBG2, with its register blocks and their
+// sizes after its namespace string when `namespace_first` is set, and before it otherwise
+pub(crate) fn dbg2_device(
+    port_type: u16,
+    port_subtype: u16,
+    addresses: &[(GenericAddressStructure, u32)],
+    namespace: &[u8],
+    namespace_first: bool,
+) -> Vec<u8> {
+    const INFO_LENGTH: usize = 22;
+    let addresses_length = addresses.len() * 16;
+    let (addresses_offset, namespace_offset) = match namespace_first {
+        true => (INFO_LENGTH + namespace.len(), INFO_LENGTH),
+        false => (INFO_LENGTH, INFO_LENGTH + addresses_length),
+    };
+    let sizes_offset = addresses_offset + addresses.len() * 12;
+    let length = INFO_LENGTH + addresses_length + namespace.len();
+
+    let mut device = vec![0];
+    device.extend_from_slice(&(length as u16).to_le_bytes());
+    device.push(addresses.len() as u8);
+    device.extend_from_slice(&(namespace.len() as u16).to_le_bytes());
+    device.extend_from_slice(&(namespace_offset as u16).to_le_bytes());
+    // No OEM data
+    device.extend_from_slice(&[0; 4]);
+    device.extend_from_slice(&port_type.to_le_bytes());
+    device.extend_from_slice(&port_subtype.to_le_bytes());
+    device.extend_from_slice(&[0; 2]);
+    device.extend_from_slice(&(addresses_offset as u16).to_le_bytes());
+    device.extend_from_slice(&(sizes_offset as u16).to_le_bytes());
+
+    let mut registers: Vec<u8> = addresses
+        .iter()
+        .flat_map(|(gas, _)| gas_bytes(gas))
+        .collect();
+    registers.extend(addresses.iter().flat_map(|(_, size)| size.to_le_bytes()));
+    match namespace_first {
+        true => device.extend(namespace.iter().chain(&registers)),
+        false => device.extend(registers.iter().chain(namespace)),
+    }
+    device
+}
+
+// Returns a DBG2 made of the Debug Device Information structures `devices`
+pub(crate) fn dbg2(devices: &[Vec<u8>]) -> Vec<u8> {
+    let mut table = header(b"DBG2", 0);
+    table.extend_from_slice(&44u32.to_le_bytes());
+    table.extend_from_slice(&(devices.len() as u32).to_le_bytes());
+    table.extend(devices.concat());
+    let length = table.len() as u32;
+    with_length(&table, length)
+}
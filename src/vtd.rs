@@ -0,0 +1,906 @@
+//! Driver for the Intel VT-d DMA remapping hardware units described by the DMAR. Each device is
+//! given its own second-level page tables, which only map the ranges it is allowed to reach, along
+//! with the reserved memory regions (RMRRs) it keeps on using after boot. Once translation is
+//! enabled, devices without a context entry have all their DMA blocked.
+use crate::cpu::{clflush, mfence};
+use crate::efi::acpi::{AcpiTables, DeviceScopeType, Drhd, DMAR, MAX_DRHD_UNITS};
+use crate::pci::{self, ecam::Ecam, PciAddress};
+use bitflags::bitflags;
+use core::cell::UnsafeCell;
+use core::ops::Range;
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+/// The number of 4 KiB pages set aside for root tables, context tables and page tables
+pub const PAGE_POOL_SIZE: usize = 512;
+
+/// The size of the pages used by the remapping structures, and of the smallest mapping
+pub const PAGE_SIZE: u64 = 4096;
+
+/// The size of the large pages second-level page tables can map, when the unit supports them
+pub const LARGE_PAGE_SIZE: u64 = 2 << 20;
+
+/// The number of times we poll a register for a command to complete, before giving up
+const MAX_SPINS: usize = 10_000_000;
+
+/// Offsets of the registers of a remapping unit
+mod reg {
+    pub const CAPABILITY: usize = 0x08;
+    pub const EXT_CAPABILITY: usize = 0x10;
+    pub const GLOBAL_COMMAND: usize = 0x18;
+    pub const GLOBAL_STATUS: usize = 0x1C;
+    pub const ROOT_TABLE_ADDR: usize = 0x20;
+    pub const CONTEXT_COMMAND: usize = 0x28;
+    pub const FAULT_STATUS: usize = 0x34;
+}
+
+bitflags! {
+    /// The Global Command Register, whose bits are mirrored by the Global Status Register
+    #[repr(transparent)]
+    #[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+    pub struct GlobalCommand: u32 {
+        /// Compatibility format interrupts bypass interrupt remapping
+        const CFI = 1 << 23;
+        /// Set the interrupt remapping table pointer (one-shot)
+        const SIRTP = 1 << 24;
+        /// Interrupt remapping enable
+        const IRE = 1 << 25;
+        /// Queued invalidation enable
+        const QIE = 1 << 26;
+        /// Write buffer flush (one-shot)
+        const WBF = 1 << 27;
+        /// Set the root table pointer (one-shot)
+        const SRTP = 1 << 30;
+        /// Translation enable
+        const TE = 1 << 31;
+    }
+}
+
+// The bits of the Global Status Register to write back to the Global Command Register to keep
+// the persistent commands as they are: everything but the one-shot commands (SRTP, SIRTP, WBF)
+// and the status-only bits
+const GSTS_PERSISTENT_MASK: u32 = 0x96FF_FFFF;
+
+// Context-command Register: invalidate the context cache, globally
+const CCMD_ICC: u64 = 1 << 63;
+const CCMD_GLOBAL: u64 = 1 << 61;
+
+// IOTLB Invalidate Register: invalidate the IOTLB, globally, draining reads and writes first
+const IOTLB_IVT: u64 = 1 << 63;
+const IOTLB_GLOBAL: u64 = 1 << 60;
+const IOTLB_DRAIN: u64 = 1 << 49 | 1 << 48;
+
+// Bits of the entries of the remapping structures
+const ENTRY_PRESENT: u64 = 1 << 0;
+const PTE_READ: u64 = 1 << 0;
+const PTE_WRITE: u64 = 1 << 1;
+const PTE_LARGE_PAGE: u64 = 1 << 7;
+const ENTRY_ADDR_MASK: u64 = 0x000F_FFFF_FFFF_F000;
+
+/// Reasons for which DMA remapping cannot be set up as asked
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VtdError {
+    /// There is no DMAR table
+    NotFound,
+    /// A remapping unit has its registers at the null address
+    InvalidRegisterBase,
+    /// A remapping unit supports none of the page table depths we know
+    UnsupportedAddressWidth(u8),
+    /// No remapping unit handles the device
+    NoUnit(PciAddress),
+    /// The range is not aligned on 4 KiB, or goes past the addresses the unit translates
+    InvalidRange { start: u64, end: u64 },
+    /// The pages set aside for the remapping structures are all in use
+    OutOfPages,
+    /// The device already has its own page tables
+    AlreadyConfined(PciAddress),
+    /// The unit did not complete a command in time
+    Timeout,
+    /// The DMAR describes more units than the given number we have room for, such that some
+    /// devices would be left without translation
+    TooManyUnits(usize),
+    /// The unit gave out all the domain IDs it supports, whose number is given
+    OutOfDomains(u32),
+}
+
+/// What a device may do with the memory it is given
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DmaAccess {
+    Read,
+    ReadWrite,
+}
+
+// A page of a remapping structure
+#[repr(C, align(4096))]
+struct Page([u64; 512]);
+
+// The pages remapping structures are allocated from, which are never freed. They are part of our
+// image, which the firmware identity maps.
+struct PagePool {
+    pages: UnsafeCell<[Page; PAGE_POOL_SIZE]>,
+    // Index of the first page not handed out yet
+    next: AtomicUsize,
+}
+
+// SAFETY: each page is only handed out once, through the atomic `next` index
+unsafe impl Sync for PagePool {}
+
+const INIT_PAGE: Page = Page([0; 512]);
+
+static PAGE_POOL: PagePool = PagePool {
+    pages: UnsafeCell::new([INIT_PAGE; PAGE_POOL_SIZE]),
+    next: AtomicUsize::new(0),
+};
+
+// Hands out a zeroed page from the pool, returning its physical address
+fn alloc_page() -> Result<u64, VtdError> {
+    let idx = PAGE_POOL.next.fetch_add(1, Ordering::SeqCst);
+    if idx >= PAGE_POOL_SIZE {
+        return Err(VtdError::OutOfPages);
+    }
+
+    // SAFETY: the page at `idx` was never handed out before, and is within the pool
+    let page = unsafe { &mut (*PAGE_POOL.pages.get())[idx] };
+    page.0.fill(0);
+
+    Ok(page as *mut Page as u64)
+}
+
+/// A single DMA remapping hardware unit, with its root table
+pub struct RemappingUnit {
+    // Address of the registers, which are identity mapped
+    base: usize,
+    // The PCI segment group of the devices the unit handles
+    segment: u16,
+    // Whether the unit handles every device of its segment group not handled by another unit
+    include_pci_all: bool,
+    // The Capability Register
+    cap: u64,
+    // The Extended Capability Register
+    ecap: u64,
+    // The number of levels of the second-level page tables
+    levels: u8,
+    // Physical address of the root table
+    root_table: u64,
+    // The domain ID given to the next device confined behind this unit
+    next_domain: u32,
+}
+
+impl RemappingUnit {
+    /// Sets up the unit described by `drhd`, reading its capabilities from its registers and
+    /// allocating an empty root table. Translation is left as the firmware left it.
+    pub fn new(drhd: &Drhd) -> Result<Self, VtdError> {
+        if drhd.register_base == 0 {
+            return Err(VtdError::InvalidRegisterBase);
+        }
+
+        let mut unit = Self {
+            base: drhd.register_base as usize,
+            segment: drhd.segment,
+            include_pci_all: drhd.include_pci_all,
+            cap: 0,
+            ecap: 0,
+            levels: 0,
+            root_table: 0,
+            // Domain 0 is reserved when the unit caches non-present entries
+            next_domain: 1,
+        };
+
+        // SAFETY: the registers are in system memory, which the firmware identity maps
+        unsafe {
+            unit.cap = unit.read64(reg::CAPABILITY);
+            unit.ecap = unit.read64(reg::EXT_CAPABILITY);
+        }
+
+        // Pick the page table depth from the Supported Adjusted Guest Address Widths, preferring
+        // 48-bit (4 levels), then 39-bit (3 levels), then 57-bit (5 levels)
+        let sagaw = (unit.cap >> 8 & 0x1F) as u8;
+        unit.levels = if sagaw & 1 << 2 != 0 {
+            4
+        } else if sagaw & 1 << 1 != 0 {
+            3
+        } else if sagaw & 1 << 3 != 0 {
+            5
+        } else {
+            return Err(VtdError::UnsupportedAddressWidth(sagaw));
+        };
+
+        unit.root_table = alloc_page()?;
+
+        Ok(unit)
+    }
+
+    unsafe fn read32(&self, offset: usize) -> u32 {
+        core::ptr::read_volatile((self.base + offset) as *const u32)
+    }
+
+    unsafe fn write32(&self, offset: usize, value: u32) {
+        core::ptr::write_volatile((self.base + offset) as *mut u32, value);
+    }
+
+    unsafe fn read64(&self, offset: usize) -> u64 {
+        core::ptr::read_volatile((self.base + offset) as *const u64)
+    }
+
+    unsafe fn write64(&self, offset: usize, value: u64) {
+        core::ptr::write_volatile((self.base + offset) as *mut u64, value);
+    }
+
+    pub fn segment(&self) -> u16 {
+        self.segment
+    }
+
+    /// Returns whether the unit handles every device of its segment group that is not handled by
+    /// another unit
+    pub fn include_pci_all(&self) -> bool {
+        self.include_pci_all
+    }
+
+    /// Returns the number of domains the unit supports
+    pub fn num_domains(&self) -> u32 {
+        1 << (4 + 2 * (self.cap & 0x7))
+    }
+
+    /// Returns the width of the addresses the unit translates, in bits
+    pub fn address_width(&self) -> u8 {
+        let mgaw = (self.cap >> 16 & 0x3F) as u8 + 1;
+        mgaw.min(12 + 9 * self.levels)
+    }
+
+    /// Returns whether the unit snoops the caches when walking the remapping structures. If not,
+    /// every entry we write is flushed to memory.
+    pub fn is_coherent(&self) -> bool {
+        self.ecap & 1 != 0
+    }
+
+    /// Returns whether the unit caches non-present entries, as emulated units do, such that
+    /// making an entry present requires an invalidation
+    pub fn caching_mode(&self) -> bool {
+        self.cap & 1 << 7 != 0
+    }
+
+    /// Returns whether second-level page tables can map 2 MiB pages
+    pub fn supports_large_pages(&self) -> bool {
+        self.cap & 1 << 34 != 0
+    }
+
+    /// Returns the value of the Fault Status Register
+    pub fn fault_status(&self) -> u32 {
+        unsafe { self.read32(reg::FAULT_STATUS) }
+    }
+
+    /// Returns whether DMA remapping is enabled
+    pub fn is_enabled(&self) -> bool {
+        self.status().contains(GlobalCommand::TE)
+    }
+
+    // Returns the Global Status Register
+    fn status(&self) -> GlobalCommand {
+        GlobalCommand::from_bits_retain(unsafe { self.read32(reg::GLOBAL_STATUS) })
+    }
+
+    // Issues `command` through the Global Command Register, keeping the persistent commands that
+    // are enabled, and waits for the unit to report it done
+    fn global_command(&self, command: GlobalCommand, set: bool) -> Result<(), VtdError> {
+        let persistent =
+            GlobalCommand::from_bits_retain(self.status().bits() & GSTS_PERSISTENT_MASK);
+        let value = if set {
+            persistent | command
+        } else {
+            persistent - command
+        };
+
+        unsafe { self.write32(reg::GLOBAL_COMMAND, value.bits()) };
+
+        for _ in 0..MAX_SPINS {
+            // The write buffer flush status is cleared once the flush is done, while the status of
+            // the other commands follows the command
+            let status = self.status();
+            let done = match command {
+                GlobalCommand::WBF => !status.contains(GlobalCommand::WBF),
+                _ => status.contains(command) == set,
+            };
+            if done {
+                return Ok(());
+            }
+            core::hint::spin_loop();
+        }
+
+        Err(VtdError::Timeout)
+    }
+
+    // Flushes the write buffer, when the unit has one
+    fn flush_write_buffer(&self) -> Result<(), VtdError> {
+        if self.cap & 1 << 4 == 0 {
+            return Ok(());
+        }
+
+        self.global_command(GlobalCommand::WBF, true)
+    }
+
+    // Invalidates every cached context entry and translation
+    fn invalidate_all(&self) -> Result<(), VtdError> {
+        self.flush_write_buffer()?;
+
+        unsafe {
+            self.write64(reg::CONTEXT_COMMAND, CCMD_ICC | CCMD_GLOBAL);
+            self.wait_clear(reg::CONTEXT_COMMAND, CCMD_ICC)?;
+
+            let iotlb = (self.ecap >> 8 & 0x3FF) as usize * 16 + 8;
+            self.write64(iotlb, IOTLB_IVT | IOTLB_GLOBAL | IOTLB_DRAIN);
+            self.wait_clear(iotlb, IOTLB_IVT)
+        }
+    }
+
+    // Waits for the bit `mask` of the 64-bit register at `offset` to be cleared by the unit
+    unsafe fn wait_clear(&self, offset: usize, mask: u64) -> Result<(), VtdError> {
+        for _ in 0..MAX_SPINS {
+            if self.read64(offset) & mask == 0 {
+                return Ok(());
+            }
+            core::hint::spin_loop();
+        }
+
+        Err(VtdError::Timeout)
+    }
+
+    // Writes `value` in the entry at `addr`, flushing it to memory if the unit does not snoop
+    fn write_entry(&self, addr: u64, value: u64) {
+        unsafe {
+            core::ptr::write_volatile(addr as *mut u64, value);
+            if !self.is_coherent() {
+                clflush(addr as usize);
+                mfence();
+            }
+        }
+    }
+
+    // Returns the address of the context entry of `device`, allocating the context table of its
+    // bus if needed
+    fn context_entry(&self, device: PciAddress) -> Result<u64, VtdError> {
+        let root_entry = self.root_table + device.bus as u64 * 16;
+        let mut context_table = unsafe { core::ptr::read_volatile(root_entry as *const u64) };
+        if context_table & ENTRY_PRESENT == 0 {
+            context_table = alloc_page()? | ENTRY_PRESENT;
+            self.write_entry(root_entry, context_table);
+        }
+
+        let devfn = (device.device as u64 & 0x1F) << 3 | device.function as u64 & 0x7;
+        Ok((context_table & ENTRY_ADDR_MASK) + devfn * 16)
+    }
+
+    /// Returns whether `device` has its own page tables behind this unit
+    pub fn is_confined(&self, device: PciAddress) -> bool {
+        let root_entry = self.root_table + device.bus as u64 * 16;
+        let context_table = unsafe { core::ptr::read_volatile(root_entry as *const u64) };
+        if context_table & ENTRY_PRESENT == 0 {
+            return false;
+        }
+
+        let devfn = (device.device as u64 & 0x1F) << 3 | device.function as u64 & 0x7;
+        let entry = (context_table & ENTRY_ADDR_MASK) + devfn * 16;
+        unsafe { core::ptr::read_volatile(entry as *const u64) & ENTRY_PRESENT != 0 }
+    }
+
+    /// Creates an empty domain, whose page tables have the depth this unit uses. Each domain
+    /// takes one of the IDs the unit supports, which are never given back.
+    pub fn new_domain(&mut self) -> Result<DmaDomain, VtdError> {
+        let id = self.next_domain;
+        if id >= self.num_domains() {
+            return Err(VtdError::OutOfDomains(self.num_domains()));
+        }
+
+        let domain = DmaDomain {
+            id: id as u16,
+            root: alloc_page()?,
+            levels: self.levels,
+            address_width: self.address_width(),
+            large_pages: self.supports_large_pages(),
+            coherent: self.is_coherent(),
+        };
+        self.next_domain += 1;
+
+        Ok(domain)
+    }
+
+    /// Makes `device` translate its DMA through the page tables of `domain`. If translation is
+    /// enabled, the cached entries are invalidated.
+    pub fn attach(&self, device: PciAddress, domain: &DmaDomain) -> Result<(), VtdError> {
+        let entry = self.context_entry(device)?;
+        let address_width = match self.levels {
+            3 => 1,
+            4 => 2,
+            _ => 3,
+        };
+
+        // Translation type 0: untranslated requests go through the second-level page tables
+        self.write_entry(entry + 8, address_width | (domain.id as u64) << 8);
+        self.write_entry(entry, domain.root | ENTRY_PRESENT);
+
+        if self.is_enabled() || self.caching_mode() {
+            self.invalidate_all()?;
+        }
+
+        Ok(())
+    }
+
+    /// Points the unit to its root table and enables DMA remapping. From then on, the DMA of
+    /// devices that were not attached to a domain is blocked.
+    pub fn enable(&self) -> Result<(), VtdError> {
+        self.flush_write_buffer()?;
+
+        unsafe { self.write64(reg::ROOT_TABLE_ADDR, self.root_table) };
+        self.global_command(GlobalCommand::SRTP, true)?;
+        self.invalidate_all()?;
+
+        self.global_command(GlobalCommand::TE, true)
+    }
+
+    /// Disables DMA remapping, such that devices reach physical memory directly again
+    pub fn disable(&self) -> Result<(), VtdError> {
+        if !self.is_enabled() {
+            return Ok(());
+        }
+
+        self.global_command(GlobalCommand::TE, false)
+    }
+}
+
+/// A set of second-level page tables, giving the addresses the devices attached to it can reach
+pub struct DmaDomain {
+    // The domain ID, which tags the cached translations
+    id: u16,
+    // Physical address of the top level page table
+    root: u64,
+    levels: u8,
+    // Width of the addresses the domain can map, in bits
+    address_width: u8,
+    // Whether 2 MiB pages can be used
+    large_pages: bool,
+    // Whether the unit snoops the caches when walking the page tables
+    coherent: bool,
+}
+
+impl DmaDomain {
+    pub fn id(&self) -> u16 {
+        self.id
+    }
+
+    /// Maps the device addresses `iova` to the physical memory starting at `phys`. Both ends of
+    /// the range, and `phys`, must be aligned on 4 KiB.
+    pub fn map(&mut self, iova: Range<u64>, phys: u64, access: DmaAccess) -> Result<(), VtdError> {
+        let invalid = VtdError::InvalidRange {
+            start: iova.start,
+            end: iova.end,
+        };
+        if !iova.start.is_multiple_of(PAGE_SIZE)
+            || !iova.end.is_multiple_of(PAGE_SIZE)
+            || !phys.is_multiple_of(PAGE_SIZE)
+        {
+            return Err(invalid);
+        }
+        if iova.end < iova.start || iova.end > 1u64 << self.address_width {
+            return Err(invalid);
+        }
+
+        let permissions = match access {
+            DmaAccess::Read => PTE_READ,
+            DmaAccess::ReadWrite => PTE_READ | PTE_WRITE,
+        };
+
+        let mut addr = iova.start;
+        while addr < iova.end {
+            let target = phys + (addr - iova.start);
+            let large = self.large_pages
+                && addr.is_multiple_of(LARGE_PAGE_SIZE)
+                && target.is_multiple_of(LARGE_PAGE_SIZE)
+                && iova.end - addr >= LARGE_PAGE_SIZE;
+
+            if large {
+                let entry = self.walk(addr, 2)?;
+                self.write_entry(entry, target | permissions | PTE_LARGE_PAGE);
+                addr += LARGE_PAGE_SIZE;
+            } else {
+                let entry = self.walk(addr, 1)?;
+                self.write_entry(entry, target | permissions);
+                addr += PAGE_SIZE;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Maps `range` of physical memory at the same device addresses
+    pub fn identity_map(&mut self, range: Range<u64>, access: DmaAccess) -> Result<(), VtdError> {
+        let phys = range.start;
+        self.map(range, phys, access)
+    }
+
+    // Returns the address of the entry mapping `addr` in the page table of level `level`, level 1
+    // being the one mapping 4 KiB pages, allocating the page tables on the way
+    fn walk(&mut self, addr: u64, level: u8) -> Result<u64, VtdError> {
+        let mut table = self.root;
+        for current in (level + 1..=self.levels).rev() {
+            let index = addr >> (12 + 9 * (current as u64 - 1)) & 0x1FF;
+            let entry = table + index * 8;
+            let mut value = unsafe { core::ptr::read_volatile(entry as *const u64) };
+
+            if value & (PTE_READ | PTE_WRITE) == 0 {
+                value = alloc_page()? | PTE_READ | PTE_WRITE;
+                self.write_entry(entry, value);
+            } else if value & PTE_LARGE_PAGE != 0 {
+                value = self.split_large_page(value, current)? | PTE_READ | PTE_WRITE;
+                self.write_entry(entry, value);
+            }
+
+            table = value & ENTRY_ADDR_MASK;
+        }
+
+        let index = addr >> (12 + 9 * (level as u64 - 1)) & 0x1FF;
+        Ok(table + index * 8)
+    }
+
+    // Returns the entry mapping `addr` and the level of its page table, without allocating
+    // anything, or `None` if `addr` is not mapped
+    #[cfg(test)]
+    pub(crate) fn leaf_entry(&self, addr: u64) -> Option<(u64, u8)> {
+        let mut table = self.root;
+        for level in (1..=self.levels).rev() {
+            let index = addr >> (12 + 9 * (level as u64 - 1)) & 0x1FF;
+            let value = unsafe { core::ptr::read_volatile((table + index * 8) as *const u64) };
+
+            if value & (PTE_READ | PTE_WRITE) == 0 {
+                return None;
+            } else if level == 1 || value & PTE_LARGE_PAGE != 0 {
+                return Some((value, level));
+            }
+            table = value & ENTRY_ADDR_MASK;
+        }
+
+        None
+    }
+
+    // Makes a page table of the level below `level` that maps the same memory, with the same
+    // permissions, as the large page `value` of level `level`, returning its physical address
+    fn split_large_page(&self, value: u64, level: u8) -> Result<u64, VtdError> {
+        let table = alloc_page()?;
+        let size = 1u64 << (12 + 9 * (level as u64 - 2));
+        let base = value & ENTRY_ADDR_MASK & !(size * 512 - 1);
+        let mut flags = value & (PTE_READ | PTE_WRITE);
+        if level > 2 {
+            flags |= PTE_LARGE_PAGE;
+        }
+
+        for index in 0..512 {
+            self.write_entry(table + index * 8, (base + index * size) | flags);
+        }
+
+        Ok(table)
+    }
+
+    // Writes `value` in the entry at `addr`, flushing it to memory if the unit does not snoop
+    fn write_entry(&self, addr: u64, value: u64) {
+        unsafe {
+            core::ptr::write_volatile(addr as *mut u64, value);
+            if !self.coherent {
+                clflush(addr as usize);
+                mfence();
+            }
+        }
+    }
+}
+
+/// All the DMA remapping units of the platform, along with the reserved memory regions their
+/// devices keep on using
+pub struct Vtd {
+    dmar: DMAR,
+    units: [Option<RemappingUnit>; MAX_DRHD_UNITS],
+    // Number of populated entries in `units`
+    nunits: usize,
+    // Access to the configuration space of the segment groups other than 0
+    ecam: Option<Ecam>,
+}
+
+// We make a constant that is able to initialize an array of non `Copy` units
+const INIT_UNIT: Option<RemappingUnit> = None;
+
+impl Vtd {
+    /// Sets up every remapping unit described by `dmar`. `ecam` is used to walk the bridges of
+    /// the device scopes, the legacy mechanism being used for segment group 0 without it.
+    pub fn new(dmar: DMAR, ecam: Option<Ecam>) -> Result<Self, VtdError> {
+        let mut vtd = Self {
+            dmar,
+            units: [INIT_UNIT; MAX_DRHD_UNITS],
+            nunits: 0,
+            ecam,
+        };
+
+        for drhd in vtd.dmar.drhds() {
+            let slot = vtd
+                .units
+                .get_mut(vtd.nunits)
+                .ok_or(VtdError::TooManyUnits(MAX_DRHD_UNITS))?;
+            *slot = Some(RemappingUnit::new(drhd)?);
+            vtd.nunits += 1;
+        }
+
+        Ok(vtd)
+    }
+
+    /// Sets up the remapping units described by the DMAR found in `tables`
+    pub fn from_acpi(tables: &AcpiTables) -> Result<Self, VtdError> {
+        let dmar = tables.find::<DMAR>().ok_or(VtdError::NotFound)?;
+        Self::new(dmar, Ecam::from_acpi(tables))
+    }
+
+    pub fn dmar(&self) -> &DMAR {
+        &self.dmar
+    }
+
+    /// Returns the remapping units, in the order the DMAR lists them
+    pub fn units(&self) -> impl Iterator<Item = &RemappingUnit> {
+        self.units[..self.nunits].iter().flatten()
+    }
+
+    // Reads the byte at `offset` in the configuration space of `addr`
+    fn config_read_u8(&self, addr: PciAddress, offset: u8) -> Option<u8> {
+        if let Some(ecam) = &self.ecam {
+            if let Ok(value) = unsafe { ecam.read_u8(addr, offset as u16) } {
+                return Some(value);
+            }
+        }
+        if addr.segment != 0 {
+            return None;
+        }
+
+        let dword = unsafe { pci::legacy::read_u32(addr, offset & 0xFC) };
+        Some((dword >> ((offset & 3) * 8)) as u8)
+    }
+
+    // Returns whether the device scopes of `drhd` name `device`, either directly or through a
+    // bridge above it
+    fn drhd_scopes_device(&self, drhd: &Drhd, device: PciAddress) -> bool {
+        drhd.segment == device.segment
+            && drhd.scopes().any(|scope| {
+                let secondary_bus = |bridge| self.config_read_u8(bridge, 0x19);
+                let Some(addr) = scope.resolve(drhd.segment, secondary_bus) else {
+                    return false;
+                };
+
+                match scope.scope_type {
+                    DeviceScopeType::PciEndpoint => addr == device,
+                    DeviceScopeType::PciSubHierarchy => {
+                        let buses = self
+                            .config_read_u8(addr, 0x19)
+                            .zip(self.config_read_u8(addr, 0x1A));
+                        addr == device
+                            || buses.is_some_and(|(secondary, subordinate)| {
+                                (secondary..=subordinate).contains(&device.bus)
+                            })
+                    }
+                    _ => false,
+                }
+            })
+    }
+
+    // Returns the index of the unit handling `device`: the one whose scopes name it, or else
+    // the one handling every other device of its segment group
+    fn unit_index(&self, device: PciAddress) -> Option<usize> {
+        let drhds = || self.dmar.drhds().take(self.nunits).enumerate();
+
+        drhds()
+            .find(|(_, drhd)| !drhd.include_pci_all && self.drhd_scopes_device(drhd, device))
+            .or_else(|| {
+                drhds().find(|(_, drhd)| drhd.include_pci_all && drhd.segment == device.segment)
+            })
+            .map(|(idx, _)| idx)
+    }
+
+    /// Returns the unit handling `device`
+    pub fn unit_for(&self, device: PciAddress) -> Option<&RemappingUnit> {
+        self.units[self.unit_index(device)?].as_ref()
+    }
+
+    // Returns the RMRRs whose device scopes name `device`, as ranges of whole pages
+    fn rmrr_ranges(&self, device: PciAddress) -> impl Iterator<Item = Range<u64>> + '_ {
+        self.dmar
+            .rmrrs()
+            .filter(move |rmrr| {
+                rmrr.segment == device.segment
+                    && rmrr.scopes().any(|scope| {
+                        let secondary_bus = |bridge| self.config_read_u8(bridge, 0x19);
+                        scope.resolve(rmrr.segment, secondary_bus) == Some(device)
+                    })
+            })
+            .map(|rmrr| {
+                let start = rmrr.base & !(PAGE_SIZE - 1);
+                let end = rmrr.limit.saturating_add(1).next_multiple_of(PAGE_SIZE);
+                start..end
+            })
+    }
+
+    /// Confines the DMA of `device` to the physical memory `ranges`, which are identity mapped
+    /// for reading and writing, along with the RMRRs the device uses. Any other access is blocked
+    /// once translation is enabled.
+    pub fn confine(&mut self, device: PciAddress, ranges: &[Range<u64>]) -> Result<(), VtdError> {
+        let idx = self.unit_index(device).ok_or(VtdError::NoUnit(device))?;
+        let mut domain = match self.units[idx].as_mut() {
+            Some(unit) if unit.is_confined(device) => {
+                return Err(VtdError::AlreadyConfined(device))
+            }
+            Some(unit) => unit.new_domain()?,
+            None => return Err(VtdError::NoUnit(device)),
+        };
+
+        for range in ranges.iter().cloned().chain(self.rmrr_ranges(device)) {
+            domain.identity_map(range, DmaAccess::ReadWrite)?;
+        }
+
+        match &self.units[idx] {
+            Some(unit) => unit.attach(device, &domain),
+            None => Err(VtdError::NoUnit(device)),
+        }
+    }
+
+    /// Gives every device using an RMRR, and not confined yet, access to its RMRRs only, then
+    /// enables DMA remapping on every unit. From then on, devices that were not confined have
+    /// their DMA blocked.
+    pub fn enable(&mut self) -> Result<(), VtdError> {
+        let nrmrrs = self.dmar.rmrrs().count();
+        for rmrr_idx in 0..nrmrrs {
+            // Copy the RMRR out, as confining a device needs the whole driver
+            let Some(rmrr) = self.dmar.rmrrs().nth(rmrr_idx).copied() else {
+                break;
+            };
+
+            for scope in rmrr.scopes() {
+                let secondary_bus = |bridge| self.config_read_u8(bridge, 0x19);
+                let Some(device) = scope.resolve(rmrr.segment, secondary_bus) else {
+                    continue;
+                };
+
+                match self.confine(device, &[]) {
+                    Ok(()) | Err(VtdError::AlreadyConfined(_)) => {}
+                    Err(err) => return Err(err),
+                }
+            }
+        }
+
+        for unit in self.units() {
+            unit.enable()?;
+        }
+
+        Ok(())
+    }
+
+    /// Disables DMA remapping on every unit
+    pub fn disable(&self) -> Result<(), VtdError> {
+        for unit in self.units() {
+            unit.disable()?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{dmar, drhd};
+    use std::sync::atomic::AtomicU64;
+
+    // Returns the registers of a remapping unit whose Capability Register is `cap`, with a coherent
+    // Extended Capability Register, as host memory the unit can be pointed at
+    fn vtd_registers(cap: u64) -> Vec<AtomicU64> {
+        let registers: Vec<AtomicU64> = (0..0x40).map(|_| AtomicU64::new(0)).collect();
+        registers[1].store(cap, Ordering::SeqCst);
+        registers[2].store(1, Ordering::SeqCst);
+        registers
+    }
+
+    #[test]
+    fn vtd_domains() {
+        // 16 domains, as the number of domains field is 0, 4-level page tables for a 48-bit address
+        // width, and 2 MiB pages
+        const CAP: u64 = 1 << 2 << 8 | 47 << 16 | 1 << 34;
+        const READ: u64 = 1 << 0;
+        const WRITE: u64 = 1 << 1;
+        const LARGE: u64 = 1 << 7;
+        let registers = vtd_registers(CAP);
+        let table = DMAR::from_bytes(&dmar(&[drhd(registers.as_ptr() as u64, 1)])).unwrap();
+        let mut unit = RemappingUnit::new(table.drhds().next().unwrap()).unwrap();
+        assert_eq!(unit.num_domains(), 16);
+        assert_eq!(unit.address_width(), 48);
+
+        // The domain IDs run out once the unit's number of domains is reached, 0 being reserved
+        let mut domains: Vec<DmaDomain> = (1..16).map(|_| unit.new_domain().unwrap()).collect();
+        let ids: Vec<u16> = domains.iter().map(DmaDomain::id).collect();
+        assert_eq!(ids, (1..16).collect::<Vec<u16>>());
+        assert_eq!(unit.new_domain().err(), Some(VtdError::OutOfDomains(16)));
+        assert_eq!(unit.new_domain().err(), Some(VtdError::OutOfDomains(16)));
+
+        // Aligned ranges use 2 MiB pages where they can, and 4 KiB pages around them
+        let domain = &mut domains[0];
+        domain
+            .map(0x1F_F000..0x40_1000, 0x8000_0000 - 0x1000, DmaAccess::Read)
+            .unwrap();
+        assert_eq!(domain.leaf_entry(0x1F_F000), Some((0x7FFF_F000 | READ, 1)));
+        assert_eq!(
+            domain.leaf_entry(0x20_0000),
+            Some((0x8000_0000 | READ | LARGE, 2))
+        );
+        assert_eq!(
+            domain.leaf_entry(0x3F_F000),
+            Some((0x8000_0000 | READ | LARGE, 2))
+        );
+        assert_eq!(domain.leaf_entry(0x40_0000), Some((0x8020_0000 | READ, 1)));
+        assert_eq!(domain.leaf_entry(0x40_1000), None);
+        assert_eq!(domain.leaf_entry(0x1F_E000), None);
+
+        // A 4 KiB page mapped inside the 2 MiB page splits it, the rest of it keeping its mapping
+        // and its permissions
+        domain
+            .map(0x20_3000..0x20_4000, 0x9000_0000, DmaAccess::ReadWrite)
+            .unwrap();
+        assert_eq!(
+            domain.leaf_entry(0x20_3000),
+            Some((0x9000_0000 | READ | WRITE, 1))
+        );
+        assert_eq!(domain.leaf_entry(0x20_0000), Some((0x8000_0000 | READ, 1)));
+        assert_eq!(domain.leaf_entry(0x20_2000), Some((0x8000_2000 | READ, 1)));
+        assert_eq!(domain.leaf_entry(0x20_4000), Some((0x8000_4000 | READ, 1)));
+        assert_eq!(domain.leaf_entry(0x3F_F000), Some((0x801F_F000 | READ, 1)));
+        assert_eq!(domain.leaf_entry(0x40_0000), Some((0x8020_0000 | READ, 1)));
+
+        // Mappings far apart get their own page tables at every level, and leave each other alone
+        domain
+            .identity_map(0x7F_0000_0000..0x7F_0000_2000, DmaAccess::ReadWrite)
+            .unwrap();
+        assert_eq!(
+            domain.leaf_entry(0x7F_0000_1000),
+            Some((0x7F_0000_1000 | READ | WRITE, 1))
+        );
+        assert_eq!(
+            domain.leaf_entry(0x20_3000),
+            Some((0x9000_0000 | READ | WRITE, 1))
+        );
+        assert_eq!(domains[1].leaf_entry(0x20_3000), None);
+
+        // Unaligned ranges, and ranges past the address width
+        let domain = &mut domains[1];
+        let invalid = |start, end| Some(VtdError::InvalidRange { start, end });
+        let read = DmaAccess::Read;
+        assert_eq!(
+            domain.map(0x1000..0x1800, 0x1000, read).err(),
+            invalid(0x1000, 0x1800)
+        );
+        assert_eq!(
+            domain.map(0x1800..0x2000, 0x1000, read).err(),
+            invalid(0x1800, 0x2000)
+        );
+        assert_eq!(
+            domain.map(0x1000..0x2000, 0x1800, read).err(),
+            invalid(0x1000, 0x2000)
+        );
+        let end = 1 << 48;
+        assert_eq!(domain.map(end - 0x1000..end, 0, read).err(), None);
+        let past = end + 0x1000;
+        assert_eq!(domain.map(end..past, 0, read).err(), invalid(end, past));
+
+        // Without large pages, even aligned ranges use 4 KiB pages
+        let registers = vtd_registers(CAP & !(1 << 34));
+        let table = DMAR::from_bytes(&dmar(&[drhd(registers.as_ptr() as u64, 1)])).unwrap();
+        let mut unit = RemappingUnit::new(table.drhds().next().unwrap()).unwrap();
+        let mut domain = unit.new_domain().unwrap();
+        domain
+            .identity_map(0x20_0000..0x40_0000, DmaAccess::ReadWrite)
+            .unwrap();
+        assert_eq!(
+            domain.leaf_entry(0x20_0000),
+            Some((0x20_0000 | READ | WRITE, 1))
+        );
+        assert_eq!(
+            domain.leaf_entry(0x3F_F000),
+            Some((0x3F_F000 | READ | WRITE, 1))
+        );
+    }
+}
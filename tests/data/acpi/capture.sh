@@ -0,0 +1,59 @@
+#!/bin/sh
+# Captures the ACPI tables of QEMU's q35 and pc machines into `q35/` and `pc/`, as the unit tests
+# read them: one `acpixtract` binary per table, and `layout.txt` giving the address each table was
+# found at. pril is built with the `acpidump` feature, which prints every table over the serial
+# port in the `acpidump` text format, and booted from a FAT drive with OVMF.
+#
+# usage: tests/data/acpi/capture.sh [q35|pc]...
+#
+# Both machines boot with OVMF, so the pc tables come with an XSDT, unlike the SeaBIOS ones
+# `generate.py` describes.
+set -e
+
+here=$(cd "$(dirname "$0")" && pwd)
+root=$(cd "$here/../../.." && pwd)
+work=$(mktemp -d)
+trap 'rm -rf "$work"' EXIT
+
+cargo build --manifest-path "$root/Cargo.toml" --features acpidump
+mkdir -p "$work/esp/EFI/BOOT"
+cp "$root/target/x86_64-unknown-uefi/debug/pril.efi" "$work/esp/EFI/BOOT/BOOTX64.EFI"
+
+capture() {
+    machine=$1
+    shift
+    log="$work/$machine.log"
+
+    # pril powers the machine off once it is done, the timeout is for when it cannot
+    timeout 120 qemu-system-x86_64 \
+        -machine "$machine" \
+        "$@" \
+        -m 256 \
+        -display none \
+        -no-reboot \
+        -serial "file:$log" \
+        -bios "$root/bios/OVMF.fd" \
+        -drive "format=raw,file=fat:rw:$work/esp" || true
+
+    mkdir -p "$work/$machine"
+    (cd "$work/$machine" && acpixtract -a "$log" >/dev/null)
+
+    # Each table starts with a line like `DSDT @ 0x000000007FB7B000`
+    grep -E '^[A-Z0-9]{4} @ 0x[0-9A-F]+' "$log" | tr -d '\r' | while read -r signature _ addr; do
+        name=$(echo "$signature" | tr 'A-Z' 'a-z')
+        printf '%s.dat 0x%x\n' "$name" "$addr"
+    done >"$work/$machine/layout.txt"
+
+    rm -f "$here/$machine"/*.dat
+    mkdir -p "$here/$machine"
+    cp "$work/$machine"/*.dat "$work/$machine/layout.txt" "$here/$machine/"
+    echo "$machine: $(ls "$here/$machine" | tr '\n' ' ')"
+}
+
+for machine in ${*:-q35 pc}; do
+    case $machine in
+        q35) capture q35 -smp 2 ;;
+        pc) capture pc ;;
+        *) echo "Unknown machine $machine" >&2; exit 1 ;;
+    esac
+done
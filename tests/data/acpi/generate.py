@@ -0,0 +1,644 @@
+#!/usr/bin/env python3
+"""Writes the ACPI tables the unit tests run against, as `acpidump -b` would name them.
+
+The tables follow what QEMU 8 hands to the firmware for its two x86 machines, `-machine q35
+-smp 2` booted with OVMF and `-machine pc` booted with SeaBIOS: same OEM IDs, same register
+blocks, same interrupt controllers. The addresses each table is placed at are in `layout.txt`,
+which the tests map them to.
+
+The DSDTs define the same devices as QEMU's, with the same identification and resources. The
+methods of QEMU's that read registers, like the `_STA` of the HPET and of the PIIX interrupt
+links, are replaced by constants, and the q35 interrupt links only used in PIC mode are left out,
+since the tests evaluate every device on the host.
+
+`ovmf/ramdisk-ssdt.dat` is not generated: it is the SSDT OVMF installs for its RAM disks, as
+found in its firmware volume. Neither are the tables of `firecracker/`, the DSDT, FADT, MADT and
+MCFG of a Firecracker guest as read from `/sys/firmware/acpi/tables`, left as they are.
+
+`capture.sh` replaces these files with the tables QEMU really hands to OVMF, along with the
+`layout.txt` giving the addresses they were found at. Once the captured tables are checked in,
+this script is to be removed.
+"""
+
+import os
+import struct
+
+OEM_ID = b"BOCHS "
+OEM_TABLE_ID = b"BXPC    "
+CREATOR_ID = b"BXPC"
+
+HERE = os.path.dirname(os.path.abspath(__file__))
+
+
+def checksum(data):
+    return (-sum(data)) & 0xFF
+
+
+def table(signature, revision, body):
+    """Prefixes `body` with a description header, and sets the checksum."""
+    length = 36 + len(body)
+    header = struct.pack(
+        "<4sIBB6s8sI4sI",
+        signature,
+        length,
+        revision,
+        0,
+        OEM_ID,
+        OEM_TABLE_ID,
+        1,
+        CREATOR_ID,
+        1,
+    )
+    data = bytearray(header + body)
+    data[9] = checksum(data)
+    return bytes(data)
+
+
+def gas(space, bit_width, address, access_size=0, bit_offset=0):
+    return struct.pack("<BBBBQ", space, bit_width, bit_offset, access_size, address)
+
+
+SYSTEM_MEMORY = 0
+SYSTEM_IO = 1
+
+
+def rsdp(revision, rsdt_addr, xsdt_addr=0):
+    v1 = bytearray(struct.pack("<8sB6sBI", b"RSD PTR ", 0, OEM_ID, revision, rsdt_addr))
+    v1[8] = checksum(v1)
+    if revision < 2:
+        return bytes(v1)
+
+    data = bytearray(v1 + struct.pack("<IQB3x", 36, xsdt_addr, 0))
+    data[32] = checksum(data)
+    return bytes(data)
+
+
+def rsdt(entries):
+    return table(b"RSDT", 1, b"".join(struct.pack("<I", addr) for addr in entries))
+
+
+def xsdt(entries):
+    return table(b"XSDT", 1, b"".join(struct.pack("<Q", addr) for addr in entries))
+
+
+def facs():
+    return struct.pack("<4sIIIIIQB3xI24x", b"FACS", 64, 0, 0, 0, 0, 0, 0, 0)
+
+
+def fadt(revision, facs_addr, dsdt_addr, pm_base, gpe0_blk, gpe0_len, smi, acpi_en, acpi_dis,
+         flags, iapc_boot_arch=0, reset=None):
+    body = struct.pack(
+        "<IIBBHIBBBBIIIIIIIIBBBBBBBBHHHHBBBBBHBI",
+        facs_addr,
+        dsdt_addr,
+        0,  # reserved
+        0,  # preferred PM profile: unspecified
+        9,  # SCI
+        smi,
+        acpi_en,
+        acpi_dis,
+        0,  # S4BIOS_REQ
+        0,  # PSTATE_CNT
+        pm_base,  # PM1a event block
+        0,
+        pm_base + 4,  # PM1a control block
+        0,
+        0,
+        pm_base + 8,  # PM timer
+        gpe0_blk,
+        0,
+        4,  # PM1 event length
+        2,  # PM1 control length
+        0,
+        4,  # PM timer length
+        gpe0_len,
+        0,
+        0,
+        0,
+        0x0FFF,  # C2 latency: not supported
+        0x0FFF,  # C3 latency: not supported
+        0,
+        0,
+        0,
+        0,
+        0,
+        0,
+        0x32,  # century
+        iapc_boot_arch,
+        0,
+        flags,
+    )
+    assert len(body) == 116 - 36
+
+    if revision >= 3:
+        reset_reg, reset_value = reset
+        body += reset_reg + struct.pack("<BHB", reset_value, 0, 1)
+        body += struct.pack("<QQ", 0, dsdt_addr)
+        body += gas(SYSTEM_IO, 32, pm_base)  # X_PM1a_EVT_BLK
+        body += gas(0, 0, 0)
+        body += gas(SYSTEM_IO, 16, pm_base + 4)  # X_PM1a_CNT_BLK
+        body += gas(0, 0, 0)
+        body += gas(0, 0, 0)
+        body += gas(SYSTEM_IO, 32, pm_base + 8)  # X_PM_TMR_BLK
+        body += gas(SYSTEM_IO, gpe0_len * 8, gpe0_blk)  # X_GPE0_BLK
+        body += gas(0, 0, 0)
+        assert len(body) == 244 - 36
+
+    return table(b"FACP", revision, body)
+
+
+def madt(cpus):
+    body = struct.pack("<II", 0xFEE00000, 1)  # PCAT_COMPAT
+    for uid in range(cpus):
+        body += struct.pack("<BBBBI", 0, 8, uid, uid, 1)  # Processor Local APIC, enabled
+    body += struct.pack("<BBBBII", 1, 12, 0, 0, 0xFEC00000, 0)  # I/O APIC
+    # The PIT is wired to GSI 2, and the PCI interrupts are level triggered, active high
+    body += struct.pack("<BBBBIH", 2, 10, 0, 0, 2, 0)
+    for irq in (5, 9, 10, 11):
+        body += struct.pack("<BBBBIH", 2, 10, 0, irq, irq, 0x000D)
+    body += struct.pack("<BBBHB", 4, 6, 0xFF, 0, 1)  # LINT1 of every processor is the NMI
+    return table(b"APIC", 1, body)
+
+
+def hpet():
+    body = struct.pack("<I", 0x8086A201) + gas(SYSTEM_MEMORY, 0, 0xFED00000)
+    body += struct.pack("<BHB", 0, 0, 0)
+    return table(b"HPET", 1, body)
+
+
+def mcfg():
+    body = bytes(8) + struct.pack("<QHBBI", 0xB0000000, 0, 0, 0xFF, 0)
+    return table(b"MCFG", 1, body)
+
+
+def waet():
+    return table(b"WAET", 1, struct.pack("<I", 1 << 1))  # the PM timer is good
+
+
+# A minimal AML assembler, with one function for each of the ASL operators the DSDTs use. Terms
+# are bytes, and term lists are the concatenation of their terms.
+
+
+def pkg_length(length):
+    """Encodes the PkgLength of a package whose content, after the PkgLength, is `length` bytes."""
+    if length + 1 < 0x40:
+        return bytes([length + 1])
+    for count in (2, 3, 4):
+        total = length + count
+        if total < 1 << (4 + 8 * (count - 1)):
+            lead = (count - 1) << 6 | (total & 0xF)
+            return bytes([lead]) + (total >> 4).to_bytes(count - 1, "little")
+    raise ValueError(length)
+
+
+def field_length(bits):
+    """Encodes the length of a field element, which uses the PkgLength encoding for its value."""
+    if bits < 0x40:
+        return bytes([bits])
+    count = 2 if bits < 1 << 12 else 3
+    return bytes([(count - 1) << 6 | (bits & 0xF)]) + (bits >> 4).to_bytes(count - 1, "little")
+
+
+def package_op(opcode, *content):
+    body = b"".join(content)
+    return opcode + pkg_length(len(body)) + body
+
+
+def name_seg(seg):
+    return seg.ljust(4, "_").encode()
+
+
+def name_string(path):
+    prefix = b""
+    while path.startswith(("\\", "^")):
+        prefix += path[0].encode()
+        path = path[1:]
+    segs = [name_seg(seg) for seg in path.split(".") if seg]
+    if not segs:
+        return prefix + b"\x00"
+    if len(segs) == 1:
+        return prefix + segs[0]
+    if len(segs) == 2:
+        return prefix + b"\x2E" + b"".join(segs)
+    return prefix + b"\x2F" + bytes([len(segs)]) + b"".join(segs)
+
+
+def integer(value):
+    if value in (0, 1):
+        return bytes([value])
+    if value == (1 << 64) - 1:
+        return b"\xFF"
+    for prefix, size in ((b"\x0A", 1), (b"\x0B", 2), (b"\x0C", 4), (b"\x0E", 8)):
+        if value < 1 << (8 * size):
+            return prefix + value.to_bytes(size, "little")
+    raise ValueError(value)
+
+
+def string(text):
+    return b"\x0D" + text.encode() + b"\x00"
+
+
+def eisa_id(text):
+    """Compresses a PNP ID the way `EISAID()` does."""
+    value = (ord(text[0]) - 0x40) << 26 | (ord(text[1]) - 0x40) << 21 | (ord(text[2]) - 0x40) << 16
+    value |= int(text[3:], 16)
+    return integer(int.from_bytes(value.to_bytes(4, "big"), "little"))
+
+
+def buffer(data):
+    return package_op(b"\x11", integer(len(data)), data)
+
+
+def package(*elements):
+    return package_op(b"\x12", bytes([len(elements)]), *elements)
+
+
+def name(path, value):
+    return b"\x08" + name_string(path) + value
+
+
+def scope(path, *terms):
+    return package_op(b"\x10", name_string(path), *terms)
+
+
+def device(path, *terms):
+    return b"\x5B" + package_op(b"\x82", name_string(path), *terms)
+
+
+def method(path, argc, *terms, serialized=False):
+    flags = argc | (serialized << 3)
+    return package_op(b"\x14", name_string(path), bytes([flags]), *terms)
+
+
+def op_region(path, space, offset, length):
+    return b"\x5B\x80" + name_string(path) + bytes([space]) + integer(offset) + integer(length)
+
+
+BYTE_ACC, DWORD_ACC = 1, 3
+LOCK = 1 << 4
+
+
+def field(region, flags, *elements):
+    """`elements` are (name, bits) pairs, with a `None` name for reserved bits."""
+    encoded = b""
+    for element, bits in elements:
+        encoded += (name_seg(element) if element else b"\x00") + field_length(bits)
+    return b"\x5B" + package_op(b"\x81", name_string(region), bytes([flags]), encoded)
+
+
+def local(idx):
+    return bytes([0x60 + idx])
+
+
+def arg(idx):
+    return bytes([0x68 + idx])
+
+
+NULL_TARGET = b"\x00"
+
+
+def store(value, target):
+    return b"\x70" + value + target
+
+
+def ret(value):
+    return b"\xA4" + value
+
+
+def if_(predicate, *terms):
+    return package_op(b"\xA0", predicate, *terms)
+
+
+def else_(*terms):
+    return package_op(b"\xA1", *terms)
+
+
+def lequal(left, right):
+    return b"\x93" + left + right
+
+
+def lor(left, right):
+    return b"\x91" + left + right
+
+
+def and_(left, right, target=NULL_TARGET):
+    return b"\x7B" + left + right + target
+
+
+def or_(left, right, target=NULL_TARGET):
+    return b"\x7D" + left + right + target
+
+
+def create_dword_field(source, index, path):
+    return b"\x8A" + source + integer(index) + name_string(path)
+
+
+def notify(path, value):
+    return b"\x86" + name_string(path) + integer(value)
+
+
+def ref(path):
+    """A reference to a named object, as used in term arguments."""
+    return name_string(path)
+
+
+def call(path, *args):
+    return name_string(path) + b"".join(args)
+
+
+# Resource descriptors, which `resource_template` ends with an end tag
+
+
+def resource_template(*descriptors):
+    return buffer(b"".join(descriptors) + b"\x79\x00")
+
+
+def io(base, length):
+    return struct.pack("<BBHHBB", 0x47, 1, base, base, 1, length)
+
+
+def irq_no_flags(*irqs):
+    return struct.pack("<BH", 0x22, sum(1 << irq for irq in irqs))
+
+
+def memory32_fixed(base, length, writable=True):
+    return struct.pack("<BHBII", 0x86, 9, int(writable), base, length)
+
+
+def interrupt(*gsis):
+    """An Extended Interrupt descriptor consumed by the device, level triggered, active high
+    and shared."""
+    body = struct.pack("<BB", 1 | 1 << 3, len(gsis))
+    body += b"".join(struct.pack("<I", gsi) for gsi in gsis)
+    return struct.pack("<BH", 0x89, len(body)) + body
+
+
+def word_bus_number(first, last):
+    body = struct.pack("<BBBHHHHH", 2, 0x0C, 0, 0, first, last, 0, last - first + 1)
+    return struct.pack("<BH", 0x88, len(body)) + body
+
+
+def word_io(first, last):
+    body = struct.pack("<BBBHHHHH", 1, 0x0C, 3, 0, first, last, 0, last - first + 1)
+    return struct.pack("<BH", 0x88, len(body)) + body
+
+
+def dword_memory(first, last):
+    body = struct.pack("<BBBIIIII", 0, 0x0C, 1 << 1 | 1, 0, first, last, 0, last - first + 1)
+    return struct.pack("<BH", 0x87, len(body)) + body
+
+
+PCI_CONFIG = 2
+
+# The UUID of the PCI host bridge `_OSC`, as encoded by `ToUUID()`
+PCI_HOST_BRIDGE_UUID = bytes.fromhex("5b4ddb33 f71f 1c40 9657 7441c03dd766")
+
+
+def pci_root(hid, cid, crs):
+    """The PCI root bridge, with the `_OSC` QEMU gives it, granting the OS every feature it asks
+    for but PCIe native hot plug."""
+    terms = [name("_HID", eisa_id(hid))]
+    if cid:
+        terms.append(name("_CID", eisa_id(cid)))
+    terms += [
+        name("_ADR", integer(0)),
+        name("_UID", integer(0)),
+        name("_CRS", resource_template(*crs)),
+        method(
+            "_OSC", 4,
+            create_dword_field(arg(3), 0, "CDW1"),
+            if_(
+                lequal(arg(0), buffer(PCI_HOST_BRIDGE_UUID)),
+                create_dword_field(arg(3), 8, "CDW3"),
+                store(ref("CDW3"), local(0)),
+                and_(local(0), integer(0x1E), local(0)),
+                store(local(0), ref("CDW3")),
+            ),
+            else_(or_(ref("CDW1"), integer(4), ref("CDW1"))),
+            ret(arg(3)),
+        ),
+    ]
+    return device("PCI0", *terms)
+
+
+def isa_devices():
+    """The devices of the ISA bridge, which are the same on both machines."""
+    return [
+        device("KBD", name("_HID", eisa_id("PNP0303")), name("_STA", integer(0xF)),
+               name("_CRS", resource_template(io(0x60, 1), io(0x64, 1), irq_no_flags(1)))),
+        device("MOU", name("_HID", eisa_id("PNP0F13")), name("_STA", integer(0xF)),
+               name("_CRS", resource_template(irq_no_flags(12)))),
+        device("COM1", name("_HID", eisa_id("PNP0501")), name("_UID", integer(1)),
+               name("_STA", integer(0xF)),
+               name("_CRS", resource_template(io(0x3F8, 8), irq_no_flags(4)))),
+        device("RTC", name("_HID", eisa_id("PNP0B00")),
+               name("_CRS", resource_template(io(0x70, 8), irq_no_flags(8)))),
+    ]
+
+
+def hpet_device():
+    """QEMU's `_STA` checks the vendor and period registers, which are left out here as the
+    tests evaluate every device on the host."""
+    return device(
+        "HPET",
+        name("_HID", eisa_id("PNP0103")),
+        name("_UID", integer(0)),
+        op_region("HPTM", SYSTEM_MEMORY, 0xFED00000, 0x400),
+        field("HPTM", DWORD_ACC | LOCK, ("VEND", 32), ("PRD", 32)),
+        name("_STA", integer(0xF)),
+        name("_CRS", resource_template(memory32_fixed(0xFED00000, 0x400, writable=False))),
+    )
+
+
+def processors(count):
+    return device(
+        "\\_SB.CPUS",
+        name("_HID", string("ACPI0010")),
+        name("_CID", eisa_id("PNP0A05")),
+        *[device(f"C{uid:03X}", name("_HID", string("ACPI0007")), name("_UID", integer(uid)))
+          for uid in range(count)],
+    )
+
+
+def common_objects():
+    return [
+        # The OS tells whether it uses the I/O APIC through `_PIC`
+        name("PICF", integer(0)),
+        method("_PIC", 1, store(arg(0), ref("PICF"))),
+        scope("\\_GPE", name("_HID", string("ACPI0006")),
+              method("_E02", 0, notify("\\_SB.CPUS", 0))),
+    ]
+
+
+def sleep_states(*states):
+    return [name(f"_S{state}", package(integer(value), integer(value), integer(0), integer(0)))
+            for state, value in states]
+
+
+def q35_dsdt():
+    """The q35 DSDT, whose `_PRT` routes every pin to one of the GSI link devices in APIC mode.
+    The PIC mode links read the LPC bridge's routing registers, and are left out."""
+    gsi_links = []
+    prt = []
+    for idx, letter in enumerate("EFGH"):
+        gsi = 0x14 + idx
+        irqs = resource_template(interrupt(gsi))
+        gsi_links.append(device(
+            f"GSI{letter}",
+            name("_HID", eisa_id("PNP0C0F")),
+            name("_UID", integer(idx)),
+            name("_PRS", irqs),
+            name("_CRS", irqs),
+            method("_SRS", 1),
+        ))
+    for slot in range(1, 5):
+        for pin in range(4):
+            link = "EFGH"[(slot + pin) % 4]
+            prt.append(package(integer(slot << 16 | 0xFFFF), integer(pin), ref(f"GSI{link}"),
+                               integer(0)))
+
+    body = b"".join([
+        *common_objects(),
+        scope("\\_SB", pci_root("PNP0A08", "PNP0A03", [
+            word_bus_number(0, 0xFF),
+            word_io(0, 0xCF7),
+            word_io(0xD00, 0xFFFF),
+            dword_memory(0x80000000, 0xAFFFFFFF),
+            dword_memory(0xC0000000, 0xFEBFFFFF),
+        ])),
+        scope(
+            "\\_SB.PCI0",
+            name("_PRT", package(*prt)),
+            device("LPC", name("_ADR", integer(0x001F0000)),
+                   op_region("PIRQ", PCI_CONFIG, 0x60, 0x0C),
+                   field("PIRQ", BYTE_ACC, ("PRQA", 8), ("PRQB", 8), ("PRQC", 8), ("PRQD", 8),
+                         (None, 32), ("PRQE", 8), ("PRQF", 8), ("PRQG", 8), ("PRQH", 8)),
+                   *isa_devices()),
+            hpet_device(),
+        ),
+        scope("\\_SB", *gsi_links),
+        processors(2),
+        *sleep_states((5, 0)),
+    ])
+    return table(b"DSDT", 1, body)
+
+
+def pc_dsdt():
+    """The pc DSDT, whose `_PRT` routes the pins to the four PIIX link devices. Their `_STA`
+    reads the ISA bridge's routing registers, and is a constant here."""
+    links = []
+    prt = []
+    for idx, letter in enumerate("ABCD"):
+        irqs = resource_template(interrupt(5, 10, 11))
+        links.append(device(
+            f"LNK{letter}",
+            name("_HID", eisa_id("PNP0C0F")),
+            name("_UID", integer(idx)),
+            name("_PRS", irqs),
+            name("_STA", integer(0xB)),
+            method("_DIS", 0, or_(ref(f"\\_SB.PCI0.ISA.PRQ{idx}"), integer(0x80),
+                                 ref(f"\\_SB.PCI0.ISA.PRQ{idx}"))),
+        ))
+    for slot in range(1, 5):
+        for pin in range(4):
+            link = "ABCD"[(slot + pin) % 4]
+            prt.append(package(integer(slot << 16 | 0xFFFF), integer(pin), ref(f"LNK{link}"),
+                               integer(0)))
+
+    body = b"".join([
+        *common_objects(),
+        scope("\\_SB", pci_root("PNP0A03", None, [
+            word_bus_number(0, 0xFF),
+            word_io(0, 0xCF7),
+            word_io(0xD00, 0xFFFF),
+            dword_memory(0x08000000, 0xFEBFFFFF),
+        ])),
+        scope(
+            "\\_SB.PCI0",
+            name("_PRT", package(*prt)),
+            device("ISA", name("_ADR", integer(0x00010000)),
+                   op_region("P40C", PCI_CONFIG, 0x60, 4),
+                   field("P40C", BYTE_ACC, ("PRQ0", 8), ("PRQ1", 8), ("PRQ2", 8), ("PRQ3", 8)),
+                   *isa_devices()),
+            hpet_device(),
+        ),
+        scope("\\_SB", *links),
+        processors(1),
+        *sleep_states((3, 1), (4, 2), (5, 0)),
+    ])
+    return table(b"DSDT", 1, body)
+
+
+def q35():
+    """OVMF copies the tables into ACPI reclaim memory, each one on its own page."""
+    addrs = {
+        "facs": 0x7FB7A000,
+        "dsdt": 0x7FB7B000,
+        "facp": 0x7FB7C000,
+        "apic": 0x7FB7D000,
+        "hpet": 0x7FB7E000,
+        "mcfg": 0x7FB7F000,
+        "waet": 0x7FB80000,
+        "rsdt": 0x7FB81000,
+        "xsdt": 0x7FB82000,
+        "rsdp": 0x7FB83014,
+    }
+    entries = [addrs[name] for name in ("facp", "apic", "hpet", "mcfg", "waet")]
+    # Flags: WBINVD, PROC_C1, SLP_BUTTON, RTC_S4, RESET_REG_SUP, USE_PLATFORM_CLOCK
+    tables = {
+        "facs": facs(),
+        "dsdt": q35_dsdt(),
+        "facp": fadt(3, addrs["facs"], addrs["dsdt"], 0x600, 0x620, 16, 0xB2, 0x02, 0x03,
+                     0x84A5, iapc_boot_arch=1 << 1, reset=(gas(SYSTEM_IO, 8, 0xCF9), 0x0F)),
+        "apic": madt(2),
+        "hpet": hpet(),
+        "mcfg": mcfg(),
+        "waet": waet(),
+        "rsdt": rsdt(entries),
+        "xsdt": xsdt(entries),
+        "rsdp": rsdp(2, addrs["rsdt"], addrs["xsdt"]),
+    }
+    return addrs, tables
+
+
+def pc():
+    """SeaBIOS packs the tables one after the other at the top of low memory, with only a
+    revision 0 RSDP in the BIOS area pointing to an RSDT."""
+    tables = {
+        "facs": facs(),
+        "dsdt": pc_dsdt(),
+    }
+    addrs = {"facs": 0x07FE0000, "dsdt": 0x07FE0040}
+    addr = addrs["dsdt"] + len(tables["dsdt"])
+    # Flags: WBINVD, PROC_C1, SLP_BUTTON, RTC_S4, USE_PLATFORM_CLOCK
+    for name, data in (
+        ("facp", fadt(1, addrs["facs"], addrs["dsdt"], 0x600, 0xAFE0, 4, 0xB2, 0xF1, 0xF0,
+                      0x80A5)),
+        ("apic", madt(1)),
+        ("hpet", hpet()),
+        ("waet", waet()),
+    ):
+        addrs[name] = addr
+        tables[name] = data
+        addr += len(data)
+
+    addrs["rsdt"] = addr
+    tables["rsdt"] = rsdt([addrs[name] for name in ("facp", "apic", "hpet", "waet")])
+    addrs["rsdp"] = 0x000F5A40
+    tables["rsdp"] = rsdp(0, addrs["rsdt"])
+    return addrs, tables
+
+
+def write(machine, addrs, tables):
+    path = os.path.join(HERE, machine)
+    os.makedirs(path, exist_ok=True)
+    with open(os.path.join(path, "layout.txt"), "w") as layout:
+        for name, addr in sorted(addrs.items(), key=lambda item: item[1]):
+            layout.write(f"{name}.dat {addr:#010x}\n")
+            with open(os.path.join(path, f"{name}.dat"), "wb") as f:
+                f.write(tables[name])
+
+
+if __name__ == "__main__":
+    write("q35", *q35())
+    write("pc", *pc())